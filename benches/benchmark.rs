@@ -88,7 +88,7 @@ inline fn highshelf(cutoff_hz, gain_db, q_value, sample_rate_hz) -> (a1, a2, a3,
     m1 = k * (1.0 - a) * a
     m2 = 1.0 - a * a
 }
-inline fn process(x, ic1eq, ic2eq, a1, a2, a3, m0, m1, m2) -> (n_x, n_ic1eq, n_ic2eq) {
+pub inline fn process(x, ic1eq, ic2eq, a1, a2, a3, m0, m1, m2) -> (n_x, n_ic1eq, n_ic2eq) {
     v3 = x - ic2eq
     v1 = a1 * ic1eq + a2 * v3
     v2 = ic2eq + a2 * ic1eq + a3 * v3
@@ -96,7 +96,7 @@ inline fn process(x, ic1eq, ic2eq, a1, a2, a3, m0, m1, m2) -> (n_x, n_ic1eq, n_i
     n_ic2eq = 2.0 * v2 - ic2eq
     n_x = m0 * x + m1 * v1 + m2 * v2
 }
-fn main(iterations: i64, output_arr: &[f32]) -> (sum) {
+pub fn main(iterations: i64, output_arr: &[f32]) -> (sum) {
     output_slice = output_arr[..iterations]
     fs = 48000.0
     f1_a1, f1_a2, f1_a3, f1_m0, f1_m1, f1_m2 = highpass(100.0, 1.0, fs)
@@ -246,7 +246,7 @@ fn write_wav(samples: &[f32], path: &str) {
 fn compile_bench(b: &mut Bencher) {
     //setup_logging();
     let code = r#"
-    fn main() -> (c) {
+    pub fn main() -> (c) {
         if true {
             c = 7.4 * 2.7
         } else {
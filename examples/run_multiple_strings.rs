@@ -7,7 +7,7 @@ fn main() -> anyhow::Result<()> {
     let mut jit = jit::JIT::default();
 
     let code = r#"
-    fn add(a, b) -> (c) {
+    pub fn add(a, b) -> (c) {
         c = a + b
     }
     "#;
@@ -28,7 +28,7 @@ fn main() -> anyhow::Result<()> {
     println!("the answer is: {}", func(3.0f32, 5.0f32));
 
     let code = r#"
-    fn mult(a, b) -> (c) {
+    pub fn mult(a, b) -> (c) {
         c = a * b
     }
     "#;
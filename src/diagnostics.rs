@@ -0,0 +1,544 @@
+use std::collections::BTreeSet;
+use std::fmt::Display;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use peg::error::ParseError;
+use peg::str::LineCol;
+use serde::Serialize;
+use thiserror::Error;
+
+use crate::frontend::CodeRef;
+use crate::validator::{ExprType, TypeError};
+
+/// Stable codes for each kind of diagnostic. These don't change between
+/// versions, so tools can match on them instead of on the message.
+pub mod codes {
+    /// The code could not be parsed
+    pub const PARSE_ERROR: &str = "E0001";
+    /// An included file could not be found or read
+    pub const INCLUDE_ERROR: &str = "E0002";
+    pub const TYPE_MISMATCH: &str = "E0100";
+    pub const TYPE_MISMATCH_SPECIFIC: &str = "E0101";
+    pub const TUPLE_LENGTH_MISMATCH: &str = "E0102";
+    pub const UNKNOWN_FUNCTION: &str = "E0103";
+    pub const UNKNOWN_VARIABLE: &str = "E0104";
+    pub const UNKNOWN_STRUCT: &str = "E0105";
+    pub const UNKNOWN_FIELD: &str = "E0106";
+    pub const UNSUPPORTED_EXPR: &str = "E0107";
+    /// Any other error that stopped the code from compiling
+    pub const COMPILE_ERROR: &str = "E0200";
+    // Warnings from the lint pass, see `lint::Lint`
+    pub const UNUSED_VARIABLE: &str = "W0001";
+    pub const UNUSED_PARAMETER: &str = "W0002";
+    pub const UNUSED_RESULT: &str = "W0003";
+    pub const UNREACHABLE_CODE: &str = "W0004";
+    pub const DEAD_ASSIGNMENT: &str = "W0005";
+    pub const UNUSED_FUNCTION: &str = "W0006";
+    pub const UNUSED_STRUCT: &str = "W0007";
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    Error,
+    Warning,
+    Note,
+}
+
+impl Display for Severity {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Severity::Error => write!(f, "error"),
+            Severity::Warning => write!(f, "warning"),
+            Severity::Note => write!(f, "note"),
+        }
+    }
+}
+
+/// The code a diagnostic points at. `start` and `end` are byte offsets into
+/// the file, lines and columns start at 1 and end columns are exclusive.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Span {
+    pub file: Option<PathBuf>,
+    pub start: usize,
+    pub end: Option<usize>,
+    pub line: Option<usize>,
+    pub column: Option<usize>,
+    pub end_line: Option<usize>,
+    pub end_column: Option<usize>,
+    /// What the span has to do with the diagnostic, shown next to it
+    pub label: Option<String>,
+}
+
+impl Span {
+    pub fn from_code_ref(code_ref: &CodeRef, file_index_table: &Option<Vec<PathBuf>>) -> Self {
+        Span {
+            file: code_ref_file(code_ref, file_index_table),
+            start: code_ref.pos,
+            end: code_ref.end,
+            line: code_ref.line,
+            column: code_ref.column,
+            end_line: code_ref.end_line,
+            end_column: code_ref.end_column,
+            label: None,
+        }
+    }
+
+    pub fn with_label(mut self, label: impl Into<String>) -> Self {
+        self.label = Some(label.into());
+        self
+    }
+
+    /// Where the span starts, as file:line:column
+    pub fn location(&self) -> String {
+        let file_name = self
+            .file
+            .as_ref()
+            .map_or("".to_string(), |file| format!("{}:", file.display()));
+        format!(
+            "{}{}:{}",
+            file_name,
+            self.line.unwrap_or(1),
+            self.column.unwrap_or(1)
+        )
+    }
+}
+
+/// An error at a place in the code that isn't a type error, like a duplicate
+/// definition. `push_error` makes `code_ref` the primary span, and each of
+/// `related` a labelled secondary span.
+#[derive(Error, Debug, Clone)]
+#[error("{code_ref} {message}")]
+pub struct LocatedError {
+    /// One of `codes`
+    pub code: &'static str,
+    pub code_ref: CodeRef,
+    pub message: String,
+    pub related: Vec<(CodeRef, String)>,
+}
+
+impl LocatedError {
+    pub fn new(code_ref: &CodeRef, message: impl Into<String>) -> Self {
+        LocatedError {
+            code: codes::COMPILE_ERROR,
+            code_ref: *code_ref,
+            message: message.into(),
+            related: Vec::new(),
+        }
+    }
+
+    pub fn with_code(mut self, code: &'static str) -> Self {
+        self.code = code;
+        self
+    }
+
+    pub fn with_related(mut self, code_ref: &CodeRef, label: impl Into<String>) -> Self {
+        self.related.push((*code_ref, label.into()));
+        self
+    }
+}
+
+/// A fix for a diagnostic. With a span and a replacement it is an edit a tool
+/// can apply, otherwise just advice.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Suggestion {
+    pub message: String,
+    pub span: Option<Span>,
+    pub replacement: Option<String>,
+}
+
+/// An error or warning from parsing or compiling, with everything needed to
+/// show it to the user or hand it to an editor.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    /// One of `codes`
+    pub code: String,
+    pub message: String,
+    /// Where the problem is
+    pub primary_span: Option<Span>,
+    /// Other code that explains the problem, like where an expected type came
+    /// from
+    pub secondary_spans: Vec<Span>,
+    pub notes: Vec<String>,
+    pub suggestions: Vec<Suggestion>,
+}
+
+impl Diagnostic {
+    pub fn error(code: &str, message: impl Into<String>, primary_span: Option<Span>) -> Self {
+        Diagnostic {
+            severity: Severity::Error,
+            code: code.to_string(),
+            message: message.into(),
+            primary_span,
+            secondary_spans: Vec::new(),
+            notes: Vec::new(),
+            suggestions: Vec::new(),
+        }
+    }
+
+    pub fn warning(code: &str, message: impl Into<String>, primary_span: Option<Span>) -> Self {
+        Diagnostic {
+            severity: Severity::Warning,
+            ..Diagnostic::error(code, message, primary_span)
+        }
+    }
+
+    pub fn from_type_error(error: &TypeError, file_index_table: &Option<Vec<PathBuf>>) -> Self {
+        let mut diagnostic = Diagnostic::error(
+            error.error_code(),
+            error.to_string(),
+            Some(Span::from_code_ref(error.code_ref(), file_index_table)),
+        );
+        if let TypeError::TypeMismatch {
+            c,
+            expected,
+            actual,
+        } = error
+        {
+            let expected_ref = expected.get_code_ref();
+            if expected_ref.line.is_some() && expected_ref.pos != c.pos {
+                diagnostic.secondary_spans.push(
+                    Span::from_code_ref(&expected_ref, file_index_table)
+                        .with_label(format!("expected {} because of this", expected)),
+                );
+            }
+            let is_number =
+                |ty: &ExprType| matches!(ty, ExprType::F32(_) | ExprType::I64(_) | ExprType::U8(_));
+            if is_number(expected) && is_number(actual) {
+                diagnostic.suggestions.push(Suggestion {
+                    message: format!("convert the {} with `.{}()`", actual, expected),
+                    span: None,
+                    replacement: None,
+                });
+            }
+        }
+        diagnostic
+    }
+
+    /// Related code without a location, like a std declaration, becomes a
+    /// note with its label instead of a secondary span.
+    pub fn from_located_error(
+        error: &LocatedError,
+        file_index_table: &Option<Vec<PathBuf>>,
+    ) -> Self {
+        // Declarations added by the host, like the std lib, have no location
+        let span = |code_ref: &CodeRef| {
+            code_ref
+                .line
+                .map(|_| Span::from_code_ref(code_ref, file_index_table))
+        };
+        let mut diagnostic = Diagnostic::error(error.code, &error.message, span(&error.code_ref));
+        for (code_ref, label) in &error.related {
+            match span(code_ref) {
+                Some(span) => diagnostic.secondary_spans.push(span.with_label(label)),
+                None => diagnostic.notes.push(label.to_string()),
+            }
+        }
+        diagnostic
+    }
+
+    /// A syntax error, `construct` is what was being parsed, like "function
+    /// signature"
+    pub fn from_parse_error(
+        error: &ParseError<LineCol>,
+        construct: &str,
+        file: Option<&Path>,
+    ) -> Self {
+        let location = &error.location;
+        let span = Span {
+            file: file.map(|file| file.to_path_buf()),
+            start: location.offset,
+            end: None,
+            line: Some(location.line),
+            column: Some(location.column),
+            end_line: None,
+            end_column: None,
+            label: None,
+        };
+        let expected = expected_tokens(error);
+        let message = if expected.is_empty() {
+            format!("Could not parse {}", construct)
+        } else {
+            format!("Could not parse {}; expected {}", construct, expected)
+        };
+        Diagnostic::error(codes::PARSE_ERROR, message, Some(span))
+    }
+
+    /// Render the diagnostic like rustc does, see `render_snippet`.
+    /// Secondary spans are underlined with `-` and labelled, then the notes
+    /// and suggestions follow.
+    pub fn render(&self, source: Option<&str>) -> String {
+        let mut out = format!("{}[{}]: {}", self.severity, self.code, self.message);
+        if let Some(span) = self
+            .primary_span
+            .as_ref()
+            .filter(|span| span.line.is_some())
+        {
+            out.push_str(&format!("\n --> {}", span.location()));
+            render_span(&mut out, span, '^', source);
+        }
+        for span in &self.secondary_spans {
+            out.push_str(&format!("\n ::: {}", span.location()));
+            render_span(&mut out, span, '-', source);
+        }
+        for note in &self.notes {
+            out.push_str(&format!("\n  = note: {}", note));
+        }
+        for suggestion in &self.suggestions {
+            out.push_str(&format!("\n  = help: {}", suggestion.message));
+        }
+        out
+    }
+
+    fn sort_key(&self) -> (bool, Option<PathBuf>, usize) {
+        match &self.primary_span {
+            Some(span) => (false, span.file.clone(), span.start),
+            None => (true, None, 0),
+        }
+    }
+}
+
+/// All of the diagnostics from parsing or compiling, returned as the error
+/// by `parse`, `parse_with_context` and `JIT::translate`. Its Display is every
+/// diagnostic rendered with the code it points at.
+#[derive(Debug, Default, Serialize)]
+pub struct Diagnostics {
+    pub diagnostics: Vec<Diagnostic>,
+    #[serde(skip)]
+    rendered: Vec<String>,
+}
+
+impl Diagnostics {
+    pub fn is_empty(&self) -> bool {
+        self.diagnostics.is_empty()
+    }
+
+    pub fn has_errors(&self) -> bool {
+        self.diagnostics
+            .iter()
+            .any(|d| d.severity == Severity::Error)
+    }
+
+    pub fn push(&mut self, diagnostic: Diagnostic) {
+        self.diagnostics.push(diagnostic);
+    }
+
+    /// Add the diagnostics for an error. Type errors and `LocatedError`s keep
+    /// their spans, other errors only have their message.
+    pub fn push_error(&mut self, error: anyhow::Error, file_index_table: &Option<Vec<PathBuf>>) {
+        let error = match error.downcast::<Diagnostics>() {
+            Ok(diagnostics) => {
+                self.diagnostics.extend(diagnostics.diagnostics);
+                return;
+            }
+            Err(error) => error,
+        };
+        let error = match error.downcast::<TypeError>() {
+            Ok(type_error) => {
+                self.push(Diagnostic::from_type_error(&type_error, file_index_table));
+                return;
+            }
+            Err(error) => error,
+        };
+        let diagnostic = match error.downcast::<LocatedError>() {
+            Ok(located) => Diagnostic::from_located_error(&located, file_index_table),
+            Err(error) => Diagnostic::error(codes::COMPILE_ERROR, error.to_string(), None),
+        };
+        self.diagnostics.push(diagnostic);
+    }
+
+    /// Sort the diagnostics by location and render them. Errors in inlined
+    /// functions are found again each time the function is inlined, those are
+    /// only kept once. `source` is the code that wasn't loaded from a file,
+    /// see `render_snippet`.
+    pub fn finish(mut self, source: Option<&str>) -> Self {
+        self.diagnostics.sort_by_key(|d| d.sort_key());
+        self.diagnostics.dedup();
+        self.rendered = self.diagnostics.iter().map(|d| d.render(source)).collect();
+        self
+    }
+
+    /// The diagnostics as a JSON array
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(&self.diagnostics).unwrap()
+    }
+}
+
+impl From<Diagnostic> for Diagnostics {
+    fn from(diagnostic: Diagnostic) -> Self {
+        Diagnostics {
+            diagnostics: vec![diagnostic],
+            rendered: Vec::new(),
+        }
+    }
+}
+
+impl Display for Diagnostics {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.rendered.join("\n\n"))?;
+        let count = |severity| {
+            self.diagnostics
+                .iter()
+                .filter(|d| d.severity == severity)
+                .count()
+        };
+        let (errors, warnings) = (count(Severity::Error), count(Severity::Warning));
+        if errors + warnings > 1 {
+            let mut counts = Vec::new();
+            if errors > 0 {
+                counts.push(format!(
+                    "{} error{}",
+                    errors,
+                    if errors == 1 { "" } else { "s" }
+                ));
+            }
+            if warnings > 0 {
+                counts.push(format!(
+                    "{} warning{}",
+                    warnings,
+                    if warnings == 1 { "" } else { "s" }
+                ));
+            }
+            write!(f, "\n\n{}", counts.join(", "))?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for Diagnostics {}
+
+/// The tokens the parser expected, written the way they appear in code:
+/// "`(`, `->` or identifier". Character classes from the grammar are named
+/// for what they match, whitespace and comments are left out.
+fn expected_tokens(error: &ParseError<LineCol>) -> String {
+    let tokens: BTreeSet<String> = error
+        .expected
+        .tokens()
+        .filter_map(|token| {
+            if token == "EOF" {
+                Some("end of file".to_string())
+            } else if token.len() >= 2 && token.starts_with('"') && token.ends_with('"') {
+                let literal = token[1..token.len() - 1]
+                    .replace("\\\"", "\"")
+                    .replace("\\\\", "\\");
+                Some(format!("`{}`", literal))
+            } else if token.starts_with('[') {
+                if token.contains("'a'") {
+                    Some("identifier".to_string())
+                } else if token.contains("'0'") {
+                    Some("number".to_string())
+                } else if token.contains("'-'") {
+                    Some("`-`".to_string())
+                } else {
+                    None
+                }
+            } else if token.ends_with("expected identifier") {
+                Some("identifier".to_string())
+            } else {
+                Some(token.to_string())
+            }
+        })
+        .collect();
+    let tokens: Vec<String> = tokens.into_iter().collect();
+    match tokens.split_last() {
+        Some((last, rest)) if !rest.is_empty() => format!("{} or {}", rest.join(", "), last),
+        Some((last, _)) => last.to_string(),
+        None => "".to_string(),
+    }
+}
+
+/// The file a CodeRef points into, from the file index table
+pub fn code_ref_file(
+    code_ref: &CodeRef,
+    file_index_table: &Option<Vec<PathBuf>>,
+) -> Option<PathBuf> {
+    let file_index = code_ref.file_index?;
+    file_index_table.as_ref()?.get(file_index as usize).cloned()
+}
+
+/// Render an error like rustc does, with the line of code it points at and
+/// the span underlined:
+/// ```text
+/// error: Type mismatch; expected i64, found f32
+///  --> src/main.sarus:3:11
+///   |
+/// 3 |     x = a + 1.0
+///   |           ^
+/// ```
+/// The code is loaded from the CodeRef's file in the file index table. Code
+/// that wasn't loaded from a file, like code given to `parse`, is taken from
+/// `source` instead. Without either only the location is shown.
+pub fn render_snippet(
+    code_ref: &CodeRef,
+    message: &str,
+    file_index_table: &Option<Vec<PathBuf>>,
+    source: Option<&str>,
+) -> String {
+    let mut out = format!("error: {}", message);
+    if code_ref.line.is_none() {
+        return out;
+    }
+    if code_ref.file_index.is_some() && code_ref_file(code_ref, file_index_table).is_none() {
+        // The file is unknown, source is some other code
+        return out;
+    }
+    let span = Span::from_code_ref(code_ref, file_index_table);
+    out.push_str(&format!("\n --> {}", span.location()));
+    render_span(&mut out, &span, '^', source);
+    out
+}
+
+/// Add the line of code the span is on to `out`, underlined with `marker`
+fn render_span(out: &mut String, span: &Span, marker: char, source: Option<&str>) {
+    let line = match span.line {
+        Some(line) => line,
+        None => return,
+    };
+    let code = match &span.file {
+        Some(file) => fs::read_to_string(file).ok(),
+        None => source.map(|source| source.to_string()),
+    };
+    if let Some((text, underline)) = code.and_then(|code| underline(&code, span, marker)) {
+        let gutter = " ".repeat(line.to_string().len());
+        out.push_str(&format!("\n{} |\n{} | {}", gutter, line, text));
+        out.push_str(&format!("\n{} | {}", gutter, underline));
+        if let Some(label) = &span.label {
+            out.push_str(&format!(" {}", label));
+        }
+    }
+}
+
+/// The line of code at the span, and the markers underlining it. The span is
+/// cut off at the end of the line.
+fn underline(code: &str, span: &Span, marker: char) -> Option<(String, String)> {
+    let pos = span.start;
+    if pos > code.len() || !code.is_char_boundary(pos) {
+        return None;
+    }
+    let line_start = code[..pos].rfind('\n').map_or(0, |i| i + 1);
+    let line_end = code[pos..].find('\n').map_or(code.len(), |i| pos + i);
+    let end = match span.end {
+        Some(end) if end > pos && code.is_char_boundary(end.min(line_end)) => end.min(line_end),
+        // Without an end, underline the word at pos
+        _ => {
+            pos + code[pos..line_end]
+                .find(|c: char| !(c.is_alphanumeric() || c == '_'))
+                .unwrap_or(line_end - pos)
+        }
+    };
+
+    // Keep tabs, so the markers line up with the code however tabs are shown
+    let indent: String = code[line_start..pos]
+        .chars()
+        .map(|c| if c == '\t' { '\t' } else { ' ' })
+        .collect();
+    let markers = marker
+        .to_string()
+        .repeat(code[pos..end].chars().count().max(1));
+    Some((
+        code[line_start..line_end].trim_end().to_string(),
+        format!("{}{}", indent, markers),
+    ))
+}
@@ -1,6 +1,7 @@
 use tracing::error;
 use tracing::trace;
 
+use crate::diagnostics::{codes, Diagnostic, Diagnostics, LocatedError};
 use crate::validator::ArraySizedExpr;
 use crate::validator::ExprType;
 
@@ -38,6 +39,7 @@ impl Display for SarusRange {
 pub enum Unaryop {
     Not,
     Negative,
+    Deref,
     Slice(SarusRange),
 }
 
@@ -46,6 +48,7 @@ impl Display for Unaryop {
         match self {
             Unaryop::Not => write!(f, "!"),
             Unaryop::Negative => write!(f, "-"),
+            Unaryop::Deref => write!(f, "*"),
             Unaryop::Slice(range) => write!(f, "[{}]", range),
         }
     }
@@ -105,22 +108,65 @@ impl Display for Cmp {
 pub struct CodeRef {
     pub pos: usize,
     pub line: Option<usize>,
+    pub column: Option<usize>,
+    /// Byte offset just past the end of the span, if it is known. Otherwise
+    /// the span is the token at `pos`.
+    pub end: Option<usize>,
+    pub end_line: Option<usize>,
+    pub end_column: Option<usize>,
     pub file_index: Option<u64>, //Index that holds the file name / path
 }
 
+/// The line and column of a byte offset, both starting at 1. Columns count
+/// chars, not bytes.
+fn line_column(code: &str, pos: usize) -> Option<(usize, usize)> {
+    if pos < code.len() && code.is_char_boundary(pos) {
+        let before = &code[..pos];
+        let line_start = before.rfind('\n').map_or(0, |i| i + 1);
+        Some((
+            before.matches('\n').count() + 1,
+            before[line_start..].chars().count() + 1,
+        ))
+    } else {
+        None
+    }
+}
+
 impl CodeRef {
     pub fn new(pos: usize, code_ctx: &CodeContext) -> Self {
-        let line = if pos < code_ctx.code.len() {
-            Some(code_ctx.code[..pos].matches('\n').count() + 1)
-        } else {
-            None
+        let (line, column) = match line_column(code_ctx.code, pos) {
+            Some((line, column)) => (Some(line), Some(column)),
+            None => (None, None),
         };
         CodeRef {
             pos,
             line,
+            column,
+            end: None,
+            end_line: None,
+            end_column: None,
             file_index: code_ctx.file_index,
         }
     }
+
+    /// A CodeRef for the code from `pos` up to `end`
+    pub fn span(pos: usize, end: usize, code_ctx: &CodeContext) -> Self {
+        let mut code_ref = CodeRef::new(pos, code_ctx);
+        if end > pos {
+            code_ref.end = Some(end);
+            // The end is exclusive, so the location is that of the last char
+            let last = code_ctx.code[..end]
+                .char_indices()
+                .last()
+                .map_or(end, |(i, _)| i);
+            if let Some((line, column)) = line_column(code_ctx.code, last) {
+                code_ref.end_line = Some(line);
+                code_ref.end_column = Some(column + 1);
+            }
+        }
+        code_ref
+    }
+
     pub fn s(&self, t: &Option<Vec<PathBuf>>) -> String {
         let s = if let Some(file_index) = self.file_index {
             if let Some(file_index_table) = t {
@@ -136,7 +182,9 @@ impl CodeRef {
         } else {
             "".to_string()
         };
-        if let Some(line) = self.line {
+        if let (Some(line), Some(column)) = (self.line, self.column) {
+            format!("line {}:{}:{}", s, line, column)
+        } else if let Some(line) = self.line {
             format!("line {}:{}", s, line)
         } else {
             format!("pos {}:{}", s, self.pos)
@@ -149,6 +197,10 @@ impl Default for CodeRef {
         CodeRef {
             pos: 0,
             line: None,
+            column: None,
+            end: None,
+            end_line: None,
+            end_column: None,
             file_index: None,
         }
     }
@@ -191,6 +243,7 @@ pub enum Expr {
         code_ref: CodeRef,
         exprs: Vec<Expr>,
         len: usize,
+        len_const: Option<String>, //length given by a const, len is set once consts are evaluated
     },
     Identifier {
         code_ref: CodeRef,
@@ -242,6 +295,7 @@ pub enum Expr {
         code_ref: CodeRef,
         name: String,
         fields: Vec<StructAssignField>,
+        base: Option<Box<Expr>>, //remaining fields are copied from base in `Name { a: 1.0, ..base }`
     },
     Match {
         code_ref: CodeRef,
@@ -273,6 +327,9 @@ pub enum Expr {
         args: Vec<Expr>,
         is_macro: bool,
     },
+    /// Address of the global data named `name`. The parser doesn't produce
+    /// this anymore, `*name` dereferences a reference instead.
+    #[deprecated(note = "use a `static` for data that lives outside of a function")]
     GlobalDataAddr {
         code_ref: CodeRef,
         name: String,
@@ -285,6 +342,7 @@ pub enum Expr {
         code_ref: CodeRef,
         expr: Box<Expr>,
         idx_expr: Box<Expr>,
+        index_mode: IndexMode,
     },
     Declaration {
         code_ref: CodeRef,
@@ -300,6 +358,7 @@ impl Expr {
         s[0..end].to_string()
     }
 
+    #[allow(deprecated)]
     pub fn get_code_ref(&self) -> &CodeRef {
         match self {
             Expr::LiteralFloat { code_ref, .. }
@@ -332,6 +391,7 @@ impl Expr {
         }
     }
 
+    #[allow(deprecated)]
     pub fn get_code_ref_mut(&mut self) -> &mut CodeRef {
         match self {
             Expr::LiteralFloat { code_ref, .. }
@@ -393,6 +453,7 @@ impl Expr {
             code_ref: Default::default(),
             exprs: vec![expr.clone()],
             len,
+            len_const: None,
         }
     }
     pub fn identifier(name: &str) -> Self {
@@ -468,6 +529,7 @@ impl Expr {
             code_ref: Default::default(),
             name: name.to_string(),
             fields: fields.clone(),
+            base: None,
         }
     }
     pub fn while_loop(cond: &Expr, iter_body: &Option<Vec<Expr>>, loop_body: &Vec<Expr>) -> Self {
@@ -503,6 +565,7 @@ impl Expr {
             code_ref: Default::default(),
             expr: Box::new(expr.clone()),
             idx_expr: Box::new(idx_expr.clone()),
+            index_mode: IndexMode::Checked,
         }
     }
     pub fn declaration(decl: &Declaration) -> Self {
@@ -515,6 +578,7 @@ impl Expr {
 
 //TODO indentation, tests
 impl Display for Expr {
+    #[allow(deprecated)]
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
             Expr::LiteralFloat { code_ref: _, val } => write!(f, "{val}"),
@@ -525,8 +589,12 @@ impl Display for Expr {
                 code_ref: _,
                 exprs,
                 len,
+                len_const,
             } => {
-                if exprs.len() == 1 {
+                if let Some(len_const) = len_const {
+                    let first = &exprs[0];
+                    write!(f, "[{first}; {len_const}]")
+                } else if exprs.len() == 1 {
                     let first = &exprs[0];
                     write!(f, "[{first}; {len}]")
                 } else {
@@ -661,11 +729,15 @@ impl Display for Expr {
                 code_ref: _,
                 name: struct_name,
                 fields,
+                base,
             } => {
                 writeln!(f, "{struct_name}{{")?;
                 for field in fields.iter() {
                     writeln!(f, "{field},")?;
                 }
+                if let Some(base) = base {
+                    writeln!(f, "..{base}")?;
+                }
                 writeln!(f, "}}")?;
                 Ok(())
             }
@@ -735,7 +807,8 @@ impl Display for Expr {
                 code_ref: _,
                 expr,
                 idx_expr,
-            } => write!(f, "{expr}[{idx_expr}]"),
+                index_mode,
+            } => write!(f, "{expr}{index_mode}[{idx_expr}]"),
             Expr::Declaration {
                 code_ref: _,
                 declaration,
@@ -749,7 +822,10 @@ pub enum Declaration {
     Function(Function),
     Metadata(Vec<String>, String),
     Struct(Struct),
-    Include(String), //Naive implementation that will change significantly.
+    Include(String, Option<String>, CodeRef), //path, the namespace if included with `as name`, and where it is included
+    Constant(Constant),
+    Static(Static),
+    TypeAlias(TypeAlias),
 }
 
 impl Display for Declaration {
@@ -765,7 +841,13 @@ impl Display for Declaration {
                 Ok(())
             }
             Declaration::Struct(e) => write!(f, "{}", e),
-            Declaration::Include(path) => writeln!(f, "include {}", path),
+            Declaration::Include(path, None, _) => writeln!(f, "include \"{}\"", path),
+            Declaration::Include(path, Some(namespace), _) => {
+                writeln!(f, "include \"{}\" as {}", path, namespace)
+            }
+            Declaration::Constant(e) => writeln!(f, "{}", e),
+            Declaration::Static(e) => writeln!(f, "{}", e),
+            Declaration::TypeAlias(e) => writeln!(f, "{}", e),
         }
     }
 }
@@ -776,17 +858,22 @@ pub struct Arg {
     pub expr_type: ExprType,
     pub no_type_listed: bool,
     pub closure_arg: Option<Function>,
+    pub default: Option<Expr>, //only used for struct fields
 }
 
 impl Display for Arg {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         if self.no_type_listed {
-            write!(f, "{}", self.name)
+            write!(f, "{}", self.name)?;
         } else if let Some(closure_arg) = &self.closure_arg {
-            write!(f, "{}: {}", self.name, closure_arg)
+            write!(f, "{}: {}", self.name, closure_arg)?;
         } else {
-            write!(f, "{}: {}", self.name, self.expr_type)
+            write!(f, "{}: {}", self.name, self.expr_type)?;
+        }
+        if let Some(default) = &self.default {
+            write!(f, " = {}", default)?;
         }
+        Ok(())
     }
 }
 
@@ -834,14 +921,62 @@ impl Display for InlineKind {
     }
 }
 
+/// How an index into an array or slice is kept in bounds
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum IndexMode {
+    /// `a[i]`, uses the bounds check policy of the function
+    Checked,
+    /// `a.wrap[i]`, wraps around to the start or end of the array
+    Wrap,
+    /// `a.clamp[i]`, uses the first or last item of the array
+    Clamp,
+    /// The index is already known to be in bounds
+    Unchecked,
+}
+
+impl Display for IndexMode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            IndexMode::Checked | IndexMode::Unchecked => write!(f, ""),
+            IndexMode::Wrap => write!(f, ".wrap"),
+            IndexMode::Clamp => write!(f, ".clamp"),
+        }
+    }
+}
+
+/// What happens when an index is out of bounds
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum BoundsCheck {
+    /// Runtime error, see `RuntimeErrorMode`
+    Abort,
+    /// Clamp the index into bounds, and report the error to the host
+    ClampAndReport,
+    /// No checks
+    Unchecked,
+}
+
+impl Display for BoundsCheck {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            BoundsCheck::Abort => write!(f, "abort"),
+            BoundsCheck::ClampAndReport => write!(f, "clamp_and_report"),
+            BoundsCheck::Unchecked => write!(f, "unchecked"),
+        }
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct Function {
+    pub code_ref: CodeRef,
     pub name: String,
     pub params: Vec<Arg>,
     pub returns: Vec<Arg>,
     pub body: Vec<Expr>,
     pub extern_func: bool,
     pub inline: InlineKind,
+    pub override_func: bool, //replaces another function with the same name
+    pub pub_func: bool,      //exported from the JIT and callable from rust
+    pub bounds_check: Option<BoundsCheck>, //None uses the caller's or the JIT's policy
 }
 
 impl Function {
@@ -867,21 +1002,34 @@ impl Function {
 
     pub fn external(name: String, params: Vec<Arg>, returns: Vec<Arg>) -> Self {
         Function {
+            code_ref: CodeRef::default(),
             name,
             params,
             returns,
             body: vec![],
             extern_func: true,
             inline: InlineKind::Never,
+            override_func: false,
+            pub_func: false,
+            bounds_check: None,
         }
     }
 }
 
 impl Display for Function {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.override_func {
+            write!(f, "override ")?;
+        }
+        if self.pub_func {
+            write!(f, "pub ")?;
+        }
         if self.extern_func {
             write!(f, "extern ")?;
         }
+        if let Some(bounds_check) = self.bounds_check {
+            write!(f, "bounds({}) ", bounds_check)?;
+        }
         write!(f, "fn {} (", self.name)?;
         for (i, param) in self.params.iter().enumerate() {
             write!(f, "{}", param)?;
@@ -910,14 +1058,19 @@ impl Display for Function {
 
 #[derive(Debug, Clone)]
 pub struct Struct {
+    pub code_ref: CodeRef,
     pub name: String,
     pub fields: Vec<Arg>,
     pub extern_struct: bool,
     pub enum_struct: bool,
+    pub override_struct: bool, //replaces another struct with the same name
 }
 
 impl Display for Struct {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.override_struct {
+            write!(f, "override ")?;
+        }
         write!(f, "struct {} {{", self.name)?;
         if !self.extern_struct {
             writeln!(f)?;
@@ -933,6 +1086,51 @@ impl Display for Struct {
     }
 }
 
+#[derive(Debug, Clone)]
+pub struct Constant {
+    pub code_ref: CodeRef,
+    pub name: String, //either NAME or Type::NAME for associated constants
+    pub expr_type: ExprType,
+    pub expr: Expr,
+}
+
+impl Display for Constant {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "const {}: {} = {}", self.name, self.expr_type, self.expr)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Static {
+    pub code_ref: CodeRef,
+    pub name: String,
+    pub expr_type: ExprType,
+    pub expr: Expr,
+}
+
+impl Display for Static {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "static {}: {} = {}",
+            self.name, self.expr_type, self.expr
+        )
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct TypeAlias {
+    pub code_ref: CodeRef,
+    pub name: String,
+    pub expr_type: ExprType,
+}
+
+impl Display for TypeAlias {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "type {} = {}", self.name, self.expr_type)
+    }
+}
+
 // TODO there must be a better way.
 pub fn pretty_indent(code: &str) -> String {
     let mut f = String::from("");
@@ -973,13 +1171,31 @@ peg::parser!(pub grammar parser(code_ctx: &CodeContext) for str {
         / metadata()
         / structdef()
         / include()
+        / constant()
+        / static_()
+        / type_alias()
 
     rule include() -> Declaration
-        = _ "include" _ "\"" body:$[^'"']* "\"" { Declaration::Include(body.join("")) }
+        = _ pos:position!() "include" _ "\"" body:$[^'"']* "\"" namespace:(require_ws() "as" require_ws() n:identifier() {n})? { Declaration::Include(body.join(""), namespace, CodeRef::new(pos, code_ctx)) }
 
     rule structdef() -> Declaration
-        = _ ext:("extern")? _ kind:$("struct"/"enum") _ name:$(s:identifier() ("::" (ty:type_label() ** "::"))?) _ "{" _ fields:(a:arg() comma() {a})* _ "}" _
-          {Declaration::Struct(Struct{name: name.to_string(), fields, extern_struct: ext.is_some(), enum_struct: if kind == "enum" {true} else {false} })}
+        = _ pos:position!() ovr:("override" require_ws())? ext:("extern")? _ kind:$("struct"/"enum") _ name:$(s:identifier() ("::" (ty:type_label() ** "::"))?) _ "{" _ fields:(a:struct_field() ** comma()) comma()? _ "}" _
+          {Declaration::Struct(Struct{code_ref: CodeRef::new(pos, code_ctx), name: name.to_string(), fields, extern_struct: ext.is_some(), enum_struct: if kind == "enum" {true} else {false}, override_struct: ovr.is_some() })}
+
+    rule constant() -> Declaration
+        = _ pos:position!() "const" require_ws() name:identifier() _ ":" _ t:type_label() _ "=" _ e:expression() _
+          { Declaration::Constant(Constant { code_ref: CodeRef::new(pos, code_ctx), name, expr_type: t, expr: e }) }
+
+    rule static_() -> Declaration
+        = _ pos:position!() "static" require_ws() name:identifier() _ ":" _ t:type_label() _ "=" _ e:expression() _
+          { Declaration::Static(Static { code_ref: CodeRef::new(pos, code_ctx), name, expr_type: t, expr: e }) }
+
+    rule type_alias() -> Declaration
+        = _ pos:position!() "type" require_ws() name:identifier() _ "=" _ t:type_label() _
+          { Declaration::TypeAlias(TypeAlias { code_ref: CodeRef::new(pos, code_ctx), name, expr_type: t }) }
+
+    rule struct_field() -> Arg
+        = a:arg() default:("=" _ e:expression() _ {e})? { Arg {default, ..a} }
 
     rule metadata() -> Declaration
         = _ "@" _ headings:(i:(metadata_identifier()** ([' ' | '\t'])) {i}) ([' ' | '\t'])* "\n" body:$[^'@']* "@" _ {Declaration::Metadata(headings, body.join(""))}
@@ -989,7 +1205,7 @@ peg::parser!(pub grammar parser(code_ctx: &CodeContext) for str {
         / expected!("identifier")
 
     rule function() -> Declaration
-        = _ ext:("extern")? _ inline:function_inline_kind()? _  "fn" _ name:identifier() _
+        = _ pos:position!() ovr:("override" require_ws())? pb:("pub" require_ws())? ext:("extern")? _ inline:function_inline_kind()? _ bounds:function_bounds_check()? _ "fn" _ name:identifier() _
         "(" params:(i:arg() ** comma()) ")" _
         "->" _
         "(" returns:(i:arg() ** comma()) _ ")"
@@ -1003,65 +1219,84 @@ peg::parser!(pub grammar parser(code_ctx: &CodeContext) for str {
                 }
             }
             Declaration::Function(Function {
+            code_ref: CodeRef::new(pos, code_ctx),
             name,
             params,
             returns,
             body,
             extern_func: ext.is_some(),
             inline: if let Some(inline) = inline {inline} else {InlineKind::Default},
+            override_func: ovr.is_some(),
+            pub_func: pb.is_some(),
+            bounds_check: bounds,
         }) }
 
     rule closure_definition(name: String) -> Function
-        = "|" params:(i:arg() ** comma()) "|" _
+        = pos:position!() "|" params:(i:arg() ** comma()) "|" _
          "->" _
          "(" returns:(i:arg() ** comma()) _ ")"
          {
              Function {
+             code_ref: CodeRef::new(pos, code_ctx),
              name,
              params,
              returns,
              body: vec![],
              extern_func: false,
              inline: InlineKind::Always,
+             override_func: false,
+             pub_func: false,
+             bounds_check: None,
         } }
 
     rule closure_declaration(name: String) -> Declaration
-        = "|" params:(i:arg() ** comma()) "|" _
+        = pos:position!() "|" params:(i:arg() ** comma()) "|" _
         "->" _
         "(" returns:(i:arg() ** comma()) _ ")"
         body:block()
         {
             Declaration::Function(Function {
+            code_ref: CodeRef::new(pos, code_ctx),
             name,
             params,
             returns,
             body,
             extern_func: false,
             inline: InlineKind::Always,
+            override_func: false,
+            pub_func: false,
+            bounds_check: None,
         }) }
 
+    rule function_bounds_check() -> BoundsCheck
+    = "bounds(" _ b:("abort" {BoundsCheck::Abort} / "clamp_and_report" {BoundsCheck::ClampAndReport} / "unchecked" {BoundsCheck::Unchecked}) _ ")" {b}
+
     rule function_inline_kind() -> InlineKind
     = "inline" {InlineKind::Often}
     / "never_inline" {InlineKind::Never}
     / "always_inline" {InlineKind::Always}
 
     rule arg() -> Arg
-        = _ i:identifier() _ ":" _ c:closure_definition(i) _ { Arg {name: c.name.to_string(), expr_type: ExprType::Void(Default::default()), no_type_listed: false, closure_arg: Some(c) } }
-        / _ i:identifier() _ ":" _ t:type_label() _ { Arg {name: i, expr_type: t, no_type_listed: false, closure_arg: None } }
-        / _ pos:position!() i:identifier() _ { Arg {name: i, expr_type: ExprType::F32(CodeRef::new(pos, code_ctx)), no_type_listed: true, closure_arg: None } }
+        = _ i:identifier() _ ":" _ c:closure_definition(i) _ { Arg {name: c.name.to_string(), expr_type: ExprType::Void(Default::default()), no_type_listed: false, closure_arg: Some(c), default: None } }
+        / _ i:identifier() _ ":" _ t:type_label() _ { Arg {name: i, expr_type: t, no_type_listed: false, closure_arg: None, default: None } }
+        / _ pos:position!() i:identifier() _ { Arg {name: i, expr_type: ExprType::F32(CodeRef::new(pos, code_ctx)), no_type_listed: true, closure_arg: None, default: None } }
 
     rule type_label() -> ExprType
-        = _ pos:position!() "f32" { ExprType::F32(CodeRef::new(pos, code_ctx)) }
-        / _ pos:position!() "i64" { ExprType::I64(CodeRef::new(pos, code_ctx)) }
-        / _ pos:position!() "u8" { ExprType::U8(CodeRef::new(pos, code_ctx)) }
-        / _ pos:position!() "&[" ty:type_label() "]" { ExprType::Array(CodeRef::new(pos, code_ctx), Box::new(ty), ArraySizedExpr::Unsized) }
-        / _ pos:position!() "[" ty:type_label() "]" { ExprType::Array(CodeRef::new(pos, code_ctx), Box::new(ty), ArraySizedExpr::Slice) }
+        = _ pos:position!() "f32" end:position!() { ExprType::F32(CodeRef::span(pos, end, code_ctx)) }
+        / _ pos:position!() "i64" end:position!() { ExprType::I64(CodeRef::span(pos, end, code_ctx)) }
+        / _ pos:position!() "u8" end:position!() { ExprType::U8(CodeRef::span(pos, end, code_ctx)) }
+        / _ pos:position!() "&[" ty:type_label() "]" end:position!() { ExprType::Array(CodeRef::span(pos, end, code_ctx), Box::new(ty), ArraySizedExpr::Unsized) }
+        / _ pos:position!() "[" ty:type_label() "]" end:position!() { ExprType::Array(CodeRef::span(pos, end, code_ctx), Box::new(ty), ArraySizedExpr::Slice) }
+        / _ pos:position!() "&" ty:type_label() end:position!() { ExprType::Ref(CodeRef::span(pos, end, code_ctx), Box::new(ty)) }
         / _ pos:position!() "&" { ExprType::Address(CodeRef::new(pos, code_ctx)) }
-        / _ pos:position!() "bool" { ExprType::Bool(CodeRef::new(pos, code_ctx)) }
-        / _ pos:position!() n:$(identifier() "::" (type_label() ** "::")) { ExprType::Struct(CodeRef::new(pos, code_ctx), Box::new(n.to_string())) }
-        / _ pos:position!() n:identifier() { ExprType::Struct(CodeRef::new(pos, code_ctx), Box::new(n)) }
-        / _ pos:position!() "[" _  ty:type_label()  _ ";" _ len:$(['0'..='9']+) _ "]" {
-            ExprType::Array(CodeRef::new(pos, code_ctx), Box::new(ty), ArraySizedExpr::Fixed(len.parse::<usize>().unwrap()))
+        / _ pos:position!() "bool" end:position!() { ExprType::Bool(CodeRef::span(pos, end, code_ctx)) }
+        / _ pos:position!() n:$(identifier() "::" (type_label() ** "::")) end:position!() { ExprType::Struct(CodeRef::span(pos, end, code_ctx), Box::new(n.to_string())) }
+        / _ pos:position!() n:identifier() end:position!() { ExprType::Struct(CodeRef::span(pos, end, code_ctx), Box::new(n)) }
+        / _ pos:position!() "[" _  ty:type_label()  _ ";" _ len:$(['0'..='9']+) _ "]" end:position!() {
+            ExprType::Array(CodeRef::span(pos, end, code_ctx), Box::new(ty), ArraySizedExpr::Fixed(len.parse::<usize>().unwrap()))
+        }
+        / _ pos:position!() "[" _  ty:type_label()  _ ";" _ len_const:identifier() _ "]" end:position!() {
+            ExprType::Array(CodeRef::span(pos, end, code_ctx), Box::new(ty), ArraySizedExpr::Const(len_const))
         }
 
     rule block() -> Vec<Expr>
@@ -1078,13 +1313,13 @@ peg::parser!(pub grammar parser(code_ctx: &CodeContext) for str {
         / return_()
 
     rule break_() -> Expr
-        = _ pos:position!() "break" _ {Expr::Break { code_ref: CodeRef::new(pos, code_ctx) } }
+        = _ pos:position!() "break" end:position!() _ {Expr::Break { code_ref: CodeRef::span(pos, end, code_ctx) } }
 
     rule continue_() -> Expr
-        = _ pos:position!() "continue" _ {Expr::Continue { code_ref: CodeRef::new(pos, code_ctx) } }
+        = _ pos:position!() "continue" end:position!() _ {Expr::Continue { code_ref: CodeRef::span(pos, end, code_ctx) } }
 
     rule return_() -> Expr
-        = _ pos:position!() "return" _ {Expr::Return { code_ref: CodeRef::new(pos, code_ctx) } }
+        = _ pos:position!() "return" end:position!() _ {Expr::Return { code_ref: CodeRef::span(pos, end, code_ctx) } }
 
     rule expression_declaration() -> Expr
         = _ pos:position!() i:identifier()  _ declaration:closure_declaration(i) {
@@ -1140,61 +1375,70 @@ peg::parser!(pub grammar parser(code_ctx: &CodeContext) for str {
 
     #[cache]
     rule binary_op() -> Expr = precedence!{
-        a:@ _ pos:position!() "&&" _ b:(@) { Expr::Binop { code_ref: CodeRef::new(pos, code_ctx),
+        a:@ _ pos:position!() "&&" end:position!() _ b:(@) { Expr::Binop { code_ref: CodeRef::span(pos, end, code_ctx),
                                                            op: Binop::LogicalAnd,
                                                            lhs: Box::new(a),
                                                            rhs:  Box::new(b) } }
-        a:@ _ pos:position!() "||" _ b:(@) { Expr::Binop { code_ref: CodeRef::new(pos, code_ctx),
+        a:@ _ pos:position!() "||" end:position!() _ b:(@) { Expr::Binop { code_ref: CodeRef::span(pos, end, code_ctx),
                                                            op: Binop::LogicalOr,
                                                            lhs: Box::new(a),
                                                            rhs:  Box::new(b) } }
         --
-        a:@ _ pos:position!() "==" _ b:(@) { Expr::Compare { code_ref: CodeRef::new(pos, code_ctx),
+        a:@ _ pos:position!() "==" end:position!() _ b:(@) { Expr::Compare { code_ref: CodeRef::span(pos, end, code_ctx),
                                                              cmp: Cmp::Eq,
                                                              lhs: Box::new(a),
                                                              rhs: Box::new(b) } }
-        a:@ _ pos:position!() "!=" _ b:(@) { Expr::Compare { code_ref: CodeRef::new(pos, code_ctx),
+        a:@ _ pos:position!() "!=" end:position!() _ b:(@) { Expr::Compare { code_ref: CodeRef::span(pos, end, code_ctx),
                                                              cmp: Cmp::Ne,
                                                              lhs: Box::new(a),
                                                              rhs: Box::new(b) } }
-        a:@ _ pos:position!() "<"  _ b:(@) { Expr::Compare { code_ref: CodeRef::new(pos, code_ctx),
+        a:@ _ pos:position!() "<" end:position!() _ b:(@) { Expr::Compare { code_ref: CodeRef::span(pos, end, code_ctx),
                                                              cmp: Cmp::Lt,
                                                              lhs: Box::new(a),
                                                              rhs: Box::new(b) } }
-        a:@ _ pos:position!() "<=" _ b:(@) { Expr::Compare { code_ref: CodeRef::new(pos, code_ctx),
+        a:@ _ pos:position!() "<=" end:position!() _ b:(@) { Expr::Compare { code_ref: CodeRef::span(pos, end, code_ctx),
                                                              cmp: Cmp::Le,
                                                              lhs: Box::new(a),
                                                              rhs: Box::new(b) } }
-        a:@ _ pos:position!() ">"  _ b:(@) { Expr::Compare { code_ref: CodeRef::new(pos, code_ctx),
+        a:@ _ pos:position!() ">" end:position!() _ b:(@) { Expr::Compare { code_ref: CodeRef::span(pos, end, code_ctx),
                                                              cmp: Cmp::Gt,
                                                              lhs: Box::new(a),
                                                              rhs: Box::new(b) } }
-        a:@ _ pos:position!() ">=" _ b:(@) { Expr::Compare { code_ref: CodeRef::new(pos, code_ctx),
+        a:@ _ pos:position!() ">=" end:position!() _ b:(@) { Expr::Compare { code_ref: CodeRef::span(pos, end, code_ctx),
                                                              cmp: Cmp::Ge,
                                                              lhs: Box::new(a),
                                                              rhs: Box::new(b) } }
         --
-        a:@ _ pos:position!() "+" _ b:(@) { Expr::Binop { code_ref: CodeRef::new(pos, code_ctx),
+        a:@ _ pos:position!() "+" end:position!() _ b:(@) { Expr::Binop { code_ref: CodeRef::span(pos, end, code_ctx),
                                                           op: Binop::Add,
                                                           lhs: Box::new(a),
                                                           rhs: Box::new(b) } }
         --
-        a:@ _ pos:position!() "-" _ b:(@) { Expr::Binop { code_ref: CodeRef::new(pos, code_ctx),
+        a:@ _ pos:position!() "-" end:position!() _ b:(@) { Expr::Binop { code_ref: CodeRef::span(pos, end, code_ctx),
                                                           op: Binop::Sub,
                                                           lhs: Box::new(a),
                                                           rhs: Box::new(b) } }
         --
-        a:@ _ pos:position!() "*" _ b:(@) { Expr::Binop { code_ref: CodeRef::new(pos, code_ctx),
+        //a `*` starting a new line is only multiplication when whitespace follows it, otherwise `*p = 1.0` would be read as part of the previous line
+        a:@ [' ' | '\t']* pos:position!() "*" end:position!() _ b:(@) { Expr::Binop { code_ref: CodeRef::span(pos, end, code_ctx),
+                                                          op: Binop::Mul,
+                                                          lhs: Box::new(a),
+                                                          rhs: Box::new(b) } }
+        a:@ _ pos:position!() "*" end:position!() require_ws() _ b:(@) { Expr::Binop { code_ref: CodeRef::span(pos, end, code_ctx),
                                                           op: Binop::Mul,
                                                           lhs: Box::new(a),
                                                           rhs: Box::new(b) } }
         --
-        a:@ _ pos:position!() "/" _ b:(@) { Expr::Binop { code_ref: CodeRef::new(pos, code_ctx),
+        a:@ _ pos:position!() "/" end:position!() _ b:(@) { Expr::Binop { code_ref: CodeRef::span(pos, end, code_ctx),
                                                           op: Binop::Div,
                                                           lhs: Box::new(a),
                                                           rhs: Box::new(b) } }
         --
-        a:@ _ pos:position!() "." _ b:(@) { Expr::Binop { code_ref: CodeRef::new(pos, code_ctx),
+        _ pos:position!() "*" _ e:@ { Expr::Unaryop { code_ref: CodeRef::new(pos, code_ctx),
+                                                      op: Unaryop::Deref,
+                                                      expr: Box::new(e) } }
+        --
+        a:@ _ pos:position!() "." end:position!() _ b:(@) { Expr::Binop { code_ref: CodeRef::span(pos, end, code_ctx),
                                                           op: Binop::DotAccess,
                                                           lhs: Box::new(a),
                                                           rhs: Box::new(b) } }
@@ -1205,17 +1449,26 @@ peg::parser!(pub grammar parser(code_ctx: &CodeContext) for str {
 
     rule unary_op() -> Expr
         //TODO e:unary() "[" idx:expression() "]" is causing a severe performance regression vs using i:identifier() "[" idx:expression() "]"
-        = _ pos:position!() e:unary() "[" r:range() "]" { Expr::Unaryop { code_ref: CodeRef::new(pos, code_ctx),
+        = _ pos:position!() e:unary() "[" r:range() "]" end:position!() { Expr::Unaryop { code_ref: CodeRef::span(pos, end, code_ctx),
                                                                           op: Unaryop::Slice(r),
                                                                           expr: Box::new(e) } }
-        / _ pos:position!() e:unary() "[" idx:expression() "]" { Expr::ArrayAccess { code_ref: CodeRef::new(pos, code_ctx),
+        / _ pos:position!() e:unary() "." m:index_mode() "[" idx:expression() "]" end:position!() { Expr::ArrayAccess { code_ref: CodeRef::span(pos, end, code_ctx),
+                                                                                                        expr: Box::new(e),
+                                                                                                        idx_expr: Box::new(idx),
+                                                                                                        index_mode: m } }
+        / _ pos:position!() e:unary() "[" idx:expression() "]" end:position!() { Expr::ArrayAccess { code_ref: CodeRef::span(pos, end, code_ctx),
                                                                                      expr: Box::new(e),
-                                                                                     idx_expr: Box::new(idx) } }
-        / _ pos:position!() "!" e:unary() { Expr::Unaryop { code_ref: CodeRef::new(pos, code_ctx), op: Unaryop::Not, expr: Box::new(e) } }
-        / _ pos:position!() "-" e:unary() { Expr::Unaryop { code_ref: CodeRef::new(pos, code_ctx),
+                                                                                     idx_expr: Box::new(idx),
+                                                                                     index_mode: IndexMode::Checked } }
+        / _ pos:position!() "!" e:unary() end:position!() { Expr::Unaryop { code_ref: CodeRef::span(pos, end, code_ctx), op: Unaryop::Not, expr: Box::new(e) } }
+        / _ pos:position!() "-" e:unary() end:position!() { Expr::Unaryop { code_ref: CodeRef::span(pos, end, code_ctx),
                                                             op: Unaryop::Negative,
                                                             expr: Box::new(e) } }
 
+    rule index_mode() -> IndexMode
+        = "wrap" {IndexMode::Wrap}
+        / "clamp" {IndexMode::Clamp}
+
     rule range() -> SarusRange
         = _ se:expression()? _ ".." _ ee:expression()? _ {SarusRange{start: if let Some(se) = se {Some(Box::new(se))} else {None},
                                                                      end: if let Some(ee) = ee {Some(Box::new(ee))} else {None}}}
@@ -1232,8 +1485,8 @@ peg::parser!(pub grammar parser(code_ctx: &CodeContext) for str {
         //Having a _ before the () breaks in this case:
         //c = p.x + p.y + p.z
         //(p.x).print()
-        = _ pos:position!() fn_name:identifier() _macro:("!")? "(" args:((_ e:expression() _ {e}) ** comma()) ")" {
-            Expr::Call { code_ref: CodeRef::new(pos, code_ctx), fn_name, args, is_macro: _macro.is_some() }
+        = _ pos:position!() fn_name:identifier() _macro:("!")? "(" args:((_ e:expression() _ {e}) ** comma()) ")" end:position!() {
+            Expr::Call { code_ref: CodeRef::span(pos, end, code_ctx), fn_name, args, is_macro: _macro.is_some() }
         }
         / _ pos:position!() "match" _ e:expression() _ "{" fields:((_ e:match_field() _ {e})*) "}" {
             Expr::Match { code_ref: CodeRef::new(pos, code_ctx), expr_arg: Box::new(e), fields }
@@ -1241,37 +1494,39 @@ peg::parser!(pub grammar parser(code_ctx: &CodeContext) for str {
         / _ pos:position!() "match" _ e:expression() _ "{" fields:((_ e:match_field() _ {e})*) "}" {
             Expr::Match { code_ref: CodeRef::new(pos, code_ctx), expr_arg: Box::new(e), fields }
         }
-        / _ pos:position!() name:identifier() _ "{" fields:((_ e:struct_assign_field() _ {e})*) "}" {
-            Expr::NewStruct { code_ref: CodeRef::new(pos, code_ctx), name, fields }
+        / _ pos:position!() name:identifier() _ "{" fields:((_ e:struct_assign_field() _ {e})*) base:(_ ".." e:expression() _ {Box::new(e)})? "}" end:position!() {
+            Expr::NewStruct { code_ref: CodeRef::span(pos, end, code_ctx), name, fields, base }
         }
-        / _ pos:position!() name:identifier() {
-            Expr::Identifier { code_ref: CodeRef::new(pos, code_ctx), name }
+        / _ pos:position!() name:identifier() end:position!() {
+            Expr::Identifier { code_ref: CodeRef::span(pos, end, code_ctx), name }
         }
-        / _ pos:position!() "(" e:expression() _ ")" {
-            Expr::Parentheses { code_ref: CodeRef::new(pos, code_ctx), expr: Box::new(e) }
+        / _ pos:position!() "(" e:expression() _ ")" end:position!() {
+            Expr::Parentheses { code_ref: CodeRef::span(pos, end, code_ctx), expr: Box::new(e) }
         }
-        / _ pos:position!() "\"" body:$[^'"']* "\"" { Expr::LiteralString{code_ref: CodeRef::new(pos, code_ctx), val: body.join("")} }
-        / _ pos:position!() "[" e:expression()  _ ";" _ len:$(['0'..='9']+) _ "]" {
-            Expr::LiteralArray { code_ref: CodeRef::new(pos, code_ctx), exprs: vec![e], len: len.parse::<usize>().unwrap() }
+        / _ pos:position!() "\"" body:$[^'"']* "\"" end:position!() { Expr::LiteralString{code_ref: CodeRef::span(pos, end, code_ctx), val: body.join("")} }
+        / _ pos:position!() "[" e:expression()  _ ";" _ len:$(['0'..='9']+) _ "]" end:position!() {
+            Expr::LiteralArray { code_ref: CodeRef::span(pos, end, code_ctx), exprs: vec![e], len: len.parse::<usize>().unwrap(), len_const: None }
         }
-        / _ pos:position!() "[" exprs:(e:expression() ** comma() {e}) _ "]" {
+        / _ pos:position!() "[" e:expression()  _ ";" _ len_const:identifier() _ "]" end:position!() {
+            Expr::LiteralArray { code_ref: CodeRef::span(pos, end, code_ctx), exprs: vec![e], len: 0, len_const: Some(len_const) }
+        }
+        / _ pos:position!() "[" exprs:(e:expression() ** comma() {e}) _ "]" end:position!() {
             let len = exprs.len();
-            Expr::LiteralArray { code_ref: CodeRef::new(pos, code_ctx), exprs, len }
+            Expr::LiteralArray { code_ref: CodeRef::span(pos, end, code_ctx), exprs, len, len_const: None }
         }
         / l:literal() { l }
 
     rule identifier() -> String
-        = n:$(['a'..='z' | 'A'..='Z' | '_']['a'..='z' | 'A'..='Z' | '0'..='9' | '_']* "::"? ['a'..='z' | 'A'..='Z' | '0'..='9' | '_']*)  {?
+        = n:$(['a'..='z' | 'A'..='Z' | '_']['a'..='z' | 'A'..='Z' | '0'..='9' | '_']* ("::" ['a'..='z' | 'A'..='Z' | '0'..='9' | '_']*)*)  {?
             if RESERVED_WORDS.contains(&n) {Err("keyword found, expected identifier")} else {Ok(n.into())}
         }
 
     rule literal() -> Expr
-        = _ pos:position!() n:$(['0'..='9']+) "u8" { Expr::LiteralU8 { code_ref: CodeRef::new(pos, code_ctx), val: n.parse::<u8>().unwrap() } }
-        / _ pos:position!() n:$(['-']?['0'..='9']+"."['0'..='9']+) { Expr::LiteralFloat{code_ref: CodeRef::new(pos, code_ctx), val: n.parse::<f32>().unwrap()} }
-        / _ pos:position!() n:$(['-']?['0'..='9']+) { Expr::LiteralInt { code_ref: CodeRef::new(pos, code_ctx), val: n.parse::<i64>().unwrap() } }
-        / _ pos:position!() "*" name:identifier() { Expr::GlobalDataAddr { code_ref: CodeRef::new(pos, code_ctx), name } }
-        / _ pos:position!() "true" { Expr::LiteralBool{code_ref: CodeRef::new(pos, code_ctx), val: true } }
-        / _ pos:position!() "false" { Expr::LiteralBool{code_ref: CodeRef::new(pos, code_ctx), val: false } }
+        = _ pos:position!() n:$(['0'..='9']+) "u8" end:position!() { Expr::LiteralU8 { code_ref: CodeRef::span(pos, end, code_ctx), val: n.parse::<u8>().unwrap() } }
+        / _ pos:position!() n:$(['-']?['0'..='9']+"."['0'..='9']+) end:position!() { Expr::LiteralFloat{code_ref: CodeRef::span(pos, end, code_ctx), val: n.parse::<f32>().unwrap()} }
+        / _ pos:position!() n:$(['-']?['0'..='9']+) end:position!() { Expr::LiteralInt { code_ref: CodeRef::span(pos, end, code_ctx), val: n.parse::<i64>().unwrap() } }
+        / _ pos:position!() "true" end:position!() { Expr::LiteralBool{code_ref: CodeRef::span(pos, end, code_ctx), val: true } }
+        / _ pos:position!() "false" end:position!() { Expr::LiteralBool{code_ref: CodeRef::span(pos, end, code_ctx), val: false } }
 
     rule struct_assign_field() -> StructAssignField
         = _ i:identifier() _ ":" _ e:expression() comma() { StructAssignField {field_name: i.into(), expr: e } }
@@ -1317,7 +1572,7 @@ pub struct CodeContext<'a> {
 pub fn parse_with_context(
     code: &str,
     file: &Path,
-) -> anyhow::Result<(Vec<Declaration>, Vec<PathBuf>)> {
+) -> Result<(Vec<Declaration>, Vec<PathBuf>), Diagnostics> {
     let mut ast = Vec::new();
     let mut file_index_table = Vec::new();
     parse_with_context_recursively(
@@ -1326,7 +1581,12 @@ pub fn parse_with_context(
         file,
         &mut file_index_table,
         &mut HashSet::new(),
-    )?;
+    )
+    .map_err(|error| {
+        let mut diagnostics = Diagnostics::default();
+        diagnostics.push_error(error, &Some(file_index_table.clone()));
+        diagnostics.finish(None)
+    })?;
     Ok((ast, file_index_table))
 }
 
@@ -1340,69 +1600,609 @@ pub fn parse_with_context_recursively(
 ) -> anyhow::Result<()> {
     trace!("parse_with_context {}", file.display());
     files_index.push(file.to_path_buf());
-    let code_ctx = CodeContext {
-        code,
-        file_index: Some((files_index.len() - 1) as u64),
-    };
-    match parser::program(code, &code_ctx) {
+    let file_index = Some((files_index.len() - 1) as u64);
+    match parse_program(code, file_index, Some(file)) {
         Ok(mut new_ast) => {
             for decl in &new_ast {
                 match decl {
-                    Declaration::Include(path_str) => {
+                    Declaration::Include(path_str, namespace, code_ref) => {
                         trace!("Found path_str {}", path_str);
-                        let path = Path::new(&path_str);
-                        let (new_code, new_file) = if path.is_absolute() {
-                            if !seen_paths.insert(path.display().to_string()) {
-                                //We have already imported this
-                                continue;
-                            }
-                            trace!("Loading file import at {:?}", path);
-                            let new_code = match fs::read_to_string(path) {
-                                Ok(new_code) => new_code,
-                                Err(e) => {
-                                    anyhow::bail!("File import error {} {}", path.display(), e)
-                                }
-                            };
-                            (new_code, path.to_path_buf())
+                        let new_file = resolve_include_path(Some(file), path_str, code_ref)?;
+                        let seen_key = if let Some(namespace) = namespace {
+                            format!("{} as {}", new_file.display(), namespace)
                         } else {
-                            let new_path = dunce::canonicalize(file.parent().unwrap().join(path))?;
-                            if !seen_paths.insert(new_path.display().to_string()) {
-                                //We have already imported this
-                                continue;
+                            new_file.display().to_string()
+                        };
+                        if !seen_paths.insert(seen_key) {
+                            //We have already imported this
+                            continue;
+                        }
+                        trace!("Loading file import at {}", new_file.display());
+                        let new_code = match fs::read_to_string(&new_file) {
+                            Ok(new_code) => new_code,
+                            Err(e) => {
+                                anyhow::bail!(LocatedError::new(
+                                    code_ref,
+                                    format!("File import error {} {}", new_file.display(), e)
+                                )
+                                .with_code(codes::INCLUDE_ERROR))
                             }
-                            trace!("Loading file import at {}", new_path.display());
-                            let new_code = match fs::read_to_string(new_path.clone()) {
-                                Ok(new_code) => new_code,
-                                Err(e) => {
-                                    anyhow::bail!("File import error {} {}", new_path.display(), e)
-                                }
-                            };
-                            (new_code, new_path)
                         };
-                        parse_with_context_recursively(
-                            ast,
-                            &new_code,
-                            &new_file,
-                            files_index,
-                            seen_paths,
-                        )?;
+                        if let Some(namespace) = namespace {
+                            // The module gets its own copy of anything it includes
+                            // that was not already included outside of it
+                            let mut module_ast = Vec::new();
+                            let mut module_seen_paths = seen_paths.clone();
+                            module_seen_paths.insert(new_file.display().to_string());
+                            parse_with_context_recursively(
+                                &mut module_ast,
+                                &new_code,
+                                &new_file,
+                                files_index,
+                                &mut module_seen_paths,
+                            )?;
+                            namespace_declarations(&mut module_ast, namespace);
+                            ast.append(&mut module_ast);
+                        } else {
+                            parse_with_context_recursively(
+                                ast,
+                                &new_code,
+                                &new_file,
+                                files_index,
+                                seen_paths,
+                            )?;
+                        }
                     }
                     _ => continue,
                 }
             }
             ast.append(&mut new_ast);
         }
-        Err(err) => anyhow::bail!("{:?} parser {}", file, err),
+        Err(diagnostics) => return Err(diagnostics.into()),
     }
 
     Ok(())
 }
 
+/// Split `Type::member` into the type and the member. The type can itself be
+/// in a namespace, like `filters::Biquad::new`.
+pub fn split_type_member(name: &str) -> [&str; 2] {
+    match name.rsplit_once("::") {
+        Some((type_name, member)) => [type_name, member],
+        None => [name, ""],
+    }
+}
+
+/// Move the declarations from a file included with `include "path" as ns`
+/// into the namespace `ns`. Everything declared is renamed to `ns::name`,
+/// along with the uses of those names inside the included declarations.
+/// Methods keep their name, but the type of self is renamed.
+fn namespace_declarations(decls: &mut [Declaration], namespace: &str) {
+    let mut namespacer = Namespacer {
+        namespace,
+        funcs: HashSet::new(),
+        values: HashSet::new(),
+        prefixes: HashSet::new(),
+        locals: HashSet::new(),
+    };
+    for decl in decls.iter() {
+        match decl {
+            Declaration::Function(func) => {
+                if !func.extern_func && !func.name.contains('.') {
+                    namespacer.funcs.insert(func.name.to_string());
+                }
+            }
+            Declaration::Struct(struct_) => {
+                namespacer
+                    .prefixes
+                    .insert(first_segment(&struct_.name).to_string());
+            }
+            Declaration::TypeAlias(alias) => {
+                namespacer
+                    .prefixes
+                    .insert(first_segment(&alias.name).to_string());
+            }
+            Declaration::Include(_, Some(inner), _) => {
+                namespacer.prefixes.insert(first_segment(inner).to_string());
+            }
+            Declaration::Constant(constant) => {
+                namespacer.values.insert(constant.name.to_string());
+            }
+            Declaration::Static(static_) => {
+                namespacer.values.insert(static_.name.to_string());
+            }
+            Declaration::Metadata(..) | Declaration::Include(_, None, _) => (),
+        }
+    }
+    for decl in decls {
+        namespacer.namespace_declaration(decl)
+    }
+}
+
+/// The namespaces of the files included with `include "path" as ns`.
+pub fn declared_namespaces(prog: &[Declaration]) -> HashSet<String> {
+    prog.iter()
+        .filter_map(|decl| match decl {
+            Declaration::Include(_, Some(namespace), _) => Some(namespace.to_string()),
+            _ => None,
+        })
+        .collect()
+}
+
+fn first_segment(name: &str) -> &str {
+    name.split("::").next().unwrap()
+}
+
+struct Namespacer<'a> {
+    namespace: &'a str,
+    funcs: HashSet<String>,    //functions declared in the namespace
+    values: HashSet<String>,   //consts and statics declared in the namespace
+    prefixes: HashSet<String>, //types and inner namespaces, names starting with these are renamed
+    locals: HashSet<String>,   //params of the current function, these shadow values
+}
+
+impl<'a> Namespacer<'a> {
+    fn rename(&self, name: &mut String) {
+        *name = format!("{}::{}", self.namespace, name);
+    }
+
+    fn has_prefix(&self, name: &str) -> bool {
+        self.prefixes.contains(first_segment(name))
+    }
+
+    fn namespace_declaration(&mut self, decl: &mut Declaration) {
+        match decl {
+            Declaration::Function(func) => self.namespace_function(func, true),
+            Declaration::Struct(struct_) => {
+                self.rename(&mut struct_.name);
+                self.namespace_args(&mut struct_.fields);
+            }
+            Declaration::Constant(Constant {
+                name,
+                expr_type,
+                expr,
+                ..
+            })
+            | Declaration::Static(Static {
+                name,
+                expr_type,
+                expr,
+                ..
+            }) => {
+                self.rename(name);
+                self.namespace_type(expr_type);
+                self.namespace_expr(expr);
+            }
+            Declaration::TypeAlias(alias) => {
+                self.rename(&mut alias.name);
+                self.namespace_type(&mut alias.expr_type);
+            }
+            Declaration::Include(_, Some(inner), _) => self.rename(inner),
+            Declaration::Metadata(..) | Declaration::Include(_, None, _) => (),
+        }
+    }
+
+    fn namespace_function(&mut self, func: &mut Function, top_level: bool) {
+        self.namespace_args(&mut func.params);
+        self.namespace_args(&mut func.returns);
+        if top_level && !func.extern_func {
+            if let Some((_, method_name)) = func.name.rsplit_once('.') {
+                //methods are named by the type of self
+                func.name = format!("{}.{}", func.params[0].expr_type, method_name);
+            } else {
+                self.rename(&mut func.name);
+            }
+        }
+        let outer_locals = self.locals.clone();
+        for arg in func.params.iter().chain(func.returns.iter()) {
+            self.locals.insert(arg.name.to_string());
+        }
+        for expr in &mut func.body {
+            self.namespace_expr(expr)
+        }
+        self.locals = outer_locals;
+    }
+
+    fn namespace_args(&mut self, args: &mut [Arg]) {
+        for arg in args {
+            self.namespace_type(&mut arg.expr_type);
+            if let Some(closure_arg) = &mut arg.closure_arg {
+                self.namespace_function(closure_arg, false);
+            }
+            if let Some(default) = &mut arg.default {
+                self.namespace_expr(default);
+            }
+        }
+    }
+
+    fn namespace_type(&self, expr_type: &mut ExprType) {
+        match expr_type {
+            ExprType::Struct(_, name) => {
+                if self.has_prefix(name) {
+                    self.rename(name)
+                }
+            }
+            ExprType::Array(_, ty, size_type) => {
+                if let ArraySizedExpr::Const(name) = size_type {
+                    if self.values.contains(name) {
+                        self.rename(name)
+                    }
+                }
+                self.namespace_type(ty)
+            }
+            ExprType::Ref(_, ty) => self.namespace_type(ty),
+            ExprType::Tuple(_, types) => {
+                for ty in types {
+                    self.namespace_type(ty)
+                }
+            }
+            _ => (),
+        }
+    }
+
+    fn namespace_body(&mut self, body: &mut [Expr]) {
+        for expr in body {
+            self.namespace_expr(expr)
+        }
+    }
+
+    #[allow(deprecated)]
+    fn namespace_expr(&mut self, expr: &mut Expr) {
+        match expr {
+            Expr::LiteralFloat { .. }
+            | Expr::LiteralInt { .. }
+            | Expr::LiteralU8 { .. }
+            | Expr::LiteralBool { .. }
+            | Expr::LiteralString { .. }
+            | Expr::Break { .. }
+            | Expr::Continue { .. }
+            | Expr::Return { .. }
+            | Expr::GlobalDataAddr { .. } => (),
+            Expr::Identifier { name, .. } => {
+                if !self.locals.contains(name.as_str())
+                    && (self.values.contains(name.as_str())
+                        || (name.contains("::") && self.has_prefix(name)))
+                {
+                    self.rename(name)
+                }
+            }
+            Expr::LiteralArray {
+                exprs, len_const, ..
+            } => {
+                if let Some(name) = len_const {
+                    if self.values.contains(name.as_str()) {
+                        self.rename(name)
+                    }
+                }
+                self.namespace_body(exprs)
+            }
+            Expr::Binop {
+                op: Binop::DotAccess,
+                lhs,
+                rhs,
+                ..
+            } => {
+                self.namespace_expr(lhs);
+                match &mut **rhs {
+                    //method calls and fields keep their names
+                    Expr::Call { args, .. } => self.namespace_body(args),
+                    Expr::Identifier { .. } => (),
+                    rhs => self.namespace_expr(rhs),
+                }
+            }
+            Expr::Binop { lhs, rhs, .. } | Expr::Compare { lhs, rhs, .. } => {
+                self.namespace_expr(lhs);
+                self.namespace_expr(rhs)
+            }
+            Expr::Unaryop { expr, .. } | Expr::Parentheses { expr, .. } => {
+                self.namespace_expr(expr)
+            }
+            Expr::IfThen {
+                condition,
+                then_body,
+                ..
+            } => {
+                self.namespace_expr(condition);
+                self.namespace_body(then_body)
+            }
+            Expr::IfElse {
+                condition,
+                then_body,
+                else_body,
+                ..
+            } => {
+                self.namespace_expr(condition);
+                self.namespace_body(then_body);
+                self.namespace_body(else_body)
+            }
+            Expr::IfThenElseIf { expr_bodies, .. } => {
+                for (a, b) in expr_bodies {
+                    self.namespace_expr(a);
+                    self.namespace_body(b)
+                }
+            }
+            Expr::IfThenElseIfElse {
+                expr_bodies,
+                else_body,
+                ..
+            } => {
+                for (a, b) in expr_bodies {
+                    self.namespace_expr(a);
+                    self.namespace_body(b)
+                }
+                self.namespace_body(else_body)
+            }
+            Expr::Assign {
+                to_exprs,
+                from_exprs,
+                ..
+            } => {
+                self.namespace_body(to_exprs);
+                self.namespace_body(from_exprs)
+            }
+            Expr::NewStruct {
+                name, fields, base, ..
+            } => {
+                if self.has_prefix(name) {
+                    self.rename(name)
+                }
+                for e in fields {
+                    self.namespace_expr(&mut e.expr)
+                }
+                if let Some(base) = base {
+                    self.namespace_expr(base)
+                }
+            }
+            Expr::Match {
+                expr_arg, fields, ..
+            } => {
+                self.namespace_expr(expr_arg);
+                for e in fields {
+                    self.namespace_expr(&mut e.expr)
+                }
+            }
+            Expr::WhileLoop {
+                condition,
+                iter_body,
+                loop_body,
+                ..
+            } => {
+                self.namespace_expr(condition);
+                if let Some(b) = iter_body {
+                    self.namespace_body(b)
+                }
+                self.namespace_body(loop_body)
+            }
+            Expr::Block { block, .. } => self.namespace_body(block),
+            Expr::Call { fn_name, args, .. } => {
+                if self.funcs.contains(fn_name.as_str())
+                    || (fn_name.contains("::") && self.has_prefix(fn_name))
+                {
+                    self.rename(fn_name)
+                }
+                self.namespace_body(args)
+            }
+            Expr::ArrayAccess { expr, idx_expr, .. } => {
+                self.namespace_expr(expr);
+                self.namespace_expr(idx_expr)
+            }
+            Expr::Declaration { declaration, .. } => {
+                if let Declaration::Function(closure) = declaration {
+                    self.namespace_function(closure, false)
+                }
+            }
+        }
+    }
+}
+
+/// Resolve the path of an included file. Relative paths are relative to the
+/// file doing the including, or the working directory if there is no file.
+/// `code_ref` is where it is included, for the error if it can't be found.
+pub fn resolve_include_path(
+    file: Option<&Path>,
+    path_str: &str,
+    code_ref: &CodeRef,
+) -> anyhow::Result<PathBuf> {
+    let path = Path::new(path_str);
+    if path.is_absolute() {
+        return Ok(path.to_path_buf());
+    }
+    let new_path = match file.and_then(|file| file.parent()) {
+        Some(dir) => dir.join(path),
+        None => path.to_path_buf(),
+    };
+    match dunce::canonicalize(&new_path) {
+        Ok(new_path) => Ok(new_path),
+        Err(e) => Err(LocatedError::new(
+            code_ref,
+            format!("File import error {} {}", new_path.display(), e),
+        )
+        .with_code(codes::INCLUDE_ERROR)
+        .into()),
+    }
+}
+
 //No imports or file context
-pub fn parse(code: &str) -> anyhow::Result<Vec<Declaration>> {
-    let code_ctx = CodeContext {
-        code,
-        file_index: None,
+pub fn parse(code: &str) -> Result<Vec<Declaration>, Diagnostics> {
+    let code = code.replace("\r\n", "\n");
+    parse_program(&code, None, None).map_err(|diagnostics| diagnostics.finish(Some(&code)))
+}
+
+/// The kinds of top-level declarations, found by `item_starts`
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+enum ItemKind {
+    Function,
+    Struct,
+    Constant,
+    Static,
+    TypeAlias,
+    Include,
+    Metadata,
+}
+
+/// How much a line changes the brace depth. Braces in strings and after `//`
+/// don't count.
+fn brace_depth_change(line: &str) -> i64 {
+    let mut change = 0;
+    let mut in_string = false;
+    let mut chars = line.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' => in_string = !in_string,
+            '\\' if in_string => {
+                chars.next();
+            }
+            '/' if !in_string && chars.peek() == Some(&'/') => break,
+            '{' if !in_string => change += 1,
+            '}' if !in_string => change -= 1,
+            _ => (),
+        }
+    }
+    change
+}
+
+/// Where each top-level declaration starts, found from the first words on
+/// each line outside of any braces. This works on code that doesn't parse, so
+/// syntax errors can say what was being parsed and the parser can pick up
+/// again after them.
+fn item_starts(code: &str) -> Vec<(usize, ItemKind)> {
+    let mut starts = Vec::new();
+    let mut in_metadata = false;
+    let mut depth = 0;
+    let mut pos = 0;
+    for line in code.split('\n') {
+        let line_pos = pos;
+        pos += line.len() + 1;
+        let trimmed = line.trim_start();
+        if in_metadata {
+            in_metadata = !trimmed.contains('@');
+            continue;
+        }
+        let line_depth = depth;
+        depth = (depth + brace_depth_change(trimmed)).max(0);
+        if line_depth > 0 || trimmed.starts_with("//") {
+            continue;
+        }
+        let start = line_pos + line.len() - trimmed.len();
+        if trimmed.starts_with('@') {
+            starts.push((start, ItemKind::Metadata));
+            in_metadata = !trimmed[1..].contains('@');
+            continue;
+        }
+        let kind = trimmed
+            .split(|c: char| !(c.is_alphanumeric() || c == '_'))
+            .filter(|word| !word.is_empty())
+            .find(|word| {
+                !matches!(
+                    *word,
+                    "override"
+                        | "pub"
+                        | "extern"
+                        | "inline"
+                        | "never_inline"
+                        | "always_inline"
+                        | "bounds"
+                        | "abort"
+                        | "clamp_and_report"
+                        | "unchecked"
+                )
+            })
+            .and_then(|word| match word {
+                "fn" => Some(ItemKind::Function),
+                "struct" | "enum" => Some(ItemKind::Struct),
+                "const" => Some(ItemKind::Constant),
+                "static" => Some(ItemKind::Static),
+                "type" => Some(ItemKind::TypeAlias),
+                "include" => Some(ItemKind::Include),
+                _ => None,
+            });
+        if let Some(kind) = kind {
+            starts.push((start, kind));
+        }
+    }
+    starts
+}
+
+/// What was being parsed at `pos`, for syntax errors
+fn parse_construct(code: &str, item: Option<(usize, ItemKind)>, pos: usize) -> &'static str {
+    let (start, kind) = match item {
+        Some(item) => item,
+        None => return "declaration",
     };
-    Ok(parser::program(&code.replace("\r\n", "\n"), &code_ctx)?)
+    let in_braces = code[start..pos].contains('{');
+    match kind {
+        ItemKind::Function if in_braces => "block",
+        ItemKind::Function => "function signature",
+        ItemKind::Struct if in_braces => "struct field",
+        ItemKind::Struct => "struct declaration",
+        ItemKind::Constant => "constant",
+        ItemKind::Static => "static",
+        ItemKind::TypeAlias => "type alias",
+        ItemKind::Include => "include",
+        ItemKind::Metadata => "metadata",
+    }
+}
+
+/// Parse a program, reporting every syntax error instead of only the first.
+/// After an error the declaration it is in is blanked out, and the code is
+/// parsed again from the next top-level `fn`, `struct` or other declaration.
+/// Blanking keeps every other position the same, so spans stay correct.
+fn parse_program(
+    code: &str,
+    file_index: Option<u64>,
+    file: Option<&Path>,
+) -> Result<Vec<Declaration>, Diagnostics> {
+    let starts = item_starts(code);
+    let mut diagnostics = Diagnostics::default();
+    let mut blanked = code.to_string();
+    let mut last_error = None;
+    loop {
+        let code_ctx = CodeContext {
+            code: &blanked,
+            file_index,
+        };
+        let err = match parser::program(&blanked, &code_ctx) {
+            Ok(ast) if diagnostics.is_empty() => return Ok(ast),
+            Ok(_) => return Err(diagnostics),
+            Err(err) => err,
+        };
+        let pos = err.location.offset.min(code.len());
+        if last_error.map_or(false, |last| pos <= last) {
+            // Nothing more can be recovered
+            return Err(diagnostics);
+        }
+        last_error = Some(pos);
+
+        let item = starts
+            .iter()
+            .rev()
+            .find(|(start, _)| *start <= pos)
+            .copied();
+        diagnostics.push(Diagnostic::from_parse_error(
+            &err,
+            parse_construct(code, item, pos),
+            file,
+        ));
+
+        let start = match item {
+            Some((start, _)) => start,
+            None => code[..pos].rfind('\n').map_or(0, |i| i + 1),
+        };
+        let end = starts
+            .iter()
+            .map(|(start, _)| *start)
+            .find(|start| *start > pos)
+            .unwrap_or(code.len());
+        let spaces: String = blanked[start..end]
+            .chars()
+            .map(|c| {
+                if c == '\n' {
+                    "\n".to_string()
+                } else {
+                    " ".repeat(c.len_utf8())
+                }
+            })
+            .collect();
+        blanked.replace_range(start..end, &spaces);
+    }
 }
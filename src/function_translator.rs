@@ -1,18 +1,24 @@
+use crate::diagnostics::{codes, LocatedError};
 use crate::frontend::*;
-use crate::jit::Env;
+use crate::jit::{find_references, Env};
+use crate::runtime_error::{RuntimeErrorCode, RuntimeErrorMode, MAX_CALL_STACK};
 use crate::sarus_std_lib;
 use crate::sarus_std_lib::check_core_generics;
 pub use crate::structs::*;
+use crate::validator::index_mode_error;
+use crate::validator::ref_target_type;
 use crate::validator::ArraySizedExpr;
 use crate::validator::ExprType;
+use crate::validator::TypeError;
 pub use crate::variables::*;
 use cranelift::codegen::ir::immediates::Offset32;
 use cranelift::codegen::ir::ArgumentPurpose;
 use cranelift::prelude::*;
 pub use cranelift_jit::{JITBuilder, JITModule};
-use cranelift_module::{Linkage, Module};
+use cranelift_module::{DataContext, FuncOrDataId, Linkage, Module};
 use std::collections::HashMap;
 use std::collections::HashSet;
+use std::fs;
 use tracing::info;
 use tracing::instrument;
 use tracing::trace;
@@ -67,6 +73,32 @@ pub struct FunctionTranslator<'a> {
 
     pub deep_stack_debug: bool,
 
+    // Panic instead of writing past the end of the deep stack
+    pub deep_stack_checks: bool,
+
+    // Bytes of native stack that sarus code can use, checked on function entry
+    pub stack_budget: Option<usize>,
+
+    // True if this call entered sarus code from the host, and the address of
+    // this thread's stack base, set by stack_guard_enter
+    pub stack_guard_outer: Option<(Value, Value)>,
+
+    // Whether runtime errors abort, or are recorded and returned to the host
+    pub runtime_errors: RuntimeErrorMode,
+
+    // Bounds check policy for functions that don't set their own
+    pub bounds_check: BoundsCheck,
+
+    // Check integer arithmetic for overflow and division by zero
+    pub overflow_checks: bool,
+
+    // Push the name of each function entered onto the error slot's call stack
+    pub call_stack_tracking: bool,
+
+    // How many call stack entries this function has pushed, including inlined
+    // functions, so returns can pop all of them
+    pub call_stack_pushes: usize,
+
     // Each time a while block in entered, the exit is pushed here. This is for
     // doing early exits with break
     pub while_exit_blocks: Vec<Block>,
@@ -74,12 +106,29 @@ pub struct FunctionTranslator<'a> {
     // Each time a while block in entered, the exit is pushed here. This is for
     // doing early exits with break
     pub while_continue_blocks: Vec<Block>,
+
+    // Array variables that alias another array variable (from `b = a`),
+    // mapped to the name of the variable they alias. Along with the names each
+    // of those arrays has been mutated through, this is used to warn when the
+    // same array is mutated through more than one name.
+    // use last on vec for current function
+    pub array_aliases: Vec<HashMap<String, String>>,
+    pub array_alias_writes: Vec<HashMap<String, HashSet<String>>>,
+
+    // Type errors found so far. Translation goes on to the next statement
+    // after one, so all of the errors in the function are found.
+    pub type_errors: Vec<TypeError>,
+
+    // Variables that a statement with an error would have assigned. Errors in
+    // statements that use them are left out, since they follow from the first.
+    pub poisoned_vars: HashSet<String>,
 }
 
 impl<'a> FunctionTranslator<'a> {
     /// When you write out instructions in Cranelift, you get back `Value`s. You
     /// can then use these references in other instructions.
     #[instrument(name = "expr", skip(self, expr))]
+    #[allow(deprecated)]
     pub fn translate_expr(&mut self, expr: &Expr) -> anyhow::Result<SValue> {
         self.expr_depth += 1;
         info!(
@@ -91,10 +140,7 @@ impl<'a> FunctionTranslator<'a> {
         );
 
         if self.builder.is_filled() {
-            anyhow::bail!(
-                "{} unreachable code",
-                expr.get_code_ref().s(&self.env.file_idx)
-            )
+            anyhow::bail!(LocatedError::new(expr.get_code_ref(), "unreachable code"))
         }
 
         let _ = ExprType::of(
@@ -114,6 +160,7 @@ impl<'a> FunctionTranslator<'a> {
                 code_ref,
                 exprs,
                 len,
+                ..
             } => self.translate_array_create(code_ref, exprs, *len),
             Expr::Binop {
                 code_ref: _,
@@ -147,22 +194,26 @@ impl<'a> FunctionTranslator<'a> {
             Expr::Identifier { code_ref, name } => {
                 if name.contains("::") {
                     //check if this is an enum
-                    let parts = name.split("::").collect::<Vec<_>>();
+                    let parts = split_type_member(name);
                     if let Some(struct_) = self.env.struct_map.get(parts[0]) {
                         if struct_.enum_struct {
                             let struct_ = struct_.clone();
                             if let Some(field) = struct_.fields.get(parts[1]) {
                                 let field_index_val = self.i64const((field.index - 1) as i64);
                                 return Ok(SValue::I64(field_index_val));
-                            } else {
-                                panic!("field {} not found", parts[1]) //TODO make an error for this
                             }
+                            //otherwise this could be an associated constant
                         }
                     }
                 }
                 if let Ok(svar) = self.get_variable(code_ref, name) {
                     let svar = svar.clone();
                     SValue::get_from_variable(&mut self.builder, &svar)
+                } else if let Some(pointee) = self.translate_static(name)? {
+                    match pointee {
+                        SValue::Array(..) => Ok(pointee), //the array is the static data itself
+                        pointee => self.ref_load(code_ref, &pointee),
+                    }
                 } else if let Some(sval) = self.translate_constant(code_ref, name)? {
                     Ok(sval)
                 } else if self
@@ -189,7 +240,8 @@ impl<'a> FunctionTranslator<'a> {
                 code_ref,
                 name,
                 fields,
-            } => self.translate_new_struct(code_ref, name, fields),
+                base,
+            } => self.translate_new_struct(code_ref, name, fields, base),
             Expr::Match {
                 code_ref,
                 expr_arg,
@@ -244,9 +296,10 @@ impl<'a> FunctionTranslator<'a> {
                 code_ref,
                 expr,
                 idx_expr,
+                index_mode,
             } => {
                 let idx_val = self.idx_expr_to_val(idx_expr)?;
-                self.translate_array_get(code_ref, expr, idx_val, false)
+                self.translate_array_get(code_ref, expr, idx_val, false, *index_mode)
             }
             Expr::Declaration {
                 code_ref: _,
@@ -255,7 +308,10 @@ impl<'a> FunctionTranslator<'a> {
                 Declaration::Function(_closure) => Ok(SValue::Void),
                 Declaration::Metadata(_, _) => todo!(),
                 Declaration::Struct(_) => todo!(),
-                Declaration::Include(_) => todo!(),
+                Declaration::Include(..) => todo!(),
+                Declaration::Constant(_) => todo!(),
+                Declaration::Static(_) => todo!(),
+                Declaration::TypeAlias(_) => todo!(),
             },
         };
 
@@ -266,14 +322,40 @@ impl<'a> FunctionTranslator<'a> {
     pub fn get_variable(&mut self, code_ref: &CodeRef, name: &str) -> anyhow::Result<&SVariable> {
         match self.variables.last().unwrap().get(name) {
             Some(v) => Ok(v),
-            None => anyhow::bail!(
-                "{} variable {} not found",
-                code_ref.s(&self.env.file_idx),
-                name
-            ),
+            None => anyhow::bail!(LocatedError::new(
+                code_ref,
+                format!("variable {} not found", name)
+            )),
         }
     }
 
+    /// Translate a statement of a body. A type error is kept in type_errors
+    /// instead of being returned, so the rest of the function is still
+    /// checked. The function is not compiled if there were any.
+    pub fn translate_statement(&mut self, expr: &Expr) -> anyhow::Result<()> {
+        let start = StatementStart::new(self);
+        let error = match self.translate_expr(expr) {
+            Ok(_) => return Ok(()),
+            Err(error) => error,
+        };
+        start.restore(self);
+        let mut used = Vec::new();
+        find_references(expr, &mut used);
+        let follows_error = used.iter().any(|name| self.poisoned_vars.contains(name));
+        if !follows_error {
+            // Other errors still stop the function
+            self.type_errors.push(error.downcast::<TypeError>()?);
+        }
+        if let Expr::Assign { to_exprs, .. } = expr {
+            let mut assigned = Vec::new();
+            for e in to_exprs {
+                find_references(e, &mut assigned);
+            }
+            self.poisoned_vars.extend(assigned);
+        }
+        Ok(())
+    }
+
     pub fn return_(&mut self, early_return: bool) -> anyhow::Result<()> {
         let func = self.func_stack.last().unwrap().clone();
 
@@ -296,6 +378,7 @@ impl<'a> FunctionTranslator<'a> {
                     .builder
                     .use_var(return_variable.expect_u8(code_ref, "return_variable")?),
                 ExprType::Array(code_ref, ty, size_type) => match size_type {
+                    ArraySizedExpr::Const(name) => return Err(ArraySizedExpr::unresolved(name)),
                     ArraySizedExpr::Unsized => self.builder.use_var(return_variable.expect_array(
                         code_ref,
                         *ty.clone(),
@@ -310,12 +393,15 @@ impl<'a> FunctionTranslator<'a> {
                 ExprType::Address(code_ref) => self
                     .builder
                     .use_var(return_variable.expect_address(code_ref, "return_variable")?),
+                ExprType::Ref(code_ref, _) => self
+                    .builder
+                    .use_var(return_variable.expect_ref(code_ref, "return_variable")?),
                 ExprType::Void(_code_ref) => continue,
                 ExprType::Bool(code_ref) => self
                     .builder
                     .use_var(return_variable.expect_bool(code_ref, "return_variable")?),
                 ExprType::Tuple(code_ref, _) => {
-                    anyhow::bail!("{} tuple not supported in return", code_ref)
+                    anyhow::bail!(LocatedError::new(code_ref, "tuple not supported in return"))
                 }
                 //We don't actually return structs, they are passed in as StackSlotKind::StructReturnSlot and written to from there
                 ExprType::Struct(_code_ref, _) => continue, //self.builder.use_var(return_variable.expect_struct(n, "codegen return variables")?)
@@ -323,6 +409,12 @@ impl<'a> FunctionTranslator<'a> {
             return_values.push(v);
         }
         self.dealloc_deep_stack(!early_return, early_return);
+        self.stack_guard_exit();
+        // There can be other returns after an early return, which need to pop
+        // the same entries
+        let call_stack_pushes = self.call_stack_pushes;
+        self.call_stack_pop(call_stack_pushes);
+        self.call_stack_pushes = call_stack_pushes;
         // Emit the return instruction.
         self.builder.ins().return_(&return_values);
         Ok(())
@@ -332,10 +424,7 @@ impl<'a> FunctionTranslator<'a> {
         if let Some(current_exit_block) = self.while_exit_blocks.last() {
             self.builder.ins().jump(*current_exit_block, &[]);
         } else {
-            anyhow::bail!(
-                "{} break outside while loop",
-                code_ref.s(&self.env.file_idx)
-            )
+            anyhow::bail!(LocatedError::new(code_ref, "break outside while loop"))
         }
         Ok(SValue::Void)
     }
@@ -344,20 +433,17 @@ impl<'a> FunctionTranslator<'a> {
         if let Some(current_continue_block) = self.while_continue_blocks.last() {
             self.builder.ins().jump(*current_continue_block, &[]);
         } else {
-            anyhow::bail!(
-                "{} continue outside while loop",
-                code_ref.s(&self.env.file_idx)
-            )
+            anyhow::bail!(LocatedError::new(code_ref, "continue outside while loop"))
         }
         Ok(SValue::Void)
     }
 
     fn translate_return(&mut self, code_ref: &CodeRef) -> anyhow::Result<SValue> {
         if self.func_stack.len() > 1 {
-            anyhow::bail!(
-                "{} early return in inline function not yet supported",
-                code_ref.s(&self.env.file_idx)
-            )
+            anyhow::bail!(LocatedError::new(
+                code_ref,
+                "early return in inline function not yet supported"
+            ))
         }
 
         self.return_(true)?;
@@ -365,6 +451,106 @@ impl<'a> FunctionTranslator<'a> {
         Ok(SValue::Void)
     }
 
+    /// Embed a file as a data object and return a slice of its contents
+    fn translate_include(
+        &mut self,
+        code_ref: &CodeRef,
+        fn_name: &str,
+        args: &[Expr],
+        elem_type: &ExprType,
+    ) -> anyhow::Result<SValue> {
+        let path_str = match args {
+            [Expr::LiteralString { val, .. }] => val,
+            _ => anyhow::bail!(LocatedError::new(
+                code_ref,
+                format!("{} takes a single string literal path", fn_name)
+            )),
+        };
+        let file = match (&self.env.file_idx, code_ref.file_index) {
+            (Some(files), Some(file_index)) => files.get(file_index as usize).cloned(),
+            _ => None,
+        };
+        let path = resolve_include_path(file.as_deref(), path_str, code_ref)?;
+        let data_name = format!("__{}__{}", fn_name, path.display());
+        let width = elem_type.width(self.ptr_ty, &self.env.struct_map).unwrap();
+
+        //the data holds the length then the elements, so a file is only
+        //read the first time it is included
+        let data_id = match self.module.get_name(&data_name) {
+            //already embedded by another call
+            Some(FuncOrDataId::Data(data_id)) => data_id,
+            _ => {
+                let bytes = match fs::read(&path) {
+                    Ok(bytes) => bytes,
+                    Err(e) => anyhow::bail!(LocatedError::new(
+                        code_ref,
+                        format!("{} error {} {}", fn_name, path.display(), e)
+                    )
+                    .with_code(codes::INCLUDE_ERROR)),
+                };
+                if fn_name == "include_str" && std::str::from_utf8(&bytes).is_err() {
+                    anyhow::bail!(LocatedError::new(
+                        code_ref,
+                        format!("{} {} is not valid UTF-8", fn_name, path.display())
+                    ))
+                }
+                if bytes.len() % width != 0 {
+                    anyhow::bail!(LocatedError::new(
+                        code_ref,
+                        format!(
+                            "{} {} is {} bytes, which is not a multiple of the {} size {}",
+                            fn_name,
+                            path.display(),
+                            bytes.len(),
+                            elem_type,
+                            width
+                        )
+                    ))
+                }
+                trace!(
+                    "{} {} embedding {} bytes from {}",
+                    code_ref.s(&self.env.file_idx),
+                    fn_name,
+                    bytes.len(),
+                    path.display()
+                );
+                let len = bytes.len() / width;
+                let mut data = (len as u64).to_ne_bytes().to_vec();
+                data.extend_from_slice(&bytes);
+                let mut data_ctx = DataContext::new();
+                data_ctx.set_align(8);
+                data_ctx.define(data.into_boxed_slice());
+                let data_id = self
+                    .module
+                    .declare_data(&data_name, Linkage::Local, false, false)
+                    .map_err(|e| anyhow::anyhow!("{}", e))?;
+                self.module
+                    .define_data(data_id, &data_ctx)
+                    .map_err(|e| anyhow::anyhow!("{}", e))?;
+                data_id
+            }
+        };
+        let local_id = self.module.declare_data_in_func(data_id, self.builder.func);
+        let data_address = self.builder.ins().symbol_value(self.ptr_ty, local_id);
+        let len_val = self.builder.ins().load(
+            types::I64,
+            MemFlags::trusted(),
+            data_address,
+            Offset32::new(0),
+        );
+        let elems_address = self.builder.ins().iadd_imm(data_address, 8);
+
+        let slice_start = self.alloc((self.ptr_width + 2 * 8) as usize)?;
+        self.store(elems_address, slice_start, 0);
+        self.store(len_val, slice_start, self.ptr_width);
+        self.store(len_val, slice_start, self.ptr_width + 8);
+
+        Ok(SValue::Array(
+            Box::new(SValue::from(&mut self.builder, elem_type, slice_start)?),
+            ArraySized::Slice,
+        ))
+    }
+
     fn translate_string(&mut self, literal: &str) -> anyhow::Result<SValue> {
         let bytes = literal.as_bytes();
         let stack_slot = self.builder.create_stack_slot(StackSlotData::new(
@@ -390,7 +576,7 @@ impl<'a> FunctionTranslator<'a> {
         }
         let len_val = self.i64const(bytes.len() as i64);
 
-        let slice_start = self.alloc((self.ptr_width + 2 * 8) as usize);
+        let slice_start = self.alloc((self.ptr_width + 2 * 8) as usize)?;
 
         self.store(stack_slot_address, slice_start, 0);
         self.store(len_val, slice_start, self.ptr_width);
@@ -423,14 +609,13 @@ impl<'a> FunctionTranslator<'a> {
 
         let item_width = match first_item_type.width(self.ptr_ty, &self.env.struct_map) {
             Some(item_width) => item_width,
-            None => anyhow::bail!(
-                "{} expression {} has no size",
-                code_ref.s(&self.env.file_idx),
-                first_item
-            ),
+            None => anyhow::bail!(LocatedError::new(
+                code_ref,
+                format!("expression {} has no size", first_item)
+            )),
         };
 
-        let stack_slot_address = self.alloc((item_width * len) as usize);
+        let stack_slot_address = self.alloc((item_width * len) as usize)?;
 
         let len_val = self.i64const(len as i64);
 
@@ -477,17 +662,17 @@ impl<'a> FunctionTranslator<'a> {
                 | ExprType::F32(_)
                 | ExprType::I64(_)
                 | ExprType::U8(_)
-                | ExprType::Address(_) => {
+                | ExprType::Address(_)
+                | ExprType::Ref(_, _) => {
                     self.store(set_val, stack_slot_address_abs_pos, 0);
                 }
                 ExprType::Struct(_code_ref, _) | ExprType::Array(_code_ref, _, _) => {
                     self.mem_copy(set_val, stack_slot_address_abs_pos, item_width);
                 }
-                ExprType::Tuple(_, _) | ExprType::Void(_) => anyhow::bail!(
-                    "{} cannot assign expression {} to array",
+                ExprType::Tuple(_, _) | ExprType::Void(_) => anyhow::bail!(LocatedError::new(
                     code_ref,
-                    first_item
-                ),
+                    format!("cannot assign expression {} to array", first_item)
+                )),
             }
 
             let i_val = self.iadd(i_val, inc_val);
@@ -513,7 +698,8 @@ impl<'a> FunctionTranslator<'a> {
                     | ExprType::F32(_)
                     | ExprType::I64(_)
                     | ExprType::U8(_)
-                    | ExprType::Address(_) => {
+                    | ExprType::Address(_)
+                    | ExprType::Ref(_, _) => {
                         self.store(val, stack_slot_address, (i * item_width) as i64);
                     }
                     ExprType::Struct(code_ref, _) | ExprType::Array(code_ref, _, _) => {
@@ -528,11 +714,10 @@ impl<'a> FunctionTranslator<'a> {
                         );
                         self.mem_copy(val, stack_slot_offset, item_width);
                     }
-                    ExprType::Tuple(_, _) | ExprType::Void(_) => anyhow::bail!(
-                        "{} cannot assign expression {} to array",
+                    ExprType::Tuple(_, _) | ExprType::Void(_) => anyhow::bail!(LocatedError::new(
                         code_ref,
-                        first_item
-                    ),
+                        format!("cannot assign expression {} to array", first_item)
+                    )),
                 }
             }
         }
@@ -551,6 +736,7 @@ impl<'a> FunctionTranslator<'a> {
     }
 
     #[instrument(name = "binop", skip(self, op, lhs, rhs, get_address))]
+    #[allow(deprecated)]
     fn translate_binop(
         &mut self,
         op: Binop,
@@ -650,6 +836,7 @@ impl<'a> FunctionTranslator<'a> {
                     code_ref,
                     expr,
                     idx_expr,
+                    index_mode,
                 } => {
                     if !path.is_empty() {
                         let mut spath = path
@@ -659,7 +846,13 @@ impl<'a> FunctionTranslator<'a> {
                         let name = if let Expr::Identifier { code_ref: _, name } = *expr.clone() {
                             name
                         } else {
-                            anyhow::bail!("{} array access on dot binop of non identifier not supported yet {}", code_ref, expr)
+                            anyhow::bail!(LocatedError::new(
+                                code_ref,
+                                format!(
+                                    "array access on dot binop of non identifier not supported yet {}",
+                                    expr
+                                )
+                            ))
                         };
                         spath.push(name.to_string());
                         let (sval_address, parent_struct, struct_def) =
@@ -681,12 +874,17 @@ impl<'a> FunctionTranslator<'a> {
                             &struct_def.expr_type,
                             idx_val,
                             get_address,
-                            true,
+                            *index_mode,
                         )?);
                     } else {
                         let idx_val = self.idx_expr_to_val(idx_expr)?;
-                        lhs_val =
-                            Some(self.translate_array_get(code_ref, expr, idx_val, get_address)?);
+                        lhs_val = Some(self.translate_array_get(
+                            code_ref,
+                            expr,
+                            idx_val,
+                            get_address,
+                            *index_mode,
+                        )?);
                     }
 
                     path = Vec::new();
@@ -710,11 +908,13 @@ impl<'a> FunctionTranslator<'a> {
                 | Expr::LiteralFloat { code_ref, .. }
                 | Expr::LiteralInt { code_ref, .. }
                 | Expr::LiteralU8 { code_ref, .. }
-                | Expr::LiteralBool { code_ref, .. } => anyhow::bail!(
-                    "{} dot binop not supported, try putting expression in parenthesis: ({})",
+                | Expr::LiteralBool { code_ref, .. } => anyhow::bail!(LocatedError::new(
                     code_ref,
-                    expr
-                ),
+                    format!(
+                        "dot binop not supported, try putting expression in parenthesis: ({})",
+                        expr
+                    )
+                )),
             }
         }
         if !path.is_empty() {
@@ -757,6 +957,7 @@ impl<'a> FunctionTranslator<'a> {
         lhs: &Expr,
         rhs: &Expr,
     ) -> anyhow::Result<SValue> {
+        let code_ref = lhs.get_code_ref();
         let lhs_v = self.translate_expr(lhs)?;
         let rhs_v = self.translate_expr(rhs)?;
         match lhs_v {
@@ -765,11 +966,11 @@ impl<'a> FunctionTranslator<'a> {
                 _ => anyhow::bail!("operation not supported: {:?} {} {:?}", lhs_v, op, rhs_v),
             },
             SValue::I64(a) => match rhs_v {
-                SValue::I64(b) => Ok(SValue::I64(self.binop_int(op, a, b)?)),
+                SValue::I64(b) => Ok(SValue::I64(self.binop_int(code_ref, op, a, b)?)),
                 _ => anyhow::bail!("operation not supported: {:?} {} {:?}", lhs_v, op, rhs_v),
             },
             SValue::U8(a) => match rhs_v {
-                SValue::U8(b) => Ok(SValue::U8(self.binop_u8(op, a, b)?)),
+                SValue::U8(b) => Ok(SValue::U8(self.binop_u8(code_ref, op, a, b)?)),
                 _ => anyhow::bail!("operation not supported: {:?} {} {:?}", lhs_v, op, rhs_v),
             },
             SValue::Bool(a) => match rhs_v {
@@ -781,6 +982,7 @@ impl<'a> FunctionTranslator<'a> {
             | SValue::Array(_, _)
             | SValue::Address(_)
             | SValue::Struct(_, _)
+            | SValue::Ref(_)
             | SValue::Tuple(_) => {
                 anyhow::bail!("operation not supported: {:?} {} {:?}", lhs_v, op, rhs_v)
             }
@@ -809,6 +1011,7 @@ impl<'a> FunctionTranslator<'a> {
                 | SValue::Array(_, _)
                 | SValue::Address(_)
                 | SValue::Struct(_, _)
+                | SValue::Ref(_)
                 | SValue::Tuple(_) => {
                     anyhow::bail!("operation not supported: {} {:?}", op, lhs)
                 }
@@ -823,6 +1026,22 @@ impl<'a> FunctionTranslator<'a> {
                 | SValue::Array(_, _)
                 | SValue::Address(_)
                 | SValue::Struct(_, _)
+                | SValue::Ref(_)
+                | SValue::Tuple(_) => {
+                    anyhow::bail!("operation not supported: {} {:?}", op, lhs)
+                }
+            },
+            Unaryop::Deref => match lhs {
+                SValue::Ref(pointee) => self.ref_load(code_ref, &pointee)?,
+                SValue::Void
+                | SValue::F32(_)
+                | SValue::I64(_)
+                | SValue::U8(_)
+                | SValue::Bool(_)
+                | SValue::Unknown(_)
+                | SValue::Array(_, _)
+                | SValue::Address(_)
+                | SValue::Struct(_, _)
                 | SValue::Tuple(_) => {
                     anyhow::bail!("operation not supported: {} {:?}", op, lhs)
                 }
@@ -831,7 +1050,7 @@ impl<'a> FunctionTranslator<'a> {
                 SValue::Array(array_start_sval, size_type) => match size_type {
                     ArraySized::Unsized => {
                         //slice is (address of array start, length, capacity)
-                        let slice_start = self.alloc((self.ptr_width + 2 * 8) as usize);
+                        let slice_start = self.alloc((self.ptr_width + 2 * 8) as usize)?;
 
                         let start_v = if let Some(start) = &range.start {
                             if let SValue::I64(v) = self.translate_expr(&start)? {
@@ -839,14 +1058,16 @@ impl<'a> FunctionTranslator<'a> {
                                 let b_under = self.icmp(Cmp::Lt, v, zero_v);
                                 self.call_panic_if(
                                     b_under,
-                                    &format!(
-                                        "{} index out of bounds",
-                                        code_ref.s(&self.env.file_idx)
-                                    ),
+                                    RuntimeErrorCode::OutOfBounds,
+                                    code_ref,
+                                    "index out of bounds",
                                 )?;
                                 Some(v)
                             } else {
-                                anyhow::bail!("{} only i64 type supported for indexing", code_ref)
+                                anyhow::bail!(LocatedError::new(
+                                    code_ref,
+                                    "only i64 type supported for indexing"
+                                ))
                             }
                         } else {
                             None
@@ -856,13 +1077,16 @@ impl<'a> FunctionTranslator<'a> {
                             if let SValue::I64(v) = self.translate_expr(&end)? {
                                 v
                             } else {
-                                anyhow::bail!("{} only i64 type supported for indexing", code_ref)
+                                anyhow::bail!(LocatedError::new(
+                                    code_ref,
+                                    "only i64 type supported for indexing"
+                                ))
                             }
                         } else {
-                            anyhow::bail!(
-                                "{} slice end must be specified for unsized array",
-                                code_ref
-                            )
+                            anyhow::bail!(LocatedError::new(
+                                code_ref,
+                                "slice end must be specified for unsized array"
+                            ))
                         };
 
                         if range.start.is_some() || range.end.is_some() {
@@ -870,7 +1094,9 @@ impl<'a> FunctionTranslator<'a> {
                             let b_over = self.icmp(Cmp::Ge, st_v, end_v);
                             self.call_panic_if(
                                 b_over,
-                                &format!("{} index out of bounds", code_ref.s(&self.env.file_idx)),
+                                RuntimeErrorCode::OutOfBounds,
+                                code_ref,
+                                "index out of bounds",
                             )?;
                         }
 
@@ -917,7 +1143,7 @@ impl<'a> FunctionTranslator<'a> {
                     }
                     ArraySized::Slice => {
                         //slice is (address of array start, length, capacity)
-                        let new_slice_start = self.alloc((self.ptr_width + 2 * 8) as usize);
+                        let new_slice_start = self.alloc((self.ptr_width + 2 * 8) as usize)?;
 
                         let orig_slice_address =
                             array_start_sval.inner("translate_unaryop slice")?;
@@ -932,14 +1158,16 @@ impl<'a> FunctionTranslator<'a> {
                                 let b_under = self.icmp(Cmp::Lt, v, zero_v);
                                 self.call_panic_if(
                                     b_under,
-                                    &format!(
-                                        "{} index out of bounds",
-                                        code_ref.s(&self.env.file_idx)
-                                    ),
+                                    RuntimeErrorCode::OutOfBounds,
+                                    code_ref,
+                                    "index out of bounds",
                                 )?;
                                 Some(v)
                             } else {
-                                anyhow::bail!("{} only i64 type supported for indexing", code_ref)
+                                anyhow::bail!(LocatedError::new(
+                                    code_ref,
+                                    "only i64 type supported for indexing"
+                                ))
                             }
                         } else {
                             None
@@ -950,14 +1178,16 @@ impl<'a> FunctionTranslator<'a> {
                                 let b_over = self.icmp(Cmp::Gt, v, orig_slice_cap);
                                 self.call_panic_if(
                                     b_over,
-                                    &format!(
-                                        "{} index out of bounds",
-                                        code_ref.s(&self.env.file_idx)
-                                    ),
+                                    RuntimeErrorCode::OutOfBounds,
+                                    code_ref,
+                                    "index out of bounds",
                                 )?;
                                 v
                             } else {
-                                anyhow::bail!("{} only i64 type supported for indexing", code_ref)
+                                anyhow::bail!(LocatedError::new(
+                                    code_ref,
+                                    "only i64 type supported for indexing"
+                                ))
                             }
                         } else {
                             orig_slice_len
@@ -968,7 +1198,9 @@ impl<'a> FunctionTranslator<'a> {
                             let b_over = self.icmp(Cmp::Gt, st_v, end_v);
                             self.call_panic_if(
                                 b_over,
-                                &format!("{} index out of bounds", code_ref.s(&self.env.file_idx)),
+                                RuntimeErrorCode::OutOfBounds,
+                                code_ref,
+                                "index out of bounds",
                             )?;
                         }
 
@@ -1011,7 +1243,7 @@ impl<'a> FunctionTranslator<'a> {
                     }
                     ArraySized::Fixed(_sval_len, len) => {
                         //slice is (address of array start, length, capacity)
-                        let new_slice_start = self.alloc((self.ptr_width + 2 * 8) as usize);
+                        let new_slice_start = self.alloc((self.ptr_width + 2 * 8) as usize)?;
                         let arr_len_val = self.i64const(len as i64);
 
                         let start_v = if let Some(start) = &range.start {
@@ -1020,14 +1252,16 @@ impl<'a> FunctionTranslator<'a> {
                                 let b_under = self.icmp(Cmp::Lt, v, zero_v);
                                 self.call_panic_if(
                                     b_under,
-                                    &format!(
-                                        "{} index out of bounds",
-                                        code_ref.s(&self.env.file_idx)
-                                    ),
+                                    RuntimeErrorCode::OutOfBounds,
+                                    code_ref,
+                                    "index out of bounds",
                                 )?;
                                 Some(v)
                             } else {
-                                anyhow::bail!("{} only i64 type supported for indexing", code_ref)
+                                anyhow::bail!(LocatedError::new(
+                                    code_ref,
+                                    "only i64 type supported for indexing"
+                                ))
                             }
                         } else {
                             None
@@ -1038,14 +1272,16 @@ impl<'a> FunctionTranslator<'a> {
                                 let b_over = self.icmp(Cmp::Gt, v, arr_len_val);
                                 self.call_panic_if(
                                     b_over,
-                                    &format!(
-                                        "{} index out of bounds",
-                                        code_ref.s(&self.env.file_idx)
-                                    ),
+                                    RuntimeErrorCode::OutOfBounds,
+                                    code_ref,
+                                    "index out of bounds",
                                 )?;
                                 v
                             } else {
-                                anyhow::bail!("{} only i64 type supported for indexing", code_ref)
+                                anyhow::bail!(LocatedError::new(
+                                    code_ref,
+                                    "only i64 type supported for indexing"
+                                ))
                             }
                         } else {
                             arr_len_val
@@ -1056,7 +1292,9 @@ impl<'a> FunctionTranslator<'a> {
                             let b_over = self.icmp(Cmp::Gt, st_v, end_v);
                             self.call_panic_if(
                                 b_over,
-                                &format!("{} index out of bounds", code_ref.s(&self.env.file_idx)),
+                                RuntimeErrorCode::OutOfBounds,
+                                code_ref,
+                                "index out of bounds",
                             )?;
                         }
 
@@ -1109,6 +1347,7 @@ impl<'a> FunctionTranslator<'a> {
                 | SValue::Unknown(_)
                 | SValue::Address(_)
                 | SValue::Struct(_, _)
+                | SValue::Ref(_)
                 | SValue::Tuple(_) => {
                     anyhow::bail!("operation not supported: {} {:?}", op, lhs)
                 }
@@ -1128,31 +1367,236 @@ impl<'a> FunctionTranslator<'a> {
         })
     }
 
-    fn binop_int(&mut self, op: Binop, lhs: Value, rhs: Value) -> anyhow::Result<Value> {
+    fn binop_int(
+        &mut self,
+        code_ref: &CodeRef,
+        op: Binop,
+        lhs: Value,
+        rhs: Value,
+    ) -> anyhow::Result<Value> {
         Ok(match op {
+            Binop::Add | Binop::Sub | Binop::Mul if self.overflow_checks => {
+                self.checked_int_op(code_ref, op, false, lhs, rhs)?
+            }
             Binop::Add => self.iadd(lhs, rhs),
             Binop::Sub => self.isub(lhs, rhs),
             Binop::Mul => self.imul(lhs, rhs),
-            Binop::Div => self.sdiv(lhs, rhs),
+            Binop::Div => {
+                if self.overflow_checks {
+                    self.check_int_div(code_ref, false, lhs, rhs)?;
+                }
+                self.sdiv(lhs, rhs)
+            }
             Binop::LogicalAnd | Binop::LogicalOr | Binop::DotAccess => {
                 anyhow::bail!("operation not supported: {:?} {} {:?}", lhs, op, rhs)
             }
         })
     }
 
-    //TODO error for overflow when in debug
-    fn binop_u8(&mut self, op: Binop, lhs: Value, rhs: Value) -> anyhow::Result<Value> {
+    fn binop_u8(
+        &mut self,
+        code_ref: &CodeRef,
+        op: Binop,
+        lhs: Value,
+        rhs: Value,
+    ) -> anyhow::Result<Value> {
         Ok(match op {
+            Binop::Add | Binop::Sub | Binop::Mul if self.overflow_checks => {
+                self.checked_int_op(code_ref, op, true, lhs, rhs)?
+            }
             Binop::Add => self.iadd(lhs, rhs),
             Binop::Sub => self.isub(lhs, rhs),
             Binop::Mul => self.imul(lhs, rhs),
-            Binop::Div => self.udiv(lhs, rhs),
+            Binop::Div => {
+                if self.overflow_checks {
+                    self.check_int_div(code_ref, true, lhs, rhs)?;
+                }
+                self.udiv(lhs, rhs)
+            }
             Binop::LogicalAnd | Binop::LogicalOr | Binop::DotAccess => {
                 anyhow::bail!("operation not supported: {:?} {} {:?}", lhs, op, rhs)
             }
         })
     }
 
+    /// Add, subtract or multiply integers, returning the wrapped result and
+    /// whether it overflowed
+    fn int_op_overflow(
+        &mut self,
+        op: Binop,
+        is_u8: bool,
+        lhs: Value,
+        rhs: Value,
+    ) -> anyhow::Result<(Value, Value)> {
+        if is_u8 {
+            // Do the op in 64 bits, then it overflowed if it's outside of 0..=255
+            let lhs = self.builder.ins().uextend(types::I64, lhs);
+            let rhs = self.builder.ins().uextend(types::I64, rhs);
+            let result = match op {
+                Binop::Add => self.iadd(lhs, rhs),
+                Binop::Sub => self.isub(lhs, rhs),
+                Binop::Mul => self.imul(lhs, rhs),
+                _ => anyhow::bail!("operation not supported: {} with overflow", op),
+            };
+            let overflow = self
+                .builder
+                .ins()
+                .icmp_imm(IntCC::UnsignedGreaterThan, result, 255);
+            return Ok((self.builder.ins().ireduce(types::I8, result), overflow));
+        }
+        let (result, sign_bits) = match op {
+            Binop::Add => {
+                // Overflowed if the result has a different sign than both inputs
+                let result = self.iadd(lhs, rhs);
+                let lhs_diff = self.builder.ins().bxor(lhs, result);
+                let rhs_diff = self.builder.ins().bxor(rhs, result);
+                (result, self.builder.ins().band(lhs_diff, rhs_diff))
+            }
+            Binop::Sub => {
+                // Overflowed if the inputs have different signs, and the result
+                // has a different sign than lhs
+                let result = self.isub(lhs, rhs);
+                let input_diff = self.builder.ins().bxor(lhs, rhs);
+                let lhs_diff = self.builder.ins().bxor(lhs, result);
+                (result, self.builder.ins().band(input_diff, lhs_diff))
+            }
+            Binop::Mul => {
+                // Overflowed if the high 64 bits aren't just the sign of the low 64 bits
+                let result = self.imul(lhs, rhs);
+                let high = self.builder.ins().smulhi(lhs, rhs);
+                let sign = self.builder.ins().sshr_imm(result, 63);
+                let overflow = self.builder.ins().icmp(IntCC::NotEqual, high, sign);
+                return Ok((result, overflow));
+            }
+            _ => anyhow::bail!("operation not supported: {} with overflow", op),
+        };
+        let overflow = self
+            .builder
+            .ins()
+            .icmp_imm(IntCC::SignedLessThan, sign_bits, 0);
+        Ok((result, overflow))
+    }
+
+    /// Add, subtract or multiply integers, with a runtime error on overflow
+    fn checked_int_op(
+        &mut self,
+        code_ref: &CodeRef,
+        op: Binop,
+        is_u8: bool,
+        lhs: Value,
+        rhs: Value,
+    ) -> anyhow::Result<Value> {
+        let (result, overflow) = self.int_op_overflow(op, is_u8, lhs, rhs)?;
+        let verb = match op {
+            Binop::Add => "add",
+            Binop::Sub => "subtract",
+            _ => "multiply",
+        };
+        self.call_panic_if(
+            overflow,
+            RuntimeErrorCode::Overflow,
+            code_ref,
+            &format!("attempt to {} with overflow", verb),
+        )?;
+        Ok(result)
+    }
+
+    /// Add, subtract or multiply integers, stopping at the min or max on overflow
+    fn saturating_int_op(
+        &mut self,
+        op: Binop,
+        is_u8: bool,
+        lhs: Value,
+        rhs: Value,
+    ) -> anyhow::Result<Value> {
+        let (result, overflow) = self.int_op_overflow(op, is_u8, lhs, rhs)?;
+        let limit = if is_u8 {
+            let limit = if let Binop::Sub = op { 0 } else { 255 };
+            self.builder.ins().iconst(types::I8, limit)
+        } else {
+            // The result would have had the sign of lhs, or for mul the sign of lhs * rhs
+            let sign_source = if let Binop::Mul = op {
+                self.builder.ins().bxor(lhs, rhs)
+            } else {
+                lhs
+            };
+            let is_negative = self
+                .builder
+                .ins()
+                .icmp_imm(IntCC::SignedLessThan, sign_source, 0);
+            let min = self.i64const(i64::MIN);
+            let max = self.i64const(i64::MAX);
+            self.builder.ins().select(is_negative, min, max)
+        };
+        Ok(self.builder.ins().select(overflow, limit, result))
+    }
+
+    /// Runtime error if dividing by zero, or dividing i64::MIN by -1
+    fn check_int_div(
+        &mut self,
+        code_ref: &CodeRef,
+        is_u8: bool,
+        lhs: Value,
+        rhs: Value,
+    ) -> anyhow::Result<()> {
+        let is_zero = self.builder.ins().icmp_imm(IntCC::Equal, rhs, 0);
+        self.call_panic_if(
+            is_zero,
+            RuntimeErrorCode::DivideByZero,
+            code_ref,
+            "attempt to divide by zero",
+        )?;
+        if !is_u8 {
+            let is_min = self.builder.ins().icmp_imm(IntCC::Equal, lhs, i64::MIN);
+            let is_minus_one = self.builder.ins().icmp_imm(IntCC::Equal, rhs, -1);
+            let overflow = self.builder.ins().band(is_min, is_minus_one);
+            self.call_panic_if(
+                overflow,
+                RuntimeErrorCode::Overflow,
+                code_ref,
+                "attempt to divide with overflow",
+            )?;
+        }
+        Ok(())
+    }
+
+    /// `wrapping_`, `checked_` and `saturating_` `add`, `sub` and `mul` methods
+    /// on i64 and u8. These behave the same whether or not overflow checks are on.
+    fn translate_int_methods(
+        &mut self,
+        code_ref: &CodeRef,
+        fn_name: &str,
+        args: &[SValue],
+    ) -> anyhow::Result<Option<SValue>> {
+        let (ty, method) = match fn_name.split_once('.') {
+            Some((ty @ ("i64" | "u8"), method)) => (ty, method),
+            _ => return Ok(None),
+        };
+        let (kind, op) = match method.split_once('_') {
+            Some((kind @ ("wrapping" | "checked" | "saturating"), op)) => (kind, op),
+            _ => return Ok(None),
+        };
+        let op = match op {
+            "add" => Binop::Add,
+            "sub" => Binop::Sub,
+            "mul" => Binop::Mul,
+            _ => return Ok(None),
+        };
+        let is_u8 = ty == "u8";
+        let lhs = args[0].inner("translate_int_methods")?;
+        let rhs = args[1].inner("translate_int_methods")?;
+        let result = match kind {
+            "wrapping" => self.int_op_overflow(op, is_u8, lhs, rhs)?.0,
+            "checked" => self.checked_int_op(code_ref, op, is_u8, lhs, rhs)?,
+            _ => self.saturating_int_op(op, is_u8, lhs, rhs)?,
+        };
+        Ok(Some(if is_u8 {
+            SValue::U8(result)
+        } else {
+            SValue::I64(result)
+        }))
+    }
+
     fn binop_bool(&mut self, op: Binop, lhs: Value, rhs: Value) -> anyhow::Result<Value> {
         Ok(match op {
             Binop::LogicalAnd => self.band(lhs, rhs),
@@ -1173,57 +1617,43 @@ impl<'a> FunctionTranslator<'a> {
         match lhs {
             SValue::F32(a) => match rhs {
                 SValue::F32(b) => Ok(SValue::Bool(self.fcmp(cmp, a, b))),
-                _ => anyhow::bail!(
-                    "{} compare not supported: {:?} {} {:?}",
-                    lhs_expr.get_code_ref().s(&self.env.file_idx),
-                    lhs,
-                    cmp,
-                    rhs
-                ),
+                _ => anyhow::bail!(LocatedError::new(
+                    lhs_expr.get_code_ref(),
+                    format!("compare not supported: {:?} {} {:?}", lhs, cmp, rhs)
+                )),
             },
             SValue::I64(a) => match rhs {
                 SValue::I64(b) => Ok(SValue::Bool(self.icmp(cmp, a, b))),
-                _ => anyhow::bail!(
-                    "{} compare not supported: {:?} {} {:?}",
-                    lhs_expr.get_code_ref().s(&self.env.file_idx),
-                    lhs,
-                    cmp,
-                    rhs
-                ),
+                _ => anyhow::bail!(LocatedError::new(
+                    lhs_expr.get_code_ref(),
+                    format!("compare not supported: {:?} {} {:?}", lhs, cmp, rhs)
+                )),
             },
             SValue::U8(a) => match rhs {
                 SValue::U8(b) => Ok(SValue::Bool(self.icmp(cmp, a, b))),
-                _ => anyhow::bail!(
-                    "{} compare not supported: {:?} {} {:?}",
-                    lhs_expr.get_code_ref().s(&self.env.file_idx),
-                    lhs,
-                    cmp,
-                    rhs
-                ),
+                _ => anyhow::bail!(LocatedError::new(
+                    lhs_expr.get_code_ref(),
+                    format!("compare not supported: {:?} {} {:?}", lhs, cmp, rhs)
+                )),
             },
             SValue::Bool(a) => match rhs {
                 SValue::Bool(b) => Ok(SValue::Bool(self.cmp_bool(cmp, a, b))),
-                _ => anyhow::bail!(
-                    "{} compare not supported: {:?} {} {:?}",
-                    lhs_expr.get_code_ref().s(&self.env.file_idx),
-                    lhs,
-                    cmp,
-                    rhs
-                ),
+                _ => anyhow::bail!(LocatedError::new(
+                    lhs_expr.get_code_ref(),
+                    format!("compare not supported: {:?} {} {:?}", lhs, cmp, rhs)
+                )),
             },
             SValue::Void
             | SValue::Unknown(_)
             | SValue::Array(_, _)
             | SValue::Address(_)
             | SValue::Struct(_, _)
+            | SValue::Ref(_)
             | SValue::Tuple(_) => {
-                anyhow::bail!(
-                    "{} compare not supported: {:?} {} {:?}",
-                    lhs_expr.get_code_ref().s(&self.env.file_idx),
-                    lhs,
-                    cmp,
-                    rhs
-                )
+                anyhow::bail!(LocatedError::new(
+                    lhs_expr.get_code_ref(),
+                    format!("compare not supported: {:?} {} {:?}", lhs, cmp, rhs)
+                ))
             }
         }
     }
@@ -1257,7 +1687,10 @@ impl<'a> FunctionTranslator<'a> {
                         if let Some(struct_field_def) = struct_field {
                             if let Some(parent_struct) = parent_struct {
                                 if parent_struct.enum_struct && struct_field_def.name == "type" {
-                                    anyhow::bail!("{} cannot assign to enum type field", code_ref)
+                                    anyhow::bail!(LocatedError::new(
+                                        code_ref,
+                                        "cannot assign to enum type field"
+                                    ))
                                 }
                             }
                             self.set_struct_field_at_address(
@@ -1271,6 +1704,11 @@ impl<'a> FunctionTranslator<'a> {
                         }
                     }
                     Expr::Identifier { code_ref, name } => {
+                        if self.assign_static(code_ref, name, &src_sval)? {
+                            continue 'expression;
+                        }
+                        self.forget_array_alias(name);
+
                         declare_variable(
                             self.ptr_ty,
                             &src_sval.expr_type(code_ref)?,
@@ -1317,10 +1755,7 @@ impl<'a> FunctionTranslator<'a> {
                                     );
                                     let src_val = src_sval.expect_struct(
                                         &struct_name.to_string(),
-                                        &format!(
-                                            "{} translate_assign",
-                                            code_ref.s(&self.env.file_idx)
-                                        ),
+                                        "translate_assign",
                                     )?;
                                     let size = self.env.struct_map[&struct_name.to_string()].size;
                                     self.mem_copy(src_val, struct_address, size);
@@ -1329,6 +1764,9 @@ impl<'a> FunctionTranslator<'a> {
                                     &arg.expr_type
                                 {
                                     match size_type {
+                                        ArraySizedExpr::Const(name) => {
+                                            return Err(ArraySizedExpr::unresolved(name))
+                                        }
                                         ArraySizedExpr::Unsized => (), //Use normal assignment below
                                         //TODO only copy to arg slice if this is inlined and the scope here is included
                                         ArraySizedExpr::Slice => {
@@ -1381,30 +1819,59 @@ impl<'a> FunctionTranslator<'a> {
                         }
 
                         if dst_svar.expr_type(code_ref)? != src_sval.expr_type(code_ref)? {
-                            anyhow::bail!(
-                                "{} cannot assign value of type {} to variable {} of type {} ",
+                            anyhow::bail!(LocatedError::new(
                                 code_ref,
-                                src_sval.expr_type(code_ref)?,
-                                dst_svar,
-                                dst_svar.expr_type(code_ref)?,
-                            )
+                                format!(
+                                    "cannot assign value of type {} to variable {} of type {} ",
+                                    src_sval.expr_type(code_ref)?,
+                                    dst_svar,
+                                    dst_svar.expr_type(code_ref)?
+                                )
+                            ))
                         }
 
                         self.builder
                             .def_var(dst_svar.inner(), src_sval.inner("translate_assign")?);
+
+                        if let (SValue::Array(..), Expr::Identifier { name: src_name, .. }) =
+                            (&src_sval, &src_exprs[i])
+                        {
+                            self.add_array_alias(name, src_name);
+                        }
                     }
                     Expr::ArrayAccess {
-                        code_ref: _,
+                        code_ref,
                         expr,
                         idx_expr,
+                        index_mode,
                     } => {
+                        if let Expr::Identifier { name, .. } = &**expr {
+                            self.check_array_alias_write(code_ref, name);
+                        }
                         let idx_val = self.idx_expr_to_val(idx_expr)?;
-                        self.translate_array_set_from_var(expr.to_string(), idx_val, &src_sval)?;
+                        self.translate_array_set_from_var(
+                            code_ref,
+                            expr.to_string(),
+                            idx_val,
+                            &src_sval,
+                            *index_mode,
+                        )?;
                     }
-                    _ => anyhow::bail!(
-                        "{} operation not supported",
-                        dst_expr.get_code_ref().s(&self.env.file_idx)
-                    ),
+                    Expr::Unaryop {
+                        code_ref,
+                        op: Unaryop::Deref,
+                        expr,
+                    } => match self.translate_expr(expr)? {
+                        SValue::Ref(pointee) => self.ref_store(code_ref, &pointee, src_sval)?,
+                        sv => anyhow::bail!(LocatedError::new(
+                            code_ref,
+                            format!("{} of type {} can not be dereferenced", expr, sv)
+                        )),
+                    },
+                    _ => anyhow::bail!(LocatedError::new(
+                        dst_expr.get_code_ref(),
+                        "operation not supported"
+                    )),
                 }
             }
             Ok(SValue::Void)
@@ -1416,6 +1883,10 @@ impl<'a> FunctionTranslator<'a> {
                             todo!()
                             //self.set_struct_field(dst_expr, values[i].clone())?
                         } else if let Expr::Identifier { code_ref, name } = dst_expr {
+                            if self.assign_static(code_ref, name, &values[i])? {
+                                continue;
+                            }
+                            self.forget_array_alias(name);
                             declare_variable(
                                 self.ptr_ty,
                                 &values[i].expr_type(code_ref)?,
@@ -1445,58 +1916,227 @@ impl<'a> FunctionTranslator<'a> {
                 | SValue::U8(_)
                 | SValue::Array(_, _)
                 | SValue::Address(_)
-                | SValue::Struct(_, _) => anyhow::bail!("operation not supported {:?}", src_exprs),
+                | SValue::Struct(_, _)
+                | SValue::Ref(_) => anyhow::bail!("operation not supported {:?}", src_exprs),
             }
         }
     }
 
+    /// Whether a variable with this name is in scope, which shadows a
+    /// `static` of the same name. A variable whose assignment had a type
+    /// error is still in scope, even though it was never declared.
+    fn variable_in_scope(&self, name: &str) -> bool {
+        self.variables.last().unwrap().contains_key(name) || self.poisoned_vars.contains(name)
+    }
+
+    /// Store to a `static` if there is one with this name and no local variable
+    /// shadows it. Returns false if this is not a static.
+    fn assign_static(
+        &mut self,
+        code_ref: &CodeRef,
+        name: &str,
+        src_sval: &SValue,
+    ) -> anyhow::Result<bool> {
+        if self.variable_in_scope(name) {
+            return Ok(false);
+        }
+        match self.translate_static(name)? {
+            Some(SValue::Array(..)) => anyhow::bail!(LocatedError::new(
+                code_ref,
+                format!(
+                    "cannot assign to static array {}, assign to its elements or use copy_from",
+                    name
+                )
+            )),
+            Some(pointee) => {
+                self.ref_store(code_ref, &pointee, src_sval.clone())?;
+                Ok(true)
+            }
+            None => Ok(false),
+        }
+    }
+
+    fn add_array_alias(&mut self, name: &str, src_name: &str) {
+        let aliases = self.array_aliases.last_mut().unwrap();
+        let root = aliases
+            .get(src_name)
+            .cloned()
+            .unwrap_or_else(|| src_name.to_string());
+        if root != name {
+            aliases.insert(name.to_string(), root);
+        }
+    }
+
+    /// Forget what an array variable aliased, and what aliased it, when it is
+    /// assigned again or goes out of scope. Arrays that aliased it still alias
+    /// each other.
+    fn forget_array_alias(&mut self, name: &str) {
+        let aliases = self.array_aliases.last_mut().unwrap();
+        let writes = self.array_alias_writes.last_mut().unwrap();
+        aliases.remove(name);
+        let mut others = aliases
+            .iter()
+            .filter(|(_, root)| *root == name)
+            .map(|(alias, _)| alias.to_string())
+            .collect::<Vec<String>>();
+        others.sort();
+        let root_writes = writes.remove(name);
+        for names in writes.values_mut() {
+            names.remove(name);
+        }
+        if let Some((new_root, rest)) = others.split_first() {
+            aliases.remove(new_root);
+            for alias in rest {
+                aliases.insert(alias.to_string(), new_root.to_string());
+            }
+            if let Some(mut root_writes) = root_writes {
+                root_writes.remove(name);
+                writes.insert(new_root.to_string(), root_writes);
+            }
+        }
+    }
+
+    /// Warn if an array is mutated through more than one name in the same
+    /// function. Use `.copy()` if the arrays were meant to be separate.
+    fn check_array_alias_write(&mut self, code_ref: &CodeRef, name: &str) {
+        let aliases = self.array_aliases.last().unwrap();
+        let root = if let Some(root) = aliases.get(name) {
+            root.to_string()
+        } else if aliases.values().any(|root| root == name) {
+            name.to_string()
+        } else {
+            return;
+        };
+        let writes = self
+            .array_alias_writes
+            .last_mut()
+            .unwrap()
+            .entry(root.to_string())
+            .or_insert_with(HashSet::new);
+        if writes.insert(name.to_string()) && writes.len() == 2 {
+            let mut names = writes.iter().cloned().collect::<Vec<String>>();
+            names.sort();
+            warn!(
+                "{} array {} is mutated through aliases {}, use .copy() if they should be separate arrays",
+                code_ref.s(&self.env.file_idx),
+                root,
+                names.join(", ")
+            );
+        }
+    }
+
+    /// Load the value a `&T` points to. Structs are already handled by
+    /// address so their reference is returned as the struct itself.
+    fn ref_load(&mut self, code_ref: &CodeRef, pointee: &SValue) -> anyhow::Result<SValue> {
+        let address = pointee.inner("ref_load")?;
+        match pointee {
+            SValue::Struct(_, _) => Ok(pointee.clone()),
+            SValue::Bool(_) => {
+                let val =
+                    self.builder
+                        .ins()
+                        .load(types::I8, MemFlags::new(), address, Offset32::new(0));
+                let t = self.builder.ins().iconst(types::I8, 1);
+                Ok(SValue::Bool(self.icmp(Cmp::Eq, t, val)))
+            }
+            SValue::F32(_) | SValue::I64(_) | SValue::U8(_) => {
+                let expr_type = pointee.expr_type(code_ref)?;
+                let val = self.builder.ins().load(
+                    expr_type.cranelift_type(self.ptr_ty, true)?,
+                    MemFlags::new(),
+                    address,
+                    Offset32::new(0),
+                );
+                SValue::from(&mut self.builder, &expr_type, val)
+            }
+            sv => anyhow::bail!(LocatedError::new(
+                code_ref,
+                format!("can't dereference &{}", sv)
+            )),
+        }
+    }
+
+    /// Store a value at the location a `&T` points to
+    fn ref_store(
+        &mut self,
+        code_ref: &CodeRef,
+        pointee: &SValue,
+        src_sval: SValue,
+    ) -> anyhow::Result<()> {
+        let expected = pointee.expr_type(code_ref)?;
+        let found = src_sval.expr_type(code_ref)?;
+        if expected != found {
+            anyhow::bail!(LocatedError::new(
+                code_ref,
+                format!(
+                    "cannot assign value of type {} through reference of type &{}",
+                    found, expected
+                )
+            ))
+        }
+        let address = pointee.inner("ref_store")?;
+        match src_sval {
+            SValue::Struct(name, src_address) => {
+                let size = self.env.struct_map[&name].size;
+                self.mem_copy(src_address, address, size);
+            }
+            SValue::Bool(val) => {
+                let val = self.builder.ins().bint(types::I8, val);
+                self.store(val, address, 0);
+            }
+            SValue::F32(val) | SValue::I64(val) | SValue::U8(val) => self.store(val, address, 0),
+            sv => anyhow::bail!(LocatedError::new(
+                code_ref,
+                format!("can't assign {} through a reference", sv)
+            )),
+        }
+        Ok(())
+    }
+
     fn translate_array_get(
         &mut self,
         code_ref: &CodeRef,
         expr: &Expr,
         idx_val: Value,
         get_address: bool,
+        index_mode: IndexMode,
     ) -> anyhow::Result<SValue> {
         let sval = self.translate_expr(expr)?;
+        if let SValue::Array(..) | SValue::Struct(..) = sval {
+            if let Some(error) =
+                index_mode_error(code_ref, expr, &sval.expr_type(code_ref)?, index_mode)
+            {
+                return Err(error.into());
+            }
+        }
 
         match &sval {
             SValue::Array(address, size_type) => {
-                let mut bound_check_at_get = true;
-                match size_type {
-                    ArraySized::Unsized => (),
-                    ArraySized::Slice => {
-                        bound_check_at_get = true;
-                    }
-                    ArraySized::Fixed(sval, _len) => {
-                        let val = sval.inner("translate_array_get")?;
-                        let b_condition_value = self.icmp(Cmp::Ge, idx_val, val);
-                        trace!(
-                            "{} checking bounds for array &{} get with idx &{} condition &{}",
-                            code_ref.s(&self.env.file_idx),
-                            val,
-                            idx_val,
-                            b_condition_value,
-                        );
-                        self.call_panic_if(
-                            b_condition_value,
-                            &format!("{} index out of bounds", code_ref.s(&self.env.file_idx)),
-                        )?;
-                        bound_check_at_get = false;
-                    }
+                let mut idx_val = idx_val;
+                let mut index_mode_at_get = index_mode;
+                if let ArraySized::Fixed(sval, _len) = size_type {
+                    let val = sval.inner("translate_array_get")?;
+                    trace!(
+                        "{} checking bounds for array &{} get with idx &{}",
+                        code_ref.s(&self.env.file_idx),
+                        val,
+                        idx_val,
+                    );
+                    idx_val = self.bounded_index(code_ref, idx_val, val, index_mode)?;
+                    index_mode_at_get = IndexMode::Unchecked;
                 }
                 self.array_get(
                     address.inner("translate_array_get")?,
                     &sval.expr_type(code_ref)?,
                     idx_val,
                     get_address,
-                    bound_check_at_get,
+                    index_mode_at_get,
                 )
             }
-            _ => anyhow::bail!(
-                "{} expression {} is not an array",
-                code_ref.s(&self.env.file_idx),
-                expr
-            ),
+            _ => anyhow::bail!(LocatedError::new(
+                code_ref,
+                format!("expression {} is not an array", expr)
+            )),
         }
     }
 
@@ -1514,52 +2154,52 @@ impl<'a> FunctionTranslator<'a> {
         array_expr_type: &ExprType,
         idx_val: Value,
         get_address: bool,
-        check_bounds: bool,
+        index_mode: IndexMode,
     ) -> anyhow::Result<SValue> {
         let mut width;
         let mut slice_len_val = None;
+        let mut idx_val = idx_val;
         let base_type = match &array_expr_type {
             ExprType::Array(code_ref, ty, size_type) => {
-                if check_bounds {
-                    match size_type {
-                        ArraySizedExpr::Unsized => (),
-                        ArraySizedExpr::Slice => {
-                            slice_len_val = Some(self.i64load(array_address, self.ptr_width)); //length is 2nd item (start, length, capacity)
-                            let b_condition_value =
-                                self.icmp(Cmp::Ge, idx_val, slice_len_val.unwrap());
-                            trace!(
-                                "{} checking bounds for slice {} &{} get of length &{} with idx &{} condition &{}",
-                                code_ref.s(&self.env.file_idx),
-                                array_expr_type,
-                                array_address,
-                                slice_len_val.unwrap(),
-                                idx_val,
-                                b_condition_value,
-                            );
-                            self.call_panic_if(
-                                b_condition_value,
-                                &format!("{} index out of bounds", code_ref.s(&self.env.file_idx)),
-                            )?;
-                        }
-                        ArraySizedExpr::Fixed(len) => {
-                            //Looks expensive
-                            let len_val = self.i64const(*len as i64);
-                            let b_condition_value = self.icmp(Cmp::Ge, idx_val, len_val);
-                            trace!(
-                                "{} checking bounds for fixed array {} &{} get of length &{} with idx &{} condition &{}",
-                                code_ref.s(&self.env.file_idx),
-                                array_expr_type,
-                                array_address,
-                                len_val,
-                                idx_val,
-                                b_condition_value,
-                            );
-                            self.call_panic_if(
-                                b_condition_value,
-                                &format!("{} index out of bounds", code_ref.s(&self.env.file_idx)),
-                            )?;
+                match size_type {
+                    ArraySizedExpr::Const(name) => return Err(ArraySizedExpr::unresolved(name)),
+                    ArraySizedExpr::Unsized => {
+                        if let Some(error) =
+                            index_mode_error(code_ref, &"an array", array_expr_type, index_mode)
+                        {
+                            return Err(error.into());
                         }
                     }
+                    ArraySizedExpr::Slice => {
+                        slice_len_val = Some(self.i64load(array_address, self.ptr_width)); //length is 2nd item (start, length, capacity)
+                        trace!(
+                            "{} checking bounds for slice {} &{} get of length &{} with idx &{}",
+                            code_ref.s(&self.env.file_idx),
+                            array_expr_type,
+                            array_address,
+                            slice_len_val.unwrap(),
+                            idx_val,
+                        );
+                        idx_val = self.bounded_index(
+                            code_ref,
+                            idx_val,
+                            slice_len_val.unwrap(),
+                            index_mode,
+                        )?;
+                    }
+                    ArraySizedExpr::Fixed(len) => {
+                        //Looks expensive
+                        let len_val = self.i64const(*len as i64);
+                        trace!(
+                            "{} checking bounds for fixed array {} &{} get of length &{} with idx &{}",
+                            code_ref.s(&self.env.file_idx),
+                            array_expr_type,
+                            array_address,
+                            len_val,
+                            idx_val,
+                        );
+                        idx_val = self.bounded_index(code_ref, idx_val, len_val, index_mode)?;
+                    }
                 }
                 let c_ty = ty.cranelift_type(self.ptr_ty, true)?;
                 width = ty
@@ -1573,6 +2213,9 @@ impl<'a> FunctionTranslator<'a> {
                     ExprType::U8(_) => (),
                     //array items are arrays
                     ExprType::Array(_, expr_type, size_type) => match size_type {
+                        ArraySizedExpr::Const(name) => {
+                            return Err(ArraySizedExpr::unresolved(name))
+                        }
                         ArraySizedExpr::Unsized => (),
                         ArraySizedExpr::Slice => {
                             trace!(
@@ -1607,7 +2250,7 @@ impl<'a> FunctionTranslator<'a> {
                                     &expr_type,
                                     array_address_at_idx_ptr,
                                 )?),
-                                ArraySized::from(&mut self.builder, &size_type),
+                                ArraySized::from(&mut self.builder, &size_type)?,
                             ));
                         }
                         ArraySizedExpr::Fixed(len) => {
@@ -1633,16 +2276,20 @@ impl<'a> FunctionTranslator<'a> {
                                     &expr_type,
                                     array_address_at_idx_ptr,
                                 )?),
-                                ArraySized::from(&mut self.builder, &size_type),
+                                ArraySized::from(&mut self.builder, &size_type)?,
                             ));
                         }
                     },
                     ExprType::Address(_) => (),
+                    ExprType::Ref(_, _) => (),
                     ExprType::Tuple(_, _) => (),
                     //array items are structs
                     ExprType::Struct(_code_ref, name) => {
                         //if the items of the array are structs return struct with same start address
                         match size_type {
+                            ArraySizedExpr::Const(name) => {
+                                return Err(ArraySizedExpr::unresolved(name))
+                            }
                             ArraySizedExpr::Slice => {
                                 let base_struct = self.env.struct_map[&name.to_string()].clone();
                                 width = base_struct.size;
@@ -1676,12 +2323,16 @@ impl<'a> FunctionTranslator<'a> {
                 c_ty
             }
             e => {
-                anyhow::bail!("{} can't index type {}", e.get_code_ref(), &array_expr_type)
+                anyhow::bail!(LocatedError::new(
+                    e.get_code_ref(),
+                    format!("can't index type {}", &array_expr_type)
+                ))
             }
         };
 
         match &array_expr_type {
             ExprType::Array(_code_ref, ty, size_type) => match size_type {
+                ArraySizedExpr::Const(name) => Err(ArraySizedExpr::unresolved(name)),
                 ArraySizedExpr::Slice => {
                     let actual_array_address = self.i64load(array_address, 0); //start is 1st item (start, length, capacity)
                     let array_address_at_idx_ptr =
@@ -1715,11 +2366,110 @@ impl<'a> FunctionTranslator<'a> {
                 }
             },
             e => {
-                anyhow::bail!("{} can't index type {}", e.get_code_ref(), &array_expr_type)
+                anyhow::bail!(LocatedError::new(
+                    e.get_code_ref(),
+                    format!("can't index type {}", &array_expr_type)
+                ))
             }
         }
     }
 
+    /// The bounds check policy of the innermost function that has one
+    fn bounds_check(&self) -> BoundsCheck {
+        self.func_stack
+            .iter()
+            .rev()
+            .find_map(|func| func.bounds_check)
+            .unwrap_or(self.bounds_check)
+    }
+
+    /// Keep an index in bounds, according to the index mode and the bounds
+    /// check policy
+    fn bounded_index(
+        &mut self,
+        code_ref: &CodeRef,
+        idx_val: Value,
+        len_val: Value,
+        index_mode: IndexMode,
+    ) -> anyhow::Result<Value> {
+        Ok(match index_mode {
+            IndexMode::Unchecked => idx_val,
+            IndexMode::Wrap => {
+                self.check_not_empty(code_ref, idx_val, len_val)?;
+                let rem = self.builder.ins().srem(idx_val, len_val);
+                let zero = self.i64const(0);
+                let is_negative = self.icmp(Cmp::Lt, rem, zero);
+                let wrapped = self.iadd(rem, len_val);
+                self.builder.ins().select(is_negative, wrapped, rem)
+            }
+            IndexMode::Clamp => {
+                self.check_not_empty(code_ref, idx_val, len_val)?;
+                self.clamp_index(idx_val, len_val)
+            }
+            IndexMode::Checked => {
+                // Negative indices are out of bounds too, as unsigned they are larger than len
+                let b_out_of_bounds =
+                    self.builder
+                        .ins()
+                        .icmp(IntCC::UnsignedGreaterThanOrEqual, idx_val, len_val);
+                let bounds = Some((idx_val, len_val));
+                match self.bounds_check() {
+                    BoundsCheck::Abort => {
+                        self.runtime_error_if(
+                            b_out_of_bounds,
+                            RuntimeErrorCode::OutOfBounds,
+                            code_ref,
+                            "index out of bounds",
+                            bounds,
+                        )?;
+                        idx_val
+                    }
+                    BoundsCheck::ClampAndReport => {
+                        self.check_not_empty(code_ref, idx_val, len_val)?;
+                        self.report_runtime_error_if(
+                            b_out_of_bounds,
+                            RuntimeErrorCode::OutOfBounds,
+                            code_ref,
+                            "index out of bounds",
+                            bounds,
+                        )?;
+                        self.clamp_index(idx_val, len_val)
+                    }
+                    BoundsCheck::Unchecked => idx_val,
+                }
+            }
+        })
+    }
+
+    /// An empty array has no item to wrap or clamp to, so indexing it is
+    /// always a runtime error
+    fn check_not_empty(
+        &mut self,
+        code_ref: &CodeRef,
+        idx_val: Value,
+        len_val: Value,
+    ) -> anyhow::Result<()> {
+        let zero = self.i64const(0);
+        let b_empty = self.icmp(Cmp::Eq, len_val, zero);
+        self.runtime_error_if(
+            b_empty,
+            RuntimeErrorCode::OutOfBounds,
+            code_ref,
+            "index into an empty array",
+            Some((idx_val, len_val)),
+        )
+    }
+
+    fn clamp_index(&mut self, idx_val: Value, len_val: Value) -> Value {
+        let zero = self.i64const(0);
+        let one = self.i64const(1);
+        let last = self.isub(len_val, one);
+        let is_over = self.icmp(Cmp::Gt, idx_val, last);
+        let idx_val = self.builder.ins().select(is_over, last, idx_val);
+        let is_under = self.icmp(Cmp::Lt, idx_val, zero);
+        self.builder.ins().select(is_under, zero, idx_val)
+    }
+
     fn get_array_address_from_ptr(
         &mut self,
         step_bytes: usize,
@@ -1735,19 +2485,37 @@ impl<'a> FunctionTranslator<'a> {
 
     fn translate_array_set_from_var(
         &mut self,
+        code_ref: &CodeRef,
         name: String,
         idx_val: Value,
         val: &SValue,
+        index_mode: IndexMode,
     ) -> anyhow::Result<SValue> {
         trace!("translate_array_set_from_var");
         //TODO crash if idx_val > ExprType::Array(_, len)
-        let variable = self.get_variable(&CodeRef::default(), &name)?.clone();
+        if !self.variable_in_scope(&name) {
+            if let Some(static_def) = self.env.statics.get(&name).cloned() {
+                if static_def.read_only {
+                    anyhow::bail!("static {} is read only", name)
+                }
+                let array_address = self.translate_global_data_symbol(&name, true);
+                self.array_set(
+                    val,
+                    &array_address,
+                    &static_def.expr_type,
+                    idx_val,
+                    index_mode,
+                )?;
+                return Ok(SValue::Void);
+            }
+        }
+        let variable = self.get_variable(code_ref, &name)?.clone();
 
         let array_address = self.builder.use_var(variable.inner());
 
-        let array_expr_type = &variable.expr_type(&CodeRef::default())?;
+        let array_expr_type = &variable.expr_type(code_ref)?;
 
-        self.array_set(val, &array_address, array_expr_type, idx_val, true)?;
+        self.array_set(val, &array_address, array_expr_type, idx_val, index_mode)?;
 
         Ok(SValue::Void)
     }
@@ -1758,10 +2526,10 @@ impl<'a> FunctionTranslator<'a> {
         array_address: &Value,
         array_expr_type: &ExprType,
         idx_val: Value,
-        check_bounds: bool,
+        index_mode: IndexMode,
     ) -> anyhow::Result<()> {
         let array_address_at_idx_ptr =
-            self.array_get(*array_address, array_expr_type, idx_val, true, check_bounds)?;
+            self.array_get(*array_address, array_expr_type, idx_val, true, index_mode)?;
 
         match array_address_at_idx_ptr {
             SValue::Void => todo!(),
@@ -1772,6 +2540,10 @@ impl<'a> FunctionTranslator<'a> {
             SValue::U8(_) => todo!(),
             SValue::Array(_, _) => todo!(),
             SValue::Tuple(_) => todo!(),
+            SValue::Ref(_) => anyhow::bail!(
+                "array_set is not supported for arrays of references, found {}",
+                array_expr_type
+            ),
             SValue::Address(address) => {
                 self.store(from_val.inner("array_set")?, address, 0);
             }
@@ -1806,7 +2578,7 @@ impl<'a> FunctionTranslator<'a> {
         self.builder.switch_to_block(then_block);
         self.builder.seal_block(then_block);
         for expr in then_body {
-            self.translate_expr(expr).unwrap();
+            self.translate_statement(expr)?;
         }
         if !self.builder.is_filled() {
             // Jump to the merge block, passing it the block return value.
@@ -1851,7 +2623,7 @@ impl<'a> FunctionTranslator<'a> {
 
         for (i, expr) in then_body.iter().enumerate() {
             if i != then_body.len() - 1 {
-                self.translate_expr(expr).unwrap();
+                self.translate_statement(expr)?;
             }
         }
 
@@ -1881,7 +2653,7 @@ impl<'a> FunctionTranslator<'a> {
 
         for (i, expr) in else_body.iter().enumerate() {
             if i != else_body.len() - 1 {
-                self.translate_expr(expr).unwrap();
+                self.translate_statement(expr)?;
             }
         }
 
@@ -1996,7 +2768,7 @@ impl<'a> FunctionTranslator<'a> {
             self.builder.seal_block(branch_blocks[i]);
             let body = &condition_bodies[i].1;
             for expr in body {
-                self.translate_expr(expr).unwrap();
+                self.translate_statement(expr)?;
             }
             if !self.builder.is_filled() {
                 self.builder.ins().jump(merge_block, &[]);
@@ -2067,7 +2839,7 @@ impl<'a> FunctionTranslator<'a> {
             let body = &condition_bodies[i].1;
             for (i, expr) in body.iter().enumerate() {
                 if i != body.len() - 1 {
-                    self.translate_expr(expr).unwrap();
+                    self.translate_statement(expr)?;
                 }
             }
             let branch_block_value = self.translate_expr(body.last().unwrap())?;
@@ -2107,7 +2879,7 @@ impl<'a> FunctionTranslator<'a> {
         self.builder.seal_block(else_block);
         for (i, expr) in else_body.iter().enumerate() {
             if i != else_body.len() - 1 {
-                self.translate_expr(expr).unwrap();
+                self.translate_statement(expr)?;
             }
         }
         let else_value = self.translate_expr(else_body.last().unwrap())?;
@@ -2198,17 +2970,17 @@ impl<'a> FunctionTranslator<'a> {
 
         self.builder.switch_to_block(body_block);
 
-        self.add_deep_stack_frame(false);
+        self.add_deep_stack_frame(false)?;
 
         for expr in loop_body {
-            self.translate_expr(expr)?;
+            self.translate_statement(expr)?;
         }
 
         if let Some(iter_body) = iter_body {
             self.builder.ins().jump(iter_block, &[]);
             self.builder.switch_to_block(iter_block);
             for expr in iter_body {
-                self.translate_expr(expr)?;
+                self.translate_statement(expr)?;
             }
             self.builder.seal_block(iter_block);
         }
@@ -2241,21 +3013,20 @@ impl<'a> FunctionTranslator<'a> {
         args: &[Expr],
     ) -> anyhow::Result<SValue> {
         if args.len() > 1 {
-            anyhow::bail!(
-                "{} only one parameter expected for enum {}",
+            anyhow::bail!(LocatedError::new(
                 code_ref,
-                enum_name
-            )
+                format!("only one parameter expected for enum {}", enum_name)
+            ))
         }
         if let Some(field) = struct_.fields.get(&field_name) {
             if args.is_empty() {
-                let struct_address = self.alloc(struct_.size);
+                let struct_address = self.alloc(struct_.size)?;
                 let field_index_val = self.i64const((field.index - 1) as i64);
                 //the enum type is stored at the first position
                 self.store(field_index_val, struct_address, 0);
                 return Ok(SValue::Struct(enum_name, struct_address));
             }
-            let struct_address = self.alloc(struct_.size);
+            let struct_address = self.alloc(struct_.size)?;
             let field_index_val = self.i64const((field.index - 1) as i64);
             //the enum type is stored at the first position
             self.store(field_index_val, struct_address, 0);
@@ -2271,12 +3042,10 @@ impl<'a> FunctionTranslator<'a> {
 
             Ok(SValue::Struct(enum_name, struct_address))
         } else {
-            anyhow::bail!(
-                "{} field {} not found for enum {}",
+            anyhow::bail!(LocatedError::new(
                 code_ref,
-                field_name,
-                enum_name
-            );
+                format!("field {} not found for enum {}", field_name, enum_name)
+            ));
         }
     }
 
@@ -2288,11 +3057,31 @@ impl<'a> FunctionTranslator<'a> {
         impl_val: Option<SValue>,
         is_macro: bool,
     ) -> anyhow::Result<SValue> {
+        if impl_val.is_none() {
+            if let Some(elem_type) = sarus_std_lib::include_elem_type(fn_name) {
+                return self.translate_include(code_ref, fn_name, args, &elem_type);
+            }
+        }
         if fn_name.contains("::") {
-            //check if this is an enum
-            let parts = fn_name.split("::").collect::<Vec<_>>();
+            let parts = split_type_member(fn_name);
+            if parts[1] == "unsafe_ref" {
+                if let Some(ty) = ref_target_type(parts[0], code_ref, &self.env.struct_map) {
+                    return match self.translate_expr(&args[0])? {
+                        SValue::Address(address) => Ok(SValue::Ref(Box::new(SValue::from(
+                            &mut self.builder,
+                            &ty,
+                            address,
+                        )?))),
+                        sv => anyhow::bail!(LocatedError::new(
+                            code_ref,
+                            format!("{} expected an address but got {}", fn_name, sv)
+                        )),
+                    };
+                }
+            }
+            //check if this is an enum, associated functions take precedence
             let enum_struct = if let Some(struct_) = self.env.struct_map.get(parts[0]) {
-                if struct_.enum_struct {
+                if struct_.enum_struct && !self.env.funcs.contains_key(fn_name) {
                     Some(struct_.clone())
                 } else {
                     None
@@ -2323,7 +3112,19 @@ impl<'a> FunctionTranslator<'a> {
         let mut arg_values = Vec::new();
         if let Some(impl_sval) = &impl_val {
             fn_name = format!("{}.{}", impl_sval.to_string(), fn_name);
-            arg_values.push(impl_sval.clone());
+            let mut impl_sval = impl_sval.clone();
+            if let SValue::Ref(pointee) = &impl_sval {
+                //methods of the referenced type can be called through the reference
+                let deref_fn_name = format!("{}.{}", pointee, orig_fn_name);
+                if !check_core_generics(orig_fn_name, impl_val.clone())
+                    && !self.env.funcs.contains_key(&fn_name)
+                    && self.env.funcs.contains_key(&deref_fn_name)
+                {
+                    fn_name = deref_fn_name;
+                    impl_sval = self.ref_load(code_ref, pointee)?;
+                }
+            }
+            arg_values.push(impl_sval);
         }
 
         for expr in args.iter() {
@@ -2366,11 +3167,10 @@ impl<'a> FunctionTranslator<'a> {
         let func = if let Some(func) = func {
             func
         } else {
-            anyhow::bail!(
-                "{} function {} not found",
-                code_ref.s(&self.env.file_idx),
-                fn_name
-            )
+            anyhow::bail!(LocatedError::new(
+                code_ref,
+                format!("function {} not found", fn_name)
+            ))
         };
 
         let mut closure_args = Vec::new();
@@ -2416,47 +3216,55 @@ impl<'a> FunctionTranslator<'a> {
                         {
                             //TODO move to validator
                             if closure.func.params.len() != closure_arg.params.len() {
-                                anyhow::bail!(
-                                "{} func {} arg {} closure parameter count does not match signature",
-                                code_ref,
-                                &func.name,
-                                &arg.name,
-                            )
+                                anyhow::bail!(LocatedError::new(
+                                    code_ref,
+                                    format!(
+                                        "func {} arg {} closure parameter count does not match signature",
+                                        &func.name,
+                                        &arg.name
+                                    )
+                                ))
                             }
                             if closure.func.returns.len() != closure_arg.returns.len() {
-                                anyhow::bail!(
-                                "{} func {} arg {} closure return count does not match signature",
-                                code_ref,
-                                &func.name,
-                                &arg.name,
-                            )
+                                anyhow::bail!(LocatedError::new(
+                                    code_ref,
+                                    format!(
+                                        "func {} arg {} closure return count does not match signature",
+                                        &func.name,
+                                        &arg.name
+                                    )
+                                ))
                             }
                             for (param, arg_param) in
                                 closure.func.params.iter().zip(closure_arg.params.iter())
                             {
                                 if param.expr_type != arg_param.expr_type {
-                                    anyhow::bail!(
-                                    "{} func {} arg {} closure parameter types do not match. Expected {} but found {}",
-                                    code_ref,
-                                    &func.name,
-                                    &arg.name,
-                                    arg_param.expr_type,
-                                    param.expr_type,
-                                )
+                                    anyhow::bail!(LocatedError::new(
+                                        code_ref,
+                                        format!(
+                                            "func {} arg {} closure parameter types do not match. Expected {} but found {}",
+                                            &func.name,
+                                            &arg.name,
+                                            arg_param.expr_type,
+                                            param.expr_type
+                                        )
+                                    ))
                                 }
                             }
                             for (return_, arg_return) in
                                 closure.func.returns.iter().zip(closure_arg.returns.iter())
                             {
                                 if return_.expr_type != arg_return.expr_type {
-                                    anyhow::bail!(
-                                    "{} func {} arg {} closure returns types do not match. Expected {} but found {}",
-                                    code_ref,
-                                    &func.name,
-                                    &arg.name,
-                                    arg_return.expr_type,
-                                    return_.expr_type,
-                                )
+                                    anyhow::bail!(LocatedError::new(
+                                        code_ref,
+                                        format!(
+                                            "func {} arg {} closure returns types do not match. Expected {} but found {}",
+                                            &func.name,
+                                            &arg.name,
+                                            arg_return.expr_type,
+                                            return_.expr_type
+                                        )
+                                    ))
                                 }
                             }
                         }
@@ -2475,12 +3283,10 @@ impl<'a> FunctionTranslator<'a> {
                             .unwrap()
                             .insert(arg.name.to_string(), closure);
                     } else {
-                        anyhow::bail!(
-                            "{} func {} arg {} is not a closure arg",
+                        anyhow::bail!(LocatedError::new(
                             code_ref,
-                            &func.name,
-                            &arg.name
-                        )
+                            format!("func {} arg {} is not a closure arg", &func.name, &arg.name)
+                        ))
                     }
                 }
             }
@@ -2497,6 +3303,7 @@ impl<'a> FunctionTranslator<'a> {
                 }
             } else if let ExprType::Array(_code_ref, expr_type, size_type) = &returns[0].expr_type {
                 match size_type {
+                    ArraySizedExpr::Const(name) => return Err(ArraySizedExpr::unresolved(name)),
                     ArraySizedExpr::Unsized => {}
                     ArraySizedExpr::Slice => {
                         stack_slot_return = Some((self.ptr_width + 2 * 8) as usize)
@@ -2555,6 +3362,29 @@ impl<'a> FunctionTranslator<'a> {
         }
     }
 
+    /// The value stored by a `static`, holding the address of its data.
+    fn translate_static(&mut self, name: &str) -> anyhow::Result<Option<SValue>> {
+        if let Some(static_def) = self.env.statics.get(name).cloned() {
+            let address = self.translate_global_data_symbol(name, !static_def.read_only);
+            Ok(Some(SValue::from(
+                &mut self.builder,
+                &static_def.expr_type,
+                address,
+            )?))
+        } else {
+            Ok(None)
+        }
+    }
+
+    fn translate_global_data_symbol(&mut self, name: &str, writable: bool) -> Value {
+        let sym = self
+            .module
+            .declare_data(name, Linkage::Export, writable, false)
+            .expect("problem declaring data object");
+        let local_id = self.module.declare_data_in_func(sym, self.builder.func);
+        self.builder.ins().symbol_value(self.ptr_ty, local_id)
+    }
+
     fn translate_global_data_addr(&mut self, data_type: Type, name: &str) -> Value {
         let sym = self
             .module
@@ -2586,11 +3416,24 @@ impl<'a> FunctionTranslator<'a> {
         Ok(SValue::Void)
     }
 
+    /// Defaults belong to the struct, not to the code creating it, so they are
+    /// translated without the caller's variables in scope. Their types were
+    /// checked once against the field types, see `JIT::check_field_defaults`.
+    fn translate_field_default(&mut self, expr: &Expr) -> anyhow::Result<SValue> {
+        self.variables.push(HashMap::new());
+        self.per_scope_vars.push(vec![HashSet::new()]);
+        let sval = self.translate_expr(expr);
+        self.per_scope_vars.pop();
+        self.variables.pop();
+        sval
+    }
+
     fn translate_new_struct(
         &mut self,
         code_ref: &CodeRef,
         struct_name: &str,
         fields: &[StructAssignField],
+        base: &Option<Box<Expr>>,
     ) -> anyhow::Result<SValue> {
         // TODO avoid unnecessary allocation
         /*
@@ -2611,9 +3454,68 @@ impl<'a> FunctionTranslator<'a> {
             the ast like: if a {[1.0;1000]} else {[2.0;1000]}
         */
 
-        let struct_address = self.alloc(self.env.struct_map[struct_name].size);
+        let struct_def = self.env.struct_map[struct_name].clone();
+        let struct_address = self.alloc(struct_def.size)?;
+
+        let mut fields = fields.to_vec();
+        let listed_fields = fields.len();
+        if let Some(base) = base {
+            // start with a copy of base, the listed fields are then written over it
+            match self.translate_expr(base)? {
+                SValue::Struct(base_name, base_address) if base_name == struct_name => {
+                    self.mem_copy(base_address, struct_address, struct_def.size)
+                }
+                sv => anyhow::bail!(LocatedError::new(
+                    code_ref,
+                    format!(
+                        "struct {} can not be updated from {} of type {}",
+                        struct_name, base, sv
+                    )
+                )),
+            }
+        } else if !struct_def.enum_struct {
+            // fields that are not listed use their default, nested structs get
+            // their own defaults
+            let mut unlisted_fields = struct_def
+                .fields
+                .values()
+                .filter(|field_def| !fields.iter().any(|f| f.field_name == field_def.name))
+                .collect::<Vec<&StructField>>();
+            unlisted_fields.sort_by_key(|field_def| field_def.index);
+            for field_def in unlisted_fields {
+                if let Some(default) = &field_def.default {
+                    fields.push(StructAssignField {
+                        field_name: field_def.name.to_string(),
+                        expr: default.clone(),
+                    });
+                } else if let Some(field_struct_def) = self
+                    .env
+                    .struct_map
+                    .get(&field_def.expr_type.to_string())
+                    .filter(|field_struct_def| !field_struct_def.enum_struct)
+                {
+                    fields.push(StructAssignField {
+                        field_name: field_def.name.to_string(),
+                        expr: Expr::NewStruct {
+                            code_ref: *code_ref,
+                            name: field_struct_def.name.to_string(),
+                            fields: Vec::new(),
+                            base: None,
+                        },
+                    });
+                } else {
+                    anyhow::bail!(LocatedError::new(
+                        code_ref,
+                        format!(
+                            "struct {} field {} has no default, so it has to be listed",
+                            struct_name, field_def.name
+                        )
+                    ))
+                }
+            }
+        }
 
-        for field in fields.iter() {
+        for (i, field) in fields.iter().enumerate() {
             let dst_field_def = &self.env.struct_map[struct_name].fields[&field.field_name].clone();
 
             let offset = self
@@ -2623,7 +3525,11 @@ impl<'a> FunctionTranslator<'a> {
 
             let field_address = self.iadd(struct_address, offset);
 
-            let sval = self.translate_expr(&field.expr)?;
+            let sval = if i < listed_fields {
+                self.translate_expr(&field.expr)?
+            } else {
+                self.translate_field_default(&field.expr)?
+            };
 
             let mem_copy = match &sval {
                 SValue::Struct(src_name, src_start_ptr) => {
@@ -2634,14 +3540,16 @@ impl<'a> FunctionTranslator<'a> {
                         &struct_name
                     );
                     if *src_name != *dst_field_def.expr_type.to_string() {
-                        anyhow::bail!(
-                            "{} struct {} expected struct {} for field {} but got {} instead",
+                        anyhow::bail!(LocatedError::new(
                             code_ref,
-                            struct_name,
-                            dst_field_def.expr_type.to_string(),
-                            dst_field_def.name,
-                            src_name
-                        )
+                            format!(
+                                "struct {} expected struct {} for field {} but got {} instead",
+                                struct_name,
+                                dst_field_def.expr_type.to_string(),
+                                dst_field_def.name,
+                                src_name
+                            )
+                        ))
                     }
 
                     Some((*src_start_ptr, self.env.struct_map[src_name].size as u64))
@@ -2676,6 +3584,7 @@ impl<'a> FunctionTranslator<'a> {
                 | SValue::I64(_)
                 | SValue::U8(_)
                 | SValue::Address(_)
+                | SValue::Ref(_)
                 | SValue::Tuple(_) => None,
             };
             if let Some((src_start_ptr, size)) = mem_copy {
@@ -2711,14 +3620,16 @@ impl<'a> FunctionTranslator<'a> {
                     self.builder.ins().bint(types::I8, val)
                 } else {
                     if sval.to_string() != dst_field_def.expr_type.to_string() {
-                        anyhow::bail!(
-                            "{} struct {} expected type {} for field {} but got {} instead",
+                        anyhow::bail!(LocatedError::new(
                             code_ref,
-                            struct_name,
-                            dst_field_def.expr_type.to_string(),
-                            dst_field_def.name,
-                            sval.to_string()
-                        )
+                            format!(
+                                "struct {} expected type {} for field {} but got {} instead",
+                                struct_name,
+                                dst_field_def.expr_type.to_string(),
+                                dst_field_def.name,
+                                sval.to_string()
+                            )
+                        ))
                     }
 
                     sval.inner("new_struct")?
@@ -2742,6 +3653,11 @@ impl<'a> FunctionTranslator<'a> {
         let start: usize;
         //println!("get_struct_field_location {:?}", &parts);
         let base_struct_var_ptr = if let Some(lhs_val) = lhs_val {
+            //field access through a reference uses the struct it points to
+            let lhs_val = match lhs_val {
+                SValue::Ref(pointee) => *pointee,
+                sv => sv,
+            };
             if let SValue::Struct(vstruct_name, base_struct_var_ptr) = lhs_val {
                 start = 0;
                 struct_name = vstruct_name;
@@ -2750,7 +3666,10 @@ impl<'a> FunctionTranslator<'a> {
                 anyhow::bail!("variable {} is not a struct type {:?}", lhs_val, parts)
             }
         } else {
-            let svar = self.get_variable(&CodeRef::default(), &parts[0])?.clone();
+            let svar = match self.get_variable(&CodeRef::default(), &parts[0])?.clone() {
+                SVariable::Ref(pointee) => *pointee,
+                svar => svar,
+            };
             if let SVariable::Struct(_var_name, vstruct_name, var, _return_struct) = svar {
                 let base_struct_var_ptr = self.builder.use_var(var);
                 start = 1;
@@ -2812,9 +3731,11 @@ impl<'a> FunctionTranslator<'a> {
             let not_current_enum_field = self.icmp(Cmp::Ne, current_index, field_index_val);
             self.call_panic_if(
                 not_current_enum_field,
+                RuntimeErrorCode::EnumField,
+                code_ref,
                 &format!(
-                    "{} enum {} is not currently set to field {}",
-                    code_ref, parent_struct.name, parent_struct_field_def.name
+                    "enum {} is not currently set to field {}",
+                    parent_struct.name, parent_struct_field_def.name
                 ),
             )?;
         }
@@ -2843,13 +3764,14 @@ impl<'a> FunctionTranslator<'a> {
                 size_type
             );
             match size_type {
-                ArraySizedExpr::Unsized | ArraySizedExpr::Slice | ArraySizedExpr::Fixed(..) => {
-                    Ok((
-                        SValue::Address(address),
-                        parent_struct,
-                        parent_struct_field_def,
-                    ))
-                }
+                ArraySizedExpr::Unsized
+                | ArraySizedExpr::Slice
+                | ArraySizedExpr::Fixed(..)
+                | ArraySizedExpr::Const(_) => Ok((
+                    SValue::Address(address),
+                    parent_struct,
+                    parent_struct_field_def,
+                )),
             }
         } else {
             trace!("SValue::Address");
@@ -2881,6 +3803,9 @@ impl<'a> FunctionTranslator<'a> {
                     &parent_struct_field_def.expr_type
                 {
                     match size_type {
+                        ArraySizedExpr::Const(name) => {
+                            return Err(ArraySizedExpr::unresolved(name))
+                        }
                         ArraySizedExpr::Unsized => (),
                         ArraySizedExpr::Slice => {
                             trace!("{}: slice {} consists of an address to the start of the array, a length and a capacity these are stored directly in struct, returning slice SValue with address of start of slice components", coderef, expr_type);
@@ -2902,7 +3827,7 @@ impl<'a> FunctionTranslator<'a> {
                                     expr_type,
                                     field_address.inner("get_struct_field")?,
                                 )?),
-                                ArraySized::from(&mut self.builder, size_type),
+                                ArraySized::from(&mut self.builder, size_type)?,
                             ));
                         }
                     }
@@ -2943,8 +3868,10 @@ impl<'a> FunctionTranslator<'a> {
             | ExprType::I64(_)
             | ExprType::U8(_)
             | ExprType::Tuple(_, _)
-            | ExprType::Address(_) => None,
+            | ExprType::Address(_)
+            | ExprType::Ref(_, _) => None,
             ExprType::Array(_code_ref, expr_type, size_type) => match size_type {
+                ArraySizedExpr::Const(name) => return Err(ArraySizedExpr::unresolved(name)),
                 ArraySizedExpr::Unsized => None,
                 ArraySizedExpr::Slice => {
                     if array_field {
@@ -3024,29 +3951,232 @@ impl<'a> FunctionTranslator<'a> {
 
     fn exec_if_end(&mut self, merge_block: Block) {
         // Jump to the merge block, passing it the block return value.
-        self.builder.ins().jump(merge_block, &[]);
+        if !self.builder.is_filled() {
+            self.builder.ins().jump(merge_block, &[]);
+        }
         // Switch to the merge block for subsequent statements.
         self.builder.switch_to_block(merge_block);
         // We've now seen all the predecessors of the merge block.
         self.builder.seal_block(merge_block);
     }
 
-    fn call_panic_if(&mut self, b_condition_value: Value, message: &str) -> anyhow::Result<()> {
+    fn call_panic_if(
+        &mut self,
+        b_condition_value: Value,
+        code: RuntimeErrorCode,
+        code_ref: &CodeRef,
+        message: &str,
+    ) -> anyhow::Result<()> {
+        self.runtime_error_if(b_condition_value, code, code_ref, message, None)
+    }
+
+    /// A runtime error, if the condition is true. The index and length are
+    /// included for bounds checks.
+    fn runtime_error_if(
+        &mut self,
+        b_condition_value: Value,
+        code: RuntimeErrorCode,
+        code_ref: &CodeRef,
+        message: &str,
+        bounds: Option<(Value, Value)>,
+    ) -> anyhow::Result<()> {
         let merge_block = self.exec_if_start(b_condition_value);
-        self.call_panic(message)?;
+        let message = self.translate_string(message)?;
+        let slice_address = message.inner("runtime_error_if")?;
+        self.raise_runtime_error(code, code_ref, slice_address, bounds)?;
         self.exec_if_end(merge_block);
         Ok(())
     }
 
-    fn call_panic(&mut self, message: &str) -> anyhow::Result<()> {
-        let arg_values = vec![Expr::LiteralString {
-            code_ref: CodeRef::default(),
-            val: message.to_string(),
-        }];
-        self.translate_call(&CodeRef::default(), "panic", &arg_values, None, false)?;
+    /// Abort, or record the error and return to the host, depending on the
+    /// RuntimeErrorMode
+    fn raise_runtime_error(
+        &mut self,
+        code: RuntimeErrorCode,
+        code_ref: &CodeRef,
+        slice_address: Value,
+        bounds: Option<(Value, Value)>,
+    ) -> anyhow::Result<()> {
+        let error_site = self.error_site(code, code_ref, slice_address, bounds)?;
+        match self.runtime_errors {
+            RuntimeErrorMode::Panic => {
+                // Only returns while static tables are initialized
+                self.call_runtime_error_fn("__abort_runtime_error", error_site);
+                self.return_zeroed();
+            }
+            RuntimeErrorMode::Return => {
+                self.call_runtime_error_fn("__record_runtime_error", error_site);
+                self.return_zeroed();
+            }
+        }
+        Ok(())
+    }
+
+    /// Report an error to the host without stopping
+    fn report_runtime_error_if(
+        &mut self,
+        b_condition_value: Value,
+        code: RuntimeErrorCode,
+        code_ref: &CodeRef,
+        message: &str,
+        bounds: Option<(Value, Value)>,
+    ) -> anyhow::Result<()> {
+        let merge_block = self.exec_if_start(b_condition_value);
+        let message = self.translate_string(message)?;
+        let slice_address = message.inner("report_runtime_error_if")?;
+        let error_site = self.error_site(code, code_ref, slice_address, bounds)?;
+        self.call_runtime_error_fn("__report_runtime_error", error_site);
+        self.exec_if_end(merge_block);
+        Ok(())
+    }
+
+    /// Build an `ErrorSite` on the stack, describing where the error happened
+    fn error_site(
+        &mut self,
+        code: RuntimeErrorCode,
+        code_ref: &CodeRef,
+        slice_address: Value,
+        bounds: Option<(Value, Value)>,
+    ) -> anyhow::Result<Value> {
+        let stack_slot = self
+            .builder
+            .create_stack_slot(StackSlotData::new(StackSlotKind::ExplicitSlot, 11 * 8));
+        let site = self
+            .builder
+            .ins()
+            .stack_addr(self.ptr_ty, stack_slot, Offset32::new(0));
+        let func_name = self.func_stack.last().unwrap().name.clone();
+        let (has_index, index, len) = match bounds {
+            Some((index, len)) => (self.i64const(1), index, len),
+            None => (self.i64const(0), self.i64const(0), self.i64const(0)),
+        };
+        let fields = [
+            self.i64const(code as i64),
+            self.ptr_load(slice_address, 0),
+            self.i64load(slice_address, self.ptr_width),
+            self.function_name_data(&func_name)?,
+            self.i64const(code_ref.pos as i64),
+            self.i64const(code_ref.line.unwrap_or(0) as i64),
+            self.i64const(code_ref.column.unwrap_or(0) as i64),
+            self.i64const(code_ref.file_index.map_or(-1, |i| i as i64)),
+            has_index,
+            index,
+            len,
+        ];
+        for (i, field) in fields.iter().enumerate() {
+            self.store(*field, site, i as i64 * 8);
+        }
+        Ok(site)
+    }
+
+    /// The address of static data holding a function's name, as its length
+    /// then its bytes. Used for runtime error locations and the call stack.
+    fn function_name_data(&mut self, func_name: &str) -> anyhow::Result<Value> {
+        let data_name = format!("__FUNCTION_NAME__{}", func_name);
+        let data_id = match self.module.get_name(&data_name) {
+            Some(FuncOrDataId::Data(data_id)) => data_id,
+            _ => {
+                let mut bytes = (func_name.len() as u64).to_ne_bytes().to_vec();
+                bytes.extend_from_slice(func_name.as_bytes());
+                let mut data_ctx = DataContext::new();
+                data_ctx.set_align(8);
+                data_ctx.define(bytes.into_boxed_slice());
+                let data_id = self
+                    .module
+                    .declare_data(&data_name, Linkage::Local, false, false)
+                    .map_err(|e| anyhow::anyhow!("{}", e))?;
+                self.module
+                    .define_data(data_id, &data_ctx)
+                    .map_err(|e| anyhow::anyhow!("{}", e))?;
+                data_id
+            }
+        };
+        let local_id = self.module.declare_data_in_func(data_id, self.builder.func);
+        Ok(self.builder.ins().symbol_value(self.ptr_ty, local_id))
+    }
+
+    /// Call `__abort_runtime_error`, `__record_runtime_error` or
+    /// `__report_runtime_error` with the JIT's error slot and an `ErrorSite`.
+    /// The message is copied, so it can be on the stack.
+    fn call_runtime_error_fn(&mut self, fn_name: &str, error_site: Value) {
+        let mut sig = self.module.make_signature();
+        sig.params.push(AbiParam::new(self.ptr_ty));
+        sig.params.push(AbiParam::new(self.ptr_ty));
+        let callee = self
+            .module
+            .declare_function(fn_name, Linkage::Import, &sig)
+            .expect("problem declaring function");
+        let local_callee = self.module.declare_func_in_func(callee, self.builder.func);
+
+        let slot = self.translate_global_data_addr(self.ptr_ty, "__ERROR_SLOT__");
+        self.builder.ins().call(local_callee, &[slot, error_site]);
+    }
+
+    /// Push the current function's name onto the error slot's call stack,
+    /// with call stack tracking on. The depth is still counted past
+    /// MAX_CALL_STACK, but the name isn't kept.
+    pub fn call_stack_push(&mut self) -> anyhow::Result<()> {
+        if !self.call_stack_tracking {
+            return Ok(());
+        }
+        let func_name = self.func_stack.last().unwrap().name.clone();
+        let name = self.function_name_data(&func_name)?;
+        let slot = self.translate_global_data_addr(self.ptr_ty, "__ERROR_SLOT__");
+        let depth = self.i64load(slot, 8);
+        let max_depth = self.i64const(MAX_CALL_STACK as i64);
+        // Past the end, names go in the extra entry after MAX_CALL_STACK
+        let b_in_bounds = self
+            .builder
+            .ins()
+            .icmp(IntCC::UnsignedLessThan, depth, max_depth);
+        let entry = self.builder.ins().select(b_in_bounds, depth, max_depth);
+        let offset = self.builder.ins().imul_imm(entry, self.ptr_width);
+        let entry_address = self.iadd(slot, offset);
+        self.store(name, entry_address, 16);
+        let depth = self.builder.ins().iadd_imm(depth, 1);
+        self.store(depth, slot, 8);
+        self.call_stack_pushes += 1;
         Ok(())
     }
 
+    /// Pop entries off of the error slot's call stack
+    fn call_stack_pop(&mut self, count: usize) {
+        if !self.call_stack_tracking || count == 0 {
+            return;
+        }
+        let slot = self.translate_global_data_addr(self.ptr_ty, "__ERROR_SLOT__");
+        let depth = self.i64load(slot, 8);
+        let depth = self.builder.ins().iadd_imm(depth, -(count as i64));
+        self.store(depth, slot, 8);
+        self.call_stack_pushes -= count;
+    }
+
+    /// Return from the function without cleaning up, after a runtime error.
+    /// The host resets the deep stack and stack guard when it takes the error.
+    fn return_zeroed(&mut self) {
+        let returns = self.builder.func.signature.returns.clone();
+        let return_values: Vec<Value> = returns
+            .iter()
+            .map(|ret| match ret.value_type {
+                types::F32 => self.builder.ins().f32const(0.0),
+                types::F64 => self.builder.ins().f64const(0.0),
+                ty if ty.is_bool() => self.builder.ins().bconst(ty, false),
+                ty => self.builder.ins().iconst(ty, 0),
+            })
+            .collect();
+        self.builder.ins().return_(&return_values);
+    }
+
+    /// After calling another sarus function, return if it recorded an error
+    fn return_if_runtime_error(&mut self) {
+        let slot = self.translate_global_data_addr(self.ptr_ty, "__ERROR_SLOT__");
+        let code = self.i64load(slot, 0);
+        let b_error = self.builder.ins().icmp_imm(IntCC::NotEqual, code, 0);
+        let merge_block = self.exec_if_start(b_error);
+        self.return_zeroed();
+        self.exec_if_end(merge_block);
+    }
+
     #[allow(dead_code)]
     fn call_i64_print(&mut self, i64val: Value, new_line: bool) -> anyhow::Result<()> {
         let fn_name = if new_line { "i64.println" } else { "i64.print" };
@@ -3127,6 +4257,10 @@ impl<'a> FunctionTranslator<'a> {
             return Ok(v);
         }
 
+        if let Some(v) = self.translate_int_methods(code_ref, fn_name, &arg_svalues)? {
+            return Ok(v);
+        }
+
         if let Some(v) = sarus_std_lib::translate_std(
             self.module.target_config().pointer_type(),
             &mut self.builder,
@@ -3140,13 +4274,21 @@ impl<'a> FunctionTranslator<'a> {
         let func = if let Some(func) = func {
             func
         } else {
-            anyhow::bail!(
-                "{} function {} not found",
-                code_ref.s(&self.env.file_idx),
-                fn_name
-            )
+            anyhow::bail!(LocatedError::new(
+                code_ref,
+                format!("function {} not found", fn_name)
+            ))
         };
 
+        if fn_name == "panic" && func.extern_func {
+            let slice_address = arg_svalues[0].inner("panic")?;
+            let b_true = self.builder.ins().bconst(types::B1, true);
+            let merge_block = self.exec_if_start(b_true);
+            self.raise_runtime_error(RuntimeErrorCode::Panic, code_ref, slice_address, None)?;
+            self.exec_if_end(merge_block);
+            return Ok(SValue::Void);
+        }
+
         let mut inline_function_requested = match func.inline {
             InlineKind::Default => false, //TODO make default still inline if it seems worth it
             InlineKind::Never => false,
@@ -3166,9 +4308,13 @@ impl<'a> FunctionTranslator<'a> {
                         func.name
                     );
                     if let InlineKind::Always = func.inline {
-                        anyhow::bail!("{} function {} is called always_inline recursively. always_inline recursion is not supported.",
-                        code_ref.s(&self.env.file_idx),
-                        func.name)
+                        anyhow::bail!(LocatedError::new(
+                            code_ref,
+                            format!(
+                                "function {} is called always_inline recursively. always_inline recursion is not supported.",
+                                func.name
+                            )
+                        ))
                     }
                     inline_function_requested = false;
                 }
@@ -3213,7 +4359,7 @@ impl<'a> FunctionTranslator<'a> {
         }
 
         let stack_slot_address = if let Some(size) = &stack_slot_return {
-            let stack_slot_address = self.alloc(*size);
+            let stack_slot_address = self.alloc(*size)?;
             arg_values.insert(0, stack_slot_address);
             if let Some(sig) = &mut sig {
                 sig.params
@@ -3267,10 +4413,13 @@ impl<'a> FunctionTranslator<'a> {
             //}
             //self.builder.block_params(func_block).to_vec()
             self.func_stack.push(func.clone()); //push inlined func onto func_stack
+            self.call_stack_push()?;
             self.variables.push(HashMap::new()); //new variable func scope for inline func
             self.per_scope_vars.push(vec![HashSet::new()]);
+            self.array_aliases.push(HashMap::new());
+            self.array_alias_writes.push(HashMap::new());
             if !inline_scope {
-                self.add_deep_stack_frame(true);
+                self.add_deep_stack_frame(true)?;
             }
 
             if let Some(closure_src_scope_name) = closure_src_scope_name {
@@ -3290,11 +4439,13 @@ impl<'a> FunctionTranslator<'a> {
                 let scope_closure_src_pos = if let Some(s) = scope_closure_src_pos {
                     s
                 } else {
-                    anyhow::bail!(
-                        "{} could not find closure src scope {}",
-                        code_ref.s(&self.env.file_idx),
-                        closure_src_scope_name
-                    )
+                    anyhow::bail!(LocatedError::new(
+                        code_ref,
+                        format!(
+                            "could not find closure src scope {}",
+                            closure_src_scope_name
+                        )
+                    ))
                 };
 
                 // append the closed over scope to the closure's variables
@@ -3344,7 +4495,7 @@ impl<'a> FunctionTranslator<'a> {
 
             // translate inline func body
             for expr in &func.body {
-                self.translate_expr(expr)?;
+                self.translate_statement(expr)?;
             }
 
             // get values from return variables
@@ -3357,9 +4508,12 @@ impl<'a> FunctionTranslator<'a> {
             self.check_unassigned_return_var_names(&func.name)?;
 
             //finished with inline scope
+            self.call_stack_pop(1);
             self.func_stack.pop();
             self.variables.pop();
             self.per_scope_vars.pop();
+            self.array_aliases.pop();
+            self.array_alias_writes.pop();
             self.unassigned_return_var_names.pop();
             if !inline_scope {
                 self.dealloc_deep_stack(false, false);
@@ -3373,7 +4527,11 @@ impl<'a> FunctionTranslator<'a> {
                 .expect("problem declaring function");
             let local_callee = self.module.declare_func_in_func(callee, self.builder.func);
             let call = self.builder.ins().call(local_callee, &arg_values);
-            self.builder.inst_results(call).to_vec()
+            let results = self.builder.inst_results(call).to_vec();
+            if self.runtime_errors == RuntimeErrorMode::Return && !func.extern_func {
+                self.return_if_runtime_error();
+            }
+            results
         } else {
             anyhow::bail!("Expected sig")
         };
@@ -3431,7 +4589,9 @@ impl<'a> FunctionTranslator<'a> {
                     let b_over = self.icmp(Cmp::Gt, new_slice_len, orig_slice_cap);
                     self.call_panic_if(
                         b_over,
-                        &format!("{} push out of bounds", code_ref.s(&self.env.file_idx)),
+                        RuntimeErrorCode::OutOfBounds,
+                        code_ref,
+                        "push out of bounds",
                     )?;
 
                     self.store(new_slice_len, slice_address, self.ptr_width);
@@ -3441,7 +4601,7 @@ impl<'a> FunctionTranslator<'a> {
                         &slice_address,
                         &args[0].expr_type(code_ref)?,
                         orig_slice_len,
-                        true,
+                        IndexMode::Checked,
                     )?;
 
                     Some(SValue::Void)
@@ -3460,10 +4620,9 @@ impl<'a> FunctionTranslator<'a> {
                     let b_under = self.icmp(Cmp::Lt, new_slice_len, zero);
                     self.call_panic_if(
                         b_under,
-                        &format!(
-                            "{} can't pop size already 0",
-                            code_ref.s(&self.env.file_idx)
-                        ),
+                        RuntimeErrorCode::OutOfBounds,
+                        code_ref,
+                        "can't pop size already 0",
                     )?;
 
                     let ret_val = self.array_get(
@@ -3471,7 +4630,7 @@ impl<'a> FunctionTranslator<'a> {
                         &args[0].expr_type(code_ref)?,
                         new_slice_len,
                         false,
-                        false,
+                        IndexMode::Unchecked,
                     )?;
 
                     self.store(new_slice_len, slice_address, self.ptr_width);
@@ -3493,11 +4652,10 @@ impl<'a> FunctionTranslator<'a> {
                     let slice_address = slice_sval.inner("translate_core_generics slice cap")?;
                     Some(SValue::I64(self.i64load(slice_address, self.ptr_width + 8)))
                 }
-                sv => anyhow::bail!(
-                    "{} push does not support {}",
-                    code_ref.s(&self.env.file_idx),
-                    sv
-                ),
+                sv => anyhow::bail!(LocatedError::new(
+                    code_ref,
+                    format!("push does not support {}", sv)
+                )),
             },
             "append" => match &args[0] {
                 SValue::Array(slice_sval, ArraySized::Slice) => {
@@ -3521,11 +4679,10 @@ impl<'a> FunctionTranslator<'a> {
                                 (src_sval.inner(ctx_msg)?, size_sval.inner(ctx_msg)?)
                             }
                         },
-                        sv => anyhow::bail!(
-                            "{} push does not support {}",
-                            code_ref.s(&self.env.file_idx),
-                            sv
-                        ),
+                        sv => anyhow::bail!(LocatedError::new(
+                            code_ref,
+                            format!("push does not support {}", sv)
+                        )),
                     };
 
                     let new_slice_len = self.iadd(orig_slice_len, to_be_appended_len);
@@ -3533,7 +4690,9 @@ impl<'a> FunctionTranslator<'a> {
                     let b_over = self.icmp(Cmp::Gt, new_slice_len, orig_slice_cap);
                     self.call_panic_if(
                         b_over,
-                        &format!("{} push out of bounds", code_ref.s(&self.env.file_idx)),
+                        RuntimeErrorCode::OutOfBounds,
+                        code_ref,
+                        "push out of bounds",
                     )?;
 
                     self.store(new_slice_len, slice_address, self.ptr_width);
@@ -3567,13 +4726,139 @@ impl<'a> FunctionTranslator<'a> {
                 }
                 sv => anyhow::bail!("push does not support {}", sv),
             },
+            "offset" => match &args[0] {
+                SValue::Ref(pointee) => {
+                    let ctx_msg = "translate_core_generics ref offset";
+                    let width = pointee
+                        .expr_type(code_ref)?
+                        .width(self.ptr_ty, &self.env.struct_map)
+                        .unwrap();
+                    let width_val = self.ptr_const(width as i64);
+                    let offset_val = self.imul(width_val, args[1].inner(ctx_msg)?);
+                    let address = self.iadd(pointee.inner(ctx_msg)?, offset_val);
+                    Some(SValue::Ref(Box::new(pointee.replace_value(address)?)))
+                }
+                _ => None,
+            },
+            "address" => match &args[0] {
+                SValue::Ref(pointee) => Some(SValue::Address(
+                    pointee.inner("translate_core_generics ref address")?,
+                )),
+                _ => None,
+            },
+            "ref" => match &args[0] {
+                SValue::Struct(_, _) => Some(SValue::Ref(Box::new(args[0].clone()))),
+                _ => None,
+            },
+            "copy" => match &args[0] {
+                SValue::Array(sval, ArraySized::Fixed(len_sval, len)) => {
+                    let item_width = sval
+                        .expr_type(code_ref)?
+                        .width(self.ptr_ty, &self.env.struct_map)
+                        .unwrap();
+                    let size = item_width * len;
+                    let dst_address = self.alloc(size)?;
+                    self.mem_copy(
+                        sval.inner("translate_core_generics array copy")?,
+                        dst_address,
+                        size,
+                    );
+                    Some(SValue::Array(
+                        Box::new(sval.replace_value(dst_address)?),
+                        ArraySized::Fixed(len_sval.clone(), *len),
+                    ))
+                }
+                SValue::Struct(name, src_address) => {
+                    let size = self.env.struct_map[name].size;
+                    let dst_address = self.alloc(size)?;
+                    self.mem_copy(*src_address, dst_address, size);
+                    Some(SValue::Struct(name.to_string(), dst_address))
+                }
+                _ => None,
+            },
+            "copy_from" => match (&args[0], &args[1]) {
+                (SValue::Struct(name, dst_address), SValue::Struct(_, src_address)) => {
+                    let size = self.env.struct_map[name].size;
+                    self.mem_copy(*src_address, *dst_address, size);
+                    Some(SValue::Void)
+                }
+                (
+                    SValue::Array(dst_sval, dst_size_type),
+                    SValue::Array(src_sval, src_size_type),
+                ) => {
+                    let ctx_msg = "translate_core_generics copy_from";
+                    let item_width = dst_sval
+                        .expr_type(code_ref)?
+                        .width(self.ptr_ty, &self.env.struct_map)
+                        .unwrap();
+
+                    let (src_start, src_len) = match src_size_type {
+                        ArraySized::Unsized => anyhow::bail!(LocatedError::new(
+                            code_ref,
+                            "copy_from does not support unsized arrays"
+                        )),
+                        ArraySized::Slice => {
+                            let src_val = src_sval.inner(ctx_msg)?;
+                            (
+                                self.i64load(src_val, 0),              //slice start
+                                self.i64load(src_val, self.ptr_width), //slice len
+                            )
+                        }
+                        ArraySized::Fixed(size_sval, _) => {
+                            (src_sval.inner(ctx_msg)?, size_sval.inner(ctx_msg)?)
+                        }
+                    };
+
+                    let dst_start = match dst_size_type {
+                        ArraySized::Unsized => anyhow::bail!(LocatedError::new(
+                            code_ref,
+                            "copy_from does not support unsized arrays"
+                        )),
+                        ArraySized::Slice => {
+                            let dst_val = dst_sval.inner(ctx_msg)?;
+                            let dst_cap = self.i64load(dst_val, self.ptr_width + 8);
+                            let b_over = self.icmp(Cmp::Gt, src_len, dst_cap);
+                            self.call_panic_if(
+                                b_over,
+                                RuntimeErrorCode::OutOfBounds,
+                                code_ref,
+                                "copy_from out of bounds",
+                            )?;
+                            self.store(src_len, dst_val, self.ptr_width);
+                            self.i64load(dst_val, 0)
+                        }
+                        ArraySized::Fixed(size_sval, _) => {
+                            let b_mismatch = self.icmp(Cmp::Ne, src_len, size_sval.inner(ctx_msg)?);
+                            self.call_panic_if(
+                                b_mismatch,
+                                RuntimeErrorCode::OutOfBounds,
+                                code_ref,
+                                "copy_from length mismatch",
+                            )?;
+                            dst_sval.inner(ctx_msg)?
+                        }
+                    };
+
+                    let item_width_v = self.i64const(item_width as i64);
+                    let len_to_copy = self.imul(item_width_v, src_len);
+
+                    self.builder.call_memmove(
+                        self.module.target_config(),
+                        dst_start,
+                        src_start,
+                        len_to_copy,
+                    );
+
+                    Some(SValue::Void)
+                }
+                _ => None,
+            },
             "unsized" => match &args[0] {
                 SValue::Array(sval, size_type) => match size_type {
-                    ArraySized::Unsized => anyhow::bail!(
-                        "{} array is already unsized {}",
-                        code_ref.s(&self.env.file_idx),
-                        &args[0]
-                    ),
+                    ArraySized::Unsized => anyhow::bail!(LocatedError::new(
+                        code_ref,
+                        format!("array is already unsized {}", &args[0])
+                    )),
                     ArraySized::Slice => Some(SValue::Array(
                         Box::new(sval.replace_value(
                             self.ptr_load(sval.inner("translate_core_generics slice unsized")?, 0),
@@ -3588,11 +4873,10 @@ impl<'a> FunctionTranslator<'a> {
                     Box::new(args[0].clone()),
                     ArraySized::Unsized,
                 )),
-                sv => anyhow::bail!(
-                    "{} unsized does not support {}",
-                    code_ref.s(&self.env.file_idx),
-                    sv
-                ),
+                sv => anyhow::bail!(LocatedError::new(
+                    code_ref,
+                    format!("unsized does not support {}", sv)
+                )),
             },
             _ => None,
         })
@@ -3639,15 +4923,16 @@ impl<'a> FunctionTranslator<'a> {
     fn per_scope_vars_leave_scope(&mut self) {
         for var in self.per_scope_vars.last_mut().unwrap().pop().unwrap() {
             self.variables.last_mut().unwrap().remove(&var);
+            self.forget_array_alias(&var);
         }
     }
 
-    fn alloc(&mut self, width: usize) -> Value {
+    fn alloc(&mut self, width: usize) -> anyhow::Result<Value> {
         // if > 4KB //TODO make adjustable
         if width > 4096 && self.use_deep_stack {
             self.alloc_deep_stack(width)
         } else {
-            self.alloc_stack(width)
+            Ok(self.alloc_stack(width))
         }
     }
 
@@ -3662,7 +4947,7 @@ impl<'a> FunctionTranslator<'a> {
             .stack_addr(self.ptr_ty, stack_slot, Offset32::new(0))
     }
 
-    fn alloc_deep_stack(&mut self, width: usize) -> Value {
+    fn alloc_deep_stack(&mut self, width: usize) -> anyhow::Result<Value> {
         //TODO consider bumping downwards (possible alignment issues?)
         //https://fitzgeraldnick.com/2019/11/01/always-bump-downwards.html
 
@@ -3683,6 +4968,7 @@ impl<'a> FunctionTranslator<'a> {
 
         // new cursor location after allocation
         let new_cursor = self.iadd(cursor, alloc_size_val);
+        self.check_deep_stack_end(new_cursor, width)?;
 
         // save new cursor location to deep stack pointer
         self.store(new_cursor, ptr_to_cursor, 0);
@@ -3710,10 +4996,10 @@ impl<'a> FunctionTranslator<'a> {
             self.use_deep_stack = prv_setting;
         }
 
-        cursor
+        Ok(cursor)
     }
 
-    pub fn deep_stack_init(&mut self) {
+    pub fn deep_stack_init(&mut self) -> anyhow::Result<()> {
         if self.use_deep_stack {
             let ptr_to_cursor =
                 self.translate_global_data_addr(self.ptr_ty, "__DEEP_STACK_CURSOR__");
@@ -3723,19 +5009,25 @@ impl<'a> FunctionTranslator<'a> {
 
             // move cursor down 1 ptr width
             let cursor_plus_ptr = self.iadd(cursor, ptr_width_v);
+            let init_end = self.iadd(cursor_plus_ptr, ptr_width_v);
+            self.check_deep_stack_end(init_end, 2 * self.ptr_width as usize)?;
 
             // store new cursor address at bottom and bottom + ptr_width
             self.store(cursor_plus_ptr, cursor, 0);
             self.store(cursor_plus_ptr, cursor_plus_ptr, 0);
         }
+        Ok(())
     }
 
-    pub fn add_deep_stack_frame(&mut self, function: bool) {
+    pub fn add_deep_stack_frame(&mut self, function: bool) -> anyhow::Result<()> {
         //TODO skip this if the function never allocates enough to use it
         if self.use_deep_stack {
             let ptr_to_cursor =
                 self.translate_global_data_addr(self.ptr_ty, "__DEEP_STACK_CURSOR__");
             let cursor = self.ptr_load(ptr_to_cursor, 0);
+            let ptr_width = self.i64const(self.ptr_width);
+            let checkpoint_end = self.iadd(cursor, ptr_width);
+            self.check_deep_stack_end(checkpoint_end, self.ptr_width as usize)?;
             let ptr_to_deep_stack_bottom_address =
                 self.translate_global_data_addr(self.ptr_ty, "__DEEP_STACK_BOTTOM__");
             let deep_stack_bottom_address = self.ptr_load(ptr_to_deep_stack_bottom_address, 0);
@@ -3757,9 +5049,7 @@ impl<'a> FunctionTranslator<'a> {
             }
 
             // move cursor forward by ptr width to make room for checkpoint
-            let ptr_width = self.i64const(self.ptr_width);
-            let new_cursor = self.iadd(cursor, ptr_width);
-            self.store(new_cursor, ptr_to_cursor, 0);
+            self.store(checkpoint_end, ptr_to_cursor, 0);
 
             if self.deep_stack_debug {
                 let prv_setting = self.use_deep_stack;
@@ -3777,6 +5067,80 @@ impl<'a> FunctionTranslator<'a> {
 
             self.deep_stack_widths.push(0);
         }
+        Ok(())
+    }
+
+    /// Record where the native stack was when the host called into sarus code,
+    /// and panic if this function is further into the stack than the budget.
+    pub fn stack_guard_enter(&mut self) -> anyhow::Result<()> {
+        if let Some(stack_budget) = self.stack_budget {
+            let stack_top = self.alloc_stack(self.ptr_width as usize);
+            let base_address = self.call_stack_base_address();
+            let base = self.ptr_load(base_address, 0);
+            let zero = self.i64const(0);
+            let is_outer = self.icmp(Cmp::Eq, base, zero);
+            let base = self.builder.ins().select(is_outer, stack_top, base);
+            self.store(base, base_address, 0);
+
+            // The stack grows down
+            let used = self.isub(base, stack_top);
+            let budget = self.i64const(stack_budget as i64);
+            let b_condition_value = self.icmp(Cmp::Gt, used, budget);
+            let message = format!(
+                "stack overflow, more than the stack budget of {} bytes is used",
+                stack_budget
+            );
+            let code_ref = self.func_stack.last().unwrap().code_ref;
+            self.call_panic_if(
+                b_condition_value,
+                RuntimeErrorCode::StackOverflow,
+                &code_ref,
+                &message,
+            )?;
+            self.stack_guard_outer = Some((is_outer, base_address));
+        }
+        Ok(())
+    }
+
+    /// The address of the stack base for the current thread, so compiled code
+    /// can be called from more than one thread at once
+    fn call_stack_base_address(&mut self) -> Value {
+        let mut sig = self.module.make_signature();
+        sig.returns.push(AbiParam::new(self.ptr_ty));
+        let callee = self
+            .module
+            .declare_function("__stack_base_address", Linkage::Import, &sig)
+            .expect("problem declaring function");
+        let local_callee = self.module.declare_func_in_func(callee, self.builder.func);
+        let call = self.builder.ins().call(local_callee, &[]);
+        self.builder.inst_results(call)[0]
+    }
+
+    /// Clear the recorded stack base when returning to the host
+    fn stack_guard_exit(&mut self) {
+        if let Some((is_outer, base_address)) = self.stack_guard_outer {
+            let base = self.ptr_load(base_address, 0);
+            let zero = self.i64const(0);
+            let base = self.builder.ins().select(is_outer, zero, base);
+            self.store(base, base_address, 0);
+        }
+    }
+
+    /// Panic if the deep stack would extend past its end
+    fn check_deep_stack_end(&mut self, new_cursor: Value, width: usize) -> anyhow::Result<()> {
+        if self.deep_stack_checks {
+            let deep_stack_end = self.translate_global_data_addr(self.ptr_ty, "__DEEP_STACK_END__");
+            let b_condition_value = self.icmp(Cmp::Gt, new_cursor, deep_stack_end);
+            let message = format!("deep stack overflow allocating {} bytes", width);
+            let code_ref = self.func_stack.last().unwrap().code_ref;
+            self.call_panic_if(
+                b_condition_value,
+                RuntimeErrorCode::DeepStackOverflow,
+                &code_ref,
+                &message,
+            )?;
+        }
+        Ok(())
     }
 
     pub fn dealloc_deep_stack(&mut self, full_exit: bool, early_return: bool) {
@@ -4010,6 +5374,80 @@ impl<'a> FunctionTranslator<'a> {
     }
 }
 
+/// The translator's stacks, as they were before a statement. A statement with
+/// a type error can stop part way through a while loop, an if or an inlined
+/// call, leaving scopes pushed and the builder in one of its blocks. Restoring
+/// this lets the next statement be checked as if the failed one wasn't there.
+struct StatementStart {
+    expr_depth: usize,
+    func_stack: usize,
+    variables: usize,
+    per_scope_vars: usize,
+    scopes: usize,
+    unassigned_return_var_names: usize,
+    deep_stack_widths: usize,
+    call_stack_pushes: usize,
+    while_exit_blocks: usize,
+    while_continue_blocks: usize,
+    array_aliases: usize,
+    array_alias_writes: usize,
+}
+
+impl StatementStart {
+    fn new(trans: &FunctionTranslator) -> Self {
+        StatementStart {
+            expr_depth: trans.expr_depth,
+            func_stack: trans.func_stack.len(),
+            variables: trans.variables.len(),
+            per_scope_vars: trans.per_scope_vars.len(),
+            scopes: trans.per_scope_vars.last().unwrap().len(),
+            unassigned_return_var_names: trans.unassigned_return_var_names.len(),
+            deep_stack_widths: trans.deep_stack_widths.len(),
+            call_stack_pushes: trans.call_stack_pushes,
+            while_exit_blocks: trans.while_exit_blocks.len(),
+            while_continue_blocks: trans.while_continue_blocks.len(),
+            array_aliases: trans.array_aliases.len(),
+            array_alias_writes: trans.array_alias_writes.len(),
+        }
+    }
+
+    fn restore(&self, trans: &mut FunctionTranslator) {
+        trans.expr_depth = self.expr_depth;
+        trans.func_stack.truncate(self.func_stack);
+        trans.variables.truncate(self.variables);
+        trans.per_scope_vars.truncate(self.per_scope_vars);
+        // Variables declared in the scopes that were left are out of scope
+        let scopes = trans.per_scope_vars.last_mut().unwrap();
+        if scopes.len() > self.scopes {
+            for scope in scopes.drain(self.scopes..) {
+                for name in scope {
+                    trans.variables.last_mut().unwrap().remove(&name);
+                }
+            }
+        }
+        trans
+            .unassigned_return_var_names
+            .truncate(self.unassigned_return_var_names);
+        trans.deep_stack_widths.truncate(self.deep_stack_widths);
+        trans.call_stack_pushes = self.call_stack_pushes;
+        trans.while_exit_blocks.truncate(self.while_exit_blocks);
+        trans
+            .while_continue_blocks
+            .truncate(self.while_continue_blocks);
+        trans.array_aliases.truncate(self.array_aliases);
+        trans.array_alias_writes.truncate(self.array_alias_writes);
+
+        // The function isn't compiled, so the block the statement stopped in
+        // only needs to be ended before going on in a new one
+        if trans.builder.current_block().is_some() && !trans.builder.is_filled() {
+            trans.builder.ins().trap(TrapCode::UnreachableCodeReached);
+        }
+        let block = trans.builder.create_block();
+        trans.builder.switch_to_block(block);
+        trans.builder.seal_block(block);
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct Closure {
     pub func: Function,
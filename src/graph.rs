@@ -217,6 +217,7 @@ fn build_graph_func(
     ));
 
     Ok(Declaration::Function(Function {
+        code_ref: CodeRef::default(),
         name: "graph".to_string(),
         params: vec![Arg {
             name: "audio".into(),
@@ -227,10 +228,14 @@ fn build_graph_func(
             ),
             no_type_listed: false,
             closure_arg: None,
+            default: None,
         }],
         returns: vec![],
         body: main_body,
         extern_func: false,
         inline: InlineKind::Default,
+        override_func: false,
+        pub_func: true,
+        bounds_check: None,
     }))
 }
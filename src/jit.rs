@@ -1,10 +1,18 @@
+use crate::diagnostics::{Diagnostic, Diagnostics, LocatedError, Severity};
 use crate::frontend::*;
 use crate::function_translator::*;
+use crate::lint;
+use crate::runtime_error::{
+    abort_runtime_error, record_runtime_error, report_runtime_error, stack_base_address, ErrorSlot,
+};
+pub use crate::runtime_error::{RuntimeError, RuntimeErrorCode, RuntimeErrorMode};
 use crate::sarus_std_lib;
 use crate::sarus_std_lib::SConstant;
 pub use crate::structs::*;
+use crate::validator::ref_target_type;
 use crate::validator::ArraySizedExpr;
 use crate::validator::ExprType;
+use crate::validator::TypeError;
 pub use crate::variables::*;
 use cranelift::codegen::ir::ArgumentPurpose;
 use cranelift::prelude::*;
@@ -54,24 +62,56 @@ pub struct JIT {
     // will go back to when leaving a stack frame
     bottom_of_deep_stack_pointer: Option<*mut u64>,
 
-    /*
-    TODO
-        to determine size of auto deep stack:
-        for each function compute max size of deep stack used
-        in each function also take into account sub calls
-        the functions will have to be in order for this to work
-        the deep stack should be the size of the largest function's
-        maximum stack size
-    CURRENTLY
-        this is just the sum of all functions max stack size
-    */
+    // The most deep stack used along any call path from the entry points,
+    // each function's own max deep stack use is found during codegen
     total_max_deep_stack_size: usize,
     use_deep_stack: bool,
+
+    // `static` declarations by name, the data for each is created with create_data
+    statics: HashMap<String, StaticDef>,
+
+    // `pub` functions, the only ones that can be retrieved with get_func
+    pub_funcs: HashSet<String>,
+
+    // Functions that codegen starts from, instead of the pub functions
+    entry_points: Option<Vec<String>>,
+
+    // Check for deep stack overflow at runtime
+    deep_stack_checks: bool,
+
+    // Bytes of native stack sarus code can use before it raises an error
+    stack_budget: Option<usize>,
+
+    // Whether runtime errors abort, or are recorded and returned to the host
+    runtime_errors: RuntimeErrorMode,
+
+    // Where compiled code records runtime errors. Compiled code has its
+    // address, so it's boxed to keep it in place, and freed with the JIT.
+    error_slot: Option<Box<ErrorSlot>>,
+
+    // Bounds check policy for functions that don't set their own
+    bounds_check: BoundsCheck,
+
+    // Check integer arithmetic for overflow and division by zero
+    overflow_checks: bool,
+
+    // Keep a call stack of sarus functions for runtime errors
+    call_stack_tracking: bool,
+
+    // The code, for showing errors in code that wasn't loaded from a file
+    source: Option<String>,
+
+    warnings: Diagnostics,
+    warnings_as_errors: bool,
 }
 
 impl Default for JIT {
     fn default() -> Self {
-        let builder = JITBuilder::new(cranelift_module::default_libcall_names()).unwrap();
+        let mut builder = JITBuilder::new(cranelift_module::default_libcall_names()).unwrap();
+        builder.symbol("__record_runtime_error", record_runtime_error as *const u8);
+        builder.symbol("__report_runtime_error", report_runtime_error as *const u8);
+        builder.symbol("__abort_runtime_error", abort_runtime_error as *const u8);
+        builder.symbol("__stack_base_address", stack_base_address as *const u8);
         let module = JITModule::new(builder);
         Self {
             builder_context: FunctionBuilderContext::new(),
@@ -84,6 +124,19 @@ impl Default for JIT {
             deep_stack_pointer: None,
             bottom_of_deep_stack_pointer: None,
             total_max_deep_stack_size: 0,
+            statics: HashMap::new(),
+            pub_funcs: HashSet::new(),
+            entry_points: None,
+            deep_stack_checks: true,
+            stack_budget: None,
+            runtime_errors: RuntimeErrorMode::Panic,
+            error_slot: None,
+            bounds_check: BoundsCheck::Abort,
+            overflow_checks: false,
+            call_stack_tracking: false,
+            source: None,
+            warnings: Diagnostics::default(),
+            warnings_as_errors: false,
         }
     }
 }
@@ -116,7 +169,11 @@ pub fn new_jit_builder() -> JITBuilder {
 }
 
 impl JIT {
-    pub fn from(jit_builder: JITBuilder, use_deep_stack: bool) -> Self {
+    pub fn from(mut jit_builder: JITBuilder, use_deep_stack: bool) -> Self {
+        jit_builder.symbol("__record_runtime_error", record_runtime_error as *const u8);
+        jit_builder.symbol("__report_runtime_error", report_runtime_error as *const u8);
+        jit_builder.symbol("__abort_runtime_error", abort_runtime_error as *const u8);
+        jit_builder.symbol("__stack_base_address", stack_base_address as *const u8);
         let module = JITModule::new(jit_builder);
         Self {
             builder_context: FunctionBuilderContext::new(),
@@ -129,36 +186,208 @@ impl JIT {
             deep_stack_pointer: None,
             bottom_of_deep_stack_pointer: None,
             total_max_deep_stack_size: 0,
+            statics: HashMap::new(),
+            pub_funcs: HashSet::new(),
+            entry_points: None,
+            deep_stack_checks: true,
+            stack_budget: None,
+            runtime_errors: RuntimeErrorMode::Panic,
+            error_slot: None,
+            bounds_check: BoundsCheck::Abort,
+            overflow_checks: false,
+            call_stack_tracking: false,
+            source: None,
+            warnings: Diagnostics::default(),
+            warnings_as_errors: false,
         }
     }
 
-    /// Compile the ast into machine code.
+    /// Only compile the given functions, and the functions they call. By
+    /// default this starts from all of the `pub` functions.
+    pub fn set_entry_points(&mut self, entry_points: &[&str]) {
+        self.entry_points = Some(entry_points.iter().map(|s| s.to_string()).collect());
+    }
+
+    /// Every allocation on the deep stack is checked against the end of the
+    /// deep stack, and raises a runtime error if it doesn't fit. The checks can be turned off
+    /// once the code is known to stay within the deep stack.
+    pub fn set_deep_stack_checks(&mut self, deep_stack_checks: bool) {
+        self.deep_stack_checks = deep_stack_checks;
+    }
+
+    /// Limit how much of the native stack the compiled code can use, counted
+    /// from where the host calls into it. Every function checks the budget on
+    /// entry and raises a runtime error if it is exceeded, instead of overflowing the stack.
+    /// The budget should leave room for the largest function's stack frame.
+    pub fn set_stack_budget(&mut self, stack_budget: Option<usize>) {
+        self.stack_budget = stack_budget;
+    }
+
+    /// By default runtime errors, like an index out of bounds, print the
+    /// error's message and location and abort the process. With `RuntimeErrorMode::Return` the error is recorded
+    /// instead, and every sarus function returns early back to the host.
+    /// Calls then need to go through `call_checked`, or be followed by
+    /// `take_runtime_error`.
+    pub fn set_runtime_errors(&mut self, runtime_errors: RuntimeErrorMode) {
+        self.runtime_errors = runtime_errors;
+    }
+
+    /// What happens when an index is out of bounds, in functions that don't
+    /// set it with `bounds(...)`. `BoundsCheck::Abort` by default. Errors
+    /// from `BoundsCheck::ClampAndReport` are taken with `take_reported_errors`.
+    pub fn set_bounds_check(&mut self, bounds_check: BoundsCheck) {
+        self.bounds_check = bounds_check;
+    }
+
+    /// Debug checks on integer `+`, `-` and `*` for overflow, and on `/` for
+    /// division by zero, which are runtime errors when on. Off by default.
+    pub fn set_overflow_checks(&mut self, overflow_checks: bool) {
+        self.overflow_checks = overflow_checks;
+    }
+
+    /// Keep a call stack of the sarus functions being run, which runtime
+    /// errors include. Each call pushes and pops the function's name, so this
+    /// is off by default.
+    pub fn set_call_stack_tracking(&mut self, call_stack_tracking: bool) {
+        self.call_stack_tracking = call_stack_tracking;
+    }
+
+    /// The code that was parsed, so compile errors can show the line they
+    /// are on. Only needed for code that wasn't loaded from a file with
+    /// `parse_with_context`, since those files are read from the file index
+    /// table.
+    pub fn set_source(&mut self, code: &str) {
+        self.source = Some(code.to_string());
+    }
+
+    /// Make `translate` fail if there are any warnings, they are returned
+    /// with the errors.
+    pub fn set_warnings_as_errors(&mut self, warnings_as_errors: bool) {
+        self.warnings_as_errors = warnings_as_errors;
+    }
+
+    /// The warnings from the last `translate`, see `lint::Lint`. Display
+    /// renders them like errors.
+    pub fn warnings(&self) -> &Diagnostics {
+        &self.warnings
+    }
+
+    /// Compile the ast into machine code. On failure every error is returned
+    /// as a `Diagnostic`.
     #[instrument(level = "info", skip(self, prog, file_index_table))]
     pub fn translate(
         &mut self,
         prog: Vec<Declaration>,
         file_index_table: Option<Vec<PathBuf>>,
+    ) -> Result<(), Diagnostics> {
+        self.translate_prog(prog, file_index_table.clone())
+            .map_err(|error| {
+                let mut diagnostics = Diagnostics::default();
+                diagnostics.push_error(error, &file_index_table);
+                diagnostics.finish(self.source.as_deref())
+            })
+    }
+
+    fn translate_prog(
+        &mut self,
+        prog: Vec<Declaration>,
+        file_index_table: Option<Vec<PathBuf>>,
     ) -> anyhow::Result<()> {
         info!("--------------- translate ---------------");
 
         let mut prog = prog;
+        apply_overrides(&mut prog)?;
+
+        TypeResolver {
+            aliases: type_aliases(&prog)?,
+            constants: None,
+        }
+        .resolve_prog(&mut prog)?;
+
+        // Declared consts are evaluated before the struct map is made since they can be used
+        // as array lengths. This means Struct::size is not available to const initializers.
+        let declared_constants = sarus_std_lib::eval_declared_constants(
+            &prog,
+            &sarus_std_lib::get_constants(&HashMap::new()),
+        )?;
+        TypeResolver {
+            aliases: HashMap::new(),
+            constants: Some(&declared_constants),
+        }
+        .resolve_prog(&mut prog)?;
 
         let struct_map = create_struct_map(&prog, self.module.target_config().pointer_type())?;
-        let constant_vars = sarus_std_lib::get_constants(&struct_map);
+        let mut constant_vars = sarus_std_lib::get_constants(&struct_map);
+        sarus_std_lib::add_declared_constants(
+            &prog,
+            &struct_map,
+            &declared_constants,
+            &mut constant_vars,
+        )?;
+
+        // Statics initialized by a function call are read only tables. The
+        // function is run at the end of translate, once everything is compiled.
+        let mut tables = Vec::new();
+        for decl in prog.iter() {
+            if let Declaration::Static(static_) = decl {
+                if let Expr::Call { .. } = static_.expr {
+                    self.check_static_name(static_, &constant_vars)?;
+                    self.statics.insert(
+                        static_.name.to_string(),
+                        StaticDef {
+                            code_ref: static_.code_ref,
+                            expr_type: static_.expr_type.clone(),
+                            read_only: true,
+                        },
+                    );
+                    tables.push(static_.clone());
+                } else {
+                    self.create_static(static_, &constant_vars)?;
+                }
+            }
+        }
 
         let mut funcs = HashMap::new();
         let mut inline_closures: HashMap<String, HashMap<String, Closure>> = HashMap::new();
+        let namespaces = declared_namespaces(&prog);
 
         for decl in prog.iter_mut() {
             match decl {
                 Declaration::Function(func) => {
-                    funcs.insert(func.name.clone(), func.clone());
+                    if let Some((type_name, _)) = func.name.rsplit_once("::") {
+                        if !namespaces.contains(type_name)
+                            && ref_target_type(type_name, &CodeRef::default(), &struct_map)
+                                .is_none()
+                        {
+                            anyhow::bail!(LocatedError::new(
+                                &func.code_ref,
+                                format!(
+                                    "associated function {} declared on unknown type {}",
+                                    func.name, type_name
+                                )
+                            ))
+                        }
+                    }
+                    if let Some(other) = funcs.insert(func.name.clone(), func.clone()) {
+                        anyhow::bail!(LocatedError::new(
+                            &func.code_ref,
+                            format!("function {} is already defined", func.name)
+                        )
+                        .with_related(&other.code_ref, "previously defined here"))
+                    }
                     setup_inline_closures(&func.name, &func.body, &mut inline_closures);
                     if let InlineKind::Always = func.inline {
                     } else {
                         for param in &func.params {
                             if param.closure_arg.is_some() {
-                                anyhow::bail!("function {} takes a closure in parameter {} but is not declared as inline_always", func.name, param.name)
+                                anyhow::bail!(LocatedError::new(
+                                    &func.code_ref,
+                                    format!(
+                                        "function {} takes a closure in parameter {} but is not declared as inline_always",
+                                        func.name,
+                                        param.name
+                                    )
+                                ))
                             }
                         }
                     }
@@ -167,9 +396,75 @@ impl JIT {
             }
         }
 
-        //let _ = order_funcs(&funcs);
+        // Functions that can't be reached from the entry points, static tables,
+        // or struct field defaults are never called, so they are skipped.
+        let mut roots = if let Some(entry_points) = &self.entry_points {
+            for name in entry_points {
+                if !funcs.contains_key(name) {
+                    anyhow::bail!("entry point {} is not a function", name)
+                }
+            }
+            entry_points.clone()
+        } else {
+            funcs
+                .values()
+                .filter(|func| func.pub_func)
+                .map(|func| func.name.to_string())
+                .collect()
+        };
+        for table in &tables {
+            find_calls(&table.expr, &mut roots);
+        }
+        for decl in &prog {
+            if let Declaration::Struct(struct_) = decl {
+                for field in &struct_.fields {
+                    if let Some(default) = &field.default {
+                        find_calls(default, &mut roots);
+                    }
+                }
+            }
+        }
+        let methods = methods_by_name(&funcs);
+        let graph = call_graph(&funcs, &methods, true);
+        let roots = resolve_calls(&roots, &funcs, &methods, true);
+        let reachable = reachable_funcs(&graph, &roots);
+        let mut deep_stack_sizes = HashMap::new();
+        let mut diagnostics = Diagnostics::default();
+
+        let globals: HashSet<String> = self.statics.keys().cloned().collect();
+        let warnings = lint::lint(&prog, &funcs, &reachable, &globals, &file_index_table);
+        if self.warnings_as_errors {
+            // Still check every function, but don't compile anything
+            for mut warning in warnings {
+                warning.severity = Severity::Error;
+                warning
+                    .notes
+                    .push("warnings are errors, see `JIT::set_warnings_as_errors`".to_string());
+                diagnostics.push(warning);
+            }
+            self.warnings = Diagnostics::default();
+        } else {
+            let mut warning_diagnostics = Diagnostics::default();
+            for warning in warnings {
+                warning_diagnostics.push(warning);
+            }
+            self.warnings = warning_diagnostics.finish(self.source.as_deref());
+        }
+
+        self.check_field_defaults(
+            &funcs,
+            &struct_map,
+            &constant_vars,
+            &file_index_table,
+            &inline_closures,
+            &mut diagnostics,
+        );
 
         for func in funcs.values() {
+            if !reachable.contains(&func.name) {
+                trace!("Function {} is never called, skipping codegen", func.name);
+                continue;
+            }
             if func.extern_func {
                 // Don't compile the contents of std func, it will be empty
                 trace!(
@@ -190,19 +485,41 @@ impl JIT {
             }
 
             // Then, translate the AST nodes into Cranelift IR.
-            self.codegen(
+            let deep_stack_size = match self.codegen(
                 func,
                 funcs.to_owned(),
                 &struct_map,
                 &constant_vars,
                 &file_index_table,
                 &inline_closures,
-            )?;
+            ) {
+                Ok(deep_stack_size) => deep_stack_size,
+                Err(error) => {
+                    // Keep going, to find the errors in the rest of the functions
+                    diagnostics.push_error(error, &file_index_table);
+                    self.builder_context = FunctionBuilderContext::new();
+                    self.module.clear_context(&mut self.ctx);
+                    continue;
+                }
+            };
+            if !diagnostics.is_empty() {
+                // Still check the rest, but don't compile anything more
+                self.module.clear_context(&mut self.ctx);
+                continue;
+            }
+            deep_stack_sizes.insert(func.name.to_string(), deep_stack_size);
             // Next, declare the function to jit. Functions must be declared
-            // before they can be called, or defined.
+            // before they can be called, or defined. Only pub functions are
+            // exported, others are local to the module.
+            let linkage = if func.pub_func {
+                self.pub_funcs.insert(func.name.to_string());
+                Linkage::Export
+            } else {
+                Linkage::Local
+            };
             let id = self
                 .module
-                .declare_function(&func.name, Linkage::Export, &self.ctx.func.signature)
+                .declare_function(&func.name, linkage, &self.ctx.func.signature)
                 .map_err(|e| anyhow::anyhow!("{}:{}:{} {:?}", file!(), line!(), column!(), e))?;
 
             trace!("cranelift func id is {}", id);
@@ -224,17 +541,28 @@ impl JIT {
             self.module.finalize_definitions();
         }
 
+        if !diagnostics.is_empty() {
+            return Err(diagnostics.into());
+        }
+
         if self.use_deep_stack {
+            let definite_graph = call_graph(&funcs, &methods, false);
+            self.total_max_deep_stack_size =
+                deep_stack_depth(&graph, &definite_graph, &roots, &deep_stack_sizes)?;
             trace!(
                 "total_max_deep_stack_size {}",
                 self.total_max_deep_stack_size
             );
-            let deep_stack = Heap::new(
-                self.total_max_deep_stack_size
-                    + 1024 * self.module.target_config().pointer_bytes() as usize, //extra space for deep stack checkpoints
-            )
-            .unwrap();
+            let deep_stack_size = self.total_max_deep_stack_size
+                + 1024 * self.module.target_config().pointer_bytes() as usize; //extra space for deep stack checkpoints
+            let deep_stack = Heap::new(deep_stack_size).unwrap();
             let deep_stack_ptr = deep_stack.get_ptr() as u64;
+            self.create_data(
+                "__DEEP_STACK_END__",
+                (deep_stack_ptr + deep_stack_size as u64)
+                    .to_ne_bytes()
+                    .to_vec(),
+            )?;
             let deep_stack_pointer = Box::into_raw(Box::new(deep_stack_ptr));
             let bottom_of_deep_stack_pointer = Box::into_raw(Box::new(deep_stack_ptr));
             self.create_data(
@@ -265,6 +593,14 @@ impl JIT {
             // inside a while loop or other stack frame
         }
 
+        let mut error_slot = Box::new(ErrorSlot::new(file_index_table.clone()));
+        let error_slot_ptr = &mut *error_slot as *mut ErrorSlot;
+        self.create_data(
+            "__ERROR_SLOT__",
+            (error_slot_ptr as u64).to_ne_bytes().to_vec(),
+        )?;
+        self.error_slot = Some(error_slot);
+
         for (name, val) in constant_vars.iter() {
             match val {
                 SConstant::Address(n) => self.create_data(name, (*n).to_ne_bytes().to_vec())?,
@@ -274,10 +610,84 @@ impl JIT {
             };
         }
 
+        for (i, table) in tables.iter().enumerate() {
+            self.create_static_table(table, &tables[i..], &funcs, &struct_map)?;
+        }
+
         Ok(())
     }
 
+    /// The size of the deep stack allocations, not including the space used
+    /// for the deep stack frames.
+    pub fn deep_stack_size(&self) -> usize {
+        self.total_max_deep_stack_size
+    }
+
+    /// Take the error recorded by the last call into compiled code, if there
+    /// was one. The deep stack and stack guard are reset, since the functions
+    /// that returned early didn't clean up after themselves.
+    pub fn take_runtime_error(&mut self) -> Option<RuntimeError> {
+        let error_slot = self.error_slot.as_mut()?;
+        if error_slot.code == 0 {
+            return None;
+        }
+        error_slot.code = 0;
+        error_slot.call_depth = 0;
+        let error = error_slot.error.take();
+
+        if let (Some(deep_stack), Some(deep_stack_pointer), Some(bottom_of_deep_stack_pointer)) = (
+            &self.deep_stack,
+            self.deep_stack_pointer,
+            self.bottom_of_deep_stack_pointer,
+        ) {
+            unsafe {
+                *deep_stack_pointer = deep_stack.get_ptr() as u64;
+                *bottom_of_deep_stack_pointer = deep_stack.get_ptr() as u64;
+            }
+        }
+        if self.stack_budget.is_some() {
+            unsafe { *stack_base_address() = 0 };
+        }
+
+        error
+    }
+
+    /// Take the errors that compiled code recovered from, like an index that
+    /// was clamped into bounds with `BoundsCheck::ClampAndReport`.
+    pub fn take_reported_errors(&mut self) -> Vec<RuntimeError> {
+        match &mut self.error_slot {
+            Some(error_slot) => std::mem::take(&mut error_slot.reported),
+            None => Vec::new(),
+        }
+    }
+
+    /// Run a call into compiled code, returning the runtime error it recorded
+    /// instead of its result, if there was one.
+    /// ```ignore
+    /// let func_ptr = jit.get_func("main")?;
+    /// let func = unsafe { mem::transmute::<_, extern "C" fn(i64) -> i64>(func_ptr) };
+    /// let result = jit.call_checked(|| func(5));
+    /// ```
+    pub fn call_checked<T>(&mut self, f: impl FnOnce() -> T) -> Result<T, RuntimeError> {
+        let ret = f();
+        match self.take_runtime_error() {
+            Some(error) => Err(error),
+            None => Ok(ret),
+        }
+    }
+
+    /// Get a pointer to a `pub` function declared in the sarus code.
     pub fn get_func(&mut self, fn_name: &str) -> anyhow::Result<*const u8> {
+        if !self.pub_funcs.contains(fn_name) {
+            if self.module.get_name(fn_name).is_some() {
+                anyhow::bail!("function {} is not pub", fn_name)
+            }
+            anyhow::bail!("No function {} found", fn_name)
+        }
+        self.get_func_ptr(fn_name)
+    }
+
+    fn get_func_ptr(&mut self, fn_name: &str) -> anyhow::Result<*const u8> {
         match self.module.get_name(fn_name) {
             Some(func) => match func {
                 cranelift_module::FuncOrDataId::Func(id) => {
@@ -291,6 +701,26 @@ impl JIT {
         }
     }
 
+    /// Get a pointer to the data of a `static` declared in the sarus code.
+    /// Reads and writes through the pointer are seen by the sarus code.
+    /// Statics initialized by a function are read only and must not be written.
+    pub fn get_static<T>(&mut self, static_name: &str) -> anyhow::Result<*mut T> {
+        if !self.statics.contains_key(static_name) {
+            anyhow::bail!("No static {} found", static_name)
+        }
+        let (ptr, size) = self.get_data(static_name)?;
+        if size != std::mem::size_of::<T>() {
+            anyhow::bail!(
+                "static {} of type {} is {} bytes, requested type is {} bytes",
+                static_name,
+                self.statics[static_name].expr_type,
+                size,
+                std::mem::size_of::<T>()
+            )
+        }
+        Ok(ptr as *mut T)
+    }
+
     pub fn get_data(&mut self, data_name: &str) -> anyhow::Result<(*const u8, usize)> {
         match self.module.get_name(data_name) {
             Some(func) => match func {
@@ -305,12 +735,21 @@ impl JIT {
 
     /// Create a zero-initialized data section.
     pub fn create_data(&mut self, name: &str, contents: Vec<u8>) -> anyhow::Result<&[u8]> {
+        self.define_data(name, contents, true)
+    }
+
+    fn define_data(
+        &mut self,
+        name: &str,
+        contents: Vec<u8>,
+        writable: bool,
+    ) -> anyhow::Result<&[u8]> {
         // The steps here are analogous to `compile`, except that data is much
         // simpler than functions.
         self.data_ctx.define(contents.into_boxed_slice());
         let id = self
             .module
-            .declare_data(name, Linkage::Export, true, false)
+            .declare_data(name, Linkage::Export, writable, false)
             .map_err(|e| anyhow::anyhow!("{}", e))?;
 
         self.module
@@ -323,6 +762,241 @@ impl JIT {
         Ok(unsafe { slice::from_raw_parts(buffer.0, buffer.1) })
     }
 
+    /// Check each struct field default once against the field's type. Defaults
+    /// can't use variables, they are translated where a struct is created
+    /// without the variables of the code creating it.
+    fn check_field_defaults(
+        &self,
+        funcs: &HashMap<String, Function>,
+        struct_map: &HashMap<String, StructDef>,
+        constant_vars: &HashMap<String, SConstant>,
+        file_index_table: &Option<Vec<PathBuf>>,
+        inline_closures: &HashMap<String, HashMap<String, Closure>>,
+        diagnostics: &mut Diagnostics,
+    ) {
+        let env = Env {
+            constant_vars: constant_vars.clone(),
+            struct_map: struct_map.clone(),
+            ptr_ty: self.module.target_config().pointer_type(),
+            funcs: funcs.clone(),
+            file_idx: file_index_table.clone(),
+            inline_closures: inline_closures.clone(),
+            temp_inline_closures: HashMap::new(),
+            statics: self.statics.clone(),
+        };
+        for struct_def in struct_map.values() {
+            for field in struct_def.fields.values() {
+                let default = match &field.default {
+                    Some(default) => default,
+                    None => continue,
+                };
+                let error = match ExprType::of(default, &env, &struct_def.name, &HashMap::new()) {
+                    Ok(actual) if actual == field.expr_type => continue,
+                    Ok(actual) => TypeError::TypeMismatch {
+                        c: *default.get_code_ref(),
+                        expected: field.expr_type.clone(),
+                        actual,
+                    },
+                    Err(error) => error,
+                };
+                diagnostics.push_error(error.into(), file_index_table);
+            }
+        }
+    }
+
+    /// Error if a static has the name of a constant or of another static
+    fn check_static_name(
+        &self,
+        static_: &Static,
+        constants: &HashMap<String, SConstant>,
+    ) -> anyhow::Result<()> {
+        let error = LocatedError::new(
+            &static_.code_ref,
+            format!("static {} already exists", static_.name),
+        );
+        if let Some(other) = self.statics.get(&static_.name) {
+            anyhow::bail!(error.with_related(&other.code_ref, "previously defined here"))
+        }
+        if constants.contains_key(&static_.name) {
+            anyhow::bail!(error)
+        }
+        Ok(())
+    }
+
+    fn create_static(
+        &mut self,
+        static_: &Static,
+        constants: &HashMap<String, SConstant>,
+    ) -> anyhow::Result<()> {
+        let code_ref = &static_.code_ref;
+        self.check_static_name(static_, constants)?;
+        let eval = |expr: &Expr| sarus_std_lib::eval_const_expr(expr, constants);
+        let bytes = match &static_.expr_type {
+            ExprType::Array(_, ty, ArraySizedExpr::Fixed(len)) => {
+                let items = match &static_.expr {
+                    Expr::LiteralArray {
+                        exprs,
+                        len: literal_len,
+                        ..
+                    } if literal_len == len => {
+                        if exprs.len() == 1 {
+                            vec![eval(&exprs[0])?; *len]
+                        } else {
+                            exprs.iter().map(eval).collect::<anyhow::Result<Vec<_>>>()?
+                        }
+                    }
+                    _ => anyhow::bail!(LocatedError::new(
+                        code_ref,
+                        format!(
+                            "static {} of type {} must be initialized with an array literal of length {}",
+                            static_.name,
+                            static_.expr_type,
+                            len
+                        )
+                    )),
+                };
+                let mut bytes = Vec::new();
+                for item in items {
+                    bytes.extend(static_scalar_bytes(static_, ty, item)?);
+                }
+                bytes
+            }
+            ty => static_scalar_bytes(static_, ty, eval(&static_.expr)?)?,
+        };
+        trace!("{} static {} {} bytes", code_ref, static_.name, bytes.len());
+        self.create_data(&static_.name, bytes)?;
+        self.statics.insert(
+            static_.name.to_string(),
+            StaticDef {
+                code_ref: static_.code_ref,
+                expr_type: static_.expr_type.clone(),
+                read_only: false,
+            },
+        );
+        Ok(())
+    }
+
+    /// Run the function that initializes a static table and store the result
+    /// in read only data. `pending` are the tables not yet initialized,
+    /// starting with this one.
+    fn create_static_table(
+        &mut self,
+        static_: &Static,
+        pending: &[Static],
+        funcs: &HashMap<String, Function>,
+        struct_map: &HashMap<String, StructDef>,
+    ) -> anyhow::Result<()> {
+        let code_ref = &static_.code_ref;
+        let (fn_name, args) = match &static_.expr {
+            Expr::Call { fn_name, args, .. } => (fn_name, args),
+            _ => unreachable!(),
+        };
+        let size = match &static_.expr_type {
+            ExprType::Array(_, _, ArraySizedExpr::Fixed(_)) => static_
+                .expr_type
+                .width(self.module.target_config().pointer_type(), struct_map),
+            _ => None,
+        };
+        let size = match size {
+            Some(size) => size,
+            None => anyhow::bail!(LocatedError::new(
+                code_ref,
+                format!(
+                    "static {} of type {} can't be initialized by a function, only fixed length arrays can",
+                    static_.name,
+                    static_.expr_type
+                )
+            )),
+        };
+        let func = match funcs.get(fn_name) {
+            Some(func) => func,
+            None => anyhow::bail!(LocatedError::new(
+                code_ref,
+                format!(
+                    "static {} is initialized by unknown function {}",
+                    static_.name, fn_name
+                )
+            )),
+        };
+        if !args.is_empty()
+            || !func.params.is_empty()
+            || func.returns.len() != 1
+            || func.returns[0].expr_type != static_.expr_type
+            || func.extern_func
+            || matches!(func.inline, InlineKind::Always)
+        {
+            anyhow::bail!(LocatedError::new(
+                code_ref,
+                format!(
+                    "static {} of type {} must be initialized by a function that takes no arguments and returns {}, found {}",
+                    static_.name,
+                    static_.expr_type,
+                    static_.expr_type,
+                    func.sig_string()?
+                )
+            ))
+        }
+
+        // The data for tables that are not initialized yet doesn't exist, make
+        // sure this function and anything it calls doesn't use them.
+        let mut stack = vec![fn_name.to_string()];
+        let mut visited = HashSet::new();
+        while let Some(name) = stack.pop() {
+            if !visited.insert(name.to_string()) {
+                continue;
+            }
+            if let Some(func) = funcs.get(&name) {
+                let mut refs = Vec::new();
+                for expr in &func.body {
+                    find_references(expr, &mut refs)
+                }
+                for r in refs {
+                    if let Some(table) = pending.iter().find(|table| table.name == r) {
+                        anyhow::bail!(LocatedError::new(
+                            code_ref,
+                            format!(
+                                "static {} is initialized by {}, which uses static {} before it is initialized",
+                                static_.name,
+                                fn_name,
+                                table.name
+                            )
+                        ))
+                    }
+                    stack.push(r);
+                }
+            }
+        }
+
+        let func_ptr = self.get_func_ptr(fn_name)?;
+        let mut contents = vec![0u8; size];
+        let func = unsafe { std::mem::transmute::<_, extern "C" fn(*mut u8)>(func_ptr) };
+        if let Some(error_slot) = &mut self.error_slot {
+            error_slot.abort_on_error = false;
+        }
+        func(contents.as_mut_ptr());
+        if let Some(error_slot) = &mut self.error_slot {
+            error_slot.abort_on_error = true;
+        }
+        if let Some(error) = self.take_runtime_error() {
+            anyhow::bail!(LocatedError::new(
+                code_ref,
+                format!(
+                    "static {} could not be initialized by {}: {}",
+                    static_.name, fn_name, error
+                )
+            ))
+        }
+        trace!(
+            "{} static {} {} bytes from {}",
+            code_ref,
+            static_.name,
+            size,
+            fn_name
+        );
+        self.define_data(&static_.name, contents, false)?;
+        Ok(())
+    }
+
     // Translate from AST nodes into Cranelift IR.
     #[instrument(
         level = "info",
@@ -344,7 +1018,7 @@ impl JIT {
         constant_vars: &HashMap<String, SConstant>,
         file_index_table: &Option<Vec<PathBuf>>,
         inline_closures: &HashMap<String, HashMap<String, Closure>>,
-    ) -> anyhow::Result<()> {
+    ) -> anyhow::Result<usize> {
         info!("{}", func.sig_string()?);
         let ptr_ty = self.module.target_config().pointer_type();
 
@@ -353,9 +1027,10 @@ impl JIT {
                 ExprType::Struct(_, ..)
                 | ExprType::Array(_, _, ArraySizedExpr::Fixed(..) | ArraySizedExpr::Slice) => {
                     if func.returns.len() > 1 {
-                        anyhow::bail!(
+                        anyhow::bail!(LocatedError::new(
+                            &func.code_ref,
                             "If returning a fixed length array, slice, or struct, only 1 return value is currently supported"
-                        )
+                        ))
                     }
                     self.ctx
                         .func
@@ -384,11 +1059,15 @@ impl JIT {
                     ExprType::U8(_code_ref) => AbiParam::new(types::I8),
                     ExprType::Array(_code_ref, _ty, _len) => AbiParam::new(ptr_ty),
                     ExprType::Address(_code_ref) => AbiParam::new(ptr_ty),
+                    ExprType::Ref(_code_ref, _) => AbiParam::new(ptr_ty),
                     ExprType::Void(_code_ref) => continue,
                     ExprType::Bool(_code_ref) => AbiParam::new(types::B1),
                     ExprType::Struct(_code_ref, _) => AbiParam::new(ptr_ty),
                     ExprType::Tuple(code_ref, _) => {
-                        anyhow::bail!("{} Tuple as parameter not supported", code_ref)
+                        anyhow::bail!(LocatedError::new(
+                            code_ref,
+                            "Tuple as parameter not supported"
+                        ))
                     }
                 }
             });
@@ -424,6 +1103,7 @@ impl JIT {
             file_idx: file_index_table.clone(),
             inline_closures: inline_closures.clone(),
             temp_inline_closures: HashMap::new(),
+            statics: self.statics.clone(),
         };
 
         //println!("declare_variables {}", func.name);
@@ -467,19 +1147,38 @@ impl JIT {
             while_exit_blocks: Vec::new(),
             while_continue_blocks: Vec::new(),
             deep_stack_debug: false,
+            deep_stack_checks: self.deep_stack_checks,
+            stack_budget: self.stack_budget,
+            stack_guard_outer: None,
+            runtime_errors: self.runtime_errors,
+            bounds_check: self.bounds_check,
+            overflow_checks: self.overflow_checks,
+            call_stack_tracking: self.call_stack_tracking,
+            call_stack_pushes: 0,
+            array_aliases: vec![HashMap::new()],
+            array_alias_writes: vec![HashMap::new()],
+            type_errors: Vec::new(),
+            poisoned_vars: HashSet::new(),
         };
+        trans.call_stack_push()?;
+        trans.stack_guard_enter()?;
         if self.use_deep_stack {
-            trans.deep_stack_init();
-            trans.add_deep_stack_frame(true);
+            trans.deep_stack_init()?;
+            trans.add_deep_stack_frame(true)?;
         }
         for expr in &func.body {
-            trans.translate_expr(expr)?;
+            trans.translate_statement(expr)?;
+        }
+        if !trans.type_errors.is_empty() {
+            let mut diagnostics = Diagnostics::default();
+            for error in &trans.type_errors {
+                diagnostics.push(Diagnostic::from_type_error(error, file_index_table));
+            }
+            return Err(diagnostics.into());
         }
 
         trans.return_(false)?;
-        if self.use_deep_stack {
-            self.total_max_deep_stack_size += trans.max_deep_stack_size;
-        }
+        let deep_stack_size = trans.max_deep_stack_size;
 
         //Keep clif around for later debug/print
         self.clif.insert(
@@ -491,7 +1190,7 @@ impl JIT {
         // Tell the builder we're done with this function.
         trans.builder.finalize();
 
-        Ok(())
+        Ok(deep_stack_size)
     }
 }
 
@@ -519,6 +1218,16 @@ pub struct Env {
     // TODO make recursively inlining an error
     // These are stored by temp_inline_closures[containing func name][closure name]
     pub temp_inline_closures: HashMap<String, HashMap<String, Closure>>,
+
+    // `static` declarations, these are global data that can be read and written
+    pub statics: HashMap<String, StaticDef>,
+}
+
+#[derive(Debug, Clone)]
+pub struct StaticDef {
+    pub code_ref: CodeRef,
+    pub expr_type: ExprType,
+    pub read_only: bool, //tables initialized by a function at JIT time
 }
 
 impl Env {
@@ -541,8 +1250,14 @@ impl Env {
     }
 }
 
+/// Find the names of the functions called in an expression. The type of self
+/// isn't known for method calls, those are added as `.method`. Identifiers are
+/// added too, since a function can be passed by name as a closure argument,
+/// `resolve_calls` drops the ones that aren't functions.
+#[allow(deprecated)]
 fn find_calls(expr: &Expr, calls: &mut Vec<String>) {
     match expr {
+        Expr::Identifier { name, .. } => calls.push(name.to_string()),
         Expr::LiteralFloat { .. }
         | Expr::LiteralInt { .. }
         | Expr::LiteralU8 { .. }
@@ -551,14 +1266,35 @@ fn find_calls(expr: &Expr, calls: &mut Vec<String>) {
         | Expr::Break { .. }
         | Expr::Continue { .. }
         | Expr::Return { .. }
-        | Expr::Declaration { .. }
-        | Expr::Identifier { .. }
         | Expr::GlobalDataAddr { .. } => (),
+        Expr::Declaration { declaration, .. } => {
+            if let Declaration::Function(closure) = declaration {
+                for e in &closure.body {
+                    find_calls(e, calls)
+                }
+            }
+        }
         Expr::LiteralArray { exprs, .. } => {
             for expr in exprs {
                 find_calls(expr, calls)
             }
         }
+        Expr::Binop {
+            op: Binop::DotAccess,
+            lhs,
+            rhs,
+            ..
+        } => {
+            find_calls(lhs, calls);
+            if let Expr::Call { fn_name, args, .. } = &**rhs {
+                calls.push(format!(".{}", fn_name));
+                for e in args {
+                    find_calls(e, calls)
+                }
+            } else {
+                find_calls(rhs, calls)
+            }
+        }
         Expr::Binop { lhs, rhs, .. } => {
             find_calls(lhs, calls);
             find_calls(rhs, calls)
@@ -627,10 +1363,13 @@ fn find_calls(expr: &Expr, calls: &mut Vec<String>) {
                 find_calls(e, calls)
             }
         }
-        Expr::NewStruct { fields, .. } => {
+        Expr::NewStruct { fields, base, .. } => {
             for e in fields {
                 find_calls(&e.expr, calls)
             }
+            if let Some(base) = base {
+                find_calls(base, calls)
+            }
         }
         Expr::Match {
             expr_arg, fields, ..
@@ -661,7 +1400,8 @@ fn find_calls(expr: &Expr, calls: &mut Vec<String>) {
                 find_calls(e, calls)
             }
         }
-        Expr::Call { args, .. } => {
+        Expr::Call { fn_name, args, .. } => {
+            calls.push(fn_name.to_string());
             for e in args {
                 find_calls(e, calls)
             }
@@ -674,17 +1414,709 @@ fn find_calls(expr: &Expr, calls: &mut Vec<String>) {
     }
 }
 
-fn order_funcs(funcs: &HashMap<String, Function>) -> Vec<String> {
-    let mut func_calls: HashMap<String, Vec<String>> = HashMap::new(); //func_name: its calls
+fn static_scalar_bytes(
+    static_: &Static,
+    expr_type: &ExprType,
+    val: SConstant,
+) -> anyhow::Result<Vec<u8>> {
+    Ok(match (expr_type, val) {
+        (ExprType::F32(_), SConstant::F32(v)) => v.to_ne_bytes().to_vec(),
+        (ExprType::I64(_), SConstant::I64(v)) => v.to_ne_bytes().to_vec(),
+        (ExprType::Bool(_), SConstant::Bool(v)) => (v as i8).to_ne_bytes().to_vec(),
+        (ExprType::F32(_) | ExprType::I64(_) | ExprType::Bool(_), v) => anyhow::bail!(LocatedError::new(
+            &static_.code_ref,
+            format!(
+                "static {} of type {} can't be initialized with {}",
+                static_.name,
+                expr_type,
+                v.expr_type(None)
+            )
+        )),
+        _ => anyhow::bail!(LocatedError::new(
+            &static_.code_ref,
+            format!(
+                "static {} of type {} is not supported, statics can be f32, i64, bool or fixed length arrays of those",
+                static_.name,
+                static_.expr_type
+            )
+        )),
+    })
+}
+
+fn const_array_len(
+    name: &str,
+    code_ref: &CodeRef,
+    constants: &HashMap<String, SConstant>,
+) -> anyhow::Result<usize> {
+    match constants.get(name) {
+        Some(SConstant::I64(n)) if *n > 0 => Ok(*n as usize),
+        Some(_) => anyhow::bail!(LocatedError::new(
+            code_ref,
+            format!("array length {} must be a positive i64 const", name)
+        )),
+        None => anyhow::bail!(LocatedError::new(
+            code_ref,
+            format!("array length {} is not a const", name)
+        )),
+    }
+}
+
+/// Functions (including methods) and structs can only be declared once. A
+/// declaration marked `override` replaces the other declaration of the same
+/// name instead, which is removed from the program.
+fn apply_overrides(prog: &mut Vec<Declaration>) -> anyhow::Result<()> {
+    struct Declared {
+        index: usize,
+        code_ref: CodeRef,
+        override_decl: bool,
+        replaced_other: bool,
+    }
+    let mut declared: HashMap<(&str, String), Declared> = HashMap::new();
+    let mut overridden = HashSet::new();
+    for (index, decl) in prog.iter().enumerate() {
+        let (kind, name, code_ref, override_decl) = match decl {
+            Declaration::Function(func) => {
+                ("function", &func.name, func.code_ref, func.override_func)
+            }
+            Declaration::Struct(struct_) => (
+                "struct",
+                &struct_.name,
+                struct_.code_ref,
+                struct_.override_struct,
+            ),
+            _ => continue,
+        };
+        if let Some(other) = declared.get_mut(&(kind, name.to_string())) {
+            if override_decl == other.override_decl {
+                // Declarations from the std lib or the host have no location,
+                // and come after the program's own declarations
+                let (at, previous, previous_index) = if code_ref.line.is_some() {
+                    (code_ref, other.code_ref, other.index)
+                } else {
+                    (other.code_ref, code_ref, index)
+                };
+                let error =
+                    LocatedError::new(&at, format!("{} {} is defined more than once", kind, name));
+                let label = if previous.line.is_some() {
+                    "previously defined here".to_string()
+                } else if let Declaration::Function(func) = &prog[previous_index] {
+                    format!(
+                        "`{}` is already declared by the std lib or the host",
+                        func.sig_string()?.trim_end_matches(" {}")
+                    )
+                } else {
+                    format!(
+                        "{} {} is already declared by the std lib or the host",
+                        kind, name
+                    )
+                };
+                anyhow::bail!(error.with_related(&previous, label))
+            }
+            if override_decl {
+                overridden.insert(other.index);
+                *other = Declared {
+                    index,
+                    code_ref,
+                    override_decl,
+                    replaced_other: true,
+                };
+            } else {
+                overridden.insert(index);
+                other.replaced_other = true;
+            }
+        } else {
+            declared.insert(
+                (kind, name.to_string()),
+                Declared {
+                    index,
+                    code_ref,
+                    override_decl,
+                    replaced_other: false,
+                },
+            );
+        }
+    }
+    for ((kind, name), d) in &declared {
+        if d.override_decl && !d.replaced_other {
+            anyhow::bail!(LocatedError::new(
+                &d.code_ref,
+                format!(
+                    "{} {} is declared override but there is no other {} {} to override",
+                    kind, name, kind, name
+                )
+            ))
+        }
+    }
+    let mut index = 0;
+    prog.retain(|_| {
+        index += 1;
+        !overridden.contains(&(index - 1))
+    });
+    Ok(())
+}
+
+/// Rewrites the types in the program. Type aliases are replaced with the type
+/// they refer to, and if constants are given, array lengths given by a const
+/// (`[f32; N]`, `[0.0; N]`) are replaced with their value.
+struct TypeResolver<'a> {
+    aliases: HashMap<String, ExprType>,
+    constants: Option<&'a HashMap<String, SConstant>>,
+}
+
+impl<'a> TypeResolver<'a> {
+    fn resolve_type(&self, expr_type: &mut ExprType) -> anyhow::Result<()> {
+        match expr_type {
+            ExprType::Struct(_, name) => {
+                if let Some(ty) = self.aliases.get(name.as_str()) {
+                    *expr_type = ty.clone();
+                }
+                Ok(())
+            }
+            ExprType::Array(code_ref, ty, size_type) => {
+                if let (ArraySizedExpr::Const(name), Some(constants)) =
+                    (&*size_type, self.constants)
+                {
+                    let len = const_array_len(name, code_ref, constants)?;
+                    *size_type = ArraySizedExpr::Fixed(len);
+                }
+                self.resolve_type(ty)
+            }
+            ExprType::Ref(_, ty) => self.resolve_type(ty),
+            ExprType::Tuple(_, types) => {
+                for ty in types {
+                    self.resolve_type(ty)?
+                }
+                Ok(())
+            }
+            _ => Ok(()),
+        }
+    }
+
+    fn resolve_args(&self, args: &mut [Arg]) -> anyhow::Result<()> {
+        for arg in args {
+            self.resolve_type(&mut arg.expr_type)?;
+            if let Some(closure_arg) = &mut arg.closure_arg {
+                self.resolve_func(closure_arg)?;
+            }
+            if let Some(default) = &mut arg.default {
+                self.resolve_expr(default)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn resolve_func(&self, func: &mut Function) -> anyhow::Result<()> {
+        self.resolve_args(&mut func.params)?;
+        self.resolve_args(&mut func.returns)?;
+        self.resolve_body(&mut func.body)
+    }
+
+    fn resolve_body(&self, body: &mut [Expr]) -> anyhow::Result<()> {
+        for e in body {
+            self.resolve_expr(e)?
+        }
+        Ok(())
+    }
+
+    #[allow(deprecated)]
+    fn resolve_expr(&self, expr: &mut Expr) -> anyhow::Result<()> {
+        match expr {
+            Expr::LiteralFloat { .. }
+            | Expr::LiteralInt { .. }
+            | Expr::LiteralU8 { .. }
+            | Expr::LiteralBool { .. }
+            | Expr::LiteralString { .. }
+            | Expr::Break { .. }
+            | Expr::Continue { .. }
+            | Expr::Return { .. }
+            | Expr::Identifier { .. }
+            | Expr::GlobalDataAddr { .. } => (),
+            Expr::LiteralArray {
+                code_ref,
+                exprs,
+                len,
+                len_const,
+            } => {
+                if let (Some(name), Some(constants)) = (len_const.as_ref(), self.constants) {
+                    *len = const_array_len(name, code_ref, constants)?;
+                }
+                self.resolve_body(exprs)?
+            }
+            Expr::Binop { lhs, rhs, .. } | Expr::Compare { lhs, rhs, .. } => {
+                self.resolve_expr(lhs)?;
+                self.resolve_expr(rhs)?
+            }
+            Expr::Unaryop { expr, .. } | Expr::Parentheses { expr, .. } => {
+                self.resolve_expr(expr)?
+            }
+            Expr::IfThen {
+                condition,
+                then_body,
+                ..
+            } => {
+                self.resolve_expr(condition)?;
+                self.resolve_body(then_body)?
+            }
+            Expr::IfElse {
+                condition,
+                then_body,
+                else_body,
+                ..
+            } => {
+                self.resolve_expr(condition)?;
+                self.resolve_body(then_body)?;
+                self.resolve_body(else_body)?
+            }
+            Expr::IfThenElseIf { expr_bodies, .. } => {
+                for (a, b) in expr_bodies {
+                    self.resolve_expr(a)?;
+                    self.resolve_body(b)?
+                }
+            }
+            Expr::IfThenElseIfElse {
+                expr_bodies,
+                else_body,
+                ..
+            } => {
+                for (a, b) in expr_bodies {
+                    self.resolve_expr(a)?;
+                    self.resolve_body(b)?
+                }
+                self.resolve_body(else_body)?
+            }
+            Expr::Assign {
+                to_exprs,
+                from_exprs,
+                ..
+            } => {
+                self.resolve_body(to_exprs)?;
+                self.resolve_body(from_exprs)?
+            }
+            Expr::NewStruct { fields, base, .. } => {
+                for e in fields {
+                    self.resolve_expr(&mut e.expr)?
+                }
+                if let Some(base) = base {
+                    self.resolve_expr(base)?
+                }
+            }
+            Expr::Match {
+                expr_arg, fields, ..
+            } => {
+                self.resolve_expr(expr_arg)?;
+                for e in fields {
+                    self.resolve_expr(&mut e.expr)?
+                }
+            }
+            Expr::WhileLoop {
+                condition,
+                iter_body,
+                loop_body,
+                ..
+            } => {
+                self.resolve_expr(condition)?;
+                if let Some(b) = iter_body {
+                    self.resolve_body(b)?
+                }
+                self.resolve_body(loop_body)?
+            }
+            Expr::Block { block, .. } => self.resolve_body(block)?,
+            Expr::Call { args, .. } => self.resolve_body(args)?,
+            Expr::ArrayAccess { expr, idx_expr, .. } => {
+                self.resolve_expr(expr)?;
+                self.resolve_expr(idx_expr)?
+            }
+            Expr::Declaration { declaration, .. } => {
+                if let Declaration::Function(closure) = declaration {
+                    self.resolve_func(closure)?
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn resolve_prog(&self, prog: &mut [Declaration]) -> anyhow::Result<()> {
+        for decl in prog {
+            match decl {
+                Declaration::Function(func) => self.resolve_func(func)?,
+                Declaration::Struct(s) => self.resolve_args(&mut s.fields)?,
+                Declaration::Constant(c) => self.resolve_type(&mut c.expr_type)?,
+                Declaration::Static(s) => {
+                    self.resolve_type(&mut s.expr_type)?;
+                    self.resolve_expr(&mut s.expr)?
+                }
+                _ => (),
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Collect the `type` aliases in the program, with any aliases they use expanded.
+fn type_aliases(prog: &[Declaration]) -> anyhow::Result<HashMap<String, ExprType>> {
+    let mut declared = HashMap::new();
+    for decl in prog {
+        if let Declaration::TypeAlias(alias) = decl {
+            if declared.insert(alias.name.as_str(), alias).is_some() {
+                anyhow::bail!(LocatedError::new(
+                    &alias.code_ref,
+                    format!("type {} already exists", alias.name)
+                ))
+            }
+        }
+    }
+    for decl in prog {
+        if let Declaration::Struct(struct_) = decl {
+            if let Some(alias) = declared.get(struct_.name.as_str()) {
+                anyhow::bail!(LocatedError::new(
+                    &alias.code_ref,
+                    format!("type {} already exists", alias.name)
+                ))
+            }
+        }
+    }
+    let mut aliases = HashMap::new();
+    for name in declared.keys() {
+        expand_alias(name, &declared, &mut aliases, &mut Vec::new())?;
+    }
+    Ok(aliases)
+}
+
+fn expand_alias(
+    name: &str,
+    declared: &HashMap<&str, &TypeAlias>,
+    aliases: &mut HashMap<String, ExprType>,
+    stack: &mut Vec<String>,
+) -> anyhow::Result<ExprType> {
+    if let Some(ty) = aliases.get(name) {
+        return Ok(ty.clone());
+    }
+    let alias = declared[name];
+    if stack.iter().any(|n| n == name) {
+        anyhow::bail!(LocatedError::new(
+            &alias.code_ref,
+            format!("type {} refers to itself", alias.name)
+        ))
+    }
+    stack.push(name.to_string());
+    let mut ty = alias.expr_type.clone();
+    expand_aliases_in_type(&mut ty, declared, aliases, stack)?;
+    stack.pop();
+    aliases.insert(name.to_string(), ty.clone());
+    Ok(ty)
+}
+
+fn expand_aliases_in_type(
+    expr_type: &mut ExprType,
+    declared: &HashMap<&str, &TypeAlias>,
+    aliases: &mut HashMap<String, ExprType>,
+    stack: &mut Vec<String>,
+) -> anyhow::Result<()> {
+    match expr_type {
+        ExprType::Struct(_, name) => {
+            if declared.contains_key(name.as_str()) {
+                *expr_type = expand_alias(&name.to_string(), declared, aliases, stack)?;
+            }
+        }
+        ExprType::Array(_, ty, _) | ExprType::Ref(_, ty) => {
+            expand_aliases_in_type(ty, declared, aliases, stack)?
+        }
+        ExprType::Tuple(_, types) => {
+            for ty in types {
+                expand_aliases_in_type(ty, declared, aliases, stack)?
+            }
+        }
+        _ => (),
+    }
+    Ok(())
+}
+
+/// Find the names of the variables, statics, and functions used in an expression.
+#[allow(deprecated)]
+pub(crate) fn find_references(expr: &Expr, refs: &mut Vec<String>) {
+    match expr {
+        Expr::LiteralFloat { .. }
+        | Expr::LiteralInt { .. }
+        | Expr::LiteralU8 { .. }
+        | Expr::LiteralBool { .. }
+        | Expr::LiteralString { .. }
+        | Expr::Break { .. }
+        | Expr::Continue { .. }
+        | Expr::Return { .. } => (),
+        Expr::Identifier { name, .. } | Expr::GlobalDataAddr { name, .. } => {
+            refs.push(name.to_string())
+        }
+        Expr::LiteralArray { exprs, .. } => {
+            for expr in exprs {
+                find_references(expr, refs)
+            }
+        }
+        Expr::Binop { lhs, rhs, .. } | Expr::Compare { lhs, rhs, .. } => {
+            find_references(lhs, refs);
+            find_references(rhs, refs)
+        }
+        Expr::Unaryop { expr, .. } | Expr::Parentheses { expr, .. } => find_references(expr, refs),
+        Expr::IfThen {
+            condition,
+            then_body,
+            ..
+        } => {
+            find_references(condition, refs);
+            for e in then_body {
+                find_references(e, refs)
+            }
+        }
+        Expr::IfElse {
+            condition,
+            then_body,
+            else_body,
+            ..
+        } => {
+            find_references(condition, refs);
+            for e in then_body.iter().chain(else_body) {
+                find_references(e, refs)
+            }
+        }
+        Expr::IfThenElseIf { expr_bodies, .. } => {
+            for (a, b) in expr_bodies {
+                find_references(a, refs);
+                for e in b {
+                    find_references(e, refs)
+                }
+            }
+        }
+        Expr::IfThenElseIfElse {
+            expr_bodies,
+            else_body,
+            ..
+        } => {
+            for (a, b) in expr_bodies {
+                find_references(a, refs);
+                for e in b {
+                    find_references(e, refs)
+                }
+            }
+            for e in else_body {
+                find_references(e, refs)
+            }
+        }
+        Expr::Assign {
+            to_exprs,
+            from_exprs,
+            ..
+        } => {
+            for e in to_exprs.iter().chain(from_exprs) {
+                find_references(e, refs)
+            }
+        }
+        Expr::NewStruct { fields, base, .. } => {
+            for e in fields {
+                find_references(&e.expr, refs)
+            }
+            if let Some(base) = base {
+                find_references(base, refs)
+            }
+        }
+        Expr::Match {
+            expr_arg, fields, ..
+        } => {
+            find_references(expr_arg, refs);
+            for e in fields {
+                find_references(&e.expr, refs)
+            }
+        }
+        Expr::WhileLoop {
+            condition,
+            iter_body,
+            loop_body,
+            ..
+        } => {
+            find_references(condition, refs);
+            if let Some(b) = iter_body {
+                for e in b {
+                    find_references(e, refs)
+                }
+            }
+            for e in loop_body {
+                find_references(e, refs)
+            }
+        }
+        Expr::Block { block, .. } => {
+            for e in block {
+                find_references(e, refs)
+            }
+        }
+        Expr::Call { fn_name, args, .. } => {
+            refs.push(fn_name.to_string());
+            for e in args {
+                find_references(e, refs)
+            }
+        }
+        Expr::ArrayAccess { expr, idx_expr, .. } => {
+            find_references(expr, refs);
+            find_references(idx_expr, refs)
+        }
+        Expr::Declaration { declaration, .. } => {
+            if let Declaration::Function(closure) = declaration {
+                for e in &closure.body {
+                    find_references(e, refs)
+                }
+            }
+        }
+    }
+}
+
+/// The methods of every type, by method name.
+fn methods_by_name(funcs: &HashMap<String, Function>) -> HashMap<&str, Vec<String>> {
+    let mut methods: HashMap<&str, Vec<String>> = HashMap::new();
+    for name in funcs.keys() {
+        if let Some((_, method)) = name.rsplit_once('.') {
+            methods
+                .entry(method)
+                .or_insert_with(Vec::new)
+                .push(name.to_string());
+        }
+    }
+    methods
+}
+
+/// The functions called by each function, method calls are matched with
+/// every method of the same name. Without `ambiguous_methods`, a method call
+/// is only matched if there is one method with that name, so the graph only
+/// has calls that are known to happen.
+fn call_graph(
+    funcs: &HashMap<String, Function>,
+    methods: &HashMap<&str, Vec<String>>,
+    ambiguous_methods: bool,
+) -> HashMap<String, Vec<String>> {
+    let mut graph = HashMap::new();
     for (func_name, func) in funcs {
         let mut calls = Vec::new();
         for expr in &func.body {
             find_calls(expr, &mut calls)
         }
-        func_calls.insert(func_name.to_string(), calls);
+        graph.insert(
+            func_name.to_string(),
+            resolve_calls(&calls, funcs, methods, ambiguous_methods),
+        );
+    }
+    graph
+}
+
+fn resolve_calls(
+    calls: &[String],
+    funcs: &HashMap<String, Function>,
+    methods: &HashMap<&str, Vec<String>>,
+    ambiguous_methods: bool,
+) -> Vec<String> {
+    let mut resolved = Vec::new();
+    for call in calls {
+        if let Some(method) = call.strip_prefix('.') {
+            if let Some(names) = methods.get(method) {
+                if ambiguous_methods || names.len() == 1 {
+                    resolved.extend(names.iter().cloned())
+                }
+            }
+        } else if funcs.contains_key(call) {
+            resolved.push(call.to_string())
+        }
+    }
+    resolved
+}
+
+/// The functions that can be reached from the roots through the call graph.
+fn reachable_funcs(graph: &HashMap<String, Vec<String>>, roots: &[String]) -> HashSet<String> {
+    let mut stack = roots.to_vec();
+    let mut reachable = HashSet::new();
+    while let Some(name) = stack.pop() {
+        if reachable.insert(name.to_string()) {
+            stack.extend(graph[&name].iter().cloned())
+        }
+    }
+    reachable
+}
+
+/// The most deep stack that can be in use at once, along any call path from
+/// the roots. A function that allocates on the deep stack can't be recursive,
+/// since there would be no limit to how much it uses. Recursion is only
+/// checked through `definite_graph`, since a method call in `graph` might be
+/// to a method of another type with the same name. If that makes a cycle, the
+/// size is instead the sum of every function that can be reached.
+fn deep_stack_depth(
+    graph: &HashMap<String, Vec<String>>,
+    definite_graph: &HashMap<String, Vec<String>>,
+    roots: &[String],
+    sizes: &HashMap<String, usize>,
+) -> anyhow::Result<usize> {
+    let size = |name: &str| sizes.get(name).copied().unwrap_or(0);
+    let mut ambiguous_cycle = false;
+    for (name, _) in sizes.iter().filter(|(_, size)| **size > 0) {
+        if let Some(path) = call_path(definite_graph, name, name) {
+            anyhow::bail!(
+                "function {} uses {} bytes of deep stack and is recursive ({}), the deep stack size can't be determined",
+                name,
+                size(name),
+                path.join(" -> ")
+            )
+        }
+        if call_path(graph, name, name).is_some() {
+            ambiguous_cycle = true;
+        }
+    }
+    if ambiguous_cycle {
+        trace!(
+            "method calls with more than one possible type form a cycle, summing deep stack sizes"
+        );
+        return Ok(reachable_funcs(graph, roots)
+            .iter()
+            .map(|name| size(name))
+            .sum());
+    }
+    // The functions left in cycles use no deep stack, so this settles once
+    // every path has been followed
+    let mut depths: HashMap<&str, usize> = HashMap::new();
+    loop {
+        let mut changed = false;
+        for (name, calls) in graph {
+            let depth = size(name)
+                + calls
+                    .iter()
+                    .map(|call| depths.get(call.as_str()).copied().unwrap_or(0))
+                    .max()
+                    .unwrap_or(0);
+            if depths.get(name.as_str()) != Some(&depth) {
+                depths.insert(name.as_str(), depth);
+                changed = true;
+            }
+        }
+        if !changed {
+            break;
+        }
+    }
+    Ok(roots
+        .iter()
+        .map(|root| depths.get(root.as_str()).copied().unwrap_or(0))
+        .max()
+        .unwrap_or(0))
+}
+
+/// The functions called on the way from one function to another, if any.
+fn call_path(graph: &HashMap<String, Vec<String>>, from: &str, to: &str) -> Option<Vec<String>> {
+    let mut visited = HashSet::new();
+    let mut stack = vec![vec![from.to_string()]];
+    while let Some(path) = stack.pop() {
+        for call in &graph[path.last().unwrap()] {
+            let mut path = path.clone();
+            path.push(call.to_string());
+            if call == to {
+                return Some(path);
+            }
+            if visited.insert(call.to_string()) {
+                stack.push(path)
+            }
+        }
     }
-    dbg!(func_calls);
-    todo!()
+    None
 }
 
 //TODO move to its own file if we use this
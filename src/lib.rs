@@ -8,13 +8,16 @@ pub use frontend::parse;
 pub use frontend::parse_with_context;
 pub use frontend::{Arg, Declaration, Function};
 
-use crate::frontend::InlineKind;
+use crate::frontend::{CodeRef, InlineKind};
 
+pub mod diagnostics;
 pub mod frontend;
 pub mod function_translator;
 pub mod graph;
 pub mod jit;
+pub mod lint;
 pub mod logging;
+pub mod runtime_error;
 pub mod sarus_std_lib;
 mod structs;
 pub mod validator;
@@ -37,6 +40,7 @@ pub fn default_std_jit_from_code(code: &str, use_deep_stack: bool) -> anyhow::Re
     sarus_std_lib::append_std_strings(&mut ast, &mut jit_builder);
 
     let mut jit = jit::JIT::from(jit_builder, use_deep_stack);
+    jit.set_source(code);
 
     jit.translate(ast, None)?;
     Ok(jit)
@@ -76,6 +80,7 @@ macro_rules! decl {
                         expr_type: $param,
                         no_type_listed: false,
                         closure_arg: None,
+                        default: None,
                     });
                 )*
 
@@ -87,6 +92,7 @@ macro_rules! decl {
                         expr_type: $ret,
                         no_type_listed: false,
                         closure_arg: None,
+                        default: None,
                     });
                 )*
 
@@ -104,6 +110,7 @@ fn make_decl(
     returns: Vec<(&str, ExprType)>,
 ) -> Declaration {
     Declaration::Function(Function {
+        code_ref: CodeRef::default(),
         name: name.to_string(),
         params: params
             .into_iter()
@@ -112,6 +119,7 @@ fn make_decl(
                 expr_type: expr,
                 no_type_listed: false,
                 closure_arg: None,
+                default: None,
             })
             .collect(),
         returns: returns
@@ -121,10 +129,14 @@ fn make_decl(
                 expr_type: expr,
                 no_type_listed: false,
                 closure_arg: None,
+                default: None,
             })
             .collect(),
         body: vec![],
         extern_func: true,
         inline: InlineKind::Default,
+        override_func: false,
+        pub_func: false,
+        bounds_check: None,
     })
 }
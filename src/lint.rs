@@ -0,0 +1,675 @@
+use std::collections::{HashMap, HashSet};
+use std::path::PathBuf;
+
+use crate::diagnostics::{codes, Diagnostic, Span, Suggestion};
+use crate::frontend::{Binop, CodeRef, Declaration, Expr, Function};
+use crate::jit::find_references;
+use crate::validator::ExprType;
+
+/// The warnings the lint pass gives. An item can allow some of them with an
+/// `allow` key in its metadata, `"warnings"` allows all of them:
+/// ```text
+/// @ main
+///     allow = ["unused_variable", "dead_assignment"]
+/// @
+/// ```
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Lint {
+    UnusedVariable,
+    UnusedParameter,
+    UnusedResult,
+    UnreachableCode,
+    DeadAssignment,
+    UnusedFunction,
+    UnusedStruct,
+}
+
+impl Lint {
+    /// The name used to allow the lint
+    pub fn name(&self) -> &'static str {
+        match self {
+            Lint::UnusedVariable => "unused_variable",
+            Lint::UnusedParameter => "unused_parameter",
+            Lint::UnusedResult => "unused_result",
+            Lint::UnreachableCode => "unreachable_code",
+            Lint::DeadAssignment => "dead_assignment",
+            Lint::UnusedFunction => "unused_function",
+            Lint::UnusedStruct => "unused_struct",
+        }
+    }
+
+    pub fn code(&self) -> &'static str {
+        match self {
+            Lint::UnusedVariable => codes::UNUSED_VARIABLE,
+            Lint::UnusedParameter => codes::UNUSED_PARAMETER,
+            Lint::UnusedResult => codes::UNUSED_RESULT,
+            Lint::UnreachableCode => codes::UNREACHABLE_CODE,
+            Lint::DeadAssignment => codes::DEAD_ASSIGNMENT,
+            Lint::UnusedFunction => codes::UNUSED_FUNCTION,
+            Lint::UnusedStruct => codes::UNUSED_STRUCT,
+        }
+    }
+}
+
+/// Find the warnings in a program, once it has been resolved. `reachable` is
+/// the functions that can be called from the entry points, and `globals` the
+/// statics, which can be assigned without being read.
+///
+/// Only code from the main file is checked. Names starting with `_` are never
+/// warned about, like in rust.
+pub(crate) fn lint(
+    prog: &[Declaration],
+    funcs: &HashMap<String, Function>,
+    reachable: &HashSet<String>,
+    globals: &HashSet<String>,
+    file_index_table: &Option<Vec<PathBuf>>,
+) -> Vec<Diagnostic> {
+    let mut linter = Linter {
+        funcs,
+        globals,
+        file_index_table,
+        allows: allows(prog),
+        warnings: Vec::new(),
+    };
+    for func in funcs.values() {
+        if func.extern_func || !in_main_file(&func.code_ref) {
+            continue;
+        }
+        linter.lint_function(func);
+        if !func.pub_func && !reachable.contains(&func.name) {
+            linter.warn(
+                Lint::UnusedFunction,
+                &func.name,
+                &func.code_ref,
+                format!("Function \"{}\" is never used", func.name),
+            );
+        }
+    }
+
+    let used_types = used_types(prog);
+    for decl in prog {
+        if let Declaration::Struct(struct_) = decl {
+            if struct_.extern_struct
+                || struct_.override_struct
+                || !in_main_file(&struct_.code_ref)
+                || used_types.contains(&struct_.name)
+            {
+                continue;
+            }
+            linter.warn(
+                Lint::UnusedStruct,
+                &struct_.name,
+                &struct_.code_ref,
+                format!("Struct \"{}\" is never used", struct_.name),
+            );
+        }
+    }
+    linter.warnings
+}
+
+/// Code from the file given to `parse` or `parse_with_context`, not from the
+/// std lib or an included file
+fn in_main_file(code_ref: &CodeRef) -> bool {
+    code_ref.line.is_some() && code_ref.file_index.unwrap_or(0) == 0
+}
+
+/// The lints each item allows, from the `allow` key in its metadata
+fn allows(prog: &[Declaration]) -> HashMap<String, Vec<String>> {
+    let mut allows = HashMap::new();
+    for decl in prog {
+        if let Declaration::Metadata(head, body) = decl {
+            let name = match head.first() {
+                Some(name) => name,
+                None => continue,
+            };
+            let metadata = match body.parse::<toml::Value>() {
+                Ok(metadata) => metadata,
+                Err(_) => continue,
+            };
+            if let Some(toml::Value::Array(lints)) = metadata.get("allow") {
+                allows
+                    .entry(name.to_string())
+                    .or_insert_with(Vec::new)
+                    .extend(
+                        lints
+                            .iter()
+                            .filter_map(|l| l.as_str())
+                            .map(|l| l.to_string()),
+                    );
+            }
+        }
+    }
+    allows
+}
+
+struct Linter<'a> {
+    funcs: &'a HashMap<String, Function>,
+    globals: &'a HashSet<String>,
+    file_index_table: &'a Option<Vec<PathBuf>>,
+    allows: HashMap<String, Vec<String>>,
+    warnings: Vec<Diagnostic>,
+}
+
+impl<'a> Linter<'a> {
+    /// Add a warning, unless `item` allows it. Methods can be named in
+    /// metadata without their type.
+    fn warn(&mut self, lint: Lint, item: &str, code_ref: &CodeRef, message: String) {
+        self.push(
+            lint,
+            item,
+            Diagnostic::warning(
+                lint.code(),
+                message,
+                Some(Span::from_code_ref(code_ref, self.file_index_table)),
+            ),
+        )
+    }
+
+    fn push(&mut self, lint: Lint, item: &str, mut warning: Diagnostic) {
+        let method = item.rsplit('.').next().unwrap_or(item);
+        let allowed = [item, method].iter().any(|name| {
+            self.allows.get(*name).map_or(false, |allows| {
+                allows.iter().any(|a| a == lint.name() || a == "warnings")
+            })
+        });
+        if !allowed {
+            warning.notes.push(format!(
+                "allow it with `allow = [\"{}\"]` in the metadata for {}",
+                lint.name(),
+                method
+            ));
+            self.warnings.push(warning);
+        }
+    }
+
+    fn lint_function(&mut self, func: &Function) {
+        let mut reads = HashSet::new();
+        let mut closures = HashSet::new();
+        for expr in &func.body {
+            find_reads(expr, &mut reads);
+            find_closures(expr, &mut closures);
+        }
+        let returns: HashSet<&str> = func.returns.iter().map(|a| a.name.as_str()).collect();
+
+        for param in &func.params {
+            if param.name == "self" || param.name.starts_with('_') || reads.contains(&param.name) {
+                continue;
+            }
+            let code_ref = param.expr_type.get_code_ref();
+            let code_ref = if code_ref.line.is_some() {
+                code_ref
+            } else {
+                func.code_ref
+            };
+            self.warn(
+                Lint::UnusedParameter,
+                &func.name,
+                &code_ref,
+                format!("Unused parameter \"{}\"", param.name),
+            );
+        }
+
+        let mut assigned = Vec::new();
+        for expr in &func.body {
+            find_assigned(expr, &mut assigned);
+        }
+        let mut seen = HashSet::new();
+        for (name, code_ref) in assigned {
+            if name.starts_with('_')
+                || reads.contains(name)
+                || returns.contains(name)
+                || self.globals.contains(name)
+                || func.params.iter().any(|p| p.name == name)
+                || !seen.insert(name)
+            {
+                continue;
+            }
+            let mut warning = Diagnostic::warning(
+                Lint::UnusedVariable.code(),
+                format!("Unused variable \"{}\"", name),
+                Some(Span::from_code_ref(code_ref, self.file_index_table)),
+            );
+            warning.suggestions.push(Suggestion {
+                message: format!(
+                    "if this is intentional, prefix it with an underscore: \"_{}\"",
+                    name
+                ),
+                span: Some(Span::from_code_ref(code_ref, self.file_index_table)),
+                replacement: Some(format!("_{}", name)),
+            });
+            self.push(Lint::UnusedVariable, &func.name, warning);
+        }
+
+        let info = BodyInfo {
+            func,
+            reads: &reads,
+            closures: &closures,
+            returns: &returns,
+        };
+        self.lint_body(&info, &func.body, true);
+    }
+
+    /// Check a list of statements, and the bodies inside them
+    fn lint_body(&mut self, info: &BodyInfo, stmts: &[Expr], top_level: bool) {
+        let func_name = &info.func.name;
+        for (i, stmt) in stmts.iter().enumerate() {
+            if let Some(next) = stmts.get(i + 1) {
+                if let Expr::Break { code_ref }
+                | Expr::Continue { code_ref }
+                | Expr::Return { code_ref } = stmt
+                {
+                    let mut warning = Diagnostic::warning(
+                        Lint::UnreachableCode.code(),
+                        "Unreachable code",
+                        Some(Span::from_code_ref(
+                            next.get_code_ref(),
+                            self.file_index_table,
+                        )),
+                    );
+                    warning.secondary_spans.push(
+                        Span::from_code_ref(code_ref, self.file_index_table)
+                            .with_label("any code after this is unreachable"),
+                    );
+                    self.push(Lint::UnreachableCode, func_name, warning);
+                }
+            }
+
+            if let Some((name, code_ref)) = self.unused_result(stmt) {
+                self.warn(
+                    Lint::UnusedResult,
+                    func_name,
+                    code_ref,
+                    format!("The values returned by \"{}\" are never used", name),
+                );
+            }
+
+            if let Expr::Assign { to_exprs, .. } = stmt {
+                for to_expr in to_exprs {
+                    if let Expr::Identifier { code_ref, name } = to_expr {
+                        self.lint_assignment(info, name, code_ref, &stmts[i + 1..], top_level);
+                    }
+                }
+            }
+
+            for body in bodies(stmt) {
+                self.lint_body(info, body, false);
+            }
+        }
+    }
+
+    /// Warn if the value assigned to `name` is overwritten, or reaches the
+    /// end of the function, without being read. Variables that are never read
+    /// at all are already warned about as unused.
+    fn lint_assignment(
+        &mut self,
+        info: &BodyInfo,
+        name: &str,
+        code_ref: &CodeRef,
+        rest: &[Expr],
+        top_level: bool,
+    ) {
+        if name.starts_with('_') || !info.reads.contains(name) || self.globals.contains(name) {
+            return;
+        }
+        let overwritten = match next_use(name, rest, info.closures) {
+            NextUse::Read => return,
+            NextUse::Overwritten(at) => Some(at),
+            NextUse::End if top_level && !info.returns.contains(name) => None,
+            NextUse::End => return,
+        };
+        let mut warning = Diagnostic::warning(
+            Lint::DeadAssignment.code(),
+            format!("Value assigned to \"{}\" is never read", name),
+            Some(Span::from_code_ref(code_ref, self.file_index_table)),
+        );
+        if let Some(at) = overwritten {
+            warning.secondary_spans.push(
+                Span::from_code_ref(at, self.file_index_table)
+                    .with_label("it is overwritten here before it is read"),
+            );
+        }
+        self.push(Lint::DeadAssignment, &info.func.name, warning);
+    }
+
+    /// A call used as a statement, to a function that returns values
+    fn unused_result<'e>(&self, stmt: &'e Expr) -> Option<(&'e str, &'e CodeRef)> {
+        let returns_values = |name: &str| {
+            self.funcs
+                .get(name)
+                .map_or(false, |func| !func.returns.is_empty())
+        };
+        match stmt {
+            Expr::Call {
+                fn_name,
+                code_ref,
+                is_macro: false,
+                ..
+            } if returns_values(fn_name) => Some((fn_name.as_str(), code_ref)),
+            Expr::Binop {
+                op: Binop::DotAccess,
+                rhs,
+                ..
+            } => match &**rhs {
+                Expr::Call {
+                    fn_name, code_ref, ..
+                } => {
+                    // Without types, only warn if every method of that name
+                    // returns values
+                    let mut methods = self.funcs.keys().filter(|name| {
+                        name.rsplit_once('.').map(|(_, m)| m) == Some(fn_name.as_str())
+                    });
+                    let first = methods.next()?;
+                    if returns_values(first) && methods.all(|name| returns_values(name)) {
+                        Some((fn_name.as_str(), code_ref))
+                    } else {
+                        None
+                    }
+                }
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+}
+
+struct BodyInfo<'a> {
+    func: &'a Function,
+    reads: &'a HashSet<String>,
+    closures: &'a HashSet<String>,
+    returns: &'a HashSet<&'a str>,
+}
+
+enum NextUse<'a> {
+    Read,
+    Overwritten(&'a CodeRef),
+    End,
+}
+
+/// What happens next to the value of `name`, in the statements after it is
+/// assigned. Anything that might read it, like control flow that mentions it
+/// or a call to a closure, counts as a read.
+fn next_use<'a>(name: &str, rest: &'a [Expr], closures: &HashSet<String>) -> NextUse<'a> {
+    for stmt in rest {
+        if let Expr::Break { .. } | Expr::Continue { .. } | Expr::Return { .. } = stmt {
+            return NextUse::Read;
+        }
+        let mut refs = Vec::new();
+        let overwritten = match stmt {
+            Expr::Assign {
+                to_exprs,
+                from_exprs,
+                ..
+            } => {
+                for expr in from_exprs {
+                    find_references(expr, &mut refs);
+                }
+                let mut overwritten = None;
+                for to_expr in to_exprs {
+                    match to_expr {
+                        Expr::Identifier { name: n, code_ref } if n == name => {
+                            overwritten = Some(code_ref)
+                        }
+                        Expr::Identifier { .. } => (),
+                        _ => find_references(to_expr, &mut refs),
+                    }
+                }
+                overwritten
+            }
+            _ => {
+                find_references(stmt, &mut refs);
+                None
+            }
+        };
+        if refs.iter().any(|r| r == name || closures.contains(r)) {
+            return NextUse::Read;
+        }
+        if let Some(at) = overwritten {
+            return NextUse::Overwritten(at);
+        }
+    }
+    NextUse::End
+}
+
+/// The statement lists directly inside an expression
+fn bodies(expr: &Expr) -> Vec<&[Expr]> {
+    match expr {
+        Expr::IfThen { then_body, .. } => vec![then_body.as_slice()],
+        Expr::IfElse {
+            then_body,
+            else_body,
+            ..
+        } => vec![then_body.as_slice(), else_body.as_slice()],
+        Expr::IfThenElseIf { expr_bodies, .. } => {
+            expr_bodies.iter().map(|(_, b)| b.as_slice()).collect()
+        }
+        Expr::IfThenElseIfElse {
+            expr_bodies,
+            else_body,
+            ..
+        } => expr_bodies
+            .iter()
+            .map(|(_, b)| b.as_slice())
+            .chain(Some(else_body.as_slice()))
+            .collect(),
+        Expr::WhileLoop {
+            iter_body,
+            loop_body,
+            ..
+        } => iter_body
+            .iter()
+            .map(|b| b.as_slice())
+            .chain(Some(loop_body.as_slice()))
+            .collect(),
+        Expr::Block { block, .. } => vec![block.as_slice()],
+        Expr::Match { fields, .. } => fields
+            .iter()
+            .filter_map(|f| match &f.expr {
+                Expr::Block { block, .. } => Some(block.as_slice()),
+                _ => None,
+            })
+            .collect(),
+        Expr::Declaration {
+            declaration: Declaration::Function(closure),
+            ..
+        } => vec![closure.body.as_slice()],
+        _ => vec![],
+    }
+}
+
+/// The expressions directly inside an expression, including the statements
+/// of its bodies
+fn children(expr: &Expr) -> Vec<&Expr> {
+    let mut children: Vec<&Expr> = match expr {
+        Expr::LiteralArray { exprs, .. } => exprs.iter().collect(),
+        Expr::Binop { lhs, rhs, .. } | Expr::Compare { lhs, rhs, .. } => vec![&**lhs, &**rhs],
+        Expr::Unaryop { expr, .. } | Expr::Parentheses { expr, .. } => vec![&**expr],
+        Expr::IfThen { condition, .. }
+        | Expr::IfElse { condition, .. }
+        | Expr::WhileLoop { condition, .. } => vec![&**condition],
+        Expr::IfThenElseIf { expr_bodies, .. } | Expr::IfThenElseIfElse { expr_bodies, .. } => {
+            expr_bodies.iter().map(|(c, _)| c).collect()
+        }
+        Expr::Assign {
+            to_exprs,
+            from_exprs,
+            ..
+        } => to_exprs.iter().chain(from_exprs).collect(),
+        Expr::NewStruct { fields, base, .. } => fields
+            .iter()
+            .map(|f| &f.expr)
+            .chain(base.iter().map(|b| &**b))
+            .collect(),
+        Expr::Match { expr_arg, .. } => vec![&**expr_arg],
+        Expr::Call { args, .. } => args.iter().collect(),
+        Expr::ArrayAccess { expr, idx_expr, .. } => vec![&**expr, &**idx_expr],
+        _ => vec![],
+    };
+    for body in bodies(expr) {
+        children.extend(body);
+    }
+    children
+}
+
+/// The names an expression reads: variables, statics and the functions and
+/// closures it calls. Assigning to a variable isn't reading it, and the field
+/// name in `a.b` isn't a variable.
+#[allow(deprecated)]
+fn find_reads(expr: &Expr, reads: &mut HashSet<String>) {
+    match expr {
+        Expr::Identifier { name, .. } | Expr::GlobalDataAddr { name, .. } => {
+            reads.insert(name.to_string());
+        }
+        Expr::Call { fn_name, args, .. } => {
+            reads.insert(fn_name.to_string());
+            for arg in args {
+                find_reads(arg, reads)
+            }
+        }
+        Expr::Binop {
+            op: Binop::DotAccess,
+            lhs,
+            rhs,
+            ..
+        } => {
+            find_reads(lhs, reads);
+            if let Expr::Call { args, .. } = &**rhs {
+                for arg in args {
+                    find_reads(arg, reads)
+                }
+            }
+        }
+        Expr::Assign {
+            to_exprs,
+            from_exprs,
+            ..
+        } => {
+            for to_expr in to_exprs {
+                if let Expr::Identifier { .. } = to_expr {
+                    continue;
+                }
+                find_reads(to_expr, reads)
+            }
+            for from_expr in from_exprs {
+                find_reads(from_expr, reads)
+            }
+        }
+        _ => {
+            for child in children(expr) {
+                find_reads(child, reads)
+            }
+        }
+    }
+}
+
+/// The variables assigned in a function, outside of its closures, with where
+/// each assignment is
+fn find_assigned<'a>(expr: &'a Expr, assigned: &mut Vec<(&'a str, &'a CodeRef)>) {
+    match expr {
+        Expr::Declaration { .. } => (),
+        Expr::Assign { to_exprs, .. } => {
+            for to_expr in to_exprs {
+                if let Expr::Identifier { name, code_ref } = to_expr {
+                    assigned.push((name, code_ref))
+                }
+            }
+        }
+        _ => {
+            for child in children(expr) {
+                find_assigned(child, assigned)
+            }
+        }
+    }
+}
+
+fn find_closures(expr: &Expr, closures: &mut HashSet<String>) {
+    if let Expr::Declaration {
+        declaration: Declaration::Function(closure),
+        ..
+    } = expr
+    {
+        closures.insert(closure.name.to_string());
+    }
+    for child in children(expr) {
+        find_closures(child, closures)
+    }
+}
+
+/// The names of every type that is used, with the parts of `Type::member`
+/// names, which can name a struct
+fn used_types(prog: &[Declaration]) -> HashSet<String> {
+    fn add_type(ty: &ExprType, used: &mut HashSet<String>) {
+        match ty {
+            ExprType::Struct(_, name) => {
+                used.extend(name.split("::").map(|s| s.to_string()));
+                used.insert(name.to_string());
+            }
+            ExprType::Array(_, ty, _) | ExprType::Ref(_, ty) => add_type(ty, used),
+            ExprType::Tuple(_, types) => {
+                for ty in types {
+                    add_type(ty, used)
+                }
+            }
+            _ => (),
+        }
+    }
+    fn add_expr(expr: &Expr, used: &mut HashSet<String>) {
+        match expr {
+            Expr::NewStruct { name, .. } => {
+                used.insert(name.to_string());
+            }
+            Expr::Identifier { name, .. } | Expr::Call { fn_name: name, .. } => {
+                if let Some((ty, _)) = name.rsplit_once("::") {
+                    used.extend(ty.split("::").map(|s| s.to_string()));
+                }
+            }
+            Expr::Declaration {
+                declaration: Declaration::Function(closure),
+                ..
+            } => {
+                for arg in closure.params.iter().chain(&closure.returns) {
+                    add_type(&arg.expr_type, used)
+                }
+            }
+            _ => (),
+        }
+        for child in children(expr) {
+            add_expr(child, used)
+        }
+    }
+
+    let mut used = HashSet::new();
+    for decl in prog {
+        match decl {
+            Declaration::Function(func) => {
+                for arg in func.params.iter().chain(&func.returns) {
+                    add_type(&arg.expr_type, &mut used)
+                }
+                for expr in &func.body {
+                    add_expr(expr, &mut used)
+                }
+            }
+            Declaration::Struct(struct_) => {
+                // A struct that holds references to itself isn't used by that
+                let mut fields_use = HashSet::new();
+                for field in &struct_.fields {
+                    add_type(&field.expr_type, &mut fields_use);
+                    if let Some(default) = &field.default {
+                        add_expr(default, &mut fields_use)
+                    }
+                }
+                fields_use.remove(&struct_.name);
+                used.extend(fields_use);
+            }
+            Declaration::Constant(constant) => {
+                add_type(&constant.expr_type, &mut used);
+                add_expr(&constant.expr, &mut used)
+            }
+            Declaration::Static(static_) => {
+                add_type(&static_.expr_type, &mut used);
+                add_expr(&static_.expr, &mut used)
+            }
+            _ => (),
+        }
+    }
+    used
+}
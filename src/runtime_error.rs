@@ -0,0 +1,272 @@
+use std::cell::Cell;
+use std::fmt::Display;
+use std::path::PathBuf;
+use std::slice;
+
+use crate::frontend::CodeRef;
+
+/// What compiled code does when it hits a runtime error, like an index out of
+/// bounds.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum RuntimeErrorMode {
+    /// Print the error's message, location and call stack to stderr and
+    /// abort the process. Compiled code can't unwind back through the host.
+    Panic,
+    /// Record the error in the JIT's error slot and return from every sarus
+    /// function back to the host. See `JIT::call_checked`.
+    Return,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum RuntimeErrorCode {
+    /// `panic` was called from sarus code
+    Panic = 1,
+    OutOfBounds = 2,
+    /// A field was accessed on an enum that is set to a different field
+    EnumField = 3,
+    DeepStackOverflow = 4,
+    StackOverflow = 5,
+    /// Integer overflow, with overflow checks on
+    Overflow = 6,
+    /// Integer division by zero, with overflow checks on
+    DivideByZero = 7,
+}
+
+impl RuntimeErrorCode {
+    pub fn from_i64(code: i64) -> Option<Self> {
+        Some(match code {
+            1 => RuntimeErrorCode::Panic,
+            2 => RuntimeErrorCode::OutOfBounds,
+            3 => RuntimeErrorCode::EnumField,
+            4 => RuntimeErrorCode::DeepStackOverflow,
+            5 => RuntimeErrorCode::StackOverflow,
+            6 => RuntimeErrorCode::Overflow,
+            7 => RuntimeErrorCode::DivideByZero,
+            _ => return None,
+        })
+    }
+}
+
+/// An error raised by compiled code. With `RuntimeErrorMode::Return` it is
+/// returned to the host, with `RuntimeErrorMode::Panic` its Display is
+/// printed before aborting.
+#[derive(Debug, Clone)]
+pub struct RuntimeError {
+    pub code: RuntimeErrorCode,
+    pub message: String,
+    /// The function the error happened in. Inlined functions report
+    /// themselves, not the function they were inlined into.
+    pub function: String,
+    pub code_ref: CodeRef,
+    /// The file from the JIT's file index table, if the code came from one
+    pub file: Option<PathBuf>,
+    /// The index and length, for bounds check failures
+    pub index: Option<i64>,
+    pub len: Option<i64>,
+    /// The sarus functions that were being called, outermost first. Only
+    /// kept with `JIT::set_call_stack_tracking`.
+    pub call_stack: Vec<String>,
+}
+
+impl RuntimeError {
+    /// Where the error happened, as file:line:column
+    pub fn location(&self) -> String {
+        let file = self
+            .file
+            .as_ref()
+            .map_or("".to_string(), |file| file.to_string_lossy().to_string());
+        match (self.code_ref.line, self.code_ref.column) {
+            (Some(line), Some(column)) => format!("{}:{}:{}", file, line, column),
+            (Some(line), None) => format!("{}:{}", file, line),
+            _ => format!("{}:pos {}", file, self.code_ref.pos),
+        }
+    }
+}
+
+impl Display for RuntimeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:?}: {}", self.code, self.message)?;
+        if let (Some(index), Some(len)) = (self.index, self.len) {
+            write!(f, ": the len is {} but the index is {}", len, index)?;
+        }
+        write!(f, " in {} at {}", self.function, self.location())?;
+        if !self.call_stack.is_empty() {
+            write!(f, "\ncall stack:")?;
+            for function in self.call_stack.iter().rev() {
+                write!(f, "\n    {}", function)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for RuntimeError {}
+
+/// Errors that are reported without stopping are only kept up to this
+/// many, until the host takes them.
+pub const MAX_REPORTED_ERRORS: usize = 64;
+
+/// How deep the call stack is tracked. Deeper calls are still counted, but
+/// are left out of the error's call stack.
+pub const MAX_CALL_STACK: usize = 64;
+
+/// Owned by the JIT, compiled code checks `code` after each call and returns
+/// if it is not 0. With call stack tracking, compiled code pushes the name of
+/// each function it enters onto `call_stack`, and pops it on return.
+#[repr(C)]
+pub(crate) struct ErrorSlot {
+    pub code: i64,
+    pub call_depth: i64,
+    // One extra entry, that calls past MAX_CALL_STACK write over
+    pub call_stack: [*const u8; MAX_CALL_STACK + 1],
+    pub error: Option<RuntimeError>,
+    pub reported: Vec<RuntimeError>,
+    pub file_index_table: Option<Vec<PathBuf>>,
+    /// Cleared while static tables are initialized at compile time, so
+    /// errors there fail the compile instead of aborting
+    pub abort_on_error: bool,
+}
+
+impl ErrorSlot {
+    pub fn new(file_index_table: Option<Vec<PathBuf>>) -> Self {
+        ErrorSlot {
+            code: 0,
+            call_depth: 0,
+            call_stack: [std::ptr::null(); MAX_CALL_STACK + 1],
+            error: None,
+            reported: Vec::new(),
+            file_index_table,
+            abort_on_error: true,
+        }
+    }
+}
+
+/// Built on the stack by compiled code where the error happens. Every field
+/// is 8 bytes, see `FunctionTranslator::error_site`.
+#[repr(C)]
+pub(crate) struct ErrorSite {
+    pub code: i64,
+    pub message: *const u8,
+    pub message_len: i64,
+    /// Static data holding the function name, see `read_name`
+    pub function: *const u8,
+    pub pos: i64,
+    /// 0 if unknown
+    pub line: i64,
+    /// 0 if unknown
+    pub column: i64,
+    /// -1 if unknown
+    pub file_index: i64,
+    pub has_index: i64,
+    pub index: i64,
+    pub len: i64,
+}
+
+/// Read a function name from the static data compiled code keeps it in: the
+/// length as a u64, then the bytes.
+unsafe fn read_name(name: *const u8) -> String {
+    if name.is_null() {
+        return "".to_string();
+    }
+    let len = *(name as *const u64) as usize;
+    let bytes = slice::from_raw_parts(name.add(8), len);
+    String::from_utf8_lossy(bytes).to_string()
+}
+
+fn runtime_error(slot: &ErrorSlot, site: &ErrorSite) -> RuntimeError {
+    let message = unsafe { slice::from_raw_parts(site.message, site.message_len as usize) };
+    let file_index = if site.file_index >= 0 {
+        Some(site.file_index as u64)
+    } else {
+        None
+    };
+    let file = file_index.and_then(|file_index| {
+        slot.file_index_table
+            .as_ref()
+            .and_then(|table| table.get(file_index as usize).cloned())
+    });
+    let depth = (slot.call_depth.max(0) as usize).min(MAX_CALL_STACK);
+    RuntimeError {
+        code: RuntimeErrorCode::from_i64(site.code).unwrap_or(RuntimeErrorCode::Panic),
+        message: String::from_utf8_lossy(message).to_string(),
+        function: unsafe { read_name(site.function) },
+        code_ref: CodeRef {
+            pos: site.pos as usize,
+            line: if site.line > 0 {
+                Some(site.line as usize)
+            } else {
+                None
+            },
+            column: if site.column > 0 {
+                Some(site.column as usize)
+            } else {
+                None
+            },
+            file_index,
+            ..Default::default()
+        },
+        file,
+        index: if site.has_index != 0 {
+            Some(site.index)
+        } else {
+            None
+        },
+        len: if site.has_index != 0 {
+            Some(site.len)
+        } else {
+            None
+        },
+        call_stack: slot.call_stack[..depth]
+            .iter()
+            .map(|name| unsafe { read_name(*name) })
+            .collect(),
+    }
+}
+
+/// Called by compiled code to record an error in the slot. Only the first
+/// error is kept, until the host takes it.
+pub(crate) extern "C" fn record_runtime_error(slot: *mut ErrorSlot, site: *const ErrorSite) {
+    let slot = unsafe { &mut *slot };
+    if slot.code != 0 {
+        return;
+    }
+    let site = unsafe { &*site };
+    slot.code = site.code;
+    slot.error = Some(runtime_error(slot, site));
+}
+
+/// Called by compiled code for errors that it recovers from itself, like an
+/// index that was clamped into bounds.
+pub(crate) extern "C" fn report_runtime_error(slot: *mut ErrorSlot, site: *const ErrorSite) {
+    let slot = unsafe { &mut *slot };
+    if slot.reported.len() >= MAX_REPORTED_ERRORS {
+        return;
+    }
+    let error = runtime_error(slot, unsafe { &*site });
+    slot.reported.push(error);
+}
+
+thread_local! {
+    // Where the native stack was when the host called into compiled code on
+    // this thread, 0 when no compiled code is running
+    static STACK_BASE: Cell<u64> = Cell::new(0);
+}
+
+/// Called by compiled code with a stack budget, for the address of this
+/// thread's stack base
+pub(crate) extern "C" fn stack_base_address() -> *mut u64 {
+    STACK_BASE.with(|base| base.as_ptr())
+}
+
+/// Called by compiled code with `RuntimeErrorMode::Panic`. Unwinding out of
+/// an `extern "C"` function is undefined behavior, so this aborts instead.
+pub(crate) extern "C" fn abort_runtime_error(slot_ptr: *mut ErrorSlot, site: *const ErrorSite) {
+    let slot = unsafe { &*slot_ptr };
+    if !slot.abort_on_error {
+        record_runtime_error(slot_ptr, site);
+        return;
+    }
+    let error = runtime_error(slot, unsafe { &*site });
+    eprintln!("{}", error);
+    std::process::abort();
+}
@@ -1,13 +1,16 @@
 use std::collections::{HashMap, HashSet};
 use std::slice;
 
-use crate::frontend::Expr;
+use crate::diagnostics::LocatedError;
+use crate::frontend::{Binop, Cmp, Constant, Expr, Unaryop};
 use crate::function_translator::SVariable;
 use crate::jit::{ArraySized, Env, SValue, StructDef};
-use crate::validator::{bool_t, f32_t, i64_t, str_t, u8_t, ArraySizedExpr, ExprType, TypeError};
+use crate::validator::{
+    bool_t, f32_t, i64_t, ref_target_type, str_t, u8_t, ArraySizedExpr, ExprType, TypeError,
+};
 use crate::{
     decl,
-    frontend::{Arg, CodeRef, Declaration, Function},
+    frontend::{declared_namespaces, Arg, CodeRef, Declaration, Function},
 };
 use crate::{hashmap, make_decl};
 use cranelift::frontend::FunctionBuilder;
@@ -160,7 +163,6 @@ pub fn append_std_math(
     decl!(prog, jb, "f32.recip",            f32::recip,            (f32_t()),                   (f32_t()));
     decl!(prog, jb, "f32.to_degrees",       f32::to_degrees,       (f32_t()),                   (f32_t()));
     decl!(prog, jb, "f32.to_radians",       f32::to_radians,       (f32_t()),                   (f32_t()));
-    decl!(prog, jb, "f32.cosh",             f32::cosh,             (f32_t()),                   (f32_t()));
     /*TODO
     pub fn to_bits(self) -> u64
     pub fn from_bits(v: u64) -> f32
@@ -221,6 +223,18 @@ pub fn append_std(prog: &mut Vec<Declaration>, jit_builder: &mut JITBuilder) {
         ));
     }
 
+    for (ty, t) in [("i64", i64_t()), ("u8", u8_t())] {
+        for kind in ["wrapping", "checked", "saturating"] {
+            for op in ["add", "sub", "mul"] {
+                prog.push(make_decl(
+                    &format!("{}.{}_{}", ty, kind, op),
+                    vec![("x", t.clone()), ("y", t.clone())],
+                    vec![("z", t.clone())],
+                ));
+            }
+        }
+    }
+
 
     decl!(prog, jb, "f32.print",           f32_print,           (f32_t()),                 ());
     decl!(prog, jb, "i64.print",           i64_print,           (i64_t()),                 ());
@@ -303,6 +317,17 @@ pub fn append_std_strings(prog: &mut Vec<Declaration>, jit_builder: &mut JITBuil
     jb.symbol("[u8].rfind", str_rfind as *const u8);
 }
 
+/// Element type of the slice returned by `include_bytes("path")`, `include_str`,
+/// `include_f32` and `include_i64`. The file is embedded at compile time.
+pub(crate) fn include_elem_type(fn_name: &str) -> Option<ExprType> {
+    match fn_name {
+        "include_bytes" | "include_str" => Some(u8_t()),
+        "include_f32" => Some(f32_t()),
+        "include_i64" => Some(i64_t()),
+        _ => None,
+    }
+}
+
 pub(crate) fn check_core_generics(fn_name: &str, impl_val: Option<SValue>) -> bool {
     if HashSet::from(["push", "pop", "len", "cap", "append"]).contains(fn_name) {
         if let Some(SValue::Array(_sval, ArraySized::Slice)) = &impl_val {
@@ -314,6 +339,29 @@ pub(crate) fn check_core_generics(fn_name: &str, impl_val: Option<SValue>) -> bo
             return true;
         }
     }
+    if HashSet::from(["offset", "address"]).contains(fn_name) {
+        if let Some(SValue::Ref(_sval)) = &impl_val {
+            return true;
+        }
+    }
+    if fn_name == "ref" {
+        if let Some(SValue::Struct(..)) = &impl_val {
+            return true;
+        }
+    }
+    if fn_name == "copy" {
+        if let Some(SValue::Array(_sval, ArraySized::Fixed(..)) | SValue::Struct(..)) = &impl_val {
+            return true;
+        }
+    }
+    if fn_name == "copy_from" {
+        if let Some(
+            SValue::Array(_sval, ArraySized::Fixed(..) | ArraySized::Slice) | SValue::Struct(..),
+        ) = &impl_val
+        {
+            return true;
+        }
+    }
     HashSet::from(["unsized"]).contains(fn_name)
 }
 
@@ -329,7 +377,7 @@ pub(crate) fn validate_core_generics(
         if let Some(ExprType::Array(code_ref, expr_type, ArraySizedExpr::Slice)) = lhs_val {
             if args.len() != 1 {
                 return Err(TypeError::TupleLengthMismatch {
-                    c: code_ref.s(&env.file_idx),
+                    c: *code_ref,
                     actual: args.len(),
                     expected: 1,
                 });
@@ -337,7 +385,7 @@ pub(crate) fn validate_core_generics(
             let targ = ExprType::of(&args[0], env, fn_name, variables)?;
             if **expr_type != targ {
                 return Err(TypeError::TypeMismatchSpecific {
-                    c: code_ref.s(&env.file_idx),
+                    c: *code_ref,
                     s: format!(
                         "function {} expected parameter {} to be of type {} but type {} was found",
                         fn_name, 1, expr_type, targ
@@ -351,7 +399,7 @@ pub(crate) fn validate_core_generics(
         if let Some(ExprType::Array(code_ref, expr_type, ArraySizedExpr::Slice)) = lhs_val {
             if !args.is_empty() {
                 return Err(TypeError::TupleLengthMismatch {
-                    c: code_ref.s(&env.file_idx),
+                    c: *code_ref,
                     actual: args.len(),
                     expected: 0,
                 });
@@ -369,7 +417,7 @@ pub(crate) fn validate_core_generics(
             {
                 if !args.is_empty() {
                     return Err(TypeError::TupleLengthMismatch {
-                        c: code_ref.s(&env.file_idx),
+                        c: *code_ref,
                         actual: args.len(),
                         expected: 0,
                     });
@@ -383,7 +431,7 @@ pub(crate) fn validate_core_generics(
             if let ExprType::Array(code_ref, _expr_type, ArraySizedExpr::Slice) = lhs_val {
                 if args.len() != 0 {
                     return Err(TypeError::TupleLengthMismatch {
-                        c: code_ref.s(&env.file_idx),
+                        c: *code_ref,
                         actual: args.len(),
                         expected: 0,
                     });
@@ -397,7 +445,7 @@ pub(crate) fn validate_core_generics(
             if let ExprType::Array(code_ref, expr_type, ArraySizedExpr::Slice) = lhs_val {
                 if args.len() != 1 {
                     return Err(TypeError::TupleLengthMismatch {
-                        c: code_ref.s(&env.file_idx),
+                        c: *code_ref,
                         actual: args.len(),
                         expected: 1,
                     });
@@ -408,7 +456,7 @@ pub(crate) fn validate_core_generics(
                 {
                     if *expr_type != *arg_expr_type {
                         return Err(TypeError::TypeMismatchSpecific {
-                                        c: code_ref.s(&env.file_idx),
+                                        c: code_ref,
                                         s: format!("function {} expected parameter {} to be slice or fixed array of type {} but type {} was found", fn_name, 1, lhs_val, targ)
                                     });
                     }
@@ -416,7 +464,7 @@ pub(crate) fn validate_core_generics(
                 }
                 if *lhs_val != targ {
                     return Err(TypeError::TypeMismatchSpecific {
-                                    c: code_ref.s(&env.file_idx),
+                                    c: *code_ref,
                                     s: format!("function {} expected parameter {} to be slice or fixed array of type {} but type {} was found", fn_name, 1, lhs_val, targ)
                                 });
                 }
@@ -424,10 +472,112 @@ pub(crate) fn validate_core_generics(
             }
         }
     }
+    if fn_name == "offset" {
+        if let Some(ExprType::Ref(code_ref, expr_type)) = lhs_val {
+            if args.len() != 1 {
+                return Err(TypeError::TupleLengthMismatch {
+                    c: *code_ref,
+                    actual: args.len(),
+                    expected: 1,
+                });
+            }
+            let targ = ExprType::of(&args[0], env, fn_name, variables)?;
+            if let ExprType::I64(_code_ref) = targ {
+                return Ok(Some(ExprType::Ref(*code_ref, expr_type.clone())));
+            }
+            return Err(TypeError::TypeMismatchSpecific {
+                c: *code_ref,
+                s: format!(
+                    "function {} expected parameter {} to be of type i64 but type {} was found",
+                    fn_name, 1, targ
+                ),
+            });
+        }
+    }
+    if fn_name == "address" {
+        if let Some(ExprType::Ref(code_ref, _expr_type)) = lhs_val {
+            if !args.is_empty() {
+                return Err(TypeError::TupleLengthMismatch {
+                    c: *code_ref,
+                    actual: args.len(),
+                    expected: 0,
+                });
+            }
+            return Ok(Some(ExprType::Address(*code_ref)));
+        }
+    }
+    if fn_name == "ref" {
+        if let Some(lhs_val) = lhs_val {
+            if let ExprType::Struct(code_ref, _name) = lhs_val {
+                if !args.is_empty() {
+                    return Err(TypeError::TupleLengthMismatch {
+                        c: *code_ref,
+                        actual: args.len(),
+                        expected: 0,
+                    });
+                }
+                return Ok(Some(ExprType::Ref(*code_ref, Box::new(lhs_val.clone()))));
+            }
+        }
+    }
+    if fn_name == "copy" {
+        if let Some(lhs_val) = lhs_val {
+            if let ExprType::Array(code_ref, _, ArraySizedExpr::Fixed(_))
+            | ExprType::Struct(code_ref, _) = lhs_val
+            {
+                if !args.is_empty() {
+                    return Err(TypeError::TupleLengthMismatch {
+                        c: *code_ref,
+                        actual: args.len(),
+                        expected: 0,
+                    });
+                }
+                return Ok(Some(lhs_val.clone()));
+            }
+        }
+    }
+    if fn_name == "copy_from" {
+        if let Some(lhs_val) = lhs_val {
+            if let ExprType::Array(code_ref, _, ArraySizedExpr::Fixed(_) | ArraySizedExpr::Slice)
+            | ExprType::Struct(code_ref, _) = lhs_val
+            {
+                if args.len() != 1 {
+                    return Err(TypeError::TupleLengthMismatch {
+                        c: *code_ref,
+                        actual: args.len(),
+                        expected: 1,
+                    });
+                }
+                let targ = ExprType::of(&args[0], env, fn_name, variables)?;
+                let compatible = match (lhs_val, &targ) {
+                    //fixed arrays need to be the same length, slices are checked at runtime
+                    (
+                        ExprType::Array(_, a, ArraySizedExpr::Fixed(_)),
+                        ExprType::Array(_, b, ArraySizedExpr::Slice),
+                    )
+                    | (
+                        ExprType::Array(_, a, ArraySizedExpr::Slice),
+                        ExprType::Array(_, b, ArraySizedExpr::Fixed(_) | ArraySizedExpr::Slice),
+                    ) => a == b,
+                    _ => *lhs_val == targ,
+                };
+                if !compatible {
+                    return Err(TypeError::TypeMismatchSpecific {
+                        c: *code_ref,
+                        s: format!(
+                            "function {} expected parameter {} to be of type {} but type {} was found",
+                            fn_name, 1, lhs_val, targ
+                        ),
+                    });
+                }
+                return Ok(Some(ExprType::Void(*code_ref)));
+            }
+        }
+    }
     if fn_name == "unsized" {
         if args.len() != 0 {
             return Err(TypeError::TupleLengthMismatch {
-                c: code_ref.s(&env.file_idx),
+                c: *code_ref,
                 actual: args.len(),
                 expected: 0,
             });
@@ -446,7 +596,7 @@ pub(crate) fn validate_core_generics(
                     ArraySizedExpr::Unsized,
                 ))),
                 sv => Err(TypeError::TypeMismatchSpecific {
-                    c: code_ref.s(&env.file_idx),
+                    c: *code_ref,
                     s: format!("function unsized does not support {}", sv),
                 }),
             };
@@ -573,3 +723,297 @@ pub fn get_constants(struct_map: &HashMap<String, StructDef>) -> HashMap<String,
     }
     constants
 }
+
+/// Evaluate the `const` declarations in the program at compile time.
+/// Initializers can use literals, other consts, the std constants, arithmetic,
+/// comparisons and pure std math methods like `(2.0).sqrt()`.
+pub(crate) fn eval_declared_constants(
+    prog: &[Declaration],
+    std_constants: &HashMap<String, SConstant>,
+) -> anyhow::Result<HashMap<String, SConstant>> {
+    let mut decls = HashMap::new();
+    for decl in prog {
+        if let Declaration::Constant(constant) = decl {
+            if let Some(other) = decls.insert(constant.name.as_str(), constant) {
+                anyhow::bail!(LocatedError::new(
+                    &constant.code_ref,
+                    format!("constant {} already exists", constant.name)
+                )
+                .with_related(&other.code_ref, "previously defined here"))
+            }
+        }
+    }
+    let mut evaluator = ConstEvaluator {
+        decls,
+        std_constants,
+        values: HashMap::new(),
+        stack: Vec::new(),
+    };
+    for decl in prog {
+        if let Declaration::Constant(constant) = decl {
+            evaluator.eval_constant(&constant.name)?;
+        }
+    }
+    Ok(evaluator.values)
+}
+
+/// Evaluate an expression at compile time using only the given constants.
+pub(crate) fn eval_const_expr(
+    expr: &Expr,
+    constants: &HashMap<String, SConstant>,
+) -> anyhow::Result<SConstant> {
+    ConstEvaluator {
+        decls: HashMap::new(),
+        std_constants: constants,
+        values: HashMap::new(),
+        stack: Vec::new(),
+    }
+    .eval_expr(expr)
+}
+
+/// Add the evaluated `const` declarations to the constants. Names like
+/// `Type::NAME` are associated constants of that type.
+pub(crate) fn add_declared_constants(
+    prog: &[Declaration],
+    struct_map: &HashMap<String, StructDef>,
+    declared_constants: &HashMap<String, SConstant>,
+    constants: &mut HashMap<String, SConstant>,
+) -> anyhow::Result<()> {
+    let namespaces = declared_namespaces(prog);
+    for decl in prog {
+        if let Declaration::Constant(constant) = decl {
+            let code_ref = &constant.code_ref;
+            if let Some((type_name, _)) = constant.name.rsplit_once("::") {
+                if !namespaces.contains(type_name)
+                    && ref_target_type(type_name, code_ref, struct_map).is_none()
+                {
+                    anyhow::bail!(LocatedError::new(
+                        code_ref,
+                        format!(
+                            "constant {} is associated with unknown type {}",
+                            constant.name, type_name
+                        )
+                    ))
+                }
+            }
+            if constants.contains_key(&constant.name) {
+                anyhow::bail!(LocatedError::new(
+                    code_ref,
+                    format!("constant {} already exists", constant.name)
+                ))
+            }
+            constants.insert(
+                constant.name.to_string(),
+                declared_constants[&constant.name],
+            );
+        }
+    }
+    Ok(())
+}
+
+struct ConstEvaluator<'a> {
+    decls: HashMap<&'a str, &'a Constant>,
+    std_constants: &'a HashMap<String, SConstant>,
+    values: HashMap<String, SConstant>,
+    stack: Vec<String>, //consts currently being evaluated, to catch cycles
+}
+
+impl<'a> ConstEvaluator<'a> {
+    fn eval_constant(&mut self, name: &str) -> anyhow::Result<SConstant> {
+        if let Some(val) = self.values.get(name) {
+            return Ok(*val);
+        }
+        let constant = self.decls[name];
+        if self.stack.iter().any(|n| n == name) {
+            anyhow::bail!(LocatedError::new(
+                &constant.code_ref,
+                format!("constant {} depends on itself", constant.name)
+            ))
+        }
+        self.stack.push(name.to_string());
+        let val = self.eval_expr(&constant.expr)?;
+        self.stack.pop();
+        if val.expr_type(None) != constant.expr_type {
+            anyhow::bail!(LocatedError::new(
+                &constant.code_ref,
+                format!(
+                    "constant {} declared as {} but is {}",
+                    constant.name,
+                    constant.expr_type,
+                    val.expr_type(None)
+                )
+            ))
+        }
+        self.values.insert(name.to_string(), val);
+        Ok(val)
+    }
+
+    fn eval_expr(&mut self, expr: &Expr) -> anyhow::Result<SConstant> {
+        let code_ref = expr.get_code_ref();
+        Ok(match expr {
+            Expr::LiteralFloat { val, .. } => SConstant::F32(*val),
+            Expr::LiteralInt { val, .. } => SConstant::I64(*val),
+            Expr::LiteralBool { val, .. } => SConstant::Bool(*val),
+            Expr::Parentheses { expr, .. } => self.eval_expr(expr)?,
+            Expr::Identifier { name, .. } => {
+                if self.decls.contains_key(name.as_str()) {
+                    self.eval_constant(name)?
+                } else if let Some(val) = self.std_constants.get(name) {
+                    *val
+                } else {
+                    anyhow::bail!(LocatedError::new(
+                        code_ref,
+                        format!("{} is not a constant", name)
+                    ))
+                }
+            }
+            Expr::Unaryop { op, expr, .. } => match (op, self.eval_expr(expr)?) {
+                (Unaryop::Negative, SConstant::F32(v)) => SConstant::F32(-v),
+                (Unaryop::Negative, SConstant::I64(v)) => {
+                    SConstant::I64(checked(code_ref, v.checked_neg())?)
+                }
+                (Unaryop::Not, SConstant::Bool(v)) => SConstant::Bool(!v),
+                (op, v) => anyhow::bail!(LocatedError::new(
+                    code_ref,
+                    format!("operation {} not supported on {:?}", op, v)
+                )),
+            },
+            Expr::Binop {
+                op: Binop::DotAccess,
+                lhs,
+                rhs,
+                ..
+            } => match &**rhs {
+                Expr::Call { fn_name, args, .. } => {
+                    let mut arg_vals = vec![self.eval_expr(lhs)?];
+                    for arg in args {
+                        arg_vals.push(self.eval_expr(arg)?);
+                    }
+                    eval_const_method(code_ref, fn_name, &arg_vals)?
+                }
+                _ => anyhow::bail!(LocatedError::new(
+                    code_ref,
+                    format!("{} can not be evaluated at compile time", expr)
+                )),
+            },
+            Expr::Binop { op, lhs, rhs, .. } => {
+                match (op, self.eval_expr(lhs)?, self.eval_expr(rhs)?) {
+                    (Binop::Add, SConstant::F32(a), SConstant::F32(b)) => SConstant::F32(a + b),
+                    (Binop::Sub, SConstant::F32(a), SConstant::F32(b)) => SConstant::F32(a - b),
+                    (Binop::Mul, SConstant::F32(a), SConstant::F32(b)) => SConstant::F32(a * b),
+                    (Binop::Div, SConstant::F32(a), SConstant::F32(b)) => SConstant::F32(a / b),
+                    (Binop::Add, SConstant::I64(a), SConstant::I64(b)) => {
+                        SConstant::I64(checked(code_ref, a.checked_add(b))?)
+                    }
+                    (Binop::Sub, SConstant::I64(a), SConstant::I64(b)) => {
+                        SConstant::I64(checked(code_ref, a.checked_sub(b))?)
+                    }
+                    (Binop::Mul, SConstant::I64(a), SConstant::I64(b)) => {
+                        SConstant::I64(checked(code_ref, a.checked_mul(b))?)
+                    }
+                    (Binop::Div, SConstant::I64(a), SConstant::I64(b)) => {
+                        SConstant::I64(checked(code_ref, a.checked_div(b))?)
+                    }
+                    (Binop::LogicalAnd, SConstant::Bool(a), SConstant::Bool(b)) => {
+                        SConstant::Bool(a && b)
+                    }
+                    (Binop::LogicalOr, SConstant::Bool(a), SConstant::Bool(b)) => {
+                        SConstant::Bool(a || b)
+                    }
+                    (op, a, b) => anyhow::bail!(LocatedError::new(
+                        code_ref,
+                        format!("operation {:?} {} {:?} not supported", a, op, b)
+                    )),
+                }
+            }
+            Expr::Compare { cmp, lhs, rhs, .. } => {
+                let ord = match (self.eval_expr(lhs)?, self.eval_expr(rhs)?) {
+                    (SConstant::F32(a), SConstant::F32(b)) => a.partial_cmp(&b),
+                    (SConstant::I64(a), SConstant::I64(b)) => a.partial_cmp(&b),
+                    (SConstant::Bool(a), SConstant::Bool(b)) => a.partial_cmp(&b),
+                    (a, b) => anyhow::bail!(LocatedError::new(
+                        code_ref,
+                        format!("compare not supported: {:?} {} {:?}", a, cmp, b)
+                    )),
+                };
+                SConstant::Bool(match ord {
+                    Some(ord) => match cmp {
+                        Cmp::Eq => ord.is_eq(),
+                        Cmp::Ne => ord.is_ne(),
+                        Cmp::Lt => ord.is_lt(),
+                        Cmp::Le => ord.is_le(),
+                        Cmp::Gt => ord.is_gt(),
+                        Cmp::Ge => ord.is_ge(),
+                    },
+                    None => matches!(cmp, Cmp::Ne), //NaN
+                })
+            }
+            _ => anyhow::bail!(LocatedError::new(
+                code_ref,
+                format!("{} can not be evaluated at compile time", expr)
+            )),
+        })
+    }
+}
+
+fn checked(code_ref: &CodeRef, val: Option<i64>) -> anyhow::Result<i64> {
+    match val {
+        Some(val) => Ok(val),
+        None => anyhow::bail!(LocatedError::new(
+            code_ref,
+            "overflow or division by zero in constant"
+        )),
+    }
+}
+
+/// Pure std methods that can be used in `const` initializers
+fn eval_const_method(
+    code_ref: &CodeRef,
+    fn_name: &str,
+    args: &[SConstant],
+) -> anyhow::Result<SConstant> {
+    use SConstant::{F32, I64};
+    Ok(match (fn_name, args) {
+        ("sqrt", [F32(x)]) => F32(x.sqrt()),
+        ("cbrt", [F32(x)]) => F32(x.cbrt()),
+        ("abs", [F32(x)]) => F32(x.abs()),
+        ("floor", [F32(x)]) => F32(x.floor()),
+        ("ceil", [F32(x)]) => F32(x.ceil()),
+        ("round", [F32(x)]) => F32(x.round()),
+        ("trunc", [F32(x)]) => F32(x.trunc()),
+        ("fract", [F32(x)]) => F32(x.fract()),
+        ("recip", [F32(x)]) => F32(x.recip()),
+        ("exp", [F32(x)]) => F32(x.exp()),
+        ("exp2", [F32(x)]) => F32(x.exp2()),
+        ("ln", [F32(x)]) => F32(x.ln()),
+        ("log2", [F32(x)]) => F32(x.log2()),
+        ("log10", [F32(x)]) => F32(x.log10()),
+        ("sin", [F32(x)]) => F32(x.sin()),
+        ("cos", [F32(x)]) => F32(x.cos()),
+        ("tan", [F32(x)]) => F32(x.tan()),
+        ("asin", [F32(x)]) => F32(x.asin()),
+        ("acos", [F32(x)]) => F32(x.acos()),
+        ("atan", [F32(x)]) => F32(x.atan()),
+        ("tanh", [F32(x)]) => F32(x.tanh()),
+        ("to_degrees", [F32(x)]) => F32(x.to_degrees()),
+        ("to_radians", [F32(x)]) => F32(x.to_radians()),
+        ("i64", [F32(x)]) => I64(*x as i64),
+        ("powf", [F32(x), F32(y)]) => F32(x.powf(*y)),
+        ("log", [F32(x), F32(y)]) => F32(x.log(*y)),
+        ("atan2", [F32(x), F32(y)]) => F32(x.atan2(*y)),
+        ("hypot", [F32(x), F32(y)]) => F32(x.hypot(*y)),
+        ("min", [F32(x), F32(y)]) => F32(x.min(*y)),
+        ("max", [F32(x), F32(y)]) => F32(x.max(*y)),
+        ("f32", [I64(x)]) => F32(*x as f32),
+        ("abs", [I64(x)]) => I64(x.abs()),
+        ("min", [I64(x), I64(y)]) => I64(*x.min(y)),
+        ("max", [I64(x), I64(y)]) => I64(*x.max(y)),
+        _ => anyhow::bail!(LocatedError::new(
+            code_ref,
+            format!(
+                "{} with arguments {:?} can not be evaluated at compile time",
+                fn_name, args
+            )
+        )),
+    })
+}
@@ -1,3 +1,4 @@
+use crate::diagnostics::LocatedError;
 use crate::frontend::*;
 use crate::validator::ArraySizedExpr;
 use crate::validator::ExprType;
@@ -23,13 +24,14 @@ pub struct StructField {
     pub name: String,
     pub expr_type: ExprType,
     pub enum_typeless_field: bool,
+    pub default: Option<Expr>,
 }
 
 pub fn create_struct_map(
     prog: &[Declaration],
     ptr_type: types::Type,
 ) -> anyhow::Result<HashMap<String, StructDef>> {
-    let mut in_structs = HashMap::new();
+    let mut in_structs: HashMap<String, Struct> = HashMap::new();
     for decl in prog {
         if let Declaration::Struct(mut s) = decl.clone() {
             if s.enum_struct {
@@ -40,9 +42,17 @@ pub fn create_struct_map(
                         expr_type: ExprType::I64(CodeRef::default()),
                         no_type_listed: false,
                         closure_arg: None,
+                        default: None,
                     },
                 )
             }
+            if let Some(other) = in_structs.get(&s.name) {
+                anyhow::bail!(LocatedError::new(
+                    &s.code_ref,
+                    format!("struct {} is already defined", s.name)
+                )
+                .with_related(&other.code_ref, "previously defined here"))
+            }
             in_structs.insert(s.name.to_string(), s);
         }
     }
@@ -71,6 +81,7 @@ pub fn create_struct_map(
                     name: field.name.to_string(),
                     expr_type: ExprType::Void(CodeRef::default()),
                     enum_typeless_field: struct_def.enum_struct && field.no_type_listed,
+                    default: None,
                 };
                 fields.insert(field.name.to_string(), new_field.clone());
                 fields_v.push(new_field);
@@ -85,6 +96,7 @@ pub fn create_struct_map(
                     name: field.name.to_string(),
                     expr_type: field.expr_type.clone(),
                     enum_typeless_field: struct_def.enum_struct && field.no_type_listed,
+                    default: field.default.clone(),
                 };
                 fields.insert(field.name.to_string(), new_field.clone());
                 fields_v.push(new_field);
@@ -180,6 +192,7 @@ fn get_field_size(
                     (size * len, is_struct)
                 }
             }
+            ArraySizedExpr::Const(name) => return Err(ArraySizedExpr::unresolved(name)),
         },
         _ => (
             (expr_type
@@ -235,6 +248,7 @@ fn can_insert_into_map(
         | ExprType::I64(_code_ref)
         | ExprType::U8(_code_ref)
         | ExprType::Address(_code_ref)
+        | ExprType::Ref(_code_ref, _)
         | ExprType::Tuple(_code_ref, _) => can_insert,
         ExprType::Struct(code_ref, field_struct_name) => {
             if !in_structs.contains_key(&field_struct_name.to_string()) {
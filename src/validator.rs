@@ -1,9 +1,10 @@
 use std::{collections::HashMap, fmt::Display};
 
 use crate::{
-    frontend::{Binop, CodeRef, Expr},
+    diagnostics::codes,
+    frontend::{split_type_member, Binop, CodeRef, Expr, IndexMode, Unaryop},
     jit::{Env, SVariable, StructDef},
-    sarus_std_lib::validate_core_generics,
+    sarus_std_lib::{include_elem_type, validate_core_generics},
 };
 use cranelift::prelude::types;
 use thiserror::Error;
@@ -12,30 +13,60 @@ use tracing::{error, trace};
 //TODO Make errors more information rich, also: show line in this file, and line in source
 #[derive(Debug, Clone, Error)]
 pub enum TypeError {
-    #[error("{c} Type mismatch; expected {expected}, found {actual}")]
+    #[error("Type mismatch; expected {expected}, found {actual}")]
     TypeMismatch {
-        c: String,
+        c: CodeRef,
         expected: ExprType,
         actual: ExprType,
     },
-    #[error("{c} Type mismatch; {s}")]
-    TypeMismatchSpecific { c: String, s: String },
-    #[error("{c} Tuple length mismatch; expected {expected} found {actual}")]
+    #[error("Type mismatch; {s}")]
+    TypeMismatchSpecific { c: CodeRef, s: String },
+    #[error("Tuple length mismatch; expected {expected} found {actual}")]
     TupleLengthMismatch {
-        c: String,
+        c: CodeRef,
         expected: usize,
         actual: usize,
     },
-    #[error("{0} Function \"{1}\" does not exist")]
-    UnknownFunction(String, String),
-    #[error("{0} Variable \"{1}\" does not exist")]
-    UnknownVariable(String, String),
-    #[error("{0} Struct \"{1}\" does not exist")]
-    UnknownStruct(String, String),
-    #[error("{0} Struct \"{1}\" does not have field \"{2}\"")]
-    UnknownField(String, String, String),
-    #[error("{0} Expression \"{1}\" is not supported")]
-    UnsupportedExpr(String, String),
+    #[error("Function \"{1}\" does not exist")]
+    UnknownFunction(CodeRef, String),
+    #[error("Variable \"{1}\" does not exist")]
+    UnknownVariable(CodeRef, String),
+    #[error("Struct \"{1}\" does not exist")]
+    UnknownStruct(CodeRef, String),
+    #[error("Struct \"{1}\" does not have field \"{2}\"")]
+    UnknownField(CodeRef, String, String),
+    #[error("Expression \"{1}\" is not supported")]
+    UnsupportedExpr(CodeRef, String),
+}
+
+impl TypeError {
+    /// Where in the code the error is
+    pub fn code_ref(&self) -> &CodeRef {
+        match self {
+            TypeError::TypeMismatch { c, .. }
+            | TypeError::TypeMismatchSpecific { c, .. }
+            | TypeError::TupleLengthMismatch { c, .. }
+            | TypeError::UnknownFunction(c, ..)
+            | TypeError::UnknownVariable(c, ..)
+            | TypeError::UnknownStruct(c, ..)
+            | TypeError::UnknownField(c, ..)
+            | TypeError::UnsupportedExpr(c, ..) => c,
+        }
+    }
+
+    /// The stable code for this kind of error, see `diagnostics::codes`
+    pub fn error_code(&self) -> &'static str {
+        match self {
+            TypeError::TypeMismatch { .. } => codes::TYPE_MISMATCH,
+            TypeError::TypeMismatchSpecific { .. } => codes::TYPE_MISMATCH_SPECIFIC,
+            TypeError::TupleLengthMismatch { .. } => codes::TUPLE_LENGTH_MISMATCH,
+            TypeError::UnknownFunction(..) => codes::UNKNOWN_FUNCTION,
+            TypeError::UnknownVariable(..) => codes::UNKNOWN_VARIABLE,
+            TypeError::UnknownStruct(..) => codes::UNKNOWN_STRUCT,
+            TypeError::UnknownField(..) => codes::UNKNOWN_FIELD,
+            TypeError::UnsupportedExpr(..) => codes::UNSUPPORTED_EXPR,
+        }
+    }
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
@@ -47,7 +78,16 @@ pub enum ArraySizedExpr {
     //    capacity: i64
     //}
     Slice,
-    Fixed(usize), //size is part of type signature
+    Fixed(usize),  //size is part of type signature
+    Const(String), //fixed size given by a const, replaced with Fixed once consts are evaluated
+}
+
+impl ArraySizedExpr {
+    /// The error for a `Const` length that reaches codegen. The `TypeResolver`
+    /// replaces every `Const` with `Fixed` before functions are translated.
+    pub fn unresolved(name: &str) -> anyhow::Error {
+        anyhow::anyhow!("array length {} has not been resolved", name)
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -61,6 +101,7 @@ pub enum ExprType {
     Address(CodeRef),
     Tuple(CodeRef, Vec<ExprType>),
     Struct(CodeRef, Box<String>),
+    Ref(CodeRef, Box<ExprType>), //typed address of a single scalar or struct
 }
 
 pub fn f32_t() -> ExprType {
@@ -99,6 +140,10 @@ pub fn array_t(ty: ExprType, size_type: ArraySizedExpr) -> ExprType {
     ExprType::Array(CodeRef::default(), Box::new(ty), size_type)
 }
 
+pub fn ref_t(ty: ExprType) -> ExprType {
+    ExprType::Ref(CodeRef::default(), Box::new(ty))
+}
+
 impl PartialEq for ExprType {
     fn eq(&self, other: &Self) -> bool {
         match self {
@@ -147,6 +192,11 @@ impl PartialEq for ExprType {
                     return sa == sb;
                 }
             }
+            ExprType::Ref(_, a) => {
+                if let ExprType::Ref(_, b) = other {
+                    return a == b;
+                }
+            }
         }
         false
     }
@@ -164,6 +214,7 @@ impl Display for ExprType {
                 ArraySizedExpr::Unsized => write!(f, "&[{}]", ty),
                 ArraySizedExpr::Slice => write!(f, "[{}]", ty),
                 ArraySizedExpr::Fixed(len) => write!(f, "[{}; {}]", ty, len),
+                ArraySizedExpr::Const(name) => write!(f, "[{}; {}]", ty, name),
             },
             ExprType::Address(_) => write!(f, "&"),
             ExprType::Tuple(_, inner) => {
@@ -175,10 +226,58 @@ impl Display for ExprType {
                 write!(f, ")")
             }
             ExprType::Struct(_, s) => write!(f, "{}", s),
+            ExprType::Ref(_, ty) => write!(f, "&{}", ty),
         }
     }
 }
 
+/// The type a `&T` can point to when written as `T::unsafe_ref(addr)`,
+/// only scalars and structs can be referenced
+pub(crate) fn ref_target_type(
+    name: &str,
+    code_ref: &CodeRef,
+    struct_map: &HashMap<String, StructDef>,
+) -> Option<ExprType> {
+    match name {
+        "f32" => Some(ExprType::F32(*code_ref)),
+        "i64" => Some(ExprType::I64(*code_ref)),
+        "u8" => Some(ExprType::U8(*code_ref)),
+        "bool" => Some(ExprType::Bool(*code_ref)),
+        name if struct_map.contains_key(name) => {
+            Some(ExprType::Struct(*code_ref, Box::new(name.to_string())))
+        }
+        _ => None,
+    }
+}
+
+/// Indexing with `.wrap[i]` or `.clamp[i]` needs the length of the array,
+/// which unsized arrays don't have. A struct field named `wrap` or `clamp`
+/// can't be indexed directly, since `s.wrap[i]` is read as an index mode.
+pub(crate) fn index_mode_error(
+    code_ref: &CodeRef,
+    expr: &dyn Display,
+    expr_type: &ExprType,
+    index_mode: IndexMode,
+) -> Option<TypeError> {
+    if !matches!(index_mode, IndexMode::Wrap | IndexMode::Clamp) {
+        return None;
+    }
+    let s = match expr_type {
+        ExprType::Array(_, _, ArraySizedExpr::Unsized) => format!(
+            "{}[] can't be used on {} of type {}, the length of an unsized array isn't known",
+            index_mode, expr, expr_type
+        ),
+        ExprType::Struct(..) => format!(
+            "{} is not an array, {}[] is read as an index mode. Assign a field named {} to a variable to index it",
+            expr,
+            index_mode,
+            &index_mode.to_string()[1..]
+        ),
+        _ => return None,
+    };
+    Some(TypeError::TypeMismatchSpecific { c: *code_ref, s })
+}
+
 fn get_struct_field_type(
     env: &Env,
     parts: Vec<String>,
@@ -194,12 +293,14 @@ fn get_struct_field_type(
             (svar.expr_type(code_ref).unwrap(), 1)
         } else {
             error!("");
-            return Err(TypeError::UnknownVariable(
-                code_ref.s(&env.file_idx),
-                parts[0].to_string(),
-            ));
+            return Err(TypeError::UnknownVariable(*code_ref, parts[0].to_string()));
         }
     };
+    //field access through a reference reads from the struct it points to
+    let lhs_val = match lhs_val {
+        ExprType::Ref(_code_ref, ty) => *ty,
+        ty => ty,
+    };
     match lhs_val {
         ExprType::Struct(_code_ref, struct_name) => {
             let mut struct_name = *struct_name;
@@ -208,17 +309,14 @@ fn get_struct_field_type(
                     parent_struct_field
                 } else {
                     return Err(TypeError::UnknownField(
-                        code_ref.s(&env.file_idx),
+                        *code_ref,
                         struct_name,
                         parts[start].to_string(),
                     ));
                 }
             } else {
                 dbg!(&env.struct_map);
-                return Err(TypeError::UnknownStruct(
-                    code_ref.s(&env.file_idx),
-                    struct_name,
-                ));
+                return Err(TypeError::UnknownStruct(*code_ref, struct_name));
             };
 
             if parts.len() > 2 {
@@ -230,7 +328,7 @@ fn get_struct_field_type(
                             parent_struct_field
                         } else {
                             return Err(TypeError::UnknownField(
-                                code_ref.s(&env.file_idx),
+                                *code_ref,
                                 struct_name,
                                 parts[start].to_string(),
                             ));
@@ -246,7 +344,7 @@ fn get_struct_field_type(
         v => {
             error!("");
             Err(TypeError::TypeMismatch {
-                c: v.get_code_ref().s(&env.file_idx),
+                c: v.get_code_ref(),
                 expected: ExprType::Struct(v.get_code_ref(), Box::new("".to_string())),
                 actual: v,
             })
@@ -266,6 +364,7 @@ impl ExprType {
             ExprType::Address(code_ref) => code_ref,
             ExprType::Tuple(code_ref, ..) => code_ref,
             ExprType::Struct(code_ref, ..) => code_ref,
+            ExprType::Ref(code_ref, ..) => code_ref,
         }
     }
     pub fn replace_code_ref(&mut self, new_code_ref: CodeRef) {
@@ -279,6 +378,7 @@ impl ExprType {
             ExprType::Address(code_ref) => *code_ref = new_code_ref,
             ExprType::Tuple(code_ref, ..) => *code_ref = new_code_ref,
             ExprType::Struct(code_ref, ..) => *code_ref = new_code_ref,
+            ExprType::Ref(code_ref, ..) => *code_ref = new_code_ref,
         }
     }
 
@@ -292,6 +392,7 @@ impl ExprType {
                 ArraySizedExpr::Unsized => Some(ptr_ty.bytes() as usize),
                 ArraySizedExpr::Slice => Some((ptr_ty.bytes() + 2 * 8) as usize),
                 ArraySizedExpr::Fixed(len) => ty.width(ptr_ty, struct_map).map(|width| width * len),
+                ArraySizedExpr::Const(_) => None,
             },
             ExprType::Void(_) => Some(0),
             ExprType::Bool(_) => Some(types::I8.bytes() as usize),
@@ -301,9 +402,11 @@ impl ExprType {
             ExprType::Address(_) => Some(ptr_ty.bytes() as usize),
             ExprType::Tuple(_code_ref, _expr_types) => None,
             ExprType::Struct(_code_ref, name) => Some(struct_map[&name.to_string()].size),
+            ExprType::Ref(_, _) => Some(ptr_ty.bytes() as usize),
         }
     }
 
+    #[allow(deprecated)]
     pub fn of(
         of_expr: &Expr,
         env: &Env,
@@ -317,29 +420,27 @@ impl ExprType {
                 name: id_name,
             } => {
                 if id_name.contains("::") {
-                    let parts = id_name.split("::").collect::<Vec<_>>();
+                    let parts = split_type_member(id_name);
                     if let Some(struct_) = env.struct_map.get(parts[0]) {
                         if struct_.enum_struct {
                             if let Some(_field) = struct_.fields.get(parts[1]) {
                                 return Ok(ExprType::I64(*code_ref));
-                            } else {
-                                panic!("field {} not found", parts[1]) //TODO make an error for this
                             }
+                            //otherwise this could be an associated constant
                         }
                     }
                 }
                 if variables.contains_key(id_name) {
                     variables[id_name].expr_type(code_ref).unwrap()
+                } else if let Some(static_def) = env.statics.get(id_name) {
+                    static_def.expr_type.clone()
                 } else if let Some(v) = env.constant_vars.get(id_name) {
                     v.expr_type(Some(*code_ref)) //Constants like PI, TAU...
                 } else if let Some(_closure) = env.get_inline_closure(func_name, id_name) {
                     ExprType::Void(*code_ref)
                 } else {
                     error!("{:#?}", variables);
-                    return Err(TypeError::UnknownVariable(
-                        code_ref.s(&env.file_idx),
-                        id_name.to_string(),
-                    ));
+                    return Err(TypeError::UnknownVariable(*code_ref, id_name.to_string()));
                 }
             }
             Expr::LiteralFloat { code_ref, .. } => ExprType::F32(*code_ref),
@@ -355,13 +456,14 @@ impl ExprType {
                 code_ref,
                 exprs,
                 len,
+                ..
             } => {
                 let first_typ = ExprType::of(&exprs[0], env, func_name, variables)?;
                 for e in exprs.iter().next() {
                     let typ = ExprType::of(e, env, func_name, variables)?;
                     if typ != first_typ {
                         return Err(TypeError::TypeMismatch {
-                            c: code_ref.s(&env.file_idx),
+                            c: *code_ref,
                             expected: first_typ,
                             actual: typ,
                         });
@@ -433,13 +535,21 @@ impl ExprType {
                                     continue;
                                 }
 
-                                let fn_name = format!("{sval}.{fn_name}");
+                                let method_name = fn_name;
+                                let mut fn_name = format!("{sval}.{method_name}");
+
+                                if let ExprType::Ref(_code_ref, ty) = &sval {
+                                    //methods of the referenced type can be called through the reference
+                                    let deref_fn_name = format!("{ty}.{method_name}");
+                                    if !env.funcs.contains_key(&fn_name)
+                                        && env.funcs.contains_key(&deref_fn_name)
+                                    {
+                                        fn_name = deref_fn_name;
+                                    }
+                                }
 
                                 if !&env.funcs.contains_key(&fn_name) {
-                                    return Err(TypeError::UnknownFunction(
-                                        code_ref.s(&env.file_idx),
-                                        fn_name,
-                                    ));
+                                    return Err(TypeError::UnknownFunction(*code_ref, fn_name));
                                 }
 
                                 let params = &env.funcs[&fn_name].params;
@@ -447,7 +557,7 @@ impl ExprType {
                                 if params.len() - 1 != args.len() {
                                     return Err(TypeError::TupleLengthMismatch {
                                         //TODO be more specific: function {} expected {} parameters, but {} were given
-                                        c: code_ref.s(&env.file_idx),
+                                        c: *code_ref,
                                         actual: args.len(),
                                         expected: params.len() - 1,
                                     });
@@ -459,7 +569,7 @@ impl ExprType {
                                     let targ = ExprType::of(arg, env, func_name, variables)?;
                                     if param.expr_type != targ {
                                         return Err(TypeError::TypeMismatchSpecific {
-                                                    c: code_ref.s(&env.file_idx),
+                                                    c: *code_ref,
                                                     s: format!("function {} expected parameter {} to be of type {} but type {} was found", fn_name, i, param.expr_type , targ)
                                                 });
                                     }
@@ -499,7 +609,7 @@ impl ExprType {
                                     next_expr = Some(*rhs.clone());
                                 } else {
                                     return Err(TypeError::UnsupportedExpr(
-                                        of_expr.get_code_ref().s(&env.file_idx),
+                                        *of_expr.get_code_ref(),
                                         of_expr.to_string(),
                                     ));
                                 }
@@ -512,13 +622,14 @@ impl ExprType {
                                 code_ref,
                                 expr,
                                 idx_expr,
+                                ..
                             } => {
                                 match ExprType::of(&idx_expr, env, func_name, variables)? {
                                     ExprType::I64(_code_ref) => (),
                                     e => {
                                         error!("");
                                         return Err(TypeError::TypeMismatch {
-                                            c: code_ref.s(&env.file_idx),
+                                            c: *code_ref,
                                             expected: ExprType::I64(code_ref),
                                             actual: e,
                                         });
@@ -565,7 +676,7 @@ impl ExprType {
                             | Expr::LiteralU8 { .. }
                             | Expr::LiteralBool { .. } => {
                                 return Err(TypeError::UnsupportedExpr(
-                                    of_expr.get_code_ref().s(&env.file_idx),
+                                    *of_expr.get_code_ref(),
                                     of_expr.to_string(),
                                 ))
                             }
@@ -599,13 +710,26 @@ impl ExprType {
                     } else {
                         error!("");
                         return Err(TypeError::TypeMismatch {
-                            c: binop_code_ref.s(&env.file_idx),
+                            c: *binop_code_ref,
                             expected: lt,
                             actual: rt,
                         });
                     }
                 }
             },
+            Expr::Unaryop {
+                code_ref,
+                op: Unaryop::Deref,
+                expr,
+            } => match ExprType::of(expr, env, func_name, variables)? {
+                ExprType::Ref(_code_ref, ty) => *ty,
+                ty => {
+                    return Err(TypeError::TypeMismatchSpecific {
+                        c: *code_ref,
+                        s: format!("{} of type {} can not be dereferenced", expr, ty),
+                    })
+                }
+            },
             Expr::Unaryop {
                 code_ref: _,
                 op: _,
@@ -622,7 +746,7 @@ impl ExprType {
                 if tcond != ExprType::Bool(*code_ref) {
                     error!("");
                     return Err(TypeError::TypeMismatch {
-                        c: code_ref.s(&env.file_idx),
+                        c: *code_ref,
                         expected: ExprType::Bool(*code_ref),
                         actual: tcond,
                     });
@@ -639,7 +763,7 @@ impl ExprType {
                     if tcond != ExprType::Bool(*code_ref) {
                         error!("");
                         return Err(TypeError::TypeMismatch {
-                            c: code_ref.s(&env.file_idx),
+                            c: *code_ref,
                             expected: ExprType::Bool(*code_ref),
                             actual: tcond,
                         });
@@ -659,7 +783,7 @@ impl ExprType {
                     if tcond != ExprType::Bool(*code_ref) {
                         error!("");
                         return Err(TypeError::TypeMismatch {
-                            c: code_ref.s(&env.file_idx),
+                            c: *code_ref,
                             expected: ExprType::Bool(*code_ref),
                             actual: tcond,
                         });
@@ -677,7 +801,7 @@ impl ExprType {
                         } else {
                             error!("");
                             return Err(TypeError::TypeMismatch {
-                                c: code_ref.s(&env.file_idx),
+                                c: *code_ref,
                                 expected: slast_body_type,
                                 actual: body_type,
                             });
@@ -697,7 +821,7 @@ impl ExprType {
                     if else_body_type != slast_body_type {
                         error!("");
                         return Err(TypeError::TypeMismatch {
-                            c: code_ref.s(&env.file_idx),
+                            c: *code_ref,
                             expected: slast_body_type,
                             actual: else_body_type,
                         });
@@ -718,7 +842,7 @@ impl ExprType {
                 if tcond != ExprType::Bool(*code_ref) {
                     error!("");
                     return Err(TypeError::TypeMismatch {
-                        c: code_ref.s(&env.file_idx),
+                        c: *code_ref,
                         expected: ExprType::Bool(*code_ref),
                         actual: tcond,
                     });
@@ -744,7 +868,7 @@ impl ExprType {
                 } else {
                     error!("");
                     return Err(TypeError::TypeMismatch {
-                        c: code_ref.s(&env.file_idx),
+                        c: *code_ref,
                         expected: ttrue,
                         actual: tfalse,
                     });
@@ -761,7 +885,7 @@ impl ExprType {
                 };
                 if to_exprs.len() != tlen {
                     return Err(TypeError::TupleLengthMismatch {
-                        c: code_ref.s(&env.file_idx),
+                        c: *code_ref,
                         actual: from_exprs.len(),
                         expected: tlen,
                     });
@@ -790,7 +914,7 @@ impl ExprType {
                         if lhs_type != rhs_type {
                             error!("");
                             return Err(TypeError::TypeMismatch {
-                                c: to_expr.clone().get_code_ref().s(&env.file_idx),
+                                c: *to_expr.get_code_ref(),
                                 expected: lhs_type,
                                 actual: rhs_type,
                             });
@@ -810,7 +934,7 @@ impl ExprType {
                 if idx_type != ExprType::Bool(*code_ref) {
                     error!("");
                     return Err(TypeError::TypeMismatch {
-                        c: code_ref.s(&env.file_idx),
+                        c: *code_ref,
                         expected: ExprType::Bool(*code_ref),
                         actual: idx_type,
                     });
@@ -837,11 +961,39 @@ impl ExprType {
                 args,
                 is_macro,
             } => {
+                if let Some(elem_type) = include_elem_type(fn_name) {
+                    return Ok(ExprType::Array(
+                        *code_ref,
+                        Box::new(elem_type),
+                        ArraySizedExpr::Slice,
+                    ));
+                }
                 if fn_name.contains("::") {
-                    //check if this is an enum
-                    let parts = fn_name.split("::").collect::<Vec<_>>();
+                    let parts = split_type_member(fn_name);
+                    if parts[1] == "unsafe_ref" {
+                        if let Some(ty) = ref_target_type(parts[0], code_ref, &env.struct_map) {
+                            if args.len() != 1 {
+                                return Err(TypeError::TupleLengthMismatch {
+                                    c: *code_ref,
+                                    actual: args.len(),
+                                    expected: 1,
+                                });
+                            }
+                            let targ = ExprType::of(&args[0], env, func_name, variables)?;
+                            if let ExprType::Address(_code_ref) = targ {
+                                return Ok(ExprType::Ref(*code_ref, Box::new(ty)));
+                            } else {
+                                return Err(TypeError::TypeMismatch {
+                                    c: *code_ref,
+                                    expected: ExprType::Address(*code_ref),
+                                    actual: targ,
+                                });
+                            }
+                        }
+                    }
+                    //check if this is an enum, associated functions take precedence
                     if let Some(struct_) = env.struct_map.get(parts[0]) {
-                        if struct_.enum_struct {
+                        if struct_.enum_struct && !env.funcs.contains_key(fn_name) {
                             if let Some(_field) = struct_.fields.get(parts[1]) {
                                 if _field.enum_typeless_field {
                                     return Ok(ExprType::Struct(
@@ -852,7 +1004,7 @@ impl ExprType {
                                 let targ = ExprType::of(&args[0], env, func_name, variables)?;
                                 if _field.expr_type != targ {
                                     return Err(TypeError::TypeMismatchSpecific {
-                                        c: code_ref.s(&env.file_idx),
+                                        c: *code_ref,
                                         s: format!("enum {} expected input to be of type {} but type {} was found", fn_name, _field.expr_type, targ)
                                     });
                                 }
@@ -896,7 +1048,7 @@ impl ExprType {
                     if func.params.len() != targs.len() {
                         return Err(TypeError::TupleLengthMismatch {
                             //TODO be more specific: function {} expected {} parameters, but {} were given
-                            c: code_ref.s(&env.file_idx),
+                            c: *code_ref,
                             actual: targs.len(),
                             expected: func.params.len(),
                         });
@@ -909,7 +1061,7 @@ impl ExprType {
                         } else {
                             error!("");
                             return Err(TypeError::TypeMismatchSpecific {
-                                    c: code_ref.s(&env.file_idx),
+                                    c: *code_ref,
                                     s: format!("function {} expected parameter {} to be of type {} but type {} was found", fn_name, i, param_type, targ)
                                 });
                         }
@@ -927,10 +1079,7 @@ impl ExprType {
                         }
                     }
                 } else {
-                    return Err(TypeError::UnknownFunction(
-                        code_ref.s(&env.file_idx),
-                        fn_name.to_string(),
-                    ));
+                    return Err(TypeError::UnknownFunction(*code_ref, fn_name.to_string()));
                 }
             }
             Expr::GlobalDataAddr { code_ref, name: _ } => ExprType::F32(*code_ref),
@@ -941,24 +1090,29 @@ impl ExprType {
                 code_ref,
                 expr,
                 idx_expr,
+                index_mode,
             } => {
                 match ExprType::of(&*idx_expr, env, func_name, variables)? {
                     ExprType::I64(_code_ref) => (),
                     e => {
                         error!("");
                         return Err(TypeError::TypeMismatch {
-                            c: code_ref.s(&env.file_idx),
+                            c: *code_ref,
                             expected: ExprType::I64(*code_ref),
                             actual: e,
                         });
                     }
                 };
 
-                match ExprType::of(&*expr, env, func_name, variables)? {
+                let array_type = ExprType::of(&*expr, env, func_name, variables)?;
+                if let Some(error) = index_mode_error(code_ref, expr, &array_type, *index_mode) {
+                    return Err(error);
+                }
+                match array_type {
                     ExprType::Array(_code_ref, expr_type, _size_type) => *expr_type,
                     _ => {
                         return Err(TypeError::TypeMismatchSpecific {
-                            c: code_ref.s(&env.file_idx),
+                            c: *code_ref,
                             s: format!("{} is not an array", expr),
                         })
                     }
@@ -967,15 +1121,34 @@ impl ExprType {
             Expr::NewStruct {
                 code_ref,
                 name,
-                fields: _,
+                fields,
+                base,
             } => {
-                if env.struct_map.contains_key(name) {
+                if let Some(struct_def) = env.struct_map.get(name) {
                     //Need to check field types
+                    for field in fields {
+                        if !struct_def.fields.contains_key(&field.field_name) {
+                            return Err(TypeError::UnknownField(
+                                *code_ref,
+                                name.to_string(),
+                                field.field_name.to_string(),
+                            ));
+                        }
+                    }
+                    if let Some(base) = base {
+                        let base_type = ExprType::of(base, env, func_name, variables)?;
+                        if base_type != struct_t(name) {
+                            return Err(TypeError::TypeMismatchSpecific {
+                                c: *code_ref,
+                                s: format!(
+                                    "struct {} can not be updated from {} of type {}",
+                                    name, base, base_type
+                                ),
+                            });
+                        }
+                    }
                 } else {
-                    return Err(TypeError::UnknownStruct(
-                        code_ref.s(&env.file_idx),
-                        name.to_string(),
-                    ));
+                    return Err(TypeError::UnknownStruct(*code_ref, name.to_string()));
                 }
                 ExprType::Struct(*code_ref, Box::new(name.to_string()))
             }
@@ -1009,6 +1182,7 @@ impl ExprType {
             | ExprType::U8(_)
             | ExprType::Address(_)
             | ExprType::Struct(_, _)
+            | ExprType::Ref(_, _)
             | ExprType::Array(_, _, _) => 1,
             ExprType::Tuple(_, v) => v.len(),
         }
@@ -1022,7 +1196,7 @@ impl ExprType {
         //TODO add env.file_idx for code_ref errors
         match self {
             ExprType::Void(code_ref) => Err(TypeError::TypeMismatchSpecific {
-                c: code_ref.to_string(),
+                c: *code_ref,
                 s: "Void has no cranelift analog".to_string(),
             }),
             ExprType::Bool(_code_ref) => Ok(if struct_access {
@@ -1036,8 +1210,9 @@ impl ExprType {
             ExprType::Array(_code_ref, _, _) => Ok(ptr_type),
             ExprType::Address(_code_ref) => Ok(ptr_type),
             ExprType::Struct(_code_ref, _) => Ok(ptr_type),
+            ExprType::Ref(_code_ref, _) => Ok(ptr_type),
             ExprType::Tuple(code_ref, _) => Err(TypeError::TypeMismatchSpecific {
-                c: code_ref.to_string(),
+                c: *code_ref,
                 s: "Tuple has no cranelift analog".to_string(),
             }),
         }
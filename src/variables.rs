@@ -1,3 +1,4 @@
+use crate::diagnostics::LocatedError;
 use crate::frontend::*;
 use crate::validator::ArraySizedExpr;
 use crate::validator::ExprType;
@@ -26,8 +27,11 @@ impl ArraySized {
             ArraySized::Fixed(_, size) => ArraySizedExpr::Fixed(*size),
         }
     }
-    pub fn from(builder: &mut FunctionBuilder, size_type: &ArraySizedExpr) -> ArraySized {
-        match size_type {
+    pub fn from(
+        builder: &mut FunctionBuilder,
+        size_type: &ArraySizedExpr,
+    ) -> anyhow::Result<ArraySized> {
+        Ok(match size_type {
             ArraySizedExpr::Unsized => ArraySized::Unsized,
             ArraySizedExpr::Slice => ArraySized::Slice,
             ArraySizedExpr::Fixed(len) => ArraySized::Fixed(
@@ -36,7 +40,8 @@ impl ArraySized {
                 )),
                 *len,
             ),
-        }
+            ArraySizedExpr::Const(name) => return Err(ArraySizedExpr::unresolved(name)),
+        })
     }
 }
 
@@ -52,6 +57,7 @@ pub enum SValue {
     Address(Value),
     Tuple(Vec<SValue>),
     Struct(String, Value),
+    Ref(Box<SValue>),
 }
 
 impl Display for SValue {
@@ -71,6 +77,7 @@ impl Display for SValue {
             SValue::Void => write!(f, "void"),
             SValue::Tuple(v) => write!(f, "({})", v.len()),
             SValue::Struct(name, _) => write!(f, "{}", name),
+            SValue::Ref(sval) => write!(f, "&{}", sval),
         }
     }
 }
@@ -89,11 +96,14 @@ impl SValue {
             ExprType::U8(_code_ref) => SValue::U8(value),
             ExprType::Array(_code_ref, ty, size_type) => SValue::Array(
                 Box::new(SValue::from(builder, ty, value)?),
-                ArraySized::from(builder, size_type),
+                ArraySized::from(builder, size_type)?,
             ),
             ExprType::Address(_code_ref) => SValue::Address(value),
             ExprType::Tuple(_code_ref, _) => anyhow::bail!("use SValue::from_tuple"),
             ExprType::Struct(_code_ref, name) => SValue::Struct(name.to_string(), value),
+            ExprType::Ref(_code_ref, ty) => {
+                SValue::Ref(Box::new(SValue::from(builder, ty, value)?))
+            }
         })
     }
     pub fn get_from_variable(
@@ -114,6 +124,9 @@ impl SValue {
             SVariable::Struct(_varname, structname, v, _return_struct) => {
                 SValue::Struct(structname.to_string(), builder.use_var(*v))
             }
+            SVariable::Ref(svar) => {
+                SValue::Ref(Box::new(SValue::get_from_variable(builder, svar)?))
+            }
         })
     }
     pub fn replace_value(&self, value: Value) -> anyhow::Result<SValue> {
@@ -136,6 +149,7 @@ impl SValue {
             //    SValue::Tuple(_)
             //},
             SValue::Struct(name, _) => SValue::Struct(name.to_string(), value),
+            SValue::Ref(sval) => SValue::Ref(Box::new(sval.replace_value(value)?)),
             SValue::Unknown(_) => SValue::Unknown(value),
         })
     }
@@ -153,6 +167,7 @@ impl SValue {
             ),
             SValue::Address(_) => ExprType::Address(*code_ref),
             SValue::Struct(name, _) => ExprType::Struct(*code_ref, Box::new(name.to_string())),
+            SValue::Ref(sval) => ExprType::Ref(*code_ref, Box::new(sval.expr_type(code_ref)?)),
             SValue::Void => ExprType::Void(*code_ref),
             SValue::Tuple(_) => todo!(),
         })
@@ -169,6 +184,7 @@ impl SValue {
             SValue::Void => anyhow::bail!("void has no inner {}", ctx),
             SValue::Tuple(v) => anyhow::bail!("inner does not support tuple {:?} {}", v, ctx),
             SValue::Struct(_, v) => Ok(*v),
+            SValue::Ref(sval) => Ok(sval.inner(ctx)?),
         }
     }
     pub fn expect_struct(&self, name: &str, ctx: &str) -> anyhow::Result<Value> {
@@ -195,6 +211,7 @@ pub enum SVariable {
     Array(Box<SVariable>, ArraySized),
     Address(String, Variable),
     Struct(String, String, Variable, bool),
+    Ref(Box<SVariable>),
 }
 
 impl Display for SVariable {
@@ -214,6 +231,7 @@ impl Display for SVariable {
             SVariable::Struct(name, structname, _, _return_struct) => {
                 write!(f, "struct {} {}", name, structname)
             }
+            SVariable::Ref(svar) => write!(f, "&{}", svar),
         }
     }
 }
@@ -229,6 +247,7 @@ impl SVariable {
             SVariable::Array(svar, _len) => svar.inner(),
             SVariable::Address(_, v) => *v,
             SVariable::Struct(_, _, v, _) => *v,
+            SVariable::Ref(svar) => svar.inner(),
         }
     }
     pub fn expr_type(&self, code_ref: &CodeRef) -> anyhow::Result<ExprType> {
@@ -247,30 +266,43 @@ impl SVariable {
             SVariable::Struct(_, name, _, _) => {
                 ExprType::Struct(*code_ref, Box::new(name.to_string()))
             }
+            SVariable::Ref(svar) => ExprType::Ref(*code_ref, Box::new(svar.expr_type(code_ref)?)),
         })
     }
     pub fn expect_f32(&self, code_ref: &CodeRef, ctx: &str) -> anyhow::Result<Variable> {
         match self {
             SVariable::F32(_, v) => Ok(*v),
-            v => anyhow::bail!("{} incorrect type {} expected f32 {}", code_ref, v, ctx),
+            v => anyhow::bail!(LocatedError::new(
+                code_ref,
+                format!("incorrect type {} expected f32 {}", v, ctx)
+            )),
         }
     }
     pub fn expect_i64(&self, code_ref: &CodeRef, ctx: &str) -> anyhow::Result<Variable> {
         match self {
             SVariable::I64(_, v) => Ok(*v),
-            v => anyhow::bail!("{} incorrect type {} expected i64 {}", code_ref, v, ctx),
+            v => anyhow::bail!(LocatedError::new(
+                code_ref,
+                format!("incorrect type {} expected i64 {}", v, ctx)
+            )),
         }
     }
     pub fn expect_u8(&self, code_ref: &CodeRef, ctx: &str) -> anyhow::Result<Variable> {
         match self {
             SVariable::U8(_, v) => Ok(*v),
-            v => anyhow::bail!("{} incorrect type {} expected u8 {}", code_ref, v, ctx),
+            v => anyhow::bail!(LocatedError::new(
+                code_ref,
+                format!("incorrect type {} expected u8 {}", v, ctx)
+            )),
         }
     }
     pub fn expect_bool(&self, code_ref: &CodeRef, ctx: &str) -> anyhow::Result<Variable> {
         match self {
             SVariable::Bool(_, v) => Ok(*v),
-            v => anyhow::bail!("{} incorrect type {} expected bool {}", code_ref, v, ctx),
+            v => anyhow::bail!(LocatedError::new(
+                code_ref,
+                format!("incorrect type {} expected bool {}", v, ctx)
+            )),
         }
     }
     pub fn expect_array(
@@ -283,13 +315,13 @@ impl SVariable {
         match self {
             SVariable::Array(svar, size_type) => {
                 if size_type.expr_type() != expect_size_type {
-                    anyhow::bail!(
-                        "{} incorrect length {:?} expected {:?} found {}",
+                    anyhow::bail!(LocatedError::new(
                         code_ref,
-                        expect_size_type,
-                        size_type,
-                        ctx
-                    )
+                        format!(
+                            "incorrect length {:?} expected {:?} found {}",
+                            expect_size_type, size_type, ctx
+                        )
+                    ))
                 }
                 let var_ty = svar.expr_type(code_ref)?;
                 if var_ty != expect_ty {
@@ -309,7 +341,19 @@ impl SVariable {
     pub fn expect_address(&self, code_ref: &CodeRef, ctx: &str) -> anyhow::Result<Variable> {
         match self {
             SVariable::Address(_, v) => Ok(*v),
-            v => anyhow::bail!("{} incorrect type {} expected Address {}", code_ref, v, ctx),
+            v => anyhow::bail!(LocatedError::new(
+                code_ref,
+                format!("incorrect type {} expected Address {}", v, ctx)
+            )),
+        }
+    }
+    pub fn expect_ref(&self, code_ref: &CodeRef, ctx: &str) -> anyhow::Result<Variable> {
+        match self {
+            SVariable::Ref(svar) => Ok(svar.inner()),
+            v => anyhow::bail!(LocatedError::new(
+                code_ref,
+                format!("incorrect type {} expected Ref {}", v, ctx)
+            )),
         }
     }
     pub fn expect_struct(
@@ -323,13 +367,13 @@ impl SVariable {
                 if sname == name {
                     Ok(*v)
                 } else {
-                    anyhow::bail!(
-                        "{} incorrect type {} expected Struct {} {}",
+                    anyhow::bail!(LocatedError::new(
                         code_ref,
-                        varname,
-                        name,
-                        ctx
-                    )
+                        format!(
+                            "incorrect type {} expected Struct {} {}",
+                            varname, name, ctx
+                        )
+                    ))
                 }
             }
             v => anyhow::bail!("incorrect type {} expected Struct {} {}", v, name, ctx),
@@ -349,7 +393,7 @@ impl SVariable {
             ExprType::U8(_code_ref) => SVariable::U8(name, var),
             ExprType::Array(_code_ref, ty, size_type) => SVariable::Array(
                 Box::new(SVariable::from(builder, ty, name, var)?),
-                ArraySized::from(builder, size_type),
+                ArraySized::from(builder, size_type)?,
             ),
             ExprType::Address(_code_ref) => SVariable::Address(name, var),
             ExprType::Tuple(_code_ref, _) => anyhow::bail!("use SVariable::from_tuple"),
@@ -357,7 +401,12 @@ impl SVariable {
                 SVariable::Struct(name.to_string(), name.to_string(), var, false)
                 //last bool is return struct
             }
-            ExprType::Void(code_ref) => anyhow::bail!("{} SVariable cannot be void", code_ref),
+            ExprType::Ref(_code_ref, ty) => {
+                SVariable::Ref(Box::new(SVariable::from(builder, ty, name, var)?))
+            }
+            ExprType::Void(code_ref) => {
+                anyhow::bail!(LocatedError::new(code_ref, "SVariable cannot be void"))
+            }
         })
     }
 }
@@ -500,7 +549,10 @@ pub fn declare_variable(
     }
     match expr_type {
         ExprType::Void(code_ref) => {
-            anyhow::bail!("{} can't assign void type to {}", code_ref, name)
+            anyhow::bail!(LocatedError::new(
+                code_ref,
+                format!("can't assign void type to {}", name)
+            ))
         }
         ExprType::Bool(code_ref) => {
             if !variables.contains_key(name) {
@@ -550,7 +602,7 @@ pub fn declare_variable(
                     name.into(),
                     SVariable::Array(
                         Box::new(SVariable::from(builder, ty, name.to_string(), var)?),
-                        ArraySized::from(builder, size_type),
+                        ArraySized::from(builder, size_type)?,
                     ),
                 );
                 per_scope_vars.insert(name.into());
@@ -568,6 +620,24 @@ pub fn declare_variable(
                 *index += 1;
             }
         }
+        ExprType::Ref(code_ref, ty) => {
+            if !variables.contains_key(name) {
+                trace!("{} {} {}", code_ref, expr_type, name);
+                let var = Variable::new(*index);
+                variables.insert(
+                    name.into(),
+                    SVariable::Ref(Box::new(SVariable::from(
+                        builder,
+                        ty,
+                        name.to_string(),
+                        var,
+                    )?)),
+                );
+                per_scope_vars.insert(name.into());
+                builder.declare_var(var, ptr_type);
+                *index += 1;
+            }
+        }
         ExprType::Tuple(_code_ref, expr_types) => {
             if expr_types.len() == 1 {
                 //Single nested tuple
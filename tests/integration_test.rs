@@ -60,7 +60,7 @@ fn only_run_func_with_importer(
 #[test]
 fn parentheses() -> anyhow::Result<()> {
     let code = r#"
-fn main(a, b) -> (c) {
+pub fn main(a, b) -> (c) {
     c = a * (a - b) * (a * (2.0 + b))
 }
 "#;
@@ -77,7 +77,7 @@ fn main(a, b) -> (c) {
 #[test]
 fn rust_math() -> anyhow::Result<()> {
     let code = r#"
-fn main(a, b) -> (c) {
+pub fn main(a, b) -> (c) {
     c = b
     c = c.sin()
     c = c.cos()
@@ -150,7 +150,7 @@ fn nums() -> (r) {
 #[test]
 fn rounding() -> anyhow::Result<()> {
     let code = r#"
-fn main(a, b) -> (c) {
+pub fn main(a, b) -> (c) {
     f = (1.5).floor()
     c = a.ceil() * b.floor() * a.trunc() * (a * b * -1.234).fract() * (1.5).round()
 }
@@ -171,7 +171,7 @@ fn main(a, b) -> (c) {
 #[test]
 fn minmax() -> anyhow::Result<()> {
     let code = r#"
-    fn main(a, b) -> (c) {
+    pub fn main(a, b) -> (c) {
         c = a.min(b)
     }
     "#;
@@ -183,7 +183,7 @@ fn minmax() -> anyhow::Result<()> {
     assert_eq!(a, func(a, b));
 
     let code = r#"
-    fn main(a, b) -> (c) {
+    pub fn main(a, b) -> (c) {
         c = a.max(b)
     }
     "#;
@@ -201,7 +201,7 @@ fn minmax() -> anyhow::Result<()> {
 fn comments() -> anyhow::Result<()> {
     let code = r#"
 //test
-fn main(a, b) -> (c) {//test
+pub fn main(a, b) -> (c) {//test
 //test
     //test
     d = foodd(a, b) + foodd(a, b) //test
@@ -243,7 +243,7 @@ fn foodd(a, b) -> (c) {
 #[test]
 fn multiple_returns() -> anyhow::Result<()> {
     let code = r#"
-    fn main(a, b) -> (e) {
+    pub fn main(a, b) -> (e) {
         c, d = stuff(a, b)
         c, d = d, c
         e, f = if a == b {
@@ -286,7 +286,7 @@ fn multiple_returns() -> anyhow::Result<()> {
 #[test]
 fn multiple_returns_simple() -> anyhow::Result<()> {
     let code = r#"
-    fn main(a, b) -> (e) {
+    pub fn main(a, b) -> (e) {
         c, d = stuff(a, b)
         e = c+ d
     }
@@ -309,7 +309,7 @@ fn multiple_returns_simple() -> anyhow::Result<()> {
 #[test]
 fn bools() -> anyhow::Result<()> {
     let code = r#"
-    fn main(a, b) -> (c) {
+    pub fn main(a, b) -> (c) {
         c = if true {
             a * b
         } else {
@@ -332,7 +332,7 @@ fn bools() -> anyhow::Result<()> {
 #[test]
 fn ifelse_assign() -> anyhow::Result<()> {
     let code = r#"
-    fn main(a, b) -> (c) {
+    pub fn main(a, b) -> (c) {
         c = if a < b {
             a * b
         } else {
@@ -352,7 +352,7 @@ fn ifelse_assign() -> anyhow::Result<()> {
 #[test]
 fn order() -> anyhow::Result<()> {
     let code = r#"
-    fn main(a, b) -> (c) {
+    pub fn main(a, b) -> (c) {
         c = a
     }
 "#;
@@ -368,7 +368,7 @@ fn order() -> anyhow::Result<()> {
 #[test]
 fn array_read_write() -> anyhow::Result<()> {
     let code = r#"
-fn main(arr: &[f32], b) -> () {
+pub fn main(arr: &[f32], b) -> () {
     arr[0] = arr[0] * b
     arr[1] = arr[1] * b
     arr[2] = arr[2] * b
@@ -389,7 +389,7 @@ fn main(arr: &[f32], b) -> () {
 #[test]
 fn negative() -> anyhow::Result<()> {
     let code = r#"
-    fn main(a) -> (c) {
+    pub fn main(a) -> (c) {
         c = -1.0 + a
     }
 "#;
@@ -405,7 +405,7 @@ fn negative() -> anyhow::Result<()> {
 //#[test]
 //fn if_return_types() -> anyhow::Result<()> {
 //    let code = r#"
-//    fn main(a) -> (c) {
+//    pub fn main(a) -> (c) {
 //        f, g, h = if a < 0.0 {
 //            1.0, 1, true
 //        } else {
@@ -439,7 +439,7 @@ fn compiled_graph() -> anyhow::Result<()> {
         c = a.sin()
     }
         
-    fn graph (audio: &[f32]) -> () {
+    pub fn graph(audio: &[f32]) -> () {
         i = 0
         while i <= 7 {
             vINPUT_0 = audio[i]
@@ -504,7 +504,7 @@ fn metadata() -> anyhow::Result<()> {
         c = a.sin()
     }
         
-    fn graph (audio: &[f32]) -> () {
+    pub fn graph(audio: &[f32]) -> () {
         i = 0
         while i <= 7 {
             vINPUT_0 = audio[i]
@@ -549,7 +549,7 @@ fn metadata() -> anyhow::Result<()> {
 #[test]
 fn int_while_loop() -> anyhow::Result<()> {
     let code = r#"
-    fn main(a, b) -> (e) {
+    pub fn main(a, b) -> (e) {
         e = 2.0
         i = 0
         while i < 10 {
@@ -571,7 +571,7 @@ fn int_while_loop() -> anyhow::Result<()> {
 #[test]
 fn int_to_float() -> anyhow::Result<()> {
     let code = r#"
-    fn main(a, b) -> (e) {
+    pub fn main(a, b) -> (e) {
         i = 2
         e = i.f32() * a * b * (2).f32() * 2.0 * (2).f32()
     }
@@ -589,7 +589,7 @@ fn int_to_float() -> anyhow::Result<()> {
 #[test]
 fn float_conversion() -> anyhow::Result<()> {
     let code = r#"
-    fn main(a, b) -> (e) {
+    pub fn main(a, b) -> (e) {
         i_a = a.i64()
         e = if i_a < b.i64() {
             i_a.f32().i64().f32()
@@ -610,7 +610,7 @@ fn float_conversion() -> anyhow::Result<()> {
 #[test]
 fn float_as_bool_error() -> anyhow::Result<()> {
     let code = r#"
-    fn main(a, b) -> (e) {
+    pub fn main(a, b) -> (e) {
         i_a = a
         e_i = if true {
             1
@@ -633,7 +633,7 @@ fn float_as_bool_error() -> anyhow::Result<()> {
 fn if_else_multi() -> anyhow::Result<()> {
     //setup_logging();
     let code = r#"
-fn main() -> () {   
+pub fn main() -> () {   
     a = 0
     b = 0
     c = 0
@@ -668,7 +668,7 @@ fn main() -> () {
 #[test]
 fn array_return_from_if() -> anyhow::Result<()> {
     let code = r#"
-fn main(arr1: &[f32], arr2: &[f32], b) -> () {
+pub fn main(arr1: &[f32], arr2: &[f32], b) -> () {
     arr3 = if b < 100.0 {
         arr1
     } else {
@@ -692,7 +692,7 @@ fn main(arr1: &[f32], arr2: &[f32], b) -> () {
 #[test]
 fn var_type_consistency() -> anyhow::Result<()> {
     let code = r#"
-    fn main(a, b) -> (e) {
+    pub fn main(a, b) -> (e) {
         n = 1
         n1 = n
         n2 = n1
@@ -712,7 +712,7 @@ fn var_type_consistency() -> anyhow::Result<()> {
 #[test]
 fn three_inputs() -> anyhow::Result<()> {
     let code = r#"
-    fn main(a, b, c) -> (e) {
+    pub fn main(a, b, c) -> (e) {
         e = a + b + c
     }
 "#;
@@ -730,7 +730,7 @@ fn three_inputs() -> anyhow::Result<()> {
 #[test]
 fn manual_types() -> anyhow::Result<()> {
     let code = r#"
-fn main(a: f32, b: f32) -> (c: f32) {
+pub fn main(a: f32, b: f32) -> (c: f32) {
     c = a * (a - b) * (a * (2.0 + b))
 }
 "#;
@@ -746,7 +746,7 @@ fn main(a: f32, b: f32) -> (c: f32) {
 #[test]
 fn i64_params() -> anyhow::Result<()> {
     let code = r#"
-fn main(a: f32, b: i64) -> (c: i64) {
+pub fn main(a: f32, b: i64) -> (c: i64) {
     e = a * (a - b.f32()) * (a * (2.0 + b.f32()))
     c = e.i64()
 }
@@ -764,7 +764,7 @@ fn main(a: f32, b: i64) -> (c: i64) {
 fn i64_params_multifunc() -> anyhow::Result<()> {
     //Not currently working, see BLOCKERs in jit.rs
     let code = r#"
-fn main(a: f32, b: i64) -> (c: i64) {
+pub fn main(a: f32, b: i64) -> (c: i64) {
     c = foo(a, b, 2)
 }
 fn foo(a: f32, b: i64, c: i64) -> (d: i64) {
@@ -783,7 +783,7 @@ fn foo(a: f32, b: i64, c: i64) -> (d: i64) {
 #[test]
 fn bool_params() -> anyhow::Result<()> {
     let code = r#"
-fn main(a: f32, b: bool) -> (c: f32) {
+pub fn main(a: f32, b: bool) -> (c: f32) {
     c = if b {
         a
     } else {
@@ -803,7 +803,7 @@ fn main(a: f32, b: bool) -> (c: f32) {
 #[test]
 fn if_else_return_tuple_assignment() -> anyhow::Result<()> {
     let code = r#"
-fn main() -> () {  
+pub fn main() -> () {  
     a, b = if true {1.0} else {-1.0}, if true {-1.0} else {1.0}
     a.assert_eq(1.0)
     b.assert_eq(-1.0)
@@ -815,55 +815,55 @@ fn main() -> () {
 #[test]
 fn logical_operators() -> anyhow::Result<()> {
     let code = r#"
-fn and(a: bool, b: bool) -> (c: bool) {
+pub fn and(a: bool, b: bool) -> (c: bool) {
     c = a && b
 }
-fn or(a: bool, b: bool) -> (c: bool) {
+pub fn or(a: bool, b: bool) -> (c: bool) {
     c = a || b
 }
-fn gt(a: bool, b: bool) -> (c: bool) {
+pub fn gt(a: bool, b: bool) -> (c: bool) {
     c = a > b
 }
-fn ge(a: bool, b: bool) -> (c: bool) {
+pub fn ge(a: bool, b: bool) -> (c: bool) {
     c = a >= b
 }
-fn lt(a: bool, b: bool) -> (c: bool) {
+pub fn lt(a: bool, b: bool) -> (c: bool) {
     c = a < b
 }
-fn le(a: bool, b: bool) -> (c: bool) {
+pub fn le(a: bool, b: bool) -> (c: bool) {
     c = a <= b
 }
-fn eq(a: bool, b: bool) -> (c: bool) {
+pub fn eq(a: bool, b: bool) -> (c: bool) {
     c = a == b
 }
-fn ne(a: bool, b: bool) -> (c: bool) {
+pub fn ne(a: bool, b: bool) -> (c: bool) {
     c = a != b
 }
-fn ifthen() -> (c: bool) {
+pub fn ifthen() -> (c: bool) {
     c = false
     if 1.0 < 2.0 && 2.0 < 3.0 {
         c = true
     }
 }
-fn ifthen2() -> (c: bool) {
+pub fn ifthen2() -> (c: bool) {
     c = false
     if 1.0 < 2.0 || 2.0 < 1.0 {
         c = true
     }
 }
-fn ifthenparen() -> (c: bool) {
+pub fn ifthenparen() -> (c: bool) {
     c = false
     if (1.0 < 2.0) && (2.0 < 3.0) {
         c = true
     }
 }
-fn ifthennestedparen() -> (c: bool) {
+pub fn ifthennestedparen() -> (c: bool) {
     c = false
     if ((1.0 < 2.0) && (2.0 < 3.0) && true) {
         c = true
     }
 }
-fn parenassign() -> (c: bool) {
+pub fn parenassign() -> (c: bool) {
     c = ((1.0 < 2.0) && (2.0 < 3.0) && true)
 }
 "#;
@@ -925,43 +925,43 @@ fn parenassign() -> (c: bool) {
 #[test]
 fn unary_not() -> anyhow::Result<()> {
     let code = r#"
-fn direct() -> (c: bool) {
+pub fn direct() -> (c: bool) {
     c = !true
 }
-fn direct2() -> (c: bool) {
+pub fn direct2() -> (c: bool) {
     c = !false
 }
-fn direct3() -> (c: bool) {
+pub fn direct3() -> (c: bool) {
     c = !(false)
 }
 fn not(a: bool) -> (c: bool) {
     c = !a
 }
-fn not2(a: bool) -> (c: bool) {
+pub fn not2(a: bool) -> (c: bool) {
     c = !(a)
 }
-fn ifthen() -> (c: bool) {
+pub fn ifthen() -> (c: bool) {
     c = false
     if !(false) {
         c = true
     }
 }
-fn ifthen2() -> (c: bool) {
+pub fn ifthen2() -> (c: bool) {
     c = false
     if !(!(false || !false)) {
         c = true
     }
 }
-fn ifthen3() -> (c: bool) {
+pub fn ifthen3() -> (c: bool) {
     c = true
     if !(!(1.0 < 2.0) && !(2.0 < 3.0)) {
         c = false
     }
 }
-fn nested() -> (c: bool) {
+pub fn nested() -> (c: bool) {
     c = !(!(1.0 < 2.0) && !(2.0 < 3.0))
 }
-fn parenassign() -> (c: bool) {
+pub fn parenassign() -> (c: bool) {
     c = !((1.0 < 2.0) && (2.0 < 3.0) && true)
 }
 "#;
@@ -1008,7 +1008,7 @@ fn extern_func() -> anyhow::Result<()> {
 extern fn mult(a: f32, b: f32) -> (c: f32) {}
 extern fn dbg(a: f32) -> () {}
 
-fn main(a: f32, b: f32) -> (c: f32) {
+pub fn main(a: f32, b: f32) -> (c: f32) {
     c = mult(a, b)
     dbg(a)
 }
@@ -1038,7 +1038,7 @@ extern "C" fn prt2(s: sarus_std_lib::SarusSlice<u8>) {
 #[test]
 fn create_string() -> anyhow::Result<()> {
     let code = "
-fn main(a: f32, b: f32) -> (c: f32) {
+pub fn main(a: f32, b: f32) -> (c: f32) {
     print(\"HELLO\n\")
     print(\"WORLD\n\")
     c = a
@@ -1072,7 +1072,7 @@ struct Point {
     y: f32,
     z: f32,
 }
-fn main(a: f32) -> (c: f32) {
+pub fn main(a: f32) -> (c: f32) {
     p = Point {
         x: a,
         y: 200.0,
@@ -1108,7 +1108,7 @@ struct Point {
 fn length(self: Point) -> (r: f32) {
     r = (self.x.powf(2.0) + self.y.powf(2.0) + self.z.powf(2.0)).sqrt()
 }
-fn main(a: f32) -> (c: f32) {
+pub fn main(a: f32) -> (c: f32) {
     p = Point {
         x: a,
         y: 200.0,
@@ -1177,7 +1177,7 @@ fn length(self: Point) -> (r: f32) {
     r = (self.x.powf(2.0) + self.y.powf(2.0) + self.z.powf(2.0)).sqrt()
 }
 
-fn main(n: f32) -> (c: f32) {
+pub fn main(n: f32) -> (c: f32) {
     p1 = Point {
         x: n,
         y: 200.0,
@@ -1273,7 +1273,7 @@ struct Point {
     z: f32,
 }
 
-fn main(n: f32) -> () {
+pub fn main(n: f32) -> () {
     p1 = Point {
         x: n,
         y: 200.0,
@@ -1334,7 +1334,7 @@ fn print(self: Bar) -> () {
     "}".println()
 }
 
-fn main(n: f32) -> () {
+pub fn main(n: f32) -> () {
     pe = Bar {
         x: n,
     }
@@ -1377,7 +1377,7 @@ fn square(self: f32) -> (r: f32) {
 fn square(self: i64) -> (r: i64) {
     r = self * self
 }
-fn main(a: f32, b: i64) -> (c: f32) {
+pub fn main(a: f32, b: i64) -> (c: f32) {
     c = a.square() + b.square().f32()
 }
 "#;
@@ -1393,7 +1393,7 @@ fn main(a: f32, b: i64) -> (c: f32) {
 #[test]
 fn stacked_paren() -> anyhow::Result<()> {
     let code = r#"
-fn main(a: f32) -> (c: bool) {
+pub fn main(a: f32) -> (c: bool) {
     d = a.i64().f32().i64().f32()
     e = ((((d).i64()).f32()).i64()).f32()
     c = d == e
@@ -1412,7 +1412,7 @@ fn main(a: f32) -> (c: bool) {
 //    //Not currently working: Unsupported type for imin instruction: i64
 //    //https://github.com/bytecodealliance/wasmtime/issues/3370
 //    let code = r#"
-//    fn main() -> (e) {
+//    pub fn main() -> (e) {
 //        c = imin(1, 2)
 //        //d = imax(3, 4)
 //        //f = c * d
@@ -1455,7 +1455,7 @@ fn length(self: Point) -> (r: f32) {
     r = (self.x.powf(2.0) + self.y.powf(2.0) + self.z.powf(2.0)).sqrt()
 }
 
-fn main(n: f32) -> (c: f32) {
+pub fn main(n: f32) -> (c: f32) {
     p1 = Point {
         x: n,
         y: 200.0,
@@ -1511,7 +1511,7 @@ fn set_to_0(point: Point) -> () {
     point.z = 0.0
 }
 
-fn main(n: f32) -> () {
+pub fn main(n: f32) -> () {
     p1 = Point {
         x: n,
         y: 200.0,
@@ -1594,7 +1594,7 @@ fn length(self: Line) -> (r: f32) {
          (self.a.z - self.b.z).powf(2.0)).sqrt()
 }
 
-fn main(l1: Line) -> (c: f32) {
+pub fn main(l1: Line) -> (c: f32) {
     c = l1.length()
 }
 "#;
@@ -1644,7 +1644,7 @@ struct Misc {
     b5: bool,
 }
 
-fn main(m: Misc) -> () {
+pub fn main(m: Misc) -> () {
     m.b1.println()
     m.b2.println()
     m.f1.println()
@@ -1709,7 +1709,7 @@ struct Misc2 {
     b3: bool,
 }
 
-fn main(m2: Misc2) -> () {
+pub fn main(m2: Misc2) -> () {
     m2.b1.assert_eq(true)
     m2.m.b1.assert_eq(true)
     m2.m.b2.assert_eq(false)
@@ -1784,7 +1784,7 @@ struct Misc3 {
     b3: bool,
 }
 
-fn main(m3: Misc3) -> () {
+pub fn main(m3: Misc3) -> () {
     m3.b1.assert_eq(true)
     m3.m2.b1.assert_eq(true)
     m3.m2.m.b1.assert_eq(true)
@@ -1865,7 +1865,7 @@ struct Stuff {
     z: f32,
     i: i64,
 }
-fn main(a: f32) -> (c: Stuff) {
+pub fn main(a: f32) -> (c: Stuff) {
     c = Stuff {
         w: true,
         x: a,
@@ -1874,7 +1874,7 @@ fn main(a: f32) -> (c: Stuff) {
         i: 123,
     }
 }
-fn main2(a: f32) -> (c: Stuff) {
+pub fn main2(a: f32) -> (c: Stuff) {
     s = Stuff {
         w: true,
         x: a,
@@ -1884,15 +1884,15 @@ fn main2(a: f32) -> (c: Stuff) {
     }
     c = s
 }
-fn main3(a: f32) -> (c: Stuff) {
+pub fn main3(a: f32) -> (c: Stuff) {
     s = main2(a)
     c = s
 }
-fn main4(a: f32) -> (c: Stuff) {
+pub fn main4(a: f32) -> (c: Stuff) {
     s = returns_a_stuff(a)
     c = s
 }
-fn main5(a: f32) -> (c: Stuff) {
+pub fn main5(a: f32) -> (c: Stuff) {
     s = returns_a_stuff(a)
     //TODO !s.w is not supported because unary ! and - only work with unary exprs as operand
     //This was required to get the order of operations to be correct for things like two + -four + two
@@ -2025,7 +2025,7 @@ struct Stuff {
     z: f32,
     i: i64,
 }
-fn puts_a_stuff_there(there: Stuff) -> () {
+pub fn puts_a_stuff_there(there: Stuff) -> () {
     there = Stuff {
         w: true,
         x: 100.0,
@@ -2034,14 +2034,14 @@ fn puts_a_stuff_there(there: Stuff) -> () {
         i: 123,
     }
 }
-fn takes_a_stuff(s: Stuff) -> () {
+pub fn takes_a_stuff(s: Stuff) -> () {
     s.w.assert_eq(true)
     s.x.assert_eq(100.0)
     s.y.assert_eq(200.0)
     s.z.assert_eq(300.0)
     s.i.assert_eq(123)
 }
-fn size_of_stuff() -> (size: i64) {
+pub fn size_of_stuff() -> (size: i64) {
     size = Stuff::size
 }
 "#;
@@ -2080,7 +2080,7 @@ struct AudioData {
     len: i64,
 }
 
-fn process(audio: AudioData) -> () {
+pub fn process(audio: AudioData) -> () {
     i = 0
     left = audio.left
     while i < audio.len {
@@ -2125,7 +2125,7 @@ struct AudioData {
     len: i64,
 }
 
-fn process(audio: AudioData) -> () {
+pub fn process(audio: AudioData) -> () {
     i = 0
     while i < audio.len {
         audio.right[i] = i.f32()  
@@ -2170,7 +2170,7 @@ struct BoolData {
     len: i64,
 }
 
-fn process(audio: BoolData) -> () {
+pub fn process(audio: BoolData) -> () {
     i = 0
     while i < audio.len {
         audio.right[i] = (i.f32()).rem_euclid(2.0) == 1.0
@@ -2230,7 +2230,7 @@ struct AudioSamples {
     len: i64,
 }
 
-fn process(audio: AudioSamples) -> () {
+pub fn process(audio: AudioSamples) -> () {
     i = 0
     while i < audio.len {
         sample = audio.samples[i]
@@ -2337,7 +2337,7 @@ fn FilterParams::lowshelf(cutoff_hz, gain_db, q_value) -> (params: FilterParams)
     }
 }
 
-fn process(audio: AudioData) -> () {
+pub fn process(audio: AudioData) -> () {
     lowshelf = FilterParams::lowshelf(1000.0, -10.0, 2.0)
     i = 0
     while i < audio.len {
@@ -2388,7 +2388,7 @@ struct ProcessState {
 }
 
 
-fn process(audio: AudioData) -> () {
+pub fn process(audio: AudioData) -> () {
     filter_l = Filter { ic1eq: 0.0, ic2eq: 0.0, }
     filter_r = Filter { ic1eq: 0.0, ic2eq: 0.0, }
     state = ProcessState {
@@ -2422,7 +2422,7 @@ fn set_val(self: Filter) -> () {
 #[test]
 fn src_line() -> anyhow::Result<()> {
     let code = r#"
-fn main() -> () {
+pub fn main() -> () {
     src_line().assert_eq(3)
     src_line().assert_eq(4) src_line().assert_eq(4)
     //
@@ -2436,7 +2436,7 @@ fn main() -> () {
 #[test]
 fn const_size() -> anyhow::Result<()> {
     let code = r#"
-fn main() -> () {
+pub fn main() -> () {
     f32::size.println()
 }
 "#;
@@ -2461,7 +2461,7 @@ struct A {
     d: i64,
 }
 
-fn main() -> () {
+pub fn main() -> () {
     s = A {
         a: 1.0,
         b: 2.0,
@@ -2529,7 +2529,7 @@ struct A {
     d: i64,
 }
 
-fn main() -> () {
+pub fn main() -> () {
     i = 0
     s = A {
         a: 1.0,
@@ -2589,7 +2589,7 @@ struct B {
     f: f32,
 }
 
-fn returns_a_fixed_array_in_a_struct() -> (arr: B) {    
+pub fn returns_a_fixed_array_in_a_struct() -> (arr: B) {    
     i = 0
     s = A {
         a: 1.0,
@@ -2624,7 +2624,7 @@ struct A {
 }
 
 
-fn main() -> () {
+pub fn main() -> () {
     n = returns_a_fixed_array_in_a_struct().arr
     n[0].a.println()
     i = 0
@@ -2763,7 +2763,7 @@ struct B {
     f: f32,
 }
 
-fn returns_a_fixed_array_in_a_struct() -> (arr: B) {    
+pub fn returns_a_fixed_array_in_a_struct() -> (arr: B) {    
     i = 0
     s = A {
         a: 1.0,
@@ -2797,7 +2797,7 @@ struct A {
     d: i64,
 }
 
-fn main() -> () {
+pub fn main() -> () {
     returns_a_fixed_array_in_a_struct()
     returns_a_fixed_array_in_a_struct()
     returns_a_fixed_array_in_a_struct()
@@ -2824,7 +2824,7 @@ struct C {
     arr: [i64; 10],
 }
 
-fn returns_a_fixed_array_in_a_struct() -> (struct_of_arr: C) {    
+pub fn returns_a_fixed_array_in_a_struct() -> (struct_of_arr: C) {    
     i = 0
     n = [1; 10]
     while i < 10 {
@@ -2841,7 +2841,7 @@ fn returns_a_fixed_array_in_a_struct() -> (struct_of_arr: C) {
     }
 }
 
-fn main() -> () {
+pub fn main() -> () {
     struct_of_arr = returns_a_fixed_array_in_a_struct()
     struct_of_arr.arr[0].println()
     i = 0
@@ -2870,7 +2870,7 @@ fn modify_fixed_array_arg() -> anyhow::Result<()> {
     //setup_logging();
     let code = r#"
 
-fn modifies_an_array(arr: [i64; 10]) -> () {    
+pub fn modifies_an_array(arr: [i64; 10]) -> () {    
     i = 0
     while i < 10 {
         arr[i] = i
@@ -2878,7 +2878,7 @@ fn modifies_an_array(arr: [i64; 10]) -> () {
     }
 }
 
-fn modifies_a_unbounded_array(arr: &[i64]) -> () {    
+pub fn modifies_a_unbounded_array(arr: &[i64]) -> () {    
     i = 0
     while i < 10 {
         arr[i] = i
@@ -2886,7 +2886,7 @@ fn modifies_a_unbounded_array(arr: &[i64]) -> () {
     }
 }
 
-fn main() -> () {
+pub fn main() -> () {
     n = [1; 10]
     modifies_an_array(n)
     i = 0
@@ -2920,7 +2920,7 @@ fn modify_fixed_array_assign_to_arg() -> anyhow::Result<()> {
     //setup_logging();
     let code = r#"
 
-fn modifies_an_array(arr: [i64; 10]) -> () {    
+pub fn modifies_an_array(arr: [i64; 10]) -> () {    
     arr = [2; 10]
 }
 
@@ -2938,7 +2938,7 @@ fn modifies_an_array(arr: [i64; 10]) -> () {
 fn nested_fixed_array() -> anyhow::Result<()> {
     //setup_logging();
     let code = r#"
-fn modifies_an_array(arr: [[i64; 10]; 10]) -> () {    
+pub fn modifies_an_array(arr: [[i64; 10]; 10]) -> () {    
     arr = [[2; 10]; 10]
     a = arr[0]
     a[0] = 5
@@ -2975,7 +2975,7 @@ fn make_b(a: [i64; 10]) -> (r: B) {
         arr: a,
     }
 }
-fn modifies_an_array(b: B) -> () {   
+pub fn modifies_an_array(b: B) -> () {   
     b.arr[0] = 5
     c = make_b(b.arr)
     c.arr[0] += 1 * 2
@@ -2999,12 +2999,12 @@ fn inline_function() -> anyhow::Result<()> {
     //setup_logging();
     let code = r#"
 
-inline fn add(x, y) -> (z) {
+pub inline fn add(x, y) -> (z) {
     f = x * y
     z = x + y * f
 }
 
-fn main() -> () {    
+pub fn main() -> () {    
     a = 5.0
     b = 6.0
     c = add(a, b)
@@ -3038,7 +3038,7 @@ fn init_process_state(state: ProcessState) -> () {
     }
 }
 
-fn process(params: SarusDSPModelParams, audio: AudioData, 
+pub fn process(params: SarusDSPModelParams, audio: AudioData, 
            state: ProcessState, dbg: Debugger) -> () {
     i = 0
     left = audio.in_left
@@ -3064,7 +3064,7 @@ struct Filter {
     ic2eq,
 }
 
-inline fn process(self: Filter, audio, c: Coefficients) -> (audio_out) {
+pub inline fn process(self: Filter, audio, c: Coefficients) -> (audio_out) {
     v3 = audio - self.ic2eq
     v1 = c.a1 * self.ic1eq + c.a2 * v3
     v2 = self.ic2eq + c.a2 * self.ic1eq + c.a3 * v3
@@ -3125,7 +3125,7 @@ fn float_to_int(a: f32) -> (c: i64) {
     }
     c.assert_eq(n)
 }
-fn main(a: f32) -> () {
+pub fn main(a: f32) -> () {
     float_to_int(0.0).assert_eq(0)
     float_to_int(1.0).assert_eq(1)
     float_to_int(2.0).assert_eq(2)
@@ -3180,7 +3180,7 @@ fn float_to_int2(a: f32) -> (c: i64) {
     }
     c.assert_eq(n)
 }
-fn main(a: f32) -> () {
+pub fn main(a: f32) -> () {
     float_to_int(0.0).assert_eq(0)
     float_to_int(1.0).assert_eq(1)
     float_to_int(2.0).assert_eq(2)
@@ -3220,7 +3220,7 @@ fn test_include() -> anyhow::Result<()> {
     let code = r#"
 include "./resources/include_test.sarus"
 
-fn main() -> () {    
+pub fn main() -> () {    
     a = 5.0
     b = 6.0
     c = add(a, b)
@@ -3240,7 +3240,7 @@ fn test_include_redundant() -> anyhow::Result<()> {
 include "./resources/include_test.sarus"
 include "./resources/include_test2.sarus" //Should be skipped
 
-fn main() -> () {    
+pub fn main() -> () {    
     a = 5.0
     b = 6.0
     c = add(a, b)
@@ -3252,1696 +3252,3447 @@ fn main() -> () {
     only_run_func_with_importer(ast, Some(file_index_table), |_, _| {})
 }
 
-//#[cfg(test)]
-mod inline_closures {
-
-    use super::*;
-
-    #[test]
-    fn basic() -> anyhow::Result<()> {
-        //setup_logging();
-        let code = r#"
-fn main() -> () {
-    add|| -> () {
-        c = c * 2.0
-    }
-    stuff|e| -> (f) {
-        c = c * 2.0 * e
-        f = c * e
+#[test]
+fn include_data() -> anyhow::Result<()> {
+    //setup_logging();
+    let code = r#"
+fn sum_table() -> (r: f32) {
+    table = include_f32("./resources/include_data_f32.raw")
+    r = 0.0
+    i = 0
+    while i < table.len() {
+        r += table[i]
+        i += 1
     }
-    a = 5.0
-    b = 6.0
-    c = 5.0 + 6.0
-    c.assert_eq(11.0)
-    add()
-    c.assert_eq(22.0)
-    j = stuff(3.0)
-    c.assert_eq(132.0)
-    j.assert_eq(396.0)
 }
-"#;
-
-        only_run_func(code)
-    }
 
-    #[test]
-    fn nested() -> anyhow::Result<()> {
-        //setup_logging();
-        let code = r#"
-inline fn stuff1(a, b) -> (c, k) {
-    c = a + b
-    stuff2|d| -> (f) { 
-        c = c * d
-        f = c + 5.0
-    }
-    k = stuff2(3.0)
+pub fn main() -> (r: f32) {
+    bytes = include_bytes("./resources/include_data.bin")
+    bytes.len().assert_eq(4)
+    bytes[3].assert_eq(250u8)
+    text = include_str("./resources/include_data.txt")
+    text.len().assert_eq(11)
+    text[0].assert_eq(104u8)
+    ints = include_i64("./resources/include_data_i64.raw")
+    ints[0].assert_eq(7)
+    ints[1].assert_eq(-3)
+    r = sum_table() + include_f32("./resources/include_data_f32.raw")[1]
+}
+"#;
+    let (ast, file_index_table) = parse_with_context(code, &get_test_dir().join("test.sarus"))?;
+    let mut jit =
+        default_std_jit_from_code_with_importer(ast, Some(file_index_table), |_, _| {}, true)?;
+    let func_ptr = jit.get_func("main")?;
+    let func = unsafe { mem::transmute::<_, extern "C" fn() -> f32>(func_ptr) };
+    assert_eq!(1.5, func());
+    Ok(())
 }
 
-fn main() -> () {    
-    a = 5.0
-    b = 6.0
-    h, i = stuff1(a, b)
-    h.assert_eq(33.0)
-    i.assert_eq(38.0)
+#[test]
+fn include_data_errors() -> anyhow::Result<()> {
+    let missing = r#"
+pub fn main() -> () {
+    a = include_bytes("./resources/does_not_exist.bin")
 }
 "#;
-
-        only_run_func(code)
-    }
-
-    #[test]
-    fn nested2() -> anyhow::Result<()> {
-        //setup_logging();
-        let code = r#"
-fn main() -> () {
-    j = 0.0
-    add|| -> () {
-        c = c * 2.0
-        stuff|e| -> (f) { 
-            c.assert_eq(22.0)
-            c = c * 2.0 * e
-            c.assert_eq(132.0)
-            f = c * e
-        }
-        j = stuff(3.0)
-    }
-    a = 5.0
-    b = 6.0
-    c = 5.0 + 6.0
-    c.assert_eq(11.0)
-    add()
-    c.assert_eq(132.0)
-    j.assert_eq(396.0)
+    let (ast, file_index_table) = parse_with_context(missing, &get_test_dir().join("test.sarus"))?;
+    assert!(
+        default_std_jit_from_code_with_importer(ast, Some(file_index_table), |_, _| {}, true)
+            .is_err()
+    );
+    let wrong_size = r#"
+pub fn main() -> () {
+    a = include_f32("./resources/include_data.txt")
 }
 "#;
+    let (ast, file_index_table) =
+        parse_with_context(wrong_size, &get_test_dir().join("test.sarus"))?;
+    assert!(
+        default_std_jit_from_code_with_importer(ast, Some(file_index_table), |_, _| {}, true)
+            .is_err()
+    );
+    Ok(())
+}
 
-        only_run_func(code)
-    }
+#[test]
+fn include_namespaced() -> anyhow::Result<()> {
+    //setup_logging();
+    let code = r#"
+include "./resources/namespace_test.sarus" as filters
 
-    #[test]
-    fn nested3() -> anyhow::Result<()> {
-        //setup_logging();
-        let code = r#"
-inline fn stuff1(a, b) -> (c, k) {
-    c = a + b
-    stuff2|d| -> (f) { 
-        c = c * d
-        f = c + 5.0
-    }
-    k = stuff2(3.0)
+pub fn add(x: f32, y: f32) -> (z: f32) {
+    z = x + y
 }
 
-fn main() -> () {
-    j = 0.0
-    add|| -> () {
-        c = c * 2.0
-        stuff|e| -> (f) { 
-            c.assert_eq(22.0)
-            c = c * 2.0 * e
-            c.assert_eq(132.0)
-            f = c * e
-            u, l = stuff1(c, e)
-            u.assert_eq(405.0)
-            l.assert_eq(410.0)
-        }
-        j = stuff(3.0)
-    }
-    a = 5.0
-    b = 6.0
-    c = 5.0 + 6.0
-    c.assert_eq(11.0)
-    add()
-    c.assert_eq(132.0)
-    j.assert_eq(396.0)
+pub fn main() -> (r: f32) {
+    b = filters::Biquad::new(0.5, 10)
+    b.order.assert_eq(filters::Biquad::MAX_ORDER)
+    b.process(1.0).assert_eq(4.0)
+    filters::lowpass(3.0, 0.5).assert_eq(3.0)
+    filters::add(2.0, 3.0).assert_eq(filters::mult(2.0, 3.0))
+    filters::mode_scale(filters::Mode::High()).assert_eq(10.0)
+    r = add(filters::GAIN, 1.0)
 }
 "#;
+    let (ast, file_index_table) = parse_with_context(code, &get_test_dir().join("test.sarus"))?;
+    let mut jit =
+        default_std_jit_from_code_with_importer(ast, Some(file_index_table), |_, _| {}, true)?;
+    let func_ptr = jit.get_func("main")?;
+    let func = unsafe { mem::transmute::<_, extern "C" fn() -> f32>(func_ptr) };
+    assert_eq!(3.0, func());
+    Ok(())
+}
 
-        only_run_func(code)
-    }
-
-    #[test]
-    fn passing() -> anyhow::Result<()> {
-        //setup_logging();
-        let code = r#"
-always_inline fn run_some_closure(n, some_closure: |e| -> ()) -> () {
-    some_closure(n * 5.0)
+#[test]
+fn duplicate_definitions() -> anyhow::Result<()> {
+    let code = r#"
+pub fn add(x: f32, y: f32) -> (z: f32) {
+    z = x + y
 }
-fn main() -> () {
-    stuff|e| -> () {
-        c *= e
-    }
-    c = 5.0 + 6.0
-    run_some_closure(2.0, stuff)
-    c.assert_eq(110.0)
+pub fn add(x: f32, y: f32) -> (z: f32) {
+    z = x - y
 }
-"#;
-
-        only_run_func(code)
-    }
-
-    #[test]
-    fn passing_with_return() -> anyhow::Result<()> {
-        //setup_logging();
-        let code = r#"
-always_inline fn run_some_closure(n, some_closure: |e| -> (f)) -> (f) {
-    f = some_closure(n * 5.0)
+pub fn main() -> () {
 }
-fn main() -> () {
-    stuff|e| -> (f) {
-        c *= e
-        f = c * 2.0
-    }
-    c = 5.0 + 6.0
-    j = run_some_closure(2.0, stuff)
-    c.assert_eq(110.0)
-    j.assert_eq(220.0)
+"#;
+    assert!(default_std_jit_from_code(code, true).is_err());
+    let code = r#"
+struct Point { x, y, }
+struct Point { x, y, z, }
+pub fn main() -> () {
 }
 "#;
+    assert!(default_std_jit_from_code(code, true).is_err());
+    let code = r#"
+include "./resources/include_test.sarus"
 
-        only_run_func(code)
-    }
-
-    #[test]
-    fn passing_no_param() -> anyhow::Result<()> {
-        //setup_logging();
-        let code = r#"
-always_inline fn run_some_closure(some_closure: || -> ()) -> () {
-    some_closure()
+pub fn add(x: f32, y: f32) -> (z: f32) {
+    z = x + y
 }
-fn main() -> () {
-    stuff|| -> () {
-        c *= 2.0
-    }
-    c = 5.0 + 6.0
-    run_some_closure(stuff)
-    c.assert_eq(22.0)
+pub fn main() -> () {
 }
 "#;
-
-        only_run_func(code)
-    }
-
-    #[test]
-    fn anonymous_passing() -> anyhow::Result<()> {
-        //setup_logging();
-        let code = r#"
-always_inline fn run_some_closure(n, some_closure: |f32| -> ()) -> () {
-    some_closure(n * 5.0)
+    let (ast, file_index_table) = parse_with_context(code, &get_test_dir().join("test.sarus"))?;
+    let err = default_std_jit_from_code_with_importer(ast, Some(file_index_table), |_, _| {}, true)
+        .err()
+        .unwrap()
+        .to_string();
+    assert!(err.contains("include_test.sarus:3"), "{}", err);
+    assert!(err.contains("test.sarus:4"), "{}", err);
+    let code = r#"
+fn square(self: f32) -> (y: f32) {
+    y = self * self
 }
-fn main() -> () {
-    c = 5.0 + 6.0
-    run_some_closure(2.0, |e| -> () {c *= e})
-    c.assert_eq(110.0)
+fn square(self: f32) -> (y: f32) {
+    y = self
+}
+pub fn main() -> () {
 }
 "#;
-        only_run_func(code)
-    }
-
-    #[test]
-    fn anonymous_passing_with_return() -> anyhow::Result<()> {
-        //setup_logging();
-        let code = r#"
-always_inline fn run_some_closure(n, some_closure: |e| -> (f)) -> (f) {
-    f = some_closure(n * 5.0)
+    let error = default_std_jit_from_code(code, true).err().unwrap();
+    let diagnostic = &error
+        .downcast_ref::<diagnostics::Diagnostics>()
+        .unwrap()
+        .diagnostics[0];
+    assert_eq!(Some(5), diagnostic.primary_span.as_ref().unwrap().line);
+    assert_eq!(Some(2), diagnostic.secondary_spans[0].line);
+    let code = r#"
+fn sin(self: f32) -> (y: f32) {
+    y = self
 }
-fn main() -> () {
-    c = 5.0 + 6.0
-    j = run_some_closure(2.0, |e| -> (f) {
-        c *= e
-        f = c * 2.0
-    })
-    c.assert_eq(110.0)
-    j.assert_eq(220.0)
+pub fn main() -> () {
 }
 "#;
+    let error = default_std_jit_from_code(code, true).err().unwrap();
+    let diagnostic = &error
+        .downcast_ref::<diagnostics::Diagnostics>()
+        .unwrap()
+        .diagnostics[0];
+    assert_eq!(Some(2), diagnostic.primary_span.as_ref().unwrap().line);
+    assert!(diagnostic.secondary_spans.is_empty());
+    assert!(
+        diagnostic.notes[0].contains("fn f32.sin"),
+        "{:?}",
+        diagnostic
+    );
+    Ok(())
+}
 
-        only_run_func(code)
-    }
+#[test]
+fn override_definitions() -> anyhow::Result<()> {
+    let code = r#"
+include "./resources/include_test.sarus"
 
-    #[test]
-    fn anonymous_passing_with_return_calls_inline() -> anyhow::Result<()> {
-        //setup_logging();
-        let code = r#"
-inline fn mult(a, b) -> (c) {
-    c = a * b
+override pub fn add(x: f32, y: f32) -> (z: f32) {
+    z = x + y
 }
 
-inline fn multj(a, b) -> (c) {
-    j = 6.0
-    c = mult(a, b) + j
-    c -= j
-}
+override struct Point { x, y, }
+struct Point { x, y, z, }
 
-always_inline fn run_some_closure(n, some_closure: |e| -> (f)) -> (f) {
-    f = some_closure(n * 5.0)
-}
-
-fn main() -> () {
-    c = 5.0 + 6.0
-    j = run_some_closure(2.0, |e| -> (f) {
-        c *= e
-        f = multj(c, 2.0)
-    })
-    c.assert_eq(110.0)
-    j.assert_eq(220.0)
+pub fn main() -> (r: f32) {
+    p = Point { x: 1.0, y: 2.0, }
+    r = add(p.x, p.y)
 }
 "#;
+    let (ast, file_index_table) = parse_with_context(code, &get_test_dir().join("test.sarus"))?;
+    let mut jit =
+        default_std_jit_from_code_with_importer(ast, Some(file_index_table), |_, _| {}, true)?;
+    let func_ptr = jit.get_func("main")?;
+    let func = unsafe { mem::transmute::<_, extern "C" fn() -> f32>(func_ptr) };
+    assert_eq!(3.0, func());
 
-        only_run_func(code)
-    }
-
-    #[test]
-    fn anonymous_passing2() -> anyhow::Result<()> {
-        //setup_logging();
-        let code = r#"
-always_inline fn run_some_closure(n, some_closure: |e| -> ()) -> () {
-    some_closure(n * 5.0)
+    let code = r#"
+override fn not_defined_elsewhere() -> () {
 }
-
-always_inline fn stuff(n, some_closure2: |e| -> ()) -> () {
-    run_some_closure(n, some_closure2)
+pub fn main() -> () {
 }
-
-fn main() -> () {
-    c = 5.0 + 6.0
-    stuff(2.0, |e| -> () {c *= e})
-    c.assert_eq(110.0)
+"#;
+    assert!(default_std_jit_from_code(code, true).is_err());
+    let code = r#"
+pub fn add(x: f32, y: f32) -> (z: f32) {
+    z = x + y
+}
+override pub fn add(x: f32, y: f32) -> (z: f32) {
+    z = x - y
+}
+override pub fn add(x: f32, y: f32) -> (z: f32) {
+    z = x * y
+}
+pub fn main() -> () {
 }
 "#;
-        only_run_func(code)
-    }
-
-    #[test]
-    fn inline_func_has_anonymous_passing() -> anyhow::Result<()> {
-        //setup_logging();
-        let code = r#"
-always_inline fn run_some_closure(n, some_closure: |e| -> ()) -> () {
-    some_closure(n * 5.0)
+    assert!(default_std_jit_from_code(code, true).is_err());
+    Ok(())
 }
 
-inline fn stuff() -> () {
-    c = 5.0 + 6.0
-    run_some_closure(2.0, |e| -> () {c *= e})
-    c.assert_eq(110.0)
+#[test]
+fn private_functions() -> anyhow::Result<()> {
+    let code = r#"
+fn gain(x: f32) -> (y: f32) {
+    y = x * 0.5
+}
+pub fn process(x: f32) -> (y: f32) {
+    y = gain(x)
+}
+"#;
+    let mut jit = default_std_jit_from_code(code, true)?;
+    assert!(jit.get_func("gain").is_err());
+    assert!(jit.get_func("not_defined").is_err());
+    let func_ptr = jit.get_func("process")?;
+    let func = unsafe { mem::transmute::<_, extern "C" fn(f32) -> f32>(func_ptr) };
+    assert_eq!(2.0, func(4.0));
+    Ok(())
 }
 
-fn main() -> () {
-    stuff()
+#[test]
+fn dead_function_elimination() -> anyhow::Result<()> {
+    let code = r#"
+fn unused() -> () {
+    not_a_function()
+}
+fn double(self: f32) -> (y: f32) {
+    y = self * 2.0
+}
+fn used(x: f32) -> (y: f32) {
+    y = x.double()
+}
+fn helper() -> (y: f32) {
+    y = used(1.0)
+}
+pub fn main() -> (r: f32) {
+    r = used(2.0)
 }
 "#;
+    let mut jit = default_std_jit_from_code(code, true)?;
+    assert!(jit.clif.contains_key("used"));
+    assert!(jit.clif.contains_key("f32.double"));
+    assert!(!jit.clif.contains_key("unused"));
+    assert!(!jit.clif.contains_key("helper"));
+    let func_ptr = jit.get_func("main")?;
+    let func = unsafe { mem::transmute::<_, extern "C" fn() -> f32>(func_ptr) };
+    assert_eq!(4.0, func());
+
+    let mut ast = parse(code)?;
+    let mut jit_builder = jit::new_jit_builder();
+    sarus_std_lib::append_std(&mut ast, &mut jit_builder);
+    let mut jit = jit::JIT::from(jit_builder, true);
+    jit.set_entry_points(&["helper"]);
+    jit.translate(ast, None)?;
+    assert!(jit.clif.contains_key("helper"));
+    assert!(jit.clif.contains_key("used"));
+    assert!(!jit.clif.contains_key("main"));
+    Ok(())
+}
 
-        only_run_func(code)
+#[test]
+fn deep_stack_sizing() -> anyhow::Result<()> {
+    let code = r#"
+fn first() -> (r: f32) {
+    a = [1.0; 2000]
+    r = a[1999]
+}
+fn second() -> (r: f32) {
+    a = [2.0; 2000]
+    r = a[1999] + first()
+}
+fn third() -> (r: f32) {
+    a = [3.0; 2000]
+    r = a[1999]
+}
+pub fn main() -> (r: f32) {
+    r = second() + third()
+}
+"#;
+    let one_array = r#"
+fn third() -> (r: f32) {
+    a = [3.0; 2000]
+    r = a[1999]
+}
+pub fn main() -> (r: f32) {
+    r = third()
+}
+"#;
+    let array_size = default_std_jit_from_code(one_array, true)?.deep_stack_size();
+    assert!(array_size >= 8000);
+    let mut jit = default_std_jit_from_code(code, true)?;
+    //second and first are in use at the same time, third is not
+    assert_eq!(array_size * 2, jit.deep_stack_size());
+    let func_ptr = jit.get_func("main")?;
+    let func = unsafe { mem::transmute::<_, extern "C" fn() -> f32>(func_ptr) };
+    assert_eq!(6.0, func());
+
+    let recursive = r#"
+fn recurse(n: i64) -> (r: f32) {
+    a = [1.0; 2000]
+    if n > 0 {
+        r = recurse(n - 1)
+    } else {
+        r = a[0]
     }
-
-    #[test]
-    fn inline_func_has_anonymous_passing_with_return_calls_inline() -> anyhow::Result<()> {
-        //setup_logging();
-        let code = r#"
-inline fn mult(a, b) -> (c) {
-    c = a * b
 }
-
-inline fn multj(a, b) -> (c) {
-    j = 6.0
-    c = mult(a, b) + j
-    c -= j
+pub fn main() -> (r: f32) {
+    r = recurse(3)
 }
-
-always_inline fn run_some_closure(n, some_closure: |e| -> (f)) -> (f) {
-    f = some_closure(n * 5.0)
+"#;
+    assert!(default_std_jit_from_code(recursive, true).is_err());
+    let mut jit = default_std_jit_from_code(recursive, false)?;
+    let func_ptr = jit.get_func("main")?;
+    let func = unsafe { mem::transmute::<_, extern "C" fn() -> f32>(func_ptr) };
+    assert_eq!(1.0, func());
+    Ok(())
 }
 
-fn stuff() -> () {
-    c = 5.0 + 6.0
-    j = run_some_closure(2.0, |e| -> (f) {
-        c *= e
-        f = multj(c, 2.0)
-    })
-    c.assert_eq(110.0)
-    j.assert_eq(220.0)
+#[test]
+fn deep_stack_checks() -> anyhow::Result<()> {
+    let code = r#"
+fn count(n: i64) -> (r: i64) {
+    if n > 0 {
+        r = count(n - 1) + 1
+    } else {
+        r = 0
+    }
 }
-
-fn main() -> () {
-    stuff()
+pub fn main() -> (r: i64) {
+    a = [1; 1000]
+    r = count(100) + a[999]
 }
 "#;
+    let mut jit = default_std_jit_from_code(code, true)?;
+    let checked_clif = jit.clif["main"].len();
+    let func_ptr = jit.get_func("main")?;
+    let func = unsafe { mem::transmute::<_, extern "C" fn() -> i64>(func_ptr) };
+    assert_eq!(101, func());
+
+    let mut ast = parse(code)?;
+    let mut jit_builder = jit::new_jit_builder();
+    sarus_std_lib::append_std(&mut ast, &mut jit_builder);
+    let mut jit = jit::JIT::from(jit_builder, true);
+    jit.set_deep_stack_checks(false);
+    jit.translate(ast, None)?;
+    assert!(jit.clif["main"].len() < checked_clif);
+    let func_ptr = jit.get_func("main")?;
+    let func = unsafe { mem::transmute::<_, extern "C" fn() -> i64>(func_ptr) };
+    assert_eq!(101, func());
+    Ok(())
+}
 
-        only_run_func(code)
+#[test]
+fn stack_budget() -> anyhow::Result<()> {
+    let code = r#"
+fn count(n: i64) -> (r: i64) {
+    if n > 0 {
+        r = count(n - 1) + 1
+    } else {
+        r = 0
     }
-
-    #[test]
-    fn closure_in_closure() -> anyhow::Result<()> {
-        //setup_logging();
-        let code = r#"
-inline fn mult(a, b) -> (c) {
-    c = a * b
 }
-
-always_inline fn run_some_closure2(n, some_closure: |e| -> ()) -> () {
-    some_closure(mult(n, 5.0))
+pub fn main(n: i64) -> (r: i64) {
+    r = count(n)
 }
-
-always_inline fn run_some_closure(n, some_closure: |e| -> ()) -> () {
-    some_closure(n * 5.0)
+"#;
+    let mut ast = parse(code)?;
+    let mut jit_builder = jit::new_jit_builder();
+    sarus_std_lib::append_std(&mut ast, &mut jit_builder);
+    let mut jit = jit::JIT::from(jit_builder, true);
+    jit.set_stack_budget(Some(1024 * 1024));
+    jit.translate(ast, None)?;
+    let func_ptr = jit.get_func("main")?;
+    let func = unsafe { mem::transmute::<_, extern "C" fn(i64) -> i64>(func_ptr) };
+    assert_eq!(100, func(100));
+    assert_eq!(200, func(200));
+    //each thread has its own stack base
+    let handle = std::thread::spawn(move || func(300));
+    assert_eq!(300, handle.join().unwrap());
+    Ok(())
 }
 
-inline fn stuff() -> () {
-    c = 5.0 + 6.0
-    run_some_closure(2.0, |e| -> () {
-        run_some_closure2(3.0, |f| -> () {c *= f})
-        c *= e
-    })
-    c.assert_eq(1650.0)
+#[test]
+fn stack_budget_exceeded() -> anyhow::Result<()> {
+    let code = r#"
+fn count(n: i64) -> (r: i64) {
+    if n > 0 {
+        r = count(n - 1) + 1
+    } else {
+        r = 0
+    }
 }
-
-fn main() -> () {
-    stuff()
+pub fn main(n: i64) -> (r: i64) {
+    r = count(n)
 }
 "#;
+    let mut ast = parse(code)?;
+    let mut jit_builder = jit::new_jit_builder();
+    sarus_std_lib::append_std(&mut ast, &mut jit_builder);
+    let mut jit = jit::JIT::from(jit_builder, true);
+    jit.set_stack_budget(Some(256 * 1024));
+    jit.set_runtime_errors(jit::RuntimeErrorMode::Return);
+    jit.translate(ast, None)?;
+    let func_ptr = jit.get_func("main")?;
+    let func = unsafe { mem::transmute::<_, extern "C" fn(i64) -> i64>(func_ptr) };
+    assert_eq!(100, jit.call_checked(|| func(100))?);
+    let error = jit.call_checked(|| func(1_000_000)).unwrap_err();
+    assert_eq!(jit::RuntimeErrorCode::StackOverflow, error.code);
+    assert!(error.message.contains("stack budget of 262144 bytes"));
+    //taking the error resets the stack base for the next call
+    assert_eq!(100, jit.call_checked(|| func(100))?);
+    Ok(())
+}
 
-        only_run_func(code)
+#[test]
+fn runtime_errors() -> anyhow::Result<()> {
+    let code = r#"
+fn get(i: i64) -> (x: i64) {
+    a = [0; 4]
+    a[1] = 10
+    x = a[i]
+}
+fn check(n: i64) -> () {
+    if n < 0 {
+        panic("negative")
     }
-
-    #[test]
-    fn closure_in_closure_struct() -> anyhow::Result<()> {
-        //setup_logging();
-        let code = r#"
-struct FloatVal {
-    val: f32,
 }
-
-inline fn mult(a: FloatVal, b: FloatVal) -> (c: FloatVal) {
-    c = FloatVal{ val: a.val * b.val, }
+pub fn main(i: i64) -> (x: i64) {
+    check(i)
+    x = get(i) + 1
 }
+"#;
+    let mut ast = parse(code)?;
+    let mut jit_builder = jit::new_jit_builder();
+    sarus_std_lib::append_std(&mut ast, &mut jit_builder);
+    let mut jit = jit::JIT::from(jit_builder, true);
+    jit.set_runtime_errors(jit::RuntimeErrorMode::Return);
+    jit.translate(ast, None)?;
+    let func_ptr = jit.get_func("main")?;
+    let func = unsafe { mem::transmute::<_, extern "C" fn(i64) -> i64>(func_ptr) };
+    assert_eq!(11, jit.call_checked(|| func(1))?);
 
-always_inline fn run_some_closure2(n: FloatVal, some_closure: |e: FloatVal| -> ()) -> () {
-    some_closure( mult(n, FloatVal{ val: 5.0, }) )
-}
+    let error = jit.call_checked(|| func(4)).unwrap_err();
+    assert_eq!(jit::RuntimeErrorCode::OutOfBounds, error.code);
+    assert!(error.message.contains("index out of bounds"));
+    assert_eq!(Some(5), error.code_ref.line);
 
-always_inline fn run_some_closure(n: FloatVal, some_closure: |e: FloatVal| -> ()) -> () {
-    n.val *= 5.0
-    some_closure(n)
-}
+    let error = jit.call_checked(|| func(-1)).unwrap_err();
+    assert_eq!(jit::RuntimeErrorCode::Panic, error.code);
+    assert_eq!("negative", error.message);
+    assert_eq!(Some(9), error.code_ref.line);
 
-inline fn stuff() -> () {
-    c = FloatVal { val: 5.0 + 6.0, }
-    c.val.assert_eq(11.0)
-    run_some_closure(FloatVal{ val: 2.0, }, |e: FloatVal| -> () {
-        c.val.assert_eq(11.0)
-        run_some_closure2(FloatVal{ val: 3.0, }, |f: FloatVal| -> () {
-            c.val.assert_eq(11.0) //"cl2 c ".print() 
-            e.val.assert_eq(10.0) //"cl2 e ".print() 
-            f.val.assert_eq(15.0) //"cl2 f ".print() 
-            c.val *= f.val
-        })
-        c.val.assert_eq(165.0)
-        c.val *= e.val
-        c.val.assert_eq(1650.0)
-    })
-    c.val.assert_eq(1650.0)
+    //the error is taken, so the next call succeeds
+    assert_eq!(1, jit.call_checked(|| func(0))?);
+    Ok(())
 }
 
-fn main() -> () {
-    stuff()
+#[test]
+fn runtime_error_locations() -> anyhow::Result<()> {
+    let code = r#"
+fn get(i: i64) -> (x: i64) {
+    a = [0; 4]
+    x = a[i]
+}
+pub fn main(i: i64) -> (x: i64) {
+    x = get(i) + 1
 }
 "#;
-
-        only_run_func(code)
+    let mut ast = parse(code)?;
+    let mut jit_builder = jit::new_jit_builder();
+    sarus_std_lib::append_std(&mut ast, &mut jit_builder);
+    let mut jit = jit::JIT::from(jit_builder, true);
+    jit.set_runtime_errors(jit::RuntimeErrorMode::Return);
+    jit.set_call_stack_tracking(true);
+    jit.translate(ast, None)?;
+    let func_ptr = jit.get_func("main")?;
+    let func = unsafe { mem::transmute::<_, extern "C" fn(i64) -> i64>(func_ptr) };
+    assert_eq!(1, jit.call_checked(|| func(1))?);
+
+    for _ in 0..2 {
+        let error = jit.call_checked(|| func(6)).unwrap_err();
+        assert_eq!(jit::RuntimeErrorCode::OutOfBounds, error.code);
+        assert_eq!("index out of bounds", error.message);
+        assert_eq!("get", error.function);
+        assert_eq!(Some(4), error.code_ref.line);
+        assert_eq!(Some(9), error.code_ref.column);
+        assert_eq!(Some(6), error.index);
+        assert_eq!(Some(4), error.len);
+        assert_eq!(vec!["main", "get"], error.call_stack);
+        assert!(error.to_string().contains("in get at"));
     }
-
-    #[test]
-    fn closure_takes_closure() -> anyhow::Result<()> {
-        //setup_logging();
-        let code = r#"
-always_inline fn run_some_closure(n, some_closure: |e, cl: |f| -> (j)| -> ()) -> () {
-    a = 1.0
-    some_closure(n * 5.0, |f| -> (j) {j = f + a})
+    Ok(())
 }
 
-inline fn stuff() -> () {
-    c = 5.0 + 6.0
-    run_some_closure(2.0, |e, cl: |f| -> (j)| -> () {
-        cl(3.0).assert_eq(4.0)
-        e.assert_eq(10.0)
-        c.assert_eq(11.0)
-        c *= e + cl(3.0)
-    })
-    c.assert_eq(154.0)
+#[test]
+fn bounds_modes() -> anyhow::Result<()> {
+    let code = r#"
+pub fn wrap(i: i64) -> (x: i64) {
+    a = [10, 20, 30, 40]
+    x = a.wrap[i]
 }
-
-fn main() -> () {
-    stuff()
+pub fn clamp(i: i64) -> (x: i64) {
+    a = [10, 20, 30, 40]
+    x = a.clamp[i]
+}
+pub bounds(clamp_and_report) fn reported(i: i64) -> (x: i64) {
+    a = [10, 20, 30, 40]
+    a[i] = 50
+    x = a[i]
 }
 "#;
+    let mut jit = default_std_jit_from_code(code, true)?;
+    let func_ptr = jit.get_func("wrap")?;
+    let wrap = unsafe { mem::transmute::<_, extern "C" fn(i64) -> i64>(func_ptr) };
+    assert_eq!(20, wrap(5));
+    assert_eq!(40, wrap(-1));
+    let func_ptr = jit.get_func("clamp")?;
+    let clamp = unsafe { mem::transmute::<_, extern "C" fn(i64) -> i64>(func_ptr) };
+    assert_eq!(40, clamp(9));
+    assert_eq!(10, clamp(-3));
+    let func_ptr = jit.get_func("reported")?;
+    let reported = unsafe { mem::transmute::<_, extern "C" fn(i64) -> i64>(func_ptr) };
+    assert_eq!(50, reported(1));
+    assert!(jit.take_reported_errors().is_empty());
+    assert_eq!(50, reported(7));
+    let errors = jit.take_reported_errors();
+    assert_eq!(2, errors.len());
+    assert_eq!(jit::RuntimeErrorCode::OutOfBounds, errors[0].code);
+    assert_eq!(Some(12), errors[0].code_ref.line);
+    Ok(())
+}
 
-        only_run_func(code)
-    }
-
-    #[test]
-    fn use_closure_from_parent_closure_scope() -> anyhow::Result<()> {
-        //setup_logging();
-        let code = r#"
-always_inline fn run_some_closure(n, some_closure: |e| -> ()) -> () {
-    some_closure(n * 5.0)
+#[test]
+fn overflow_checks() -> anyhow::Result<()> {
+    let code = r#"
+pub fn add(a: i64, b: i64) -> (c: i64) {
+    c = a + b
+}
+pub fn div(a: i64, b: i64) -> (c: i64) {
+    c = a / b
+}
+pub fn add_u8(a: i64, b: i64) -> (c: i64) {
+    c = (a.u8() + b.u8()).i64()
+}
+pub fn methods(a: i64, b: i64) -> (c: i64) {
+    a.wrapping_add(b).assert_eq(-9223372036854775807 - 1)
+    a.saturating_add(b).assert_eq(9223372036854775807)
+    (0 - a).saturating_sub(b + b).assert_eq(-9223372036854775807 - 1)
+    a.saturating_mul(0 - 2).assert_eq(-9223372036854775807 - 1)
+    (200u8).saturating_add(100u8).assert_eq(255u8)
+    (5u8).saturating_sub(10u8).assert_eq(0u8)
+    (200u8).wrapping_add(100u8).assert_eq(44u8)
+    c = a.checked_add(b)
+}
+"#;
+    let mut ast = parse(code)?;
+    let mut jit_builder = jit::new_jit_builder();
+    sarus_std_lib::append_std(&mut ast, &mut jit_builder);
+    let mut jit = jit::JIT::from(jit_builder, true);
+    jit.set_runtime_errors(jit::RuntimeErrorMode::Return);
+    jit.set_overflow_checks(true);
+    jit.translate(ast, None)?;
+    let func_ptr = jit.get_func("add")?;
+    let add = unsafe { mem::transmute::<_, extern "C" fn(i64, i64) -> i64>(func_ptr) };
+    let func_ptr = jit.get_func("div")?;
+    let div = unsafe { mem::transmute::<_, extern "C" fn(i64, i64) -> i64>(func_ptr) };
+    let func_ptr = jit.get_func("add_u8")?;
+    let add_u8 = unsafe { mem::transmute::<_, extern "C" fn(i64, i64) -> i64>(func_ptr) };
+    let func_ptr = jit.get_func("methods")?;
+    let methods = unsafe { mem::transmute::<_, extern "C" fn(i64, i64) -> i64>(func_ptr) };
+
+    assert_eq!(3, jit.call_checked(|| add(1, 2))?);
+    let error = jit.call_checked(|| add(i64::MAX, 1)).unwrap_err();
+    assert_eq!(jit::RuntimeErrorCode::Overflow, error.code);
+    assert_eq!(Some(3), error.code_ref.line);
+
+    assert_eq!(-3, jit.call_checked(|| div(9, -3))?);
+    let error = jit.call_checked(|| div(9, 0)).unwrap_err();
+    assert_eq!(jit::RuntimeErrorCode::DivideByZero, error.code);
+    let error = jit.call_checked(|| div(i64::MIN, -1)).unwrap_err();
+    assert_eq!(jit::RuntimeErrorCode::Overflow, error.code);
+
+    assert_eq!(255, jit.call_checked(|| add_u8(200, 55))?);
+    let error = jit.call_checked(|| add_u8(200, 56)).unwrap_err();
+    assert_eq!(jit::RuntimeErrorCode::Overflow, error.code);
+
+    let error = jit.call_checked(|| methods(i64::MAX, 1)).unwrap_err();
+    assert_eq!(jit::RuntimeErrorCode::Overflow, error.code);
+    assert_eq!(Some(19), error.code_ref.line);
+    Ok(())
 }
 
-fn main() -> () {
-    c = 5.0 + 6.0
-    add1|| -> () {c += 1.0}
-    run_some_closure(2.0, |e| -> () {c *= e add1()})
-    c.assert_eq(111.0)
+#[test]
+fn type_error_snippet() -> anyhow::Result<()> {
+    let code = r#"
+pub fn main(a: i64) -> (x: i64) {
+    x = a + 1.0
 }
 "#;
+    let error = default_std_jit_from_code(code, true)
+        .err()
+        .unwrap()
+        .to_string();
+    assert!(error.starts_with("error[E0100]: Type mismatch; expected i64, found f32"));
+    assert!(error.contains(" --> 3:11\n"));
+    assert!(error.contains("3 |     x = a + 1.0\n  |           ^\n"));
 
-        only_run_func(code)
-    }
-
-    #[test]
-    fn use_closure_from_parent_closure_scope2() -> anyhow::Result<()> {
-        //setup_logging();
-        let code = r#"
-fn main() -> () {
-    a = 1.0
-    single_proc|| -> () {
-        a += 1.0
-    }
+    let ast = parse(code)?;
+    let func = match &ast[0] {
+        Declaration::Function(func) => func,
+        _ => panic!("expected a function"),
+    };
+    let code_ref = match &func.body[0] {
+        frontend::Expr::Assign { from_exprs, .. } => *from_exprs[0].get_code_ref(),
+        _ => panic!("expected an assignment"),
+    };
+    //the span of the +
+    assert_eq!(Some(11), code_ref.column);
+    assert_eq!(Some(code_ref.pos + 1), code_ref.end);
+    assert_eq!(Some(12), code_ref.end_column);
+    Ok(())
+}
 
-    while_proc | get_coeffs: ||->(c: f32) |->() {
-        coeffs = get_coeffs()
-        single_proc()        
-    }
-    
-    while_proc(||->(c: f32) {
-        c = 5.0
-    })
+#[test]
+fn all_type_errors() -> anyhow::Result<()> {
+    let code = r#"
+fn a(x: i64) -> (y: i64) {
+    y = x + 1.0
+    z = x * 2.0
+    w = z + 1
+}
+pub fn main(x: i64) -> (y: i64) {
+    y = a(x) + true
 }
 "#;
-
-        only_run_func(code)
-    }
+    let error = default_std_jit_from_code(code, true).err().unwrap();
+    let errors = error.downcast_ref::<diagnostics::Diagnostics>().unwrap();
+    //the error using z follows from the one assigning it, so it isn't reported
+    let lines: Vec<Option<usize>> = errors
+        .diagnostics
+        .iter()
+        .map(|d| d.primary_span.as_ref().unwrap().line)
+        .collect();
+    assert_eq!(vec![Some(3), Some(4), Some(8)], lines);
+    assert!(error.to_string().ends_with("3 errors"));
+    Ok(())
 }
 
 #[test]
-fn var_define_checking() -> anyhow::Result<()> {
-    //setup_logging();
+fn structured_diagnostics() -> anyhow::Result<()> {
     let code = r#"
-
-fn main() -> () {  
-    a = 0.0
-    if false {
-        a = 5.0
-    } else {
-        a = 4.0
-    }
-    a.assert_eq(4.0)
-    b = 0.0
-    if false {
-        b = 5.0
-    } else if (true) {
-        b = 4.0
-    } else {
-        b = 3.0
-    }
-    b.assert_eq(4.0)
+pub fn main(a: i64) -> (x: i64) {
+    x = a + 1.0
 }
-
 "#;
-    only_run_func(code)
+    let error = default_std_jit_from_code(code, true).err().unwrap();
+    let diagnostics = error.downcast_ref::<diagnostics::Diagnostics>().unwrap();
+    let diagnostic = &diagnostics.diagnostics[0];
+    assert_eq!(diagnostics::Severity::Error, diagnostic.severity);
+    assert_eq!(diagnostics::codes::TYPE_MISMATCH, diagnostic.code);
+    let span = diagnostic.primary_span.as_ref().unwrap();
+    assert_eq!((Some(3), Some(11)), (span.line, span.column));
+    assert_eq!(
+        "convert the f32 with `.i64()`",
+        diagnostic.suggestions[0].message
+    );
+    let json = diagnostics.to_json();
+    assert!(
+        json.contains(r#""severity":"error","code":"E0100""#),
+        "{}",
+        json
+    );
+    assert!(json.contains(r#""line":3,"column":11"#), "{}", json);
+
+    let diagnostics = parse("fn main() -> () {\n    x = \n}").err().unwrap();
+    let diagnostic = &diagnostics.diagnostics[0];
+    assert_eq!(diagnostics::codes::PARSE_ERROR, diagnostic.code);
+    assert!(diagnostic.primary_span.as_ref().unwrap().line.is_some());
+    assert!(diagnostics.to_string().starts_with("error[E0001]"));
+    Ok(())
 }
 
 #[test]
-fn dot_access_conditionals() -> anyhow::Result<()> {
-    //setup_logging();
+fn located_errors() -> anyhow::Result<()> {
+    let spans = |code: &str| {
+        let error = default_std_jit_from_code(code, true).err().unwrap();
+        let diagnostics = error.downcast_ref::<diagnostics::Diagnostics>().unwrap();
+        let diagnostic = diagnostics.diagnostics[0].clone();
+        let line = |span: &diagnostics::Span| span.line;
+        (
+            diagnostic.code,
+            diagnostic.primary_span.as_ref().and_then(line),
+            diagnostic
+                .secondary_spans
+                .iter()
+                .map(line)
+                .collect::<Vec<_>>(),
+        )
+    };
     let code = r#"
-fn main() -> () {  
-    (if true {1.0} else {-1.0}).assert_eq(1.0)
-    (if false {1.0} else {-1.0}).assert_eq(-1.0)
-    (if false {1.0} else if true {-1.0} else {0.0}).assert_eq(-1.0)
+struct Point { x: f32, y: f32, }
+struct Point { x: f32, y: f32, z: f32, }
+"#;
+    assert_eq!(
+        (
+            diagnostics::codes::COMPILE_ERROR.to_string(),
+            Some(3),
+            vec![Some(2)]
+        ),
+        spans(code)
+    );
+    let code = r#"
+const A: i64 = 1
+const A: i64 = 2
+"#;
+    assert_eq!(Some(3), spans(code).1);
+    let code = r#"
+pub fn main() -> () {
+    break
 }
 "#;
-    only_run_func(code)
+    let (_, primary, secondary) = spans(code);
+    assert_eq!(Some(3), primary);
+    assert!(secondary.is_empty());
+    let diagnostics = parse_with_context(
+        "\ninclude \"./does_not_exist.sarus\"\n",
+        &get_test_dir().join("test.sarus"),
+    )
+    .err()
+    .unwrap();
+    let diagnostic = &diagnostics.diagnostics[0];
+    assert_eq!(diagnostics::codes::INCLUDE_ERROR, diagnostic.code);
+    assert_eq!(Some(2), diagnostic.primary_span.as_ref().unwrap().line);
+    Ok(())
 }
 
 #[test]
-fn recursion() -> anyhow::Result<()> {
-    //setup_logging();
+fn parse_error_recovery() -> anyhow::Result<()> {
     let code = r#"
-inline fn fib(n: i64) -> (r: i64) {
-    r = if n <= 1 {
-        n
-    } else {
-        fib(n - 1) + fib(n - 2)
-    }
+fn a(x: f32 -> (y: f32) {
+    y = x
 }
-fn main() -> () {  
-    i = 0
-    while i < 10 {
-        fib(i).println()
-        i += 1
+struct Point {
+    x: f32
+    y: f32
+}
+pub fn main() -> () {
+    b = (1.0
+}
+"#;
+    let diagnostics = parse(code).err().unwrap();
+    let errors: Vec<(Option<usize>, &str)> = diagnostics
+        .diagnostics
+        .iter()
+        .map(|d| (d.primary_span.as_ref().unwrap().line, d.message.as_str()))
+        .collect();
+    assert_eq!(3, errors.len(), "{}", diagnostics);
+    assert_eq!(Some(2), errors[0].0);
+    assert!(errors[0]
+        .1
+        .starts_with("Could not parse function signature; expected"));
+    assert!(errors[0].1.contains("`,`") && errors[0].1.contains("`)`"));
+    assert_eq!(Some(7), errors[1].0);
+    assert!(errors[1].1.starts_with("Could not parse struct field"));
+    assert_eq!(Some(11), errors[2].0);
+    assert!(errors[2].1.starts_with("Could not parse block"));
+    assert!(diagnostics.to_string().ends_with("3 errors"));
+
+    // Declarations in comments and closures inside a function aren't top
+    // level, so they don't change what is being parsed
+    let code = r#"
+static A: [f32; 2] = [1.0, 2.0 3.0]
+// fn old() -> () {
+fn a(x: f32 -> (y: f32) {
+    y = x
+}
+pub fn main() -> () {
+    fn inner(x -> (y) {
+        y = x
     }
 }
 "#;
-    only_run_func(code)
+    let diagnostics = parse(code).err().unwrap();
+    let errors: Vec<(Option<usize>, &str)> = diagnostics
+        .diagnostics
+        .iter()
+        .map(|d| (d.primary_span.as_ref().unwrap().line, d.message.as_str()))
+        .collect();
+    assert_eq!(3, errors.len(), "{}", diagnostics);
+    assert_eq!(Some(2), errors[0].0);
+    assert!(errors[0].1.starts_with("Could not parse static"));
+    assert_eq!(Some(4), errors[1].0);
+    assert!(errors[1]
+        .1
+        .starts_with("Could not parse function signature"));
+    assert_eq!(Some(8), errors[2].0);
+    assert!(errors[2].1.starts_with("Could not parse block"));
+    Ok(())
 }
 
 #[test]
-fn unary_negative() -> anyhow::Result<()> {
-    //setup_logging();
+fn lint_warnings() -> anyhow::Result<()> {
     let code = r#"
-fn number() -> (y) {
+@ allowed
+    allow = ["unused_parameter"]
+@
+fn allowed(x: f32) -> (y: f32) {
+    y = 1.0
+}
+fn never_called() -> (y: f32) {
+    y = 1.0
+    return
     y = 2.0
 }
-fn main() -> () {
-    a = 5
-    b = -a
-    b.assert_eq(-5)
-    (-b).assert_eq(5)
-    c = 5.0
-    d = -c
-    d.assert_eq(-5.0)
-    (-d).assert_eq(5.0)
-    e = -number()
-    e.assert_eq(-2.0)
-    (-number()).assert_eq(-2.0)
-    (-(number())).assert_eq(-2.0)
-    (4 + -4).assert_eq(0)
-    (2 + -4 + 2).assert_eq(0)
-    ((2 + -4) + 2).assert_eq(0)
-    (2 + (-4 + 2)).assert_eq(0)
-    four = 4
-    two = 2
-    (four + -four).assert_eq(0)
-    (two + -four + two).assert_eq(0)
-    ((two + -four) + two).assert_eq(0)
-    (two + (-four + two)).assert_eq(0)
+struct Unused {
+    a: f32
+}
+fn twice(x: f32) -> (y: f32) {
+    y = x * 2.0
+}
+pub fn main(a: f32, b: f32) -> (c: f32) {
+    unused = 1.0
+    d = a
+    d = a * 2.0
+    twice(a)
+    c = allowed(d)
 }
 "#;
-    only_run_func(code)
+    let jit = default_std_jit_from_code(code, true)?;
+    let warnings: Vec<(&str, Option<usize>)> = jit
+        .warnings()
+        .diagnostics
+        .iter()
+        .map(|d| (d.code.as_str(), d.primary_span.as_ref().unwrap().line))
+        .collect();
+    assert_eq!(
+        vec![
+            ("W0006", Some(8)),
+            ("W0004", Some(11)),
+            ("W0007", Some(13)),
+            ("W0002", Some(19)),
+            ("W0001", Some(20)),
+            ("W0005", Some(21)),
+            ("W0003", Some(23)),
+        ],
+        warnings,
+        "{}",
+        jit.warnings()
+    );
+    assert!(jit.warnings().to_string().ends_with("7 warnings"));
+
+    let mut ast = parse(code)?;
+    let mut jit_builder = jit::new_jit_builder();
+    sarus_std_lib::append_std(&mut ast, &mut jit_builder);
+    let mut jit = jit::JIT::from(jit_builder, true);
+    jit.set_warnings_as_errors(true);
+    let errors = jit.translate(ast, None).err().unwrap();
+    assert_eq!(7, errors.diagnostics.len());
+    assert!(errors
+        .diagnostics
+        .iter()
+        .all(|d| d.severity == diagnostics::Severity::Error));
+    assert!(jit.get_func("main").is_err());
+    Ok(())
 }
 
 #[test]
-fn expr_array_access() -> anyhow::Result<()> {
-    //setup_logging();
+fn call_stack_after_inline_call() -> anyhow::Result<()> {
     let code = r#"
-
-fn arr(n) -> (a: [f32; 10]) {
-    a = [n; 10]
+always_inline fn double(x: i64) -> (y: i64) {
+    y = x * 2
 }
-
-fn main() -> () { 
-    a = [1.0; 10]
-    b = (a)[1]
-    b.assert_eq(1.0)
-    ([2.0; 10])[1].assert_eq(2.0)
-    [3.0; 10][1].assert_eq(3.0)
-    arr(4.0)[1].assert_eq(4.0)
+fn get(i: i64) -> (x: i64) {
+    a = [0; 4]
+    x = a[i]
+}
+pub fn main(i: i64) -> (x: i64) {
+    j = double(i)
+    x = get(j) + 1
 }
 "#;
-    only_run_func(code)
+    let mut ast = parse(code)?;
+    let mut jit_builder = jit::new_jit_builder();
+    sarus_std_lib::append_std(&mut ast, &mut jit_builder);
+    let mut jit = jit::JIT::from(jit_builder, true);
+    jit.set_runtime_errors(jit::RuntimeErrorMode::Return);
+    jit.set_call_stack_tracking(true);
+    jit.translate(ast, None)?;
+    let func_ptr = jit.get_func("main")?;
+    let func = unsafe { mem::transmute::<_, extern "C" fn(i64) -> i64>(func_ptr) };
+    assert_eq!(1, jit.call_checked(|| func(1))?);
+    assert_eq!(1, jit.call_checked(|| func(0))?);
+
+    //the inlined call is popped once, so returning from main leaves the
+    //call stack empty for the next call
+    let error = jit.call_checked(|| func(3)).unwrap_err();
+    assert_eq!(jit::RuntimeErrorCode::OutOfBounds, error.code);
+    assert_eq!("get", error.function);
+    assert_eq!(Some(6), error.index);
+    assert_eq!(vec!["main", "get"], error.call_stack);
+    Ok(())
 }
 
 #[test]
-fn declare_var_in_if() -> anyhow::Result<()> {
-    //setup_logging();
+fn statics_shadowed() -> anyhow::Result<()> {
     let code = r#"
+static LEVEL: f32 = 1.0
 
-fn main() -> () { 
-    if true {
-        a = 5
-        a.assert_eq(5)
-    }
+pub fn shadowed(LEVEL: f32) -> (y: f32) {
+    LEVEL = LEVEL * 2.0
+    y = LEVEL
+}
+pub fn level() -> (y: f32) {
+    y = LEVEL
 }
 "#;
-    only_run_func(code)
+    let mut jit = default_std_jit_from_code(code, true)?;
+    let func_ptr = jit.get_func("shadowed")?;
+    let shadowed = unsafe { mem::transmute::<_, extern "C" fn(f32) -> f32>(func_ptr) };
+    let func_ptr = jit.get_func("level")?;
+    let level = unsafe { mem::transmute::<_, extern "C" fn() -> f32>(func_ptr) };
+    //the parameter shadows the static, so the static isn't written
+    assert_eq!(6.0, shadowed(3.0));
+    assert_eq!(1.0, level());
+    Ok(())
 }
 
 #[test]
-fn init_process_state() -> anyhow::Result<()> {
-    //setup_logging();
+fn deep_stack_same_method_name() -> anyhow::Result<()> {
     let code = r#"
-struct ProcessState {
-    delay_l: [f32; 10000],
-    delay_r: [f32; 10000],
+struct Inner {
+    x: f32,
 }
-fn main() -> () {
-    state = ProcessState {
-        delay_l: [1.234; 10000],
-        delay_r: [1.234; 10000],
+fn process(self: Inner) -> (r: f32) {
+    a = [self.x; 2000]
+    r = a[1999]
+}
+struct Outer {
+    inner: Inner,
+}
+fn process(self: Outer) -> (r: f32) {
+    a = [2.0; 2000]
+    r = a[1999] + self.inner.process()
+}
+pub fn main() -> (r: f32) {
+    inner = Inner {
+        x: 1.0,
     }
-    state.delay_r[0].assert_eq(1.234)
-    state.delay_r[1].assert_eq(1.234)
-    state.delay_r[9999].assert_eq(1.234)
-    state.delay_l[0].assert_eq(1.234)
-    state.delay_l[1].assert_eq(1.234)
-    state.delay_l[9999].assert_eq(1.234)
-
-    a = [1.234; 15] //Test array init that does not use loop
-    a[0].assert_eq(1.234)
-    a[14].assert_eq(1.234)
+    outer = Outer {
+        inner: inner,
+    }
+    r = outer.process()
 }
-
 "#;
-    only_run_func(code)
+    //Outer.process calling Inner.process isn't recursion, even though the
+    //type of self.inner isn't known when the call graph is built
+    let mut jit = default_std_jit_from_code(code, true)?;
+    assert!(jit.deep_stack_size() >= 16000);
+    let func_ptr = jit.get_func("main")?;
+    let func = unsafe { mem::transmute::<_, extern "C" fn() -> f32>(func_ptr) };
+    assert_eq!(3.0, func());
+    Ok(())
 }
 
 #[test]
-fn assign_to_param_address_without_extra_alloc() -> anyhow::Result<()> {
-    //setup_logging();
+fn bounds_modes_empty() -> anyhow::Result<()> {
     let code = r#"
-
-fn takes_arr(n: [f32; 5]) -> () {
-    n = [1.0; 5]
+pub fn wrap(i: i64) -> (x: i64) {
+    a = [10, 20, 30, 40]
+    s = a[2..2]
+    x = s.wrap[i]
 }
-
-fn main() -> () { 
-    a = [0.0; 5]
-    a[1].assert_eq(0.0)
-    takes_arr(a)
-    a[1].assert_eq(1.0)
+pub fn clamp(i: i64) -> (x: i64) {
+    a = [10, 20, 30, 40]
+    s = a[2..2]
+    x = s.clamp[i]
 }
 "#;
-    only_run_func(code)
+    let mut ast = parse(code)?;
+    let mut jit_builder = jit::new_jit_builder();
+    sarus_std_lib::append_std(&mut ast, &mut jit_builder);
+    let mut jit = jit::JIT::from(jit_builder, true);
+    jit.set_runtime_errors(jit::RuntimeErrorMode::Return);
+    jit.translate(ast, None)?;
+    for name in ["wrap", "clamp"] {
+        let func_ptr = jit.get_func(name)?;
+        let func = unsafe { mem::transmute::<_, extern "C" fn(i64) -> i64>(func_ptr) };
+        let error = jit.call_checked(|| func(1)).unwrap_err();
+        assert_eq!(jit::RuntimeErrorCode::OutOfBounds, error.code);
+        assert_eq!("index into an empty array", error.message);
+        assert_eq!(Some(0), error.len);
+    }
+
+    let errors = |code: &str| -> anyhow::Result<Vec<String>> {
+        let mut ast = parse(code)?;
+        let mut jit_builder = jit::new_jit_builder();
+        sarus_std_lib::append_std(&mut ast, &mut jit_builder);
+        let mut jit = jit::JIT::from(jit_builder, true);
+        let errors = jit.translate(ast, None).err().unwrap();
+        Ok(errors.diagnostics.into_iter().map(|d| d.message).collect())
+    };
+    let unsized_array = r#"
+pub fn main(a: &[f32], i: i64) -> (x: f32) {
+    x = a.wrap[i]
 }
-
-#[test]
-fn basic_slice() -> anyhow::Result<()> {
-    //setup_logging();
-    let code = r#"
-fn main() -> () { 
-    a = [0.0; 100]
-    a[0] = 0.0
-    a[1] = 1.0
-    a[2] = 2.0
-    a[99] = 99.0
-    slice_of_a = a[..]
-    sub_slice_of_a1 = a[0..3]
-    sub_slice_of_a2 = a[..3]
-    sub_slice_of_a3 = a[2..]
-    sub_slice_of_a4 = a[1..3]
-
-    slice_of_a[0].assert_eq(0.0)
-    slice_of_a[1].assert_eq(1.0)
-    slice_of_a[2].assert_eq(2.0)
-    slice_of_a[99].assert_eq(99.0)
-
-    sub_slice_of_a1[0].assert_eq(0.0)
-    sub_slice_of_a1[1].assert_eq(1.0)
-    sub_slice_of_a1[2].assert_eq(2.0)
-
-    sub_slice_of_a2[0].assert_eq(0.0)
-    sub_slice_of_a2[1].assert_eq(1.0)
-    sub_slice_of_a2[2].assert_eq(2.0)
-
-    sub_slice_of_a3[0].assert_eq(2.0)
-    sub_slice_of_a3[99-2].assert_eq(99.0)
-
-    sub_slice_of_a4[0].assert_eq(1.0)
-    sub_slice_of_a4[1].assert_eq(2.0)
-
-    a[1] = 10.0
-    slice_of_a[1].assert_eq(10.0)
-
-    slice_of_a[2] = 20.0
-    a[2].assert_eq(20.0)
-
-    sub_slice_of_a4[0] = 5.0
-    sub_slice_of_a4[1] = 6.0
-
-    a[1].assert_eq(5.0)
-    a[2].assert_eq(6.0)
+"#;
+    let messages = errors(unsized_array)?;
+    assert!(
+        messages[0].contains("the length of an unsized array isn't known"),
+        "{:?}",
+        messages
+    );
+    let field_named_wrap = r#"
+struct Buffer {
+    wrap: [f32; 4],
+}
+pub fn main(i: i64) -> (x: f32) {
+    b = Buffer {
+        wrap: [1.0; 4],
+    }
+    x = b.wrap[i]
 }
 "#;
-    only_run_func(code)
+    let messages = errors(field_named_wrap)?;
+    assert!(
+        messages[0].contains("is read as an index mode"),
+        "{:?}",
+        messages
+    );
+    Ok(())
 }
 
 #[test]
-fn slice_contains_struct() -> anyhow::Result<()> {
-    //setup_logging();
+fn type_errors_in_blocks() -> anyhow::Result<()> {
     let code = r#"
-struct Point {
-    x, y, z,
+fn a(x: i64) -> (y: i64) {
+    i = 0
+    while i < 4 {
+        k = i + 1.0
+        i += 1
+    }
+    if x > 0 {
+        z = x + true
+    }
+    y = x
+    w = y * 2.0
+}
+pub fn main(x: i64) -> (y: i64) {
+    y = a(x)
+}
+"#;
+    let error = default_std_jit_from_code(code, true).err().unwrap();
+    let errors = error.downcast_ref::<diagnostics::Diagnostics>().unwrap();
+    //checking goes on after the while and if, outside of their blocks
+    let lines: Vec<Option<usize>> = errors
+        .diagnostics
+        .iter()
+        .map(|d| d.primary_span.as_ref().unwrap().line)
+        .collect();
+    assert_eq!(vec![Some(5), Some(9), Some(12)], lines);
+    Ok(())
 }
 
-fn main() -> () { 
-    a = [Point {
-        x:0.0, 
-        y:0.0, 
-        z:0.0,
-    }; 100]
-    a[0].y = 0.0
-    a[1].y = 1.0
-    a[2].y = 2.0
-    a[99].y = 99.0
-    slice_of_a = a[..]
-    a[1].y.assert_eq(1.0)
-    sub_slice_of_a1 = a[0..3]
-    sub_slice_of_a2 = a[..3]
-    sub_slice_of_a3 = a[2..]
-    sub_slice_of_a4 = a[1..3]
-
-    slice_of_a[0].y.assert_eq(0.0)
-    slice_of_a[1].y.assert_eq(1.0)
-    slice_of_a[2].y.assert_eq(2.0)
-    slice_of_a[99].y.assert_eq(99.0)
-
-    sub_slice_of_a1[0].y.assert_eq(0.0)
-    sub_slice_of_a1[1].y.assert_eq(1.0)
-    sub_slice_of_a1[2].y.assert_eq(2.0)
+//#[cfg(test)]
+mod inline_closures {
 
-    sub_slice_of_a2[0].y.assert_eq(0.0)
-    sub_slice_of_a2[1].y.assert_eq(1.0)
-    sub_slice_of_a2[2].y.assert_eq(2.0)
+    use super::*;
 
-    sub_slice_of_a3[0].y.assert_eq(2.0)
-    sub_slice_of_a3[99-2].y.assert_eq(99.0)
+    #[test]
+    fn basic() -> anyhow::Result<()> {
+        //setup_logging();
+        let code = r#"
+pub fn main() -> () {
+    add|| -> () {
+        c = c * 2.0
+    }
+    stuff|e| -> (f) {
+        c = c * 2.0 * e
+        f = c * e
+    }
+    a = 5.0
+    b = 6.0
+    c = 5.0 + 6.0
+    c.assert_eq(11.0)
+    add()
+    c.assert_eq(22.0)
+    j = stuff(3.0)
+    c.assert_eq(132.0)
+    j.assert_eq(396.0)
+}
+"#;
 
-    sub_slice_of_a4[0].y.assert_eq(1.0)
-    sub_slice_of_a4[1].y.assert_eq(2.0)
+        only_run_func(code)
+    }
 
-    a[1].y = 10.0
-    slice_of_a[1].y.assert_eq(10.0)
+    #[test]
+    fn nested() -> anyhow::Result<()> {
+        //setup_logging();
+        let code = r#"
+inline fn stuff1(a, b) -> (c, k) {
+    c = a + b
+    stuff2|d| -> (f) { 
+        c = c * d
+        f = c + 5.0
+    }
+    k = stuff2(3.0)
+}
 
-    slice_of_a[2].y = 20.0
-    a[2].y.assert_eq(20.0)
+pub fn main() -> () {    
+    a = 5.0
+    b = 6.0
+    h, i = stuff1(a, b)
+    h.assert_eq(33.0)
+    i.assert_eq(38.0)
+}
+"#;
 
-    sub_slice_of_a4[0].y = 5.0
-    sub_slice_of_a4[1].y = 6.0
+        only_run_func(code)
+    }
 
-    a[1].y.assert_eq(5.0)
-    a[2].y.assert_eq(6.0)
+    #[test]
+    fn nested2() -> anyhow::Result<()> {
+        //setup_logging();
+        let code = r#"
+pub fn main() -> () {
+    j = 0.0
+    add|| -> () {
+        c = c * 2.0
+        stuff|e| -> (f) { 
+            c.assert_eq(22.0)
+            c = c * 2.0 * e
+            c.assert_eq(132.0)
+            f = c * e
+        }
+        j = stuff(3.0)
+    }
+    a = 5.0
+    b = 6.0
+    c = 5.0 + 6.0
+    c.assert_eq(11.0)
+    add()
+    c.assert_eq(132.0)
+    j.assert_eq(396.0)
 }
 "#;
-    only_run_func(code)
-}
 
-#[test]
-fn pass_slice_to_func() -> anyhow::Result<()> {
-    //setup_logging();
-    let code = r#"
-struct Point {
-    x, y, z,
+        only_run_func(code)
+    }
+
+    #[test]
+    fn nested3() -> anyhow::Result<()> {
+        //setup_logging();
+        let code = r#"
+inline fn stuff1(a, b) -> (c, k) {
+    c = a + b
+    stuff2|d| -> (f) { 
+        c = c * d
+        f = c + 5.0
+    }
+    k = stuff2(3.0)
 }
 
-fn takes_slices(a: [Point], b: [Point]) -> () {
-    a[0].y.assert_eq(0.0)
-    a[1].y.assert_eq(1.0)
-    a[2].y.assert_eq(2.0)
-    a[99].y.assert_eq(99.0)
+pub fn main() -> () {
+    j = 0.0
+    add|| -> () {
+        c = c * 2.0
+        stuff|e| -> (f) { 
+            c.assert_eq(22.0)
+            c = c * 2.0 * e
+            c.assert_eq(132.0)
+            f = c * e
+            u, l = stuff1(c, e)
+            u.assert_eq(405.0)
+            l.assert_eq(410.0)
+        }
+        j = stuff(3.0)
+    }
+    a = 5.0
+    b = 6.0
+    c = 5.0 + 6.0
+    c.assert_eq(11.0)
+    add()
+    c.assert_eq(132.0)
+    j.assert_eq(396.0)
+}
+"#;
 
-    b[0].y.assert_eq(1.0)
-    b[1].y.assert_eq(2.0)
+        only_run_func(code)
+    }
 
-    a[3].y = 20.0
-    b[1].y = 10.0
+    #[test]
+    fn passing() -> anyhow::Result<()> {
+        //setup_logging();
+        let code = r#"
+always_inline fn run_some_closure(n, some_closure: |e| -> ()) -> () {
+    some_closure(n * 5.0)
 }
+pub fn main() -> () {
+    stuff|e| -> () {
+        c *= e
+    }
+    c = 5.0 + 6.0
+    run_some_closure(2.0, stuff)
+    c.assert_eq(110.0)
+}
+"#;
 
-fn main() -> () { 
-    a = [Point {
-        x:0.0, 
-        y:0.0, 
-        z:0.0,
-    }; 100]
-    a[0].y = 0.0
-    a[1].y = 1.0
-    a[2].y = 2.0
-    a[99].y = 99.0
-    slice_of_a = a[..]
-    sub_slice_of_a1 = a[1..3]
-
-    takes_slices(slice_of_a, sub_slice_of_a1)
+        only_run_func(code)
+    }
 
-    a[3].y.assert_eq(20.0)
-    a[2].y.assert_eq(10.0)
+    #[test]
+    fn passing_with_return() -> anyhow::Result<()> {
+        //setup_logging();
+        let code = r#"
+always_inline fn run_some_closure(n, some_closure: |e| -> (f)) -> (f) {
+    f = some_closure(n * 5.0)
+}
+pub fn main() -> () {
+    stuff|e| -> (f) {
+        c *= e
+        f = c * 2.0
+    }
+    c = 5.0 + 6.0
+    j = run_some_closure(2.0, stuff)
+    c.assert_eq(110.0)
+    j.assert_eq(220.0)
 }
 "#;
-    only_run_func(code)
+
+        only_run_func(code)
+    }
+
+    #[test]
+    fn passing_no_param() -> anyhow::Result<()> {
+        //setup_logging();
+        let code = r#"
+always_inline fn run_some_closure(some_closure: || -> ()) -> () {
+    some_closure()
 }
+pub fn main() -> () {
+    stuff|| -> () {
+        c *= 2.0
+    }
+    c = 5.0 + 6.0
+    run_some_closure(stuff)
+    c.assert_eq(22.0)
+}
+"#;
 
-#[test]
-fn return_slice_from_func() -> anyhow::Result<()> {
-    //setup_logging();
-    let code = r#"
-inline fn takes_slices(in: [f32]) -> (r: [f32]) {
-    r = in
+        only_run_func(code)
+    }
+
+    #[test]
+    fn anonymous_passing() -> anyhow::Result<()> {
+        //setup_logging();
+        let code = r#"
+always_inline fn run_some_closure(n, some_closure: |f32| -> ()) -> () {
+    some_closure(n * 5.0)
+}
+pub fn main() -> () {
+    c = 5.0 + 6.0
+    run_some_closure(2.0, |e| -> () {c *= e})
+    c.assert_eq(110.0)
 }
+"#;
+        only_run_func(code)
+    }
+
+    #[test]
+    fn anonymous_passing_with_return() -> anyhow::Result<()> {
+        //setup_logging();
+        let code = r#"
+always_inline fn run_some_closure(n, some_closure: |e| -> (f)) -> (f) {
+    f = some_closure(n * 5.0)
+}
+pub fn main() -> () {
+    c = 5.0 + 6.0
+    j = run_some_closure(2.0, |e| -> (f) {
+        c *= e
+        f = c * 2.0
+    })
+    c.assert_eq(110.0)
+    j.assert_eq(220.0)
+}
+"#;
+
+        only_run_func(code)
+    }
+
+    #[test]
+    fn anonymous_passing_with_return_calls_inline() -> anyhow::Result<()> {
+        //setup_logging();
+        let code = r#"
+pub inline fn mult(a, b) -> (c) {
+    c = a * b
+}
+
+inline fn multj(a, b) -> (c) {
+    j = 6.0
+    c = mult(a, b) + j
+    c -= j
+}
+
+always_inline fn run_some_closure(n, some_closure: |e| -> (f)) -> (f) {
+    f = some_closure(n * 5.0)
+}
+
+pub fn main() -> () {
+    c = 5.0 + 6.0
+    j = run_some_closure(2.0, |e| -> (f) {
+        c *= e
+        f = multj(c, 2.0)
+    })
+    c.assert_eq(110.0)
+    j.assert_eq(220.0)
+}
+"#;
+
+        only_run_func(code)
+    }
+
+    #[test]
+    fn anonymous_passing2() -> anyhow::Result<()> {
+        //setup_logging();
+        let code = r#"
+always_inline fn run_some_closure(n, some_closure: |e| -> ()) -> () {
+    some_closure(n * 5.0)
+}
+
+always_inline fn stuff(n, some_closure2: |e| -> ()) -> () {
+    run_some_closure(n, some_closure2)
+}
+
+pub fn main() -> () {
+    c = 5.0 + 6.0
+    stuff(2.0, |e| -> () {c *= e})
+    c.assert_eq(110.0)
+}
+"#;
+        only_run_func(code)
+    }
+
+    #[test]
+    fn inline_func_has_anonymous_passing() -> anyhow::Result<()> {
+        //setup_logging();
+        let code = r#"
+always_inline fn run_some_closure(n, some_closure: |e| -> ()) -> () {
+    some_closure(n * 5.0)
+}
+
+inline fn stuff() -> () {
+    c = 5.0 + 6.0
+    run_some_closure(2.0, |e| -> () {c *= e})
+    c.assert_eq(110.0)
+}
+
+pub fn main() -> () {
+    stuff()
+}
+"#;
+
+        only_run_func(code)
+    }
+
+    #[test]
+    fn inline_func_has_anonymous_passing_with_return_calls_inline() -> anyhow::Result<()> {
+        //setup_logging();
+        let code = r#"
+pub inline fn mult(a, b) -> (c) {
+    c = a * b
+}
+
+inline fn multj(a, b) -> (c) {
+    j = 6.0
+    c = mult(a, b) + j
+    c -= j
+}
+
+always_inline fn run_some_closure(n, some_closure: |e| -> (f)) -> (f) {
+    f = some_closure(n * 5.0)
+}
+
+fn stuff() -> () {
+    c = 5.0 + 6.0
+    j = run_some_closure(2.0, |e| -> (f) {
+        c *= e
+        f = multj(c, 2.0)
+    })
+    c.assert_eq(110.0)
+    j.assert_eq(220.0)
+}
+
+pub fn main() -> () {
+    stuff()
+}
+"#;
+
+        only_run_func(code)
+    }
+
+    #[test]
+    fn closure_in_closure() -> anyhow::Result<()> {
+        //setup_logging();
+        let code = r#"
+pub inline fn mult(a, b) -> (c) {
+    c = a * b
+}
+
+always_inline fn run_some_closure2(n, some_closure: |e| -> ()) -> () {
+    some_closure(mult(n, 5.0))
+}
+
+always_inline fn run_some_closure(n, some_closure: |e| -> ()) -> () {
+    some_closure(n * 5.0)
+}
+
+inline fn stuff() -> () {
+    c = 5.0 + 6.0
+    run_some_closure(2.0, |e| -> () {
+        run_some_closure2(3.0, |f| -> () {c *= f})
+        c *= e
+    })
+    c.assert_eq(1650.0)
+}
+
+pub fn main() -> () {
+    stuff()
+}
+"#;
+
+        only_run_func(code)
+    }
+
+    #[test]
+    fn closure_in_closure_struct() -> anyhow::Result<()> {
+        //setup_logging();
+        let code = r#"
+struct FloatVal {
+    val: f32,
+}
+
+pub inline fn mult(a: FloatVal, b: FloatVal) -> (c: FloatVal) {
+    c = FloatVal{ val: a.val * b.val, }
+}
+
+always_inline fn run_some_closure2(n: FloatVal, some_closure: |e: FloatVal| -> ()) -> () {
+    some_closure( mult(n, FloatVal{ val: 5.0, }) )
+}
+
+always_inline fn run_some_closure(n: FloatVal, some_closure: |e: FloatVal| -> ()) -> () {
+    n.val *= 5.0
+    some_closure(n)
+}
+
+inline fn stuff() -> () {
+    c = FloatVal { val: 5.0 + 6.0, }
+    c.val.assert_eq(11.0)
+    run_some_closure(FloatVal{ val: 2.0, }, |e: FloatVal| -> () {
+        c.val.assert_eq(11.0)
+        run_some_closure2(FloatVal{ val: 3.0, }, |f: FloatVal| -> () {
+            c.val.assert_eq(11.0) //"cl2 c ".print() 
+            e.val.assert_eq(10.0) //"cl2 e ".print() 
+            f.val.assert_eq(15.0) //"cl2 f ".print() 
+            c.val *= f.val
+        })
+        c.val.assert_eq(165.0)
+        c.val *= e.val
+        c.val.assert_eq(1650.0)
+    })
+    c.val.assert_eq(1650.0)
+}
+
+pub fn main() -> () {
+    stuff()
+}
+"#;
+
+        only_run_func(code)
+    }
+
+    #[test]
+    fn closure_takes_closure() -> anyhow::Result<()> {
+        //setup_logging();
+        let code = r#"
+always_inline fn run_some_closure(n, some_closure: |e, cl: |f| -> (j)| -> ()) -> () {
+    a = 1.0
+    some_closure(n * 5.0, |f| -> (j) {j = f + a})
+}
+
+inline fn stuff() -> () {
+    c = 5.0 + 6.0
+    run_some_closure(2.0, |e, cl: |f| -> (j)| -> () {
+        cl(3.0).assert_eq(4.0)
+        e.assert_eq(10.0)
+        c.assert_eq(11.0)
+        c *= e + cl(3.0)
+    })
+    c.assert_eq(154.0)
+}
+
+pub fn main() -> () {
+    stuff()
+}
+"#;
+
+        only_run_func(code)
+    }
+
+    #[test]
+    fn use_closure_from_parent_closure_scope() -> anyhow::Result<()> {
+        //setup_logging();
+        let code = r#"
+always_inline fn run_some_closure(n, some_closure: |e| -> ()) -> () {
+    some_closure(n * 5.0)
+}
+
+pub fn main() -> () {
+    c = 5.0 + 6.0
+    add1|| -> () {c += 1.0}
+    run_some_closure(2.0, |e| -> () {c *= e add1()})
+    c.assert_eq(111.0)
+}
+"#;
+
+        only_run_func(code)
+    }
+
+    #[test]
+    fn use_closure_from_parent_closure_scope2() -> anyhow::Result<()> {
+        //setup_logging();
+        let code = r#"
+pub fn main() -> () {
+    a = 1.0
+    single_proc|| -> () {
+        a += 1.0
+    }
+
+    while_proc | get_coeffs: ||->(c: f32) |->() {
+        coeffs = get_coeffs()
+        single_proc()        
+    }
+    
+    while_proc(||->(c: f32) {
+        c = 5.0
+    })
+}
+"#;
+
+        only_run_func(code)
+    }
+}
+
+#[test]
+fn var_define_checking() -> anyhow::Result<()> {
+    //setup_logging();
+    let code = r#"
+
+pub fn main() -> () {  
+    a = 0.0
+    if false {
+        a = 5.0
+    } else {
+        a = 4.0
+    }
+    a.assert_eq(4.0)
+    b = 0.0
+    if false {
+        b = 5.0
+    } else if (true) {
+        b = 4.0
+    } else {
+        b = 3.0
+    }
+    b.assert_eq(4.0)
+}
+
+"#;
+    only_run_func(code)
+}
+
+#[test]
+fn dot_access_conditionals() -> anyhow::Result<()> {
+    //setup_logging();
+    let code = r#"
+pub fn main() -> () {  
+    (if true {1.0} else {-1.0}).assert_eq(1.0)
+    (if false {1.0} else {-1.0}).assert_eq(-1.0)
+    (if false {1.0} else if true {-1.0} else {0.0}).assert_eq(-1.0)
+}
+"#;
+    only_run_func(code)
+}
+
+#[test]
+fn recursion() -> anyhow::Result<()> {
+    //setup_logging();
+    let code = r#"
+inline fn fib(n: i64) -> (r: i64) {
+    r = if n <= 1 {
+        n
+    } else {
+        fib(n - 1) + fib(n - 2)
+    }
+}
+pub fn main() -> () {  
+    i = 0
+    while i < 10 {
+        fib(i).println()
+        i += 1
+    }
+}
+"#;
+    only_run_func(code)
+}
+
+#[test]
+fn unary_negative() -> anyhow::Result<()> {
+    //setup_logging();
+    let code = r#"
+fn number() -> (y) {
+    y = 2.0
+}
+pub fn main() -> () {
+    a = 5
+    b = -a
+    b.assert_eq(-5)
+    (-b).assert_eq(5)
+    c = 5.0
+    d = -c
+    d.assert_eq(-5.0)
+    (-d).assert_eq(5.0)
+    e = -number()
+    e.assert_eq(-2.0)
+    (-number()).assert_eq(-2.0)
+    (-(number())).assert_eq(-2.0)
+    (4 + -4).assert_eq(0)
+    (2 + -4 + 2).assert_eq(0)
+    ((2 + -4) + 2).assert_eq(0)
+    (2 + (-4 + 2)).assert_eq(0)
+    four = 4
+    two = 2
+    (four + -four).assert_eq(0)
+    (two + -four + two).assert_eq(0)
+    ((two + -four) + two).assert_eq(0)
+    (two + (-four + two)).assert_eq(0)
+}
+"#;
+    only_run_func(code)
+}
+
+#[test]
+fn expr_array_access() -> anyhow::Result<()> {
+    //setup_logging();
+    let code = r#"
+
+fn arr(n) -> (a: [f32; 10]) {
+    a = [n; 10]
+}
+
+pub fn main() -> () { 
+    a = [1.0; 10]
+    b = (a)[1]
+    b.assert_eq(1.0)
+    ([2.0; 10])[1].assert_eq(2.0)
+    [3.0; 10][1].assert_eq(3.0)
+    arr(4.0)[1].assert_eq(4.0)
+}
+"#;
+    only_run_func(code)
+}
+
+#[test]
+fn declare_var_in_if() -> anyhow::Result<()> {
+    //setup_logging();
+    let code = r#"
+
+pub fn main() -> () { 
+    if true {
+        a = 5
+        a.assert_eq(5)
+    }
+}
+"#;
+    only_run_func(code)
+}
+
+#[test]
+fn init_process_state() -> anyhow::Result<()> {
+    //setup_logging();
+    let code = r#"
+struct ProcessState {
+    delay_l: [f32; 10000],
+    delay_r: [f32; 10000],
+}
+pub fn main() -> () {
+    state = ProcessState {
+        delay_l: [1.234; 10000],
+        delay_r: [1.234; 10000],
+    }
+    state.delay_r[0].assert_eq(1.234)
+    state.delay_r[1].assert_eq(1.234)
+    state.delay_r[9999].assert_eq(1.234)
+    state.delay_l[0].assert_eq(1.234)
+    state.delay_l[1].assert_eq(1.234)
+    state.delay_l[9999].assert_eq(1.234)
+
+    a = [1.234; 15] //Test array init that does not use loop
+    a[0].assert_eq(1.234)
+    a[14].assert_eq(1.234)
+}
+
+"#;
+    only_run_func(code)
+}
+
+#[test]
+fn assign_to_param_address_without_extra_alloc() -> anyhow::Result<()> {
+    //setup_logging();
+    let code = r#"
+
+fn takes_arr(n: [f32; 5]) -> () {
+    n = [1.0; 5]
+}
+
+pub fn main() -> () { 
+    a = [0.0; 5]
+    a[1].assert_eq(0.0)
+    takes_arr(a)
+    a[1].assert_eq(1.0)
+}
+"#;
+    only_run_func(code)
+}
+
+#[test]
+fn basic_slice() -> anyhow::Result<()> {
+    //setup_logging();
+    let code = r#"
+pub fn main() -> () { 
+    a = [0.0; 100]
+    a[0] = 0.0
+    a[1] = 1.0
+    a[2] = 2.0
+    a[99] = 99.0
+    slice_of_a = a[..]
+    sub_slice_of_a1 = a[0..3]
+    sub_slice_of_a2 = a[..3]
+    sub_slice_of_a3 = a[2..]
+    sub_slice_of_a4 = a[1..3]
+
+    slice_of_a[0].assert_eq(0.0)
+    slice_of_a[1].assert_eq(1.0)
+    slice_of_a[2].assert_eq(2.0)
+    slice_of_a[99].assert_eq(99.0)
+
+    sub_slice_of_a1[0].assert_eq(0.0)
+    sub_slice_of_a1[1].assert_eq(1.0)
+    sub_slice_of_a1[2].assert_eq(2.0)
+
+    sub_slice_of_a2[0].assert_eq(0.0)
+    sub_slice_of_a2[1].assert_eq(1.0)
+    sub_slice_of_a2[2].assert_eq(2.0)
+
+    sub_slice_of_a3[0].assert_eq(2.0)
+    sub_slice_of_a3[99-2].assert_eq(99.0)
+
+    sub_slice_of_a4[0].assert_eq(1.0)
+    sub_slice_of_a4[1].assert_eq(2.0)
+
+    a[1] = 10.0
+    slice_of_a[1].assert_eq(10.0)
+
+    slice_of_a[2] = 20.0
+    a[2].assert_eq(20.0)
+
+    sub_slice_of_a4[0] = 5.0
+    sub_slice_of_a4[1] = 6.0
+
+    a[1].assert_eq(5.0)
+    a[2].assert_eq(6.0)
+}
+"#;
+    only_run_func(code)
+}
+
+#[test]
+fn slice_contains_struct() -> anyhow::Result<()> {
+    //setup_logging();
+    let code = r#"
+struct Point {
+    x, y, z,
+}
+
+pub fn main() -> () { 
+    a = [Point {
+        x:0.0, 
+        y:0.0, 
+        z:0.0,
+    }; 100]
+    a[0].y = 0.0
+    a[1].y = 1.0
+    a[2].y = 2.0
+    a[99].y = 99.0
+    slice_of_a = a[..]
+    a[1].y.assert_eq(1.0)
+    sub_slice_of_a1 = a[0..3]
+    sub_slice_of_a2 = a[..3]
+    sub_slice_of_a3 = a[2..]
+    sub_slice_of_a4 = a[1..3]
+
+    slice_of_a[0].y.assert_eq(0.0)
+    slice_of_a[1].y.assert_eq(1.0)
+    slice_of_a[2].y.assert_eq(2.0)
+    slice_of_a[99].y.assert_eq(99.0)
+
+    sub_slice_of_a1[0].y.assert_eq(0.0)
+    sub_slice_of_a1[1].y.assert_eq(1.0)
+    sub_slice_of_a1[2].y.assert_eq(2.0)
+
+    sub_slice_of_a2[0].y.assert_eq(0.0)
+    sub_slice_of_a2[1].y.assert_eq(1.0)
+    sub_slice_of_a2[2].y.assert_eq(2.0)
+
+    sub_slice_of_a3[0].y.assert_eq(2.0)
+    sub_slice_of_a3[99-2].y.assert_eq(99.0)
+
+    sub_slice_of_a4[0].y.assert_eq(1.0)
+    sub_slice_of_a4[1].y.assert_eq(2.0)
+
+    a[1].y = 10.0
+    slice_of_a[1].y.assert_eq(10.0)
+
+    slice_of_a[2].y = 20.0
+    a[2].y.assert_eq(20.0)
+
+    sub_slice_of_a4[0].y = 5.0
+    sub_slice_of_a4[1].y = 6.0
+
+    a[1].y.assert_eq(5.0)
+    a[2].y.assert_eq(6.0)
+}
+"#;
+    only_run_func(code)
+}
+
+#[test]
+fn pass_slice_to_func() -> anyhow::Result<()> {
+    //setup_logging();
+    let code = r#"
+struct Point {
+    x, y, z,
+}
+
+fn takes_slices(a: [Point], b: [Point]) -> () {
+    a[0].y.assert_eq(0.0)
+    a[1].y.assert_eq(1.0)
+    a[2].y.assert_eq(2.0)
+    a[99].y.assert_eq(99.0)
+
+    b[0].y.assert_eq(1.0)
+    b[1].y.assert_eq(2.0)
+
+    a[3].y = 20.0
+    b[1].y = 10.0
+}
+
+pub fn main() -> () { 
+    a = [Point {
+        x:0.0, 
+        y:0.0, 
+        z:0.0,
+    }; 100]
+    a[0].y = 0.0
+    a[1].y = 1.0
+    a[2].y = 2.0
+    a[99].y = 99.0
+    slice_of_a = a[..]
+    sub_slice_of_a1 = a[1..3]
+
+    takes_slices(slice_of_a, sub_slice_of_a1)
+
+    a[3].y.assert_eq(20.0)
+    a[2].y.assert_eq(10.0)
+}
+"#;
+    only_run_func(code)
+}
+
+#[test]
+fn return_slice_from_func() -> anyhow::Result<()> {
+    //setup_logging();
+    let code = r#"
+inline fn takes_slices(in: [f32]) -> (r: [f32]) {
+    r = in
+}
+
+pub fn main() -> () { 
+    sl1 = [1.0; 100][..]
+    sl = [1.0; 200][..]
+    sl1 = takes_slices(sl)
+    sl1[5].assert_eq(1.0)
+}
+"#;
+    only_run_func(code)
+}
+
+#[test]
+fn unsized_slice() -> anyhow::Result<()> {
+    //setup_logging();
+    let code = r#"
+pub fn modifies_an_array(arr: &[i64], len: i64) -> () {   
+    arr_slice = arr[..10]
+    a = arr_slice[0]
+    arr_slice[0] = 5
+    arr_slice[9] = 5
+}
+
+pub fn modifies_a_fixed_array(arr: [i64; 10], len: i64) -> () {   
+    arr_slice = arr[..]
+    a = arr_slice[0]
+    arr_slice[0] = 5
+    arr_slice[9] = 5
+}
+"#;
+    let mut jit = default_std_jit_from_code(code, true)?;
+    let mut arr = [1i64; 10];
+    let func_ptr = jit.get_func("modifies_an_array")?;
+    let func = unsafe { mem::transmute::<_, extern "C" fn(&mut [i64; 10], i64)>(func_ptr) };
+    let len = arr.len() as i64;
+    func(&mut arr, len);
+    assert_eq!(arr, [5, 1, 1, 1, 1, 1, 1, 1, 1, 5,]);
+    let func_ptr = jit.get_func("modifies_a_fixed_array")?;
+    let func = unsafe { mem::transmute::<_, extern "C" fn(&mut [i64; 10], i64)>(func_ptr) };
+    let len = arr.len() as i64;
+    func(&mut arr, len);
+    assert_eq!(arr, [5, 1, 1, 1, 1, 1, 1, 1, 1, 5,]);
+    Ok(())
+}
+
+#[test]
+fn slice_of_slice() -> anyhow::Result<()> {
+    //setup_logging();
+    let code = r#"
+
+pub fn main() -> () { 
+    sl = [0.0; 100][..]
+    sl[1] = 1.0
+    sl[2] = 2.0
+    sl[3] = 3.0
+    sl2 = sl[..]
+    sl2[1].assert_eq(1.0)
+    sl2[2].assert_eq(2.0)
+    sl2[3].assert_eq(3.0)
+    sl3 = sl2[1..]
+    sl3[1].assert_eq(2.0)
+    sl3[2].assert_eq(3.0)
+    sl3[3].assert_eq(0.0)
+    sl4 = sl3[1..]
+    sl4[1].assert_eq(3.0)
+    sl4[2].assert_eq(0.0)
+    sl4[3].assert_eq(0.0)
+    (sl4[0..1])[0].assert_eq(2.0)
+    (sl4[1..2])[0].assert_eq(3.0)
+
+    sl5 = sl[1..2]
+    sl5[0].assert_eq(1.0)
+    sl6 = sl[1..5]
+    sl6[0].assert_eq(1.0)
+    sl6[1].assert_eq(2.0)
+    sl6[2].assert_eq(3.0)
+
+    a = [0, 1, 2, 3, 4, 5]
+    b = a[0..2]
+    b.len().assert_eq(2)
+    b.cap().assert_eq(6)
+    
+    c = b[..6]
+    c.len().assert_eq(6)
+    c.cap().assert_eq(6)
+    c[4].assert_eq(4)
+    c[5].assert_eq(5)
+}
+"#;
+    only_run_func(code)
+}
+
+#[test]
+fn direct_array_literal() -> anyhow::Result<()> {
+    //setup_logging();
+    let code = r#"
+pub fn main() -> () {
+    arr = [0.0, 0.5+0.5, 2.0, 3.0, 4.0]
+    arr[0].assert_eq(0.0)
+    arr[1].assert_eq(1.0)
+    arr[2].assert_eq(2.0)
+    arr[3].assert_eq(3.0)
+    arr[4].assert_eq(4.0)
+}
+"#;
+    only_run_func(code)
+}
+
+#[test]
+fn push_onto_slice() -> anyhow::Result<()> {
+    //setup_logging();
+    let code = r#"
+
+pub fn main() -> () {
+    arr = [0.0; 100]
+    sl = arr[0..0]
+    sl.cap().assert_eq(arr.len())
+    sl.len().assert_eq(0)
+    sl.push(1.0)
+    sl.len().assert_eq(1)
+    sl.push(2.0)
+    sl.len().assert_eq(2)
+    sl.pop().assert_eq(2.0)
+    sl.len().assert_eq(1)
+    sl.pop().assert_eq(1.0)
+    sl.len().assert_eq(0)
+    (sl.unsized())[0].assert_eq(arr[0])
+    (sl.unsized())[1].assert_eq(arr[1])
+    (sl.unsized())[2].assert_eq(arr[2])
+}
+"#;
+    only_run_func(code)
+}
+
+#[test]
+fn append_to_slice() -> anyhow::Result<()> {
+    //setup_logging();
+    let code = r#"
+pub fn main() -> () {
+    arr = [2.0; 100]
+    sl = arr[0..3]
+    sl.append([1.0;3])
+    sl.len().assert_eq(6)
+    sl[2].assert_eq(2.0)
+    sl[3].assert_eq(1.0)
+    sl[4].assert_eq(1.0)
+    sl[5].assert_eq(1.0)
+    (sl[0..sl.len()+1])[6].assert_eq(2.0)
+    sl.append([6.0,7.0,8.0][..])
+    sl.len().assert_eq(9)
+    sl[5].assert_eq(1.0)
+    sl[6].assert_eq(6.0)
+    sl[7].assert_eq(7.0)
+    sl[8].assert_eq(8.0)
+    (sl[0..sl.len()+1])[9].assert_eq(2.0)
+}
+"#;
+    only_run_func(code)
+}
+
+#[test]
+fn append_slice_of_structs_to_slice() -> anyhow::Result<()> {
+    //setup_logging();
+    let code = r#"
+struct Point { x, y, z, }
+
+pub fn main() -> () {
+    arr = [Point { x: 0.0, y: 0.0, z: 0.0, }; 100]
+    sl = arr[0..0]
+    sl.len().assert_eq(0)
+    to_append = [
+        Point { x: 1.0, y: 2.0, z: 3.0, },
+        Point { x: 4.0, y: 5.0, z: 6.0, },
+        Point { x: 7.0, y: 8.0, z: 9.0, }
+    ]
+    sl.append(to_append)
+    sl.len().assert_eq(3)
+    sl[0].x.assert_eq(1.0)
+    sl[0].y.assert_eq(2.0)
+    sl[0].z.assert_eq(3.0)
+    sl[1].x.assert_eq(4.0)
+    sl[1].y.assert_eq(5.0)
+    sl[1].z.assert_eq(6.0)
+    sl[2].x.assert_eq(7.0)
+    sl[2].y.assert_eq(8.0)
+    sl[2].z.assert_eq(9.0)
+}
+"#;
+    only_run_func(code)
+}
+
+#[test]
+fn returns_a_slice() -> anyhow::Result<()> {
+    //setup_logging();
+    let code = r#"
+inline fn a_slice(a) -> (b: [f32]) {
+    b = [a; 100][..]
+}
+pub fn main() -> () {
+    b = a_slice(5.0)
+    b[20].assert_eq(5.0)
+}
+"#;
+    only_run_func(code)
+}
+
+#[test]
+fn scoped_vars() -> anyhow::Result<()> {
+    //setup_logging();
+    let code = r#"
+pub fn main() -> () {
+    if true {
+        a = 1.0 //does not live outside if statement
+    }
+    a = true
+}
+"#;
+    only_run_func(code)
+}
+
+#[test]
+fn u8_math() -> anyhow::Result<()> {
+    //setup_logging();
+    let code = r#"
+pub fn main() -> () {
+    a = 0
+    b = 255
+    c = 100
+    d = 5
+    ua = (0).u8()
+    ub = (255).u8()
+    uc = (100).u8()
+    ud = (5).u8()
+    (ua+ub).assert_eq((a+b).u8())
+    (ua-uc).assert_eq((a-c).u8())
+    (ua/uc).assert_eq((a/c).u8())
+    (ua*uc).assert_eq((a*c).u8())
+    (ud*ud).assert_eq((d*d).u8())
+    (ua+ub).i64().assert_eq(a+b)
+    (ua/uc).i64().assert_eq(a/c)
+    (ua*uc).i64().assert_eq(a*c)
+    (ud*ud).i64().assert_eq(d*d)
+    ua.assert_eq(0u8)
+    ub.assert_eq(255u8)
+    uc.assert_eq(100u8)
+    ud.assert_eq(5u8)
+    (0u8+255u8).assert_eq((0+255).u8())
+    (0u8-100u8).assert_eq((0-100).u8())
+    (0u8/100u8).assert_eq((0/100).u8())
+    (0u8*100u8).assert_eq((0*100).u8())
+    (5u8*5u8).assert_eq((5*5).u8())
+}
+"#;
+    only_run_func(code)
+}
+
+extern "C" fn rust_check_slice(a: sarus_std_lib::SarusSlice<u8>) {
+    assert_eq!(a.len(), 43);
+    assert_eq!(a.cap(), 1000);
+    assert_eq!(
+        std::str::from_utf8(a.slice()).unwrap(),
+        "Hello World !\"#$%&'()*+,-./0123456789:;<=>?"
+    );
+}
+
+#[test]
+fn extern_func_slice() -> anyhow::Result<()> {
+    //setup_logging();
+    let code = r#"
+    extern fn rust_check_slice(a: [u8]) -> () {}
+
+    pub fn main() -> () {
+        a = [0u8;1000][0..0]
+        a.append("Hello")
+        a.append(" ")
+        a.append("World")
+        i = 0u8
+        while i < 32u8 {
+            a.push(i + 32u8)
+            i += 1u8
+        }
+        rust_check_slice(a)
+    }
+"#;
+    let ast = parse(code)?;
+    only_run_func_with_importer(ast, None, |_ast, jit_builder| {
+        jit_builder.symbols([("rust_check_slice", rust_check_slice as *const u8)]);
+    })
+}
+
+#[test]
+fn while_iter_block() -> anyhow::Result<()> {
+    //setup_logging();
+    let code = r#"
+pub fn main() -> () {
+
+    arr = [0; 10]
+    
+    i = 0 while i < 10 { i += 1 }:{arr[i] = i}
+
+    i = 0 while i < 10 
+    { i += 1 } : {
+        arr[i].assert_eq(i)
+    }
+
+    i = 0 while i < 10 { i += 1 } : {
+        arr[i].assert_eq(i)
+    }
+
+    a = 5
+
+    i = 0 while i < 10 { 
+        a = i + 1
+        i += 1 
+    } : {
+        arr[i].assert_eq(i)
+    }
+    a.assert_eq(10)
+
+    i = 0 while i < 10 {
+        arr[i].assert_eq(i)
+        i += 1
+    }
+}
+"#;
+    only_run_func(code)
+}
+
+#[test]
+fn while_break() -> anyhow::Result<()> {
+    //setup_logging();
+    let code = r#"
+pub fn main() -> () {
+
+    arr = [0; 10]
+    a = 5
+    
+    i = 0 while i < 10 {
+        j = 0 
+        while j < 10 {
+            if i > 5 && j > 5 {
+                "!!!!".println()
+                a = j
+                break
+            }
+            j += 1
+        }
+        if i > 8 {
+            break
+        }
+        i += 1
+    }
+
+    a.assert_eq(6)
+    i.assert_eq(9)
+
+    i = 0 while true {i+=1 if i > 3 {break}} : {
+        a = 2
+    }
+    i.assert_eq(4)
+
+}
+"#;
+    only_run_func(code)
+}
+
+#[test]
+fn while_continue() -> anyhow::Result<()> {
+    //setup_logging();
+    let code = r#"
+pub fn main() -> () {
+    arr = [0; 10]
+
+    a = 0
+    i = 0 while i < 10 {i += 1} : {
+        if i > 5 {
+            continue
+        }
+        a = i
+    }
+    i.assert_eq(10)
+    a.assert_eq(5)
+
+    a = 0
+    i = 0 while i < 10 {i += 1} : {
+        if i > 5 {
+            continue
+        } else {
+            continue
+        }
+        a = i
+    }
+    i.assert_eq(10)
+    a.assert_eq(0)
+
+    a = 0
+    i = 0 while i < 10 {i += 1} : {
+        if i > 5 {
+            j = 0 while j < 10 {j += 1} : {
+                if j > 5 {
+                    a += 1
+                    break
+                } else {
+                    a += 2
+                    continue
+                }
+            }
+        } else {
+            continue
+        }
+        a += i
+    }
+    i.assert_eq(10)
+    a.assert_eq(82)
+
+}
+"#;
+    only_run_func(code)
+}
+
+#[test]
+fn early_return() -> anyhow::Result<()> {
+    //setup_logging();
+    let code = r#"
+fn other2(a: i64) -> (b: i64) {
+    b = 0
+    if a > 5 {
+        b = 3
+        return
+    } else {
+        b = 4
+        return
+    }
+    b = a
+}
+
+fn other(a: i64) -> (b: i64) {
+    b = 0
+    if a > 5 {
+        return
+    }
+    b = a
+}
+
+pub fn main() -> () {
+    other(6).assert_eq(0)
+    other(4).assert_eq(4)
+    other2(1).assert_eq(4)
+    other2(6).assert_eq(3)
+}
+"#;
+    only_run_func(code)
+}
+
+#[cfg(test)]
+mod string_tests {
+
+    use super::*;
+    #[test]
+    fn find() -> anyhow::Result<()> {
+        only_run_func(
+            r#"
+pub fn main() -> () {
+    "hello".find("l").assert_eq(2)
+    "hello".find("x").assert_eq(-1)
+    "ประเทศไทย中华Việt Nam".find("华").assert_eq(30)
+}
+        "#,
+        )
+    }
+
+    #[test]
+    fn rfind() -> anyhow::Result<()> {
+        only_run_func(
+            r#"
+pub fn main() -> () {
+    "hello".rfind("l").assert_eq(3)
+    "hello".rfind("x").assert_eq(-1)
+    "ประเทศไทย中华Việt Nam".rfind("华").assert_eq(30)
+}
+        "#,
+        )
+    }
+
+    #[test]
+    fn starts_with() -> anyhow::Result<()> {
+        only_run_func(
+            r#"
+pub fn main() -> () {
+    "".starts_with("").assert_eq(true)
+    "abc".starts_with("").assert_eq(true)
+    "abc".starts_with("a").assert_eq(true)
+    "a".starts_with("abc").assert_eq(false)
+    "".starts_with("abc").assert_eq(false)
+    "ödd".starts_with("-").assert_eq(false)
+    "ödd".starts_with("öd").assert_eq(true)
+}
+        "#,
+        )
+    }
+    #[test]
+    fn ends_with() -> anyhow::Result<()> {
+        only_run_func(
+            r#"
+pub fn main() -> () {
+    "".ends_with("").assert_eq(true)
+    "abc".ends_with("").assert_eq(true)
+    "abc".ends_with("c").assert_eq(true)
+    "a".ends_with("abc").assert_eq(false)
+    "".ends_with("abc").assert_eq(false)
+    "ddö".ends_with("-").assert_eq(false)
+    "ddö".ends_with("dö").assert_eq(true)
+}
+        "#,
+        )
+    }
+}
+#[cfg(test)]
+mod enum_tests {
+
+    use super::*;
+    #[test]
+    fn enums_num() -> anyhow::Result<()> {
+        //setup_logging();
+        only_run_func(
+            r#"
+enum Num {
+    int: i64,
+    float: f32,
+    byte: u8,
+}
+
+fn f32(self: Num) -> (n: f32) {
+    n = 0.0
+    if self.type == Num::int {
+        n = self.int.f32()
+    } else if self.type == Num::float {
+        n = self.float
+    } else {
+        n = self.byte.f32()
+    }
+}
+
+fn println(self: Num) -> () {
+    if self.type == Num::int {
+        self.int.println()
+    } else if self.type == Num::float {
+        self.float.println()
+    } else {
+        self.byte.println()
+    }
+}
+
+fn assert_eq(self: Num, other: Num) -> () {
+    self.unify(other)
+    if self.type == Num::int {
+        self.int.assert_eq(other.int)
+    } else if self.type == Num::float {
+        self.float.assert_eq(other.float)
+    } else {
+        self.byte.assert_eq(other.byte)
+    }
+}
+
+fn unify(self: Num, other: Num) -> () {
+    if self.type == other.type {
+        return
+    } else {
+        self = Num::float(self.f32())
+        other = Num::float(other.f32())
+    }
+}
+
+pub fn add(self: Num, other: Num) -> (z: Num) {
+    self.unify(other)
+    z = Num::int(100)
+    if self.type == Num::int {
+        z = Num::int(self.int + other.int)
+    } else if self.type == Num::float {
+        z = Num::float(self.float + other.float)
+    } else {
+        z = Num::byte(self.byte + other.byte)
+    }
+}
+
+pub fn main() -> () {
+    a = Num::int(5)
+    b = Num::float(5.0)
+    Num::int(5).add(Num::int(5)).assert_eq(Num::int(10))
+    Num::float(5.0).add(Num::float(5.0)).assert_eq(Num::float(10.0))
+    Num::int(5).add(Num::float(5.0)).assert_eq(Num::float(10.0))
+    Num::byte(5u8).add(Num::byte(5u8)).assert_eq(Num::byte(10u8))
+    Num::byte(5u8).add(Num::float(5.0)).assert_eq(Num::float(10.0))
+}
+    "#,
+        )
+    }
+
+    #[test]
+    fn enums_traditional() -> anyhow::Result<()> {
+        only_run_func(
+            r#"
+enum Num {
+    int,
+    float,
+    byte,
+}
+
+pub fn main() -> () {
+    Num::int().type.assert_eq(Num::int)
+    Num::float().type.assert_eq(Num::float)
+    Num::byte().type.assert_eq(Num::byte)
+    a = Num::int()
+    b = Num::float()
+    c = Num::byte()
+    a.type.assert_eq(0)
+    b.type.assert_eq(1)
+    c.type.assert_eq(2)
+}
+    "#,
+        )
+    }
+
+    #[test]
+    fn enums_mixed() -> anyhow::Result<()> {
+        only_run_func(
+            r#"
+enum Num {
+    int,
+    float,
+    something: f32,
+    byte,
+    something_else: i64,
+}
+
+pub fn main() -> () {
+    Num::int().type.assert_eq(Num::int)
+    Num::float().type.assert_eq(Num::float)
+    Num::something(100.0).type.assert_eq(Num::something)
+    Num::byte().type.assert_eq(Num::byte)
+    Num::something_else(200).type.assert_eq(Num::something_else)
+    a = Num::int()
+    b = Num::float()
+    c = Num::something(100.0)
+    d = Num::byte()
+    e = Num::something_else(200)
+
+    a.type.assert_eq(0)
+    b.type.assert_eq(1)
+
+    c.type.assert_eq(2)
+    c.something.assert_eq(100.0)
+
+    d.type.assert_eq(3)
 
-fn main() -> () { 
-    sl1 = [1.0; 100][..]
-    sl = [1.0; 200][..]
-    sl1 = takes_slices(sl)
-    sl1[5].assert_eq(1.0)
-}
-"#;
-    only_run_func(code)
+    e.type.assert_eq(4)
+    e.something_else.assert_eq(200)
+    
 }
+    "#,
+        )
+    }
 
-#[test]
-fn unsized_slice() -> anyhow::Result<()> {
-    //setup_logging();
-    let code = r#"
-fn modifies_an_array(arr: &[i64], len: i64) -> () {   
-    arr_slice = arr[..10]
-    a = arr_slice[0]
-    arr_slice[0] = 5
-    arr_slice[9] = 5
+    #[test]
+    fn rust_by_example_enum() -> anyhow::Result<()> {
+        //adapted from: https://doc.rust-lang.org/rust-by-example/custom_types/enum.html
+        only_run_func(
+            r#"
+// Create an `enum` to classify a web event. Note how both
+// names and type information together specify the variant:
+// `page_load != page_unload` and `key_press: [u8] != paste: [u8]`.
+// Each is different and independent.
+struct Click {
+    x: i64, 
+    y: i64,
 }
 
-fn modifies_a_fixed_array(arr: [i64; 10], len: i64) -> () {   
-    arr_slice = arr[..]
-    a = arr_slice[0]
-    arr_slice[0] = 5
-    arr_slice[9] = 5
-}
-"#;
-    let mut jit = default_std_jit_from_code(code, true)?;
-    let mut arr = [1i64; 10];
-    let func_ptr = jit.get_func("modifies_an_array")?;
-    let func = unsafe { mem::transmute::<_, extern "C" fn(&mut [i64; 10], i64)>(func_ptr) };
-    let len = arr.len() as i64;
-    func(&mut arr, len);
-    assert_eq!(arr, [5, 1, 1, 1, 1, 1, 1, 1, 1, 5,]);
-    let func_ptr = jit.get_func("modifies_a_fixed_array")?;
-    let func = unsafe { mem::transmute::<_, extern "C" fn(&mut [i64; 10], i64)>(func_ptr) };
-    let len = arr.len() as i64;
-    func(&mut arr, len);
-    assert_eq!(arr, [5, 1, 1, 1, 1, 1, 1, 1, 1, 5,]);
-    Ok(())
+enum WebEvent {
+    // An `enum` may either be `unit-like`,
+    page_load,
+    page_unload,
+    // or have associated types
+    key_press: [u8],
+    paste: [u8],
+    click: Click,
 }
 
-#[test]
-fn slice_of_slice() -> anyhow::Result<()> {
-    //setup_logging();
-    let code = r#"
+// A function which takes a `WebEvent` enum as an argument and
+// returns nothing.
+fn inspect(event: WebEvent) -> () {
+    if event.type == WebEvent::page_load {
+        "page loaded".println()
+    } else if event.type == WebEvent::page_unload {
+        "page unloaded".println()
+    } else if event.type == WebEvent::key_press {
+        "pressed ".print() event.key_press.println()
+    } else if event.type == WebEvent::paste {
+        "pasted ".print() event.paste.println()
+    } else if event.type == WebEvent::click {
+        "clicked at ".print() 
+        "x= ".print() event.click.x.print()
+        ", y= ".print() event.click.x.print()
+        ".".println()
+    } 
+}
 
-fn main() -> () { 
-    sl = [0.0; 100][..]
-    sl[1] = 1.0
-    sl[2] = 2.0
-    sl[3] = 3.0
-    sl2 = sl[..]
-    sl2[1].assert_eq(1.0)
-    sl2[2].assert_eq(2.0)
-    sl2[3].assert_eq(3.0)
-    sl3 = sl2[1..]
-    sl3[1].assert_eq(2.0)
-    sl3[2].assert_eq(3.0)
-    sl3[3].assert_eq(0.0)
-    sl4 = sl3[1..]
-    sl4[1].assert_eq(3.0)
-    sl4[2].assert_eq(0.0)
-    sl4[3].assert_eq(0.0)
-    (sl4[0..1])[0].assert_eq(2.0)
-    (sl4[1..2])[0].assert_eq(3.0)
+pub fn main() -> () {
+    pressed = WebEvent::key_press("x"[..])
+    pasted = WebEvent::paste("my text"[..])
+    click = WebEvent::click(Click{ x: 20, y: 80, }) 
+    load = WebEvent::page_load()
+    unload = WebEvent::page_unload()
 
-    sl5 = sl[1..2]
-    sl5[0].assert_eq(1.0)
-    sl6 = sl[1..5]
-    sl6[0].assert_eq(1.0)
-    sl6[1].assert_eq(2.0)
-    sl6[2].assert_eq(3.0)
+    inspect(pressed)
+    inspect(pasted)
+    inspect(click)
+    inspect(load)
+    inspect(unload)
 
-    a = [0, 1, 2, 3, 4, 5]
-    b = a[0..2]
-    b.len().assert_eq(2)
-    b.cap().assert_eq(6)
-    
-    c = b[..6]
-    c.len().assert_eq(6)
-    c.cap().assert_eq(6)
-    c[4].assert_eq(4)
-    c[5].assert_eq(5)
+    pressed.type.assert_eq(WebEvent::key_press)
+    pasted.type.assert_eq(WebEvent::paste)
+    click.type.assert_eq(WebEvent::click)
+    load.type.assert_eq(WebEvent::page_load)
+    unload.type.assert_eq(WebEvent::page_unload)
 }
-"#;
-    only_run_func(code)
+    "#,
+        )
+    }
 }
 
 #[test]
-fn direct_array_literal() -> anyhow::Result<()> {
-    //setup_logging();
-    let code = r#"
-fn main() -> () {
-    arr = [0.0, 0.5+0.5, 2.0, 3.0, 4.0]
-    arr[0].assert_eq(0.0)
-    arr[1].assert_eq(1.0)
-    arr[2].assert_eq(2.0)
-    arr[3].assert_eq(3.0)
-    arr[4].assert_eq(4.0)
+fn loop_lifetime() -> anyhow::Result<()> {
+    only_run_func(
+        r#"
+pub fn main() -> () {
+    a = [0;10000][..]
+    i = 0 while i < 5 {i+=1} : {
+        c = [1.0;10000]
+        if i == 0 {
+            b = [2;10000] //(while)inner, copy
+            a = b[..] //TODO compiler error
+        }
+        d = [1.0;10000]
+    }
+    e = [1.0;10000]
+    //a[5].println()
 }
-"#;
-    only_run_func(code)
+"#,
+    )
 }
 
 #[test]
-fn push_onto_slice() -> anyhow::Result<()> {
+fn loop_lifetime_simple() -> anyhow::Result<()> {
     //setup_logging();
-    let code = r#"
+    only_run_func(
+        r#"
+pub fn main() -> () {
+    //a = [0;100][..]
+    i = 0 while i < 1 {i+=1} : {
+        d = [1.0;100]
+    }
+    //(1.0).println()
+    //a = 1.0
+    //i = 0 while i < 100000000 {i+=1} : {
+    //    a *= (1.0).sin()
+    //}
+    //(2.0).println()
 
-fn main() -> () {
-    arr = [0.0; 100]
-    sl = arr[0..0]
-    sl.cap().assert_eq(arr.len())
-    sl.len().assert_eq(0)
-    sl.push(1.0)
-    sl.len().assert_eq(1)
-    sl.push(2.0)
-    sl.len().assert_eq(2)
-    sl.pop().assert_eq(2.0)
-    sl.len().assert_eq(1)
-    sl.pop().assert_eq(1.0)
-    sl.len().assert_eq(0)
-    (sl.unsized())[0].assert_eq(arr[0])
-    (sl.unsized())[1].assert_eq(arr[1])
-    (sl.unsized())[2].assert_eq(arr[2])
 }
-"#;
-    only_run_func(code)
+"#,
+    )
 }
 
 #[test]
-fn append_to_slice() -> anyhow::Result<()> {
+fn deep_stack_while_loop2() -> anyhow::Result<()> {
     //setup_logging();
     let code = r#"
-fn main() -> () {
-    arr = [2.0; 100]
-    sl = arr[0..3]
-    sl.append([1.0;3])
-    sl.len().assert_eq(6)
-    sl[2].assert_eq(2.0)
-    sl[3].assert_eq(1.0)
-    sl[4].assert_eq(1.0)
-    sl[5].assert_eq(1.0)
-    (sl[0..sl.len()+1])[6].assert_eq(2.0)
-    sl.append([6.0,7.0,8.0][..])
-    sl.len().assert_eq(9)
-    sl[5].assert_eq(1.0)
-    sl[6].assert_eq(6.0)
-    sl[7].assert_eq(7.0)
-    sl[8].assert_eq(8.0)
-    (sl[0..sl.len()+1])[9].assert_eq(2.0)
-}
-"#;
-    only_run_func(code)
+fn blank() -> () {
 }
 
-#[test]
-fn append_slice_of_structs_to_slice() -> anyhow::Result<()> {
-    //setup_logging();
-    let code = r#"
-struct Point { x, y, z, }
+fn other() -> () {
+    a = [1234; 10000]
+}
 
-fn main() -> () {
-    arr = [Point { x: 0.0, y: 0.0, z: 0.0, }; 100]
-    sl = arr[0..0]
-    sl.len().assert_eq(0)
-    to_append = [
-        Point { x: 1.0, y: 2.0, z: 3.0, },
-        Point { x: 4.0, y: 5.0, z: 6.0, },
-        Point { x: 7.0, y: 8.0, z: 9.0, }
-    ]
-    sl.append(to_append)
-    sl.len().assert_eq(3)
-    sl[0].x.assert_eq(1.0)
-    sl[0].y.assert_eq(2.0)
-    sl[0].z.assert_eq(3.0)
-    sl[1].x.assert_eq(4.0)
-    sl[1].y.assert_eq(5.0)
-    sl[1].z.assert_eq(6.0)
-    sl[2].x.assert_eq(7.0)
-    sl[2].y.assert_eq(8.0)
-    sl[2].z.assert_eq(9.0)
+fn first() -> () {
+    
+    blank()
+    blank()
+    other()
+    a = [1234; 10000]
+    other()
+}
+
+pub fn main() -> () { 
+    i = 0 while i <= 10 {
+        if i == 0 {
+        }
+        a = [1234; 10000]
+        b = [1234; 10000]
+        i += 1
+    }
+    if true {
+        
+    }
+    first()
 }
 "#;
     only_run_func(code)
 }
 
 #[test]
-fn returns_a_slice() -> anyhow::Result<()> {
+fn deep_stack_while_loop3() -> anyhow::Result<()> {
     //setup_logging();
     let code = r#"
-inline fn a_slice(a) -> (b: [f32]) {
-    b = [a; 100][..]
+fn blank() -> () {
 }
-fn main() -> () {
-    b = a_slice(5.0)
-    b[20].assert_eq(5.0)
+
+fn other() -> () {
+    a = [1234; 10000]
 }
-"#;
-    only_run_func(code)
+
+fn first() -> () {
+    
+    blank()
+    blank()
+    other()
+    a = [1234; 10000]
+    other()
 }
 
-#[test]
-fn scoped_vars() -> anyhow::Result<()> {
-    //setup_logging();
-    let code = r#"
-fn main() -> () {
-    if true {
-        a = 1.0 //does not live outside if statement
+pub fn main() -> () { 
+     i = 0 while i <= 10 {
+        first()
+        i += 1
     }
-    a = true
+    
 }
 "#;
     only_run_func(code)
 }
 
 #[test]
-fn u8_math() -> anyhow::Result<()> {
+fn deep_stack_while_loop_early_return() -> anyhow::Result<()> {
     //setup_logging();
     let code = r#"
-fn main() -> () {
-    a = 0
-    b = 255
-    c = 100
-    d = 5
-    ua = (0).u8()
-    ub = (255).u8()
-    uc = (100).u8()
-    ud = (5).u8()
-    (ua+ub).assert_eq((a+b).u8())
-    (ua-uc).assert_eq((a-c).u8())
-    (ua/uc).assert_eq((a/c).u8())
-    (ua*uc).assert_eq((a*c).u8())
-    (ud*ud).assert_eq((d*d).u8())
-    (ua+ub).i64().assert_eq(a+b)
-    (ua/uc).i64().assert_eq(a/c)
-    (ua*uc).i64().assert_eq(a*c)
-    (ud*ud).i64().assert_eq(d*d)
-    ua.assert_eq(0u8)
-    ub.assert_eq(255u8)
-    uc.assert_eq(100u8)
-    ud.assert_eq(5u8)
-    (0u8+255u8).assert_eq((0+255).u8())
-    (0u8-100u8).assert_eq((0-100).u8())
-    (0u8/100u8).assert_eq((0/100).u8())
-    (0u8*100u8).assert_eq((0*100).u8())
-    (5u8*5u8).assert_eq((5*5).u8())
+fn other(return_early: bool) -> () {
+    a = [1234; 10000]
+    i = 0 while i <= 5 {i+=1}:{
+        b = [1234; 10000]
+        if return_early {
+            return
+        }
+    }
+}
+
+pub fn main() -> () { 
+    other(true)
+    other(false)
 }
 "#;
     only_run_func(code)
 }
 
-extern "C" fn rust_check_slice(a: sarus_std_lib::SarusSlice<u8>) {
-    assert_eq!(a.len(), 43);
-    assert_eq!(a.cap(), 1000);
-    assert_eq!(
-        std::str::from_utf8(a.slice()).unwrap(),
-        "Hello World !\"#$%&'()*+,-./0123456789:;<=>?"
-    );
-}
-
 #[test]
-fn extern_func_slice() -> anyhow::Result<()> {
-    //setup_logging();
+fn ref_scalar_load_store() -> anyhow::Result<()> {
     let code = r#"
-    extern fn rust_check_slice(a: [u8]) -> () {}
-
-    fn main() -> () {
-        a = [0u8;1000][0..0]
-        a.append("Hello")
-        a.append(" ")
-        a.append("World")
-        i = 0u8
-        while i < 32u8 {
-            a.push(i + 32u8)
-            i += 1u8
-        }
-        rust_check_slice(a)
-    }
+pub fn main(a: &f32, b: &i64, c: &bool) -> (r: f32) {
+    r = *a
+    *a = *a * 2.0
+    *b = *b + 1
+    *c = *c == false
+}
 "#;
-    let ast = parse(code)?;
-    only_run_func_with_importer(ast, None, |_ast, jit_builder| {
-        jit_builder.symbols([("rust_check_slice", rust_check_slice as *const u8)]);
-    })
+    let mut jit = default_std_jit_from_code(code, true)?;
+    let func_ptr = jit.get_func("main")?;
+    let func = unsafe {
+        mem::transmute::<_, extern "C" fn(*mut f32, *mut i64, *mut bool) -> f32>(func_ptr)
+    };
+    let mut a = 1.5f32;
+    let mut b = 10i64;
+    let mut c = true;
+    assert_eq!(1.5, func(&mut a, &mut b, &mut c));
+    assert_eq!(3.0, a);
+    assert_eq!(11, b);
+    assert_eq!(false, c);
+    Ok(())
 }
 
 #[test]
-fn while_iter_block() -> anyhow::Result<()> {
-    //setup_logging();
+fn ref_deref_and_multiline_mul() -> anyhow::Result<()> {
     let code = r#"
-fn main() -> () {
-
-    arr = [0; 10]
-    
-    i = 0 while i < 10 { i += 1 }:{arr[i] = i}
-
-    i = 0 while i < 10 
-    { i += 1 } : {
-        arr[i].assert_eq(i)
-    }
-
-    i = 0 while i < 10 { i += 1 } : {
-        arr[i].assert_eq(i)
-    }
-
-    a = 5
-
-    i = 0 while i < 10 { 
-        a = i + 1
-        i += 1 
-    } : {
-        arr[i].assert_eq(i)
-    }
-    a.assert_eq(10)
-
-    i = 0 while i < 10 {
-        arr[i].assert_eq(i)
-        i += 1
-    }
+pub fn main(a: &f32, b: f32) -> (r: f32) {
+    r = b
+        * 2.0
+    *a = r
+    r = r *
+        *a
 }
 "#;
-    only_run_func(code)
+    let mut jit = default_std_jit_from_code(code, true)?;
+    let func_ptr = jit.get_func("main")?;
+    let func = unsafe { mem::transmute::<_, extern "C" fn(*mut f32, f32) -> f32>(func_ptr) };
+    let mut a = 0.0f32;
+    assert_eq!(36.0, func(&mut a, 3.0));
+    assert_eq!(6.0, a);
+    Ok(())
 }
 
 #[test]
-fn while_break() -> anyhow::Result<()> {
-    //setup_logging();
+fn ref_struct_host_buffer() -> anyhow::Result<()> {
     let code = r#"
-fn main() -> () {
-
-    arr = [0; 10]
-    a = 5
-    
-    i = 0 while i < 10 {
-        j = 0 
-        while j < 10 {
-            if i > 5 && j > 5 {
-                "!!!!".println()
-                a = j
-                break
-            }
-            j += 1
-        }
-        if i > 8 {
-            break
-        }
-        i += 1
-    }
+struct Point {
+    x: f32,
+    y: f32,
+    z: f32,
+}
 
-    a.assert_eq(6)
-    i.assert_eq(9)
+fn length(self: Point) -> (r: f32) {
+    r = (self.x.powf(2.0) + self.y.powf(2.0) + self.z.powf(2.0)).sqrt()
+}
 
-    i = 0 while true {i+=1 if i > 3 {break}} : {
-        a = 2
+pub fn main(points: &Point, count: i64, lengths: &f32) -> () {
+    i = 0 while i < count {
+        p = points.offset(i)
+        p.x = p.x + 1.0
+        *lengths.offset(i) = p.length()
+        i += 1
     }
-    i.assert_eq(4)
-
+    last = *points.offset(count - 1)
+    last.z = 0.0
 }
 "#;
-    only_run_func(code)
+    let mut jit = default_std_jit_from_code(code, true)?;
+    let func_ptr = jit.get_func("main")?;
+    let func = unsafe { mem::transmute::<_, extern "C" fn(*mut Point, i64, *mut f32)>(func_ptr) };
+    let mut points = vec![
+        Point {
+            x: 0.0,
+            y: 4.0,
+            z: 12.0,
+        },
+        Point {
+            x: 2.0,
+            y: 6.0,
+            z: 0.0,
+        },
+    ];
+    let mut lengths = vec![0.0f32; 2];
+    func(points.as_mut_ptr(), 2, lengths.as_mut_ptr());
+    assert_eq!(1.0, points[0].x);
+    assert_eq!(3.0, points[1].x);
+    assert_eq!(12.0, points[0].z);
+    assert_eq!(0.0, points[1].z);
+    assert_eq!((1.0f32 + 16.0 + 144.0).sqrt(), lengths[0]);
+    assert_eq!((9.0f32 + 36.0).sqrt(), lengths[1]);
+    Ok(())
 }
 
 #[test]
-fn while_continue() -> anyhow::Result<()> {
-    //setup_logging();
+fn ref_from_address() -> anyhow::Result<()> {
     let code = r#"
-fn main() -> () {
-    arr = [0; 10]
-
-    a = 0
-    i = 0 while i < 10 {i += 1} : {
-        if i > 5 {
-            continue
-        }
-        a = i
-    }
-    i.assert_eq(10)
-    a.assert_eq(5)
-
-    a = 0
-    i = 0 while i < 10 {i += 1} : {
-        if i > 5 {
-            continue
-        } else {
-            continue
-        }
-        a = i
-    }
-    i.assert_eq(10)
-    a.assert_eq(0)
+struct Point {
+    x: f32,
+    y: f32,
+    z: f32,
+}
 
-    a = 0
-    i = 0 while i < 10 {i += 1} : {
-        if i > 5 {
-            j = 0 while j < 10 {j += 1} : {
-                if j > 5 {
-                    a += 1
-                    break
-                } else {
-                    a += 2
-                    continue
-                }
-            }
-        } else {
-            continue
-        }
-        a += i
-    }
-    i.assert_eq(10)
-    a.assert_eq(82)
+pub fn main(addr: &) -> (r: f32) {
+    p = Point::unsafe_ref(addr)
+    y = f32::unsafe_ref(p.address()).offset(1)
+    *y = 5.0
+    r = p.y + (*p).ref().offset(0).x
+}
+"#;
+    let mut jit = default_std_jit_from_code(code, true)?;
+    let func_ptr = jit.get_func("main")?;
+    let func = unsafe { mem::transmute::<_, extern "C" fn(*mut Point) -> f32>(func_ptr) };
+    let mut p = Point {
+        x: 1.0,
+        y: 2.0,
+        z: 3.0,
+    };
+    assert_eq!(6.0, func(&mut p));
+    assert_eq!(5.0, p.y);
+    Ok(())
+}
 
+#[test]
+fn ref_store_type_mismatch() -> anyhow::Result<()> {
+    let code = r#"
+pub fn main(a: &f32) -> () {
+    *a = 1
 }
 "#;
-    only_run_func(code)
+    assert!(default_std_jit_from_code(code, true).is_err());
+    Ok(())
 }
 
 #[test]
-fn early_return() -> anyhow::Result<()> {
-    //setup_logging();
+fn array_and_struct_copy() -> anyhow::Result<()> {
     let code = r#"
-fn other2(a: i64) -> (b: i64) {
-    b = 0
-    if a > 5 {
-        b = 3
-        return
-    } else {
-        b = 4
-        return
-    }
-    b = a
+struct Point {
+    x: f32,
+    y: f32,
 }
 
-fn other(a: i64) -> (b: i64) {
-    b = 0
-    if a > 5 {
-        return
-    }
-    b = a
+pub fn main() -> (r: f32) {
+    a = [1.0, 2.0, 3.0]
+    b = a.copy()
+    b[0] = 10.0
+    p = Point { x: 1.0, y: 2.0, }
+    q = p.copy()
+    q.y = 20.0
+    r = a[0] + b[0] + p.y + q.y
+}
+"#;
+    let mut jit = default_std_jit_from_code(code, true)?;
+    let func_ptr = jit.get_func("main")?;
+    let func = unsafe { mem::transmute::<_, extern "C" fn() -> f32>(func_ptr) };
+    assert_eq!(33.0, func());
+    Ok(())
 }
 
-fn main() -> () {
-    other(6).assert_eq(0)
-    other(4).assert_eq(4)
-    other2(1).assert_eq(4)
-    other2(6).assert_eq(3)
+#[test]
+fn array_alias_reassigned() -> anyhow::Result<()> {
+    let code = r#"
+pub fn main() -> (r: f32) {
+    a = [1.0, 2.0, 3.0]
+    b = a
+    b = a.copy()
+    b[0] = 10.0
+    c = a
+    a = [4.0, 5.0, 6.0]
+    a[0] = 40.0
+    c[1] = 20.0
+    if true {
+        d = c
+        d[2] = 30.0
+    }
+    r = a[0] + b[0] + c[0] + c[1] + c[2]
 }
 "#;
-    only_run_func(code)
+    let mut jit = default_std_jit_from_code(code, true)?;
+    let func_ptr = jit.get_func("main")?;
+    let func = unsafe { mem::transmute::<_, extern "C" fn() -> f32>(func_ptr) };
+    assert_eq!(101.0, func());
+    Ok(())
 }
 
-#[cfg(test)]
-mod string_tests {
-
-    use super::*;
-    #[test]
-    fn find() -> anyhow::Result<()> {
-        only_run_func(
-            r#"
-fn main() -> () {
-    "hello".find("l").assert_eq(2)
-    "hello".find("x").assert_eq(-1)
-    "ประเทศไทย中华Việt Nam".find("华").assert_eq(30)
+#[test]
+fn copy_from() -> anyhow::Result<()> {
+    let code = r#"
+struct Point {
+    x: f32,
+    y: f32,
 }
-        "#,
-        )
-    }
 
-    #[test]
-    fn rfind() -> anyhow::Result<()> {
-        only_run_func(
-            r#"
-fn main() -> () {
-    "hello".rfind("l").assert_eq(3)
-    "hello".rfind("x").assert_eq(-1)
-    "ประเทศไทย中华Việt Nam".rfind("华").assert_eq(30)
+pub fn main() -> (r: i64) {
+    a = [0; 3]
+    a.copy_from([1, 2, 3])
+    s = [0; 8][0..1]
+    s.copy_from(a)
+    p = Point { x: 1.0, y: 2.0, }
+    p.copy_from(Point { x: 3.0, y: 4.0, })
+    r = a[2] * 100 + s.len() * 10 + s[0] + p.y.i64()
 }
-        "#,
-        )
-    }
-
-    #[test]
-    fn starts_with() -> anyhow::Result<()> {
-        only_run_func(
-            r#"
-fn main() -> () {
-    "".starts_with("").assert_eq(true)
-    "abc".starts_with("").assert_eq(true)
-    "abc".starts_with("a").assert_eq(true)
-    "a".starts_with("abc").assert_eq(false)
-    "".starts_with("abc").assert_eq(false)
-    "ödd".starts_with("-").assert_eq(false)
-    "ödd".starts_with("öd").assert_eq(true)
+"#;
+    let mut jit = default_std_jit_from_code(code, true)?;
+    let func_ptr = jit.get_func("main")?;
+    let func = unsafe { mem::transmute::<_, extern "C" fn() -> i64>(func_ptr) };
+    assert_eq!(300 + 30 + 1 + 4, func());
+    Ok(())
 }
-        "#,
-        )
-    }
-    #[test]
-    fn ends_with() -> anyhow::Result<()> {
-        only_run_func(
-            r#"
-fn main() -> () {
-    "".ends_with("").assert_eq(true)
-    "abc".ends_with("").assert_eq(true)
-    "abc".ends_with("c").assert_eq(true)
-    "a".ends_with("abc").assert_eq(false)
-    "".ends_with("abc").assert_eq(false)
-    "ddö".ends_with("-").assert_eq(false)
-    "ddö".ends_with("dö").assert_eq(true)
+
+#[test]
+fn copy_from_length_mismatch() -> anyhow::Result<()> {
+    let code = r#"
+pub fn main() -> () {
+    a = [0; 3]
+    a.copy_from([1, 2])
 }
-        "#,
-        )
-    }
+"#;
+    assert!(default_std_jit_from_code(code, true).is_err());
+    Ok(())
 }
-#[cfg(test)]
-mod enum_tests {
 
-    use super::*;
-    #[test]
-    fn enums_num() -> anyhow::Result<()> {
-        //setup_logging();
-        only_run_func(
-            r#"
-enum Num {
-    int: i64,
-    float: f32,
-    byte: u8,
+#[test]
+fn struct_field_defaults() -> anyhow::Result<()> {
+    let code = r#"
+struct Inner {
+    a: i64 = 7,
+    b: [f32; 4] = [0.0; 4],
 }
-
-fn f32(self: Num) -> (n: f32) {
-    n = 0.0
-    if self.type == Num::int {
-        n = self.int.f32()
-    } else if self.type == Num::float {
-        n = self.float
+struct Filter {
+    cutoff: f32 = 1000.0,
+    q: f32 = 0.707,
+    on: bool = true,
+    count: i64 = 0,
+    inner: Inner,
+}
+
+pub fn main() -> (r: f32) {
+    f = Filter { q: 2.0, }
+    i = Inner { b: [1.0; 4], }
+    r = if f.on && f.count == 0 && f.inner.a == 7 && i.a == 7 {
+        f.cutoff + f.q + f.inner.b[3] + i.b[3]
     } else {
-        n = self.byte.f32()
+        -1.0
     }
 }
-
-fn println(self: Num) -> () {
-    if self.type == Num::int {
-        self.int.println()
-    } else if self.type == Num::float {
-        self.float.println()
-    } else {
-        self.byte.println()
-    }
+"#;
+    let mut jit = default_std_jit_from_code(code, true)?;
+    let func_ptr = jit.get_func("main")?;
+    let func = unsafe { mem::transmute::<_, extern "C" fn() -> f32>(func_ptr) };
+    assert_eq!(1003.0, func());
+    Ok(())
 }
 
-fn assert_eq(self: Num, other: Num) -> () {
-    self.unify(other)
-    if self.type == Num::int {
-        self.int.assert_eq(other.int)
-    } else if self.type == Num::float {
-        self.float.assert_eq(other.float)
-    } else {
-        self.byte.assert_eq(other.byte)
-    }
+#[test]
+fn struct_field_defaults_errors() -> anyhow::Result<()> {
+    let no_default = r#"
+struct Filter {
+    cutoff: f32 = 1000.0,
+    gain: f32,
 }
-
-fn unify(self: Num, other: Num) -> () {
-    if self.type == other.type {
-        return
-    } else {
-        self = Num::float(self.f32())
-        other = Num::float(other.f32())
-    }
+fn main() -> (r: f32) {
+    f = Filter { cutoff: 1.0, }
+    r = f.gain
 }
-
-fn add(self: Num, other: Num) -> (z: Num) {
-    self.unify(other)
-    z = Num::int(100)
-    if self.type == Num::int {
-        z = Num::int(self.int + other.int)
-    } else if self.type == Num::float {
-        z = Num::float(self.float + other.float)
-    } else {
-        z = Num::byte(self.byte + other.byte)
-    }
+"#;
+    assert!(default_std_jit_from_code(no_default, true).is_err());
+    let wrong_type = r#"
+struct Filter {
+    cutoff: f32 = 1000,
 }
-
 fn main() -> () {
-    a = Num::int(5)
-    b = Num::float(5.0)
-    Num::int(5).add(Num::int(5)).assert_eq(Num::int(10))
-    Num::float(5.0).add(Num::float(5.0)).assert_eq(Num::float(10.0))
-    Num::int(5).add(Num::float(5.0)).assert_eq(Num::float(10.0))
-    Num::byte(5u8).add(Num::byte(5u8)).assert_eq(Num::byte(10u8))
-    Num::byte(5u8).add(Num::float(5.0)).assert_eq(Num::float(10.0))
 }
-    "#,
-        )
-    }
-
-    #[test]
-    fn enums_traditional() -> anyhow::Result<()> {
-        only_run_func(
-            r#"
-enum Num {
-    int,
-    float,
-    byte,
+"#;
+    assert!(default_std_jit_from_code(wrong_type, true).is_err());
+    // Defaults can't see the variables where the struct is created
+    let uses_local = r#"
+struct Filter {
+    cutoff: f32 = x,
+}
+fn main() -> (r: f32) {
+    x = 1.0
+    f = Filter {}
+    r = f.cutoff
+}
+"#;
+    assert!(default_std_jit_from_code(uses_local, true).is_err());
+    Ok(())
 }
 
-fn main() -> () {
-    Num::int().type.assert_eq(Num::int)
-    Num::float().type.assert_eq(Num::float)
-    Num::byte().type.assert_eq(Num::byte)
-    a = Num::int()
-    b = Num::float()
-    c = Num::byte()
-    a.type.assert_eq(0)
-    b.type.assert_eq(1)
-    c.type.assert_eq(2)
+#[test]
+fn struct_update_syntax() -> anyhow::Result<()> {
+    let code = r#"
+struct Filter {
+    cutoff: f32 = 1000.0,
+    q: f32,
+    gain: f32,
 }
-    "#,
-        )
-    }
 
-    #[test]
-    fn enums_mixed() -> anyhow::Result<()> {
-        only_run_func(
-            r#"
-enum Num {
-    int,
-    float,
-    something: f32,
-    byte,
-    something_else: i64,
+pub fn main() -> (r: f32) {
+    base = Filter { q: 2.0, gain: 3.0, }
+    f = Filter { cutoff: 500.0, ..base }
+    base.gain = 0.0
+    r = f.cutoff + f.q + f.gain + base.cutoff
+}
+"#;
+    let mut jit = default_std_jit_from_code(code, true)?;
+    let func_ptr = jit.get_func("main")?;
+    let func = unsafe { mem::transmute::<_, extern "C" fn() -> f32>(func_ptr) };
+    assert_eq!(1505.0, func());
+    Ok(())
 }
 
-fn main() -> () {
-    Num::int().type.assert_eq(Num::int)
-    Num::float().type.assert_eq(Num::float)
-    Num::something(100.0).type.assert_eq(Num::something)
-    Num::byte().type.assert_eq(Num::byte)
-    Num::something_else(200).type.assert_eq(Num::something_else)
-    a = Num::int()
-    b = Num::float()
-    c = Num::something(100.0)
-    d = Num::byte()
-    e = Num::something_else(200)
+#[test]
+fn struct_update_wrong_type() -> anyhow::Result<()> {
+    let code = r#"
+struct A { x, }
+struct B { x, }
+
+pub fn main() -> () {
+    a = A { x: 1.0, }
+    b = B { ..a }
+}
+"#;
+    assert!(default_std_jit_from_code(code, true).is_err());
+    Ok(())
+}
 
-    a.type.assert_eq(0)
-    b.type.assert_eq(1)
+#[test]
+fn associated_functions_and_constants() -> anyhow::Result<()> {
+    let code = r#"
+struct Filter {
+    cutoff: f32,
+    order: i64,
+}
 
-    c.type.assert_eq(2)
-    c.something.assert_eq(100.0)
+enum Mode {
+    Low,
+    High,
+}
 
-    d.type.assert_eq(3)
+const Filter::MAX_ORDER: i64 = 8
+const Filter::MIN_CUTOFF: f32 = 10.0
+const Mode::DEFAULT_GAIN: f32 = 0.5
 
-    e.type.assert_eq(4)
-    e.something_else.assert_eq(200)
-    
+fn Filter::new(cutoff: f32, order: i64) -> (f: Filter) {
+    f = Filter {
+        cutoff: cutoff.max(Filter::MIN_CUTOFF),
+        order: order.min(Filter::MAX_ORDER),
+    }
 }
-    "#,
-        )
+
+fn Mode::new() -> (m: Mode) {
+    m = Mode::High()
+}
+
+pub fn main() -> (r: f32) {
+    f = Filter::new(0.0, 20)
+    m = Mode::new()
+    r = f.cutoff + f.order.f32() + Mode::DEFAULT_GAIN
+    if m.type == Mode::High {
+        r += 100.0
     }
+}
+"#;
+    let mut jit = default_std_jit_from_code(code, true)?;
+    let func_ptr = jit.get_func("main")?;
+    let func = unsafe { mem::transmute::<_, extern "C" fn() -> f32>(func_ptr) };
+    assert_eq!(118.5, func());
+    Ok(())
+}
 
-    #[test]
-    fn rust_by_example_enum() -> anyhow::Result<()> {
-        //adapted from: https://doc.rust-lang.org/rust-by-example/custom_types/enum.html
-        only_run_func(
-            r#"
-// Create an `enum` to classify a web event. Note how both
-// names and type information together specify the variant:
-// `page_load != page_unload` and `key_press: [u8] != paste: [u8]`.
-// Each is different and independent.
-struct Click {
-    x: i64, 
-    y: i64,
+#[test]
+fn associated_constant_unknown_type() -> anyhow::Result<()> {
+    let code = r#"
+const Nope::MAX: i64 = 8
+
+pub fn main() -> () {
+}
+"#;
+    assert!(default_std_jit_from_code(code, true).is_err());
+    Ok(())
 }
 
-enum WebEvent {
-    // An `enum` may either be `unit-like`,
-    page_load,
-    page_unload,
-    // or have associated types
-    key_press: [u8],
-    paste: [u8],
-    click: Click,
+#[test]
+fn module_constants() -> anyhow::Result<()> {
+    let code = r#"
+const BLOCK_SIZE: i64 = 16
+const HALF_BLOCK: i64 = BLOCK_SIZE / 2 + 1 - 1
+const GAIN: f32 = (4.0).sqrt() * 0.5 + BLOCK_SIZE.f32()
+const ENABLED: bool = HALF_BLOCK > 4 && !false
+
+struct Block {
+    samples: [f32; BLOCK_SIZE] = [0.0; BLOCK_SIZE],
 }
 
-// A function which takes a `WebEvent` enum as an argument and
-// returns nothing.
-fn inspect(event: WebEvent) -> () {
-    if event.type == WebEvent::page_load {
-        "page loaded".println()
-    } else if event.type == WebEvent::page_unload {
-        "page unloaded".println()
-    } else if event.type == WebEvent::key_press {
-        "pressed ".print() event.key_press.println()
-    } else if event.type == WebEvent::paste {
-        "pasted ".print() event.paste.println()
-    } else if event.type == WebEvent::click {
-        "clicked at ".print() 
-        "x= ".print() event.click.x.print()
-        ", y= ".print() event.click.x.print()
-        ".".println()
-    } 
+pub fn main() -> (r: f32) {
+    b = Block {}
+    arr = [GAIN; HALF_BLOCK]
+    r = b.samples.len().f32() + arr.len().f32() + arr[0]
+    if ENABLED {
+        r += 100.0
+    }
+}
+"#;
+    let mut jit = default_std_jit_from_code(code, true)?;
+    let func_ptr = jit.get_func("main")?;
+    let func = unsafe { mem::transmute::<_, extern "C" fn() -> f32>(func_ptr) };
+    assert_eq!(141.0, func());
+    Ok(())
 }
 
-fn main() -> () {
-    pressed = WebEvent::key_press("x"[..])
-    pasted = WebEvent::paste("my text"[..])
-    click = WebEvent::click(Click{ x: 20, y: 80, }) 
-    load = WebEvent::page_load()
-    unload = WebEvent::page_unload()
+#[test]
+fn module_constants_errors() -> anyhow::Result<()> {
+    let cycle = r#"
+const A: i64 = B + 1
+const B: i64 = A + 1
 
-    inspect(pressed)
-    inspect(pasted)
-    inspect(click)
-    inspect(load)
-    inspect(unload)
+pub fn main() -> () {
+}
+"#;
+    assert!(default_std_jit_from_code(cycle, true).is_err());
+    let wrong_type = r#"
+const A: i64 = 1.5
 
-    pressed.type.assert_eq(WebEvent::key_press)
-    pasted.type.assert_eq(WebEvent::paste)
-    click.type.assert_eq(WebEvent::click)
-    load.type.assert_eq(WebEvent::page_load)
-    unload.type.assert_eq(WebEvent::page_unload)
+pub fn main() -> () {
 }
-    "#,
-        )
-    }
+"#;
+    assert!(default_std_jit_from_code(wrong_type, true).is_err());
+    let bad_len = r#"
+const LEN: f32 = 4.0
+
+pub fn main() -> () {
+    arr = [0.0; LEN]
+}
+"#;
+    assert!(default_std_jit_from_code(bad_len, true).is_err());
+    Ok(())
 }
 
 #[test]
-fn loop_lifetime() -> anyhow::Result<()> {
-    only_run_func(
-        r#"
-fn main() -> () {
-    a = [0;10000][..]
-    i = 0 while i < 5 {i+=1} : {
-        c = [1.0;10000]
-        if i == 0 {
-            b = [2;10000] //(while)inner, copy
-            a = b[..] //TODO compiler error
-        }
-        d = [1.0;10000]
+fn statics() -> anyhow::Result<()> {
+    let code = r#"
+const HISTORY_LEN: i64 = 4
+static LEVEL: f32 = 0.0
+static HISTORY: [f32; HISTORY_LEN] = [0.0; HISTORY_LEN]
+static COUNT: i64 = 0
+static ENABLED: bool = true
+
+pub fn follow(x: f32) -> (y: f32) {
+    LEVEL = LEVEL * 0.5 + x.abs() * 0.5
+    HISTORY[COUNT] = LEVEL
+    COUNT += 1
+    y = if ENABLED {
+        LEVEL
+    } else {
+        0.0
     }
-    e = [1.0;10000]
-    //a[5].println()
 }
-"#,
-    )
+"#;
+    let mut jit = default_std_jit_from_code(code, true)?;
+    let func_ptr = jit.get_func("follow")?;
+    let func = unsafe { mem::transmute::<_, extern "C" fn(f32) -> f32>(func_ptr) };
+    assert_eq!(0.5, func(-1.0));
+    assert_eq!(0.75, func(1.0));
+    let count = jit.get_static::<i64>("COUNT")?;
+    let history = jit.get_static::<[f32; 4]>("HISTORY")?;
+    let level = jit.get_static::<f32>("LEVEL")?;
+    let enabled = jit.get_static::<bool>("ENABLED")?;
+    unsafe {
+        assert_eq!(2, *count);
+        assert_eq!([0.5, 0.75, 0.0, 0.0], *history);
+        *level = 2.0;
+    }
+    assert_eq!(1.0, func(0.0));
+    unsafe {
+        *enabled = false;
+    }
+    assert_eq!(0.0, func(0.0));
+    unsafe {
+        assert_eq!(4, *count);
+    }
+    assert!(jit.get_static::<f32>("COUNT").is_err());
+    assert!(jit.get_static::<f32>("NOPE").is_err());
+    Ok(())
 }
 
 #[test]
-fn loop_lifetime_simple() -> anyhow::Result<()> {
-    //setup_logging();
-    only_run_func(
-        r#"
-fn main() -> () {
-    //a = [0;100][..]
-    i = 0 while i < 1 {i+=1} : {
-        d = [1.0;100]
-    }
-    //(1.0).println()
-    //a = 1.0
-    //i = 0 while i < 100000000 {i+=1} : {
-    //    a *= (1.0).sin()
-    //}
-    //(2.0).println()
+fn statics_errors() -> anyhow::Result<()> {
+    let wrong_type = r#"
+static LEVEL: f32 = 1
 
+pub fn main() -> () {
 }
-"#,
-    )
+"#;
+    assert!(default_std_jit_from_code(wrong_type, true).is_err());
+    let not_const = r#"
+fn one() -> (r: f32) {
+    r = 1.0
 }
 
-#[test]
-fn deep_stack_while_loop2() -> anyhow::Result<()> {
-    //setup_logging();
-    let code = r#"
-fn blank() -> () {
+static LEVEL: f32 = one()
+
+pub fn main() -> () {
 }
+"#;
+    assert!(default_std_jit_from_code(not_const, true).is_err());
+    let assign_array = r#"
+static BUF: [f32; 2] = [0.0; 2]
 
-fn other() -> () {
-    a = [1234; 10000]
+pub fn main() -> () {
+    BUF = [1.0; 2]
+}
+"#;
+    assert!(default_std_jit_from_code(assign_array, true).is_err());
+    Ok(())
 }
 
-fn first() -> () {
-    
-    blank()
-    blank()
-    other()
-    a = [1234; 10000]
-    other()
+#[test]
+fn static_tables() -> anyhow::Result<()> {
+    let code = r#"
+const TABLE_LEN: i64 = 64
+static GAIN: f32 = 2.0
+static SQUARES: [i64; 4] = gen_squares()
+static RAMP: [f32; TABLE_LEN] = gen_ramp()
+
+fn square(n: i64) -> (r: i64) {
+    r = n * n
 }
 
-fn main() -> () { 
-    i = 0 while i <= 10 {
-        if i == 0 {
-        }
-        a = [1234; 10000]
-        b = [1234; 10000]
+fn gen_squares() -> (table: [i64; 4]) {
+    table = [0; 4]
+    i = 0
+    while i < 4 {
+        table[i] = square(i)
         i += 1
     }
-    if true {
-        
+}
+
+fn gen_ramp() -> (table: [f32; TABLE_LEN]) {
+    table = [0.0; TABLE_LEN]
+    i = 0
+    while i < TABLE_LEN {
+        table[i] = i.f32() * GAIN + SQUARES[3].f32()
+        i += 1
     }
-    first()
+}
+
+pub fn main(i: i64) -> (r: f32) {
+    r = RAMP[i] + SQUARES[2].f32()
 }
 "#;
-    only_run_func(code)
+    let mut jit = default_std_jit_from_code(code, true)?;
+    let func_ptr = jit.get_func("main")?;
+    let func = unsafe { mem::transmute::<_, extern "C" fn(i64) -> f32>(func_ptr) };
+    assert_eq!(13.0, func(0));
+    assert_eq!(139.0, func(63));
+    let squares = jit.get_static::<[i64; 4]>("SQUARES")?;
+    unsafe {
+        assert_eq!([0, 1, 4, 9], *squares);
+    }
+    Ok(())
 }
 
 #[test]
-fn deep_stack_while_loop3() -> anyhow::Result<()> {
-    //setup_logging();
-    let code = r#"
-fn blank() -> () {
+fn static_tables_errors() -> anyhow::Result<()> {
+    let uses_later_table = r#"
+static A: [f32; 2] = gen_a()
+static B: [f32; 2] = gen_b()
+
+fn gen_a() -> (table: [f32; 2]) {
+    table = [B[0]; 2]
 }
 
-fn other() -> () {
-    a = [1234; 10000]
+fn gen_b() -> (table: [f32; 2]) {
+    table = [1.0; 2]
 }
+"#;
+    assert!(default_std_jit_from_code(uses_later_table, true).is_err());
+    let wrong_return = r#"
+static A: [f32; 2] = gen_a()
 
-fn first() -> () {
-    
-    blank()
-    blank()
-    other()
-    a = [1234; 10000]
-    other()
+fn gen_a() -> (table: [f32; 3]) {
+    table = [1.0; 3]
+}
+"#;
+    assert!(default_std_jit_from_code(wrong_return, true).is_err());
+    let write_table = r#"
+static A: [f32; 2] = gen_a()
+
+fn gen_a() -> (table: [f32; 2]) {
+    table = [1.0; 2]
 }
 
-fn main() -> () { 
-     i = 0 while i <= 10 {
-        first()
+pub fn main() -> () {
+    A[0] = 2.0
+}
+"#;
+    assert!(default_std_jit_from_code(write_table, true).is_err());
+    let out_of_bounds = r#"
+static A: [f32; 2] = gen_a()
+
+fn gen_a() -> (table: [f32; 2]) {
+    table = [1.0; 2]
+    i = 0
+    while i < 3 {
+        table[i] = 2.0
         i += 1
     }
-    
 }
 "#;
-    only_run_func(code)
+    let error = default_std_jit_from_code(out_of_bounds, true)
+        .err()
+        .unwrap();
+    assert!(error
+        .to_string()
+        .contains("could not be initialized by gen_a"));
+    Ok(())
 }
 
 #[test]
-fn deep_stack_while_loop_early_return() -> anyhow::Result<()> {
-    //setup_logging();
+fn type_aliases() -> anyhow::Result<()> {
     let code = r#"
-fn other(return_early: bool) -> () {
-    a = [1234; 10000]
-    i = 0 while i <= 5 {i+=1}:{
-        b = [1234; 10000]
-        if return_early {
-            return
-        }
-    }
+const BLOCK_SIZE: i64 = 8
+type Sample = f32
+type Block = [Sample; BLOCK_SIZE]
+
+const HALF: Sample = 0.5
+
+struct Voice {
+    gain: Sample,
+    buf: Block = [0.0; BLOCK_SIZE],
 }
 
-fn main() -> () { 
-    other(true)
-    other(false)
+pub fn process(v: Voice, x: Sample) -> (y: Sample) {
+    v.buf[0] = x * v.gain
+    y = v.buf[0]
+}
+
+pub fn main() -> (r: f32) {
+    v = Voice { gain: 2.0 }
+    r = process(v, 3.0) + v.buf.len().f32() + HALF
 }
 "#;
-    only_run_func(code)
+    let mut jit = default_std_jit_from_code(code, true)?;
+    let func_ptr = jit.get_func("main")?;
+    let func = unsafe { mem::transmute::<_, extern "C" fn() -> f32>(func_ptr) };
+    assert_eq!(14.5, func());
+    Ok(())
+}
+
+#[test]
+fn type_aliases_errors() -> anyhow::Result<()> {
+    let cycle = r#"
+type A = [B; 2]
+type B = A
+
+pub fn main() -> () {
+}
+"#;
+    assert!(default_std_jit_from_code(cycle, true).is_err());
+    let struct_name = r#"
+struct Point {
+    x: f32,
+}
+type Point = f32
+
+pub fn main() -> () {
+}
+"#;
+    assert!(default_std_jit_from_code(struct_name, true).is_err());
+    Ok(())
 }